@@ -1,6 +1,7 @@
 #[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_json_binary, Binary, Decimal, Deps, DepsMut, Empty, Env, MessageInfo, StdResult, ensure_eq, entry_point
+    to_json_binary, Binary, Decimal, Deps, DepsMut, Empty, Env, MessageInfo, Reply, StdResult, Uint128, ensure_eq,
 };
 
 use cw2::set_contract_version;
@@ -8,16 +9,16 @@ use sg_std::StargazeMsgWrapper;
 
 use crate::error::ContractError;
 use crate::execute::{
-    accept_loan, accept_offer, cancel_offer, deposit_collaterals, make_offer, modify_collaterals,
-    refuse_offer, repay_borrowed_funds, withdraw_collateral, withdraw_defaulted_loan,
-    withdraw_refused_offer,
+    accept_counter, accept_loan, accept_offer, cancel_offer, counter_offer, cure_default, deposit_collaterals, make_offer, modify_collaterals,
+    refuse_offer, reply_accept_offer, repay_borrowed_funds, reserve_for_lender, set_loan_duration, sweeten_terms, withdraw_collateral, withdraw_collateral_and_refuse_all, abandon_listings, withdraw_defaulted_loan, withdraw_defaulted_loans,
+    withdraw_refused_offer, split_listing,
 };
 use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
 use crate::query::{
-    query_all_collaterals, query_borrower_info, query_collateral_info, query_collaterals,
-    query_contract_info, query_lender_offers, query_offer_info, query_offers,
+    query_all_collaterals, query_archived_loans, query_collaterals_filtered, query_best_offers, query_borrower_info, query_collateral_info, query_collaterals,
+    query_contract_info, query_lender_offers, query_lender_defaulted_loans, query_loan_to_value, query_offer_info, query_offer_location, query_offer_yield, query_offer_ids_for_loan, query_offer_stats, query_offers, query_offers_for_loan, query_solvency_check, query_tvl, query_withdrawable_offers,
 };
-use crate::state::{ContractInfo, CONTRACT_INFO};
+use crate::state::{ContractInfo, COLLATERAL_INFO, CONTRACT_INFO};
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:sg-nft-loan";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -31,6 +32,10 @@ pub fn instantiate(
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
+    // Same bound as when the fee rate is changed later on
+    if msg.fee_rate >= Decimal::one() {
+        return Err(ContractError::NotAcceptable {});
+    }
     let data = ContractInfo {
         name: msg.name,
         owner: deps
@@ -39,6 +44,13 @@ pub fn instantiate(
         fee_distributor: deps.api.addr_validate(&msg.fee_distributor)?,
         fee_rate: msg.fee_rate,
         global_offer_index: 0,
+        min_interest: msg.min_interest,
+        allowed_collections: None,
+        cure_blocks: 0,
+        cure_seconds: 0,
+        cure_penalty: Decimal::zero(),
+        interest_beneficiaries: vec![],
+        verify_ownership_on_deposit: false,
     };
 
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
@@ -60,6 +72,12 @@ pub fn migrate(deps: DepsMut, _env: Env, _msg: Empty) -> StdResult<Response> {
     Ok(Response::default())
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    // The only submessages with a reply are the collateral transfers of the accepted offers
+    reply_accept_offer(deps, env, msg)
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
@@ -73,16 +91,36 @@ pub fn execute(
             terms,
             comment,
             loan_preview,
-        } => deposit_collaterals(deps, env, info, tokens, terms, comment, loan_preview),
+            listing_expiry,
+        } => deposit_collaterals(deps, env, info, tokens, terms, comment, loan_preview, listing_expiry),
         ExecuteMsg::ModifyCollaterals {
             loan_id,
             terms,
             comment,
             loan_preview,
-        } => modify_collaterals(deps, env, info, loan_id, terms, comment, loan_preview),
+            listing_expiry,
+        } => modify_collaterals(deps, env, info, loan_id, terms, comment, loan_preview, listing_expiry),
         ExecuteMsg::WithdrawCollaterals { loan_id } => {
             withdraw_collateral(deps, env, info, loan_id)
         }
+        ExecuteMsg::ReserveForLender { loan_id, lender } => {
+            reserve_for_lender(deps, env, info, loan_id, lender)
+        }
+        ExecuteMsg::SetLoanDuration { loan_id, duration } => {
+            set_loan_duration(deps, env, info, loan_id, duration)
+        }
+        ExecuteMsg::SweetenTerms { loan_id, interest } => {
+            sweeten_terms(deps, env, info, loan_id, interest)
+        }
+        ExecuteMsg::WithdrawCollateralAndRefuseAll { loan_id } => {
+            withdraw_collateral_and_refuse_all(deps, env, info, loan_id)
+        }
+        ExecuteMsg::AbandonListings { loan_ids } => {
+            abandon_listings(deps, env, info, loan_ids)
+        }
+        ExecuteMsg::SplitListing { loan_id, groups } => {
+            split_listing(deps, env, info, loan_id, groups)
+        }
 
         ExecuteMsg::AcceptLoan {
             borrower,
@@ -93,12 +131,20 @@ pub fn execute(
         ExecuteMsg::AcceptOffer { global_offer_id } => {
             accept_offer(deps, env, info, global_offer_id)
         }
+        ExecuteMsg::CounterOffer {
+            global_offer_id,
+            terms,
+        } => counter_offer(deps, env, info, global_offer_id, terms),
+        ExecuteMsg::AcceptCounter { global_offer_id } => {
+            accept_counter(deps, env, info, global_offer_id)
+        }
         ExecuteMsg::MakeOffer {
             borrower,
             loan_id,
             terms,
             comment,
-        } => make_offer(deps, env, info, borrower, loan_id, terms, comment),
+            memo,
+        } => make_offer(deps, env, info, borrower, loan_id, terms, comment, memo),
 
         ExecuteMsg::CancelOffer { global_offer_id } => {
             cancel_offer(deps, env, info, global_offer_id)
@@ -118,6 +164,10 @@ pub fn execute(
         ExecuteMsg::WithdrawDefaultedLoan { borrower, loan_id } => {
             withdraw_defaulted_loan(deps, env, info, borrower, loan_id)
         }
+        ExecuteMsg::WithdrawDefaultedLoans { loans } => {
+            withdraw_defaulted_loans(deps, env, info, loans)
+        }
+        ExecuteMsg::CureDefault { loan_id } => cure_default(deps, env, info, loan_id),
 
         // Internal Contract Logic
         ExecuteMsg::SetOwner { owner } => set_owner(deps, env, info, owner),
@@ -125,12 +175,34 @@ pub fn execute(
             set_fee_distributor(deps, env, info, fee_depositor)
         }
 
+        ExecuteMsg::SetLoanFeeDistributor {
+            borrower,
+            loan_id,
+            fee_distributor,
+        } => set_loan_fee_distributor(deps, env, info, borrower, loan_id, fee_distributor),
         ExecuteMsg::SetFeeRate { fee_rate } => set_fee_rate(deps, env, info, fee_rate),
+        ExecuteMsg::SetMinInterest { min_interest } => {
+            set_min_interest(deps, env, info, min_interest)
+        }
+        ExecuteMsg::SetAllowedCollections {
+            allowed_collections,
+        } => set_allowed_collections(deps, env, info, allowed_collections),
+        ExecuteMsg::SetCureParams {
+            cure_blocks,
+            cure_seconds,
+            cure_penalty,
+        } => set_cure_params(deps, env, info, cure_blocks, cure_seconds, cure_penalty),
+        ExecuteMsg::SetInterestBeneficiaries { beneficiaries } => {
+            set_interest_beneficiaries(deps, env, info, beneficiaries)
+        }
+        ExecuteMsg::SetVerifyOwnershipOnDeposit {
+            verify_ownership_on_deposit,
+        } => set_verify_ownership_on_deposit(deps, env, info, verify_ownership_on_deposit),
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::ContractInfo {} => to_json_binary(&query_contract_info(deps)?),
         QueryMsg::BorrowerInfo { borrower } => {
@@ -143,24 +215,87 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             borrower,
             start_after,
             limit,
-        } => to_json_binary(&query_collaterals(deps, borrower, start_after, limit)?),
-        QueryMsg::AllCollaterals { start_after, limit } => {
-            to_json_binary(&query_all_collaterals(deps, start_after, limit)?)
-        }
+        } => to_json_binary(&query_collaterals(deps, env, borrower, start_after, limit)?),
+        QueryMsg::ArchivedLoans {
+            borrower,
+            start_after,
+            limit,
+        } => to_json_binary(&query_archived_loans(deps, env, borrower, start_after, limit)?),
+        QueryMsg::AllCollaterals {
+            start_after,
+            limit,
+            order,
+        } => to_json_binary(&query_all_collaterals(deps, env, start_after, limit, order)?),
+        QueryMsg::CollateralsFiltered {
+            state,
+            defaulted,
+            start_after,
+            limit,
+        } => to_json_binary(&query_collaterals_filtered(
+            deps,
+            env,
+            state,
+            defaulted,
+            start_after,
+            limit,
+        )?),
         QueryMsg::OfferInfo { global_offer_id } => {
             to_json_binary(&query_offer_info(deps, global_offer_id)?)
         }
+        QueryMsg::OfferLocation { global_offer_id } => {
+            to_json_binary(&query_offer_location(deps, global_offer_id)?)
+        }
+        QueryMsg::OfferYield { global_offer_id } => {
+            to_json_binary(&query_offer_yield(deps, global_offer_id)?)
+        }
+        QueryMsg::LoanToValue {
+            borrower,
+            loan_id,
+            collateral_floor,
+        } => to_json_binary(&query_loan_to_value(deps, borrower, loan_id, collateral_floor)?),
         QueryMsg::Offers {
             borrower,
             loan_id,
             start_after,
             limit,
         } => to_json_binary(&query_offers(deps, borrower, loan_id, start_after, limit)?),
+        QueryMsg::BestOffers {
+            borrower,
+            loan_id,
+            limit,
+        } => to_json_binary(&query_best_offers(deps, borrower, loan_id, limit)?),
+        QueryMsg::OfferIdsForLoan { borrower, loan_id } => {
+            to_json_binary(&query_offer_ids_for_loan(deps, borrower, loan_id)?)
+        }
+        QueryMsg::OfferStats { borrower, loan_id } => {
+            to_json_binary(&query_offer_stats(deps, borrower, loan_id)?)
+        }
+        QueryMsg::Tvl { start_after, limit } => {
+            to_json_binary(&query_tvl(deps, start_after, limit)?)
+        }
+        QueryMsg::SolvencyCheck {} => to_json_binary(&query_solvency_check(deps, env)?),
+        QueryMsg::OffersForLoan { borrower, loan_id } => {
+            to_json_binary(&query_offers_for_loan(deps, borrower, loan_id)?)
+        }
         QueryMsg::LenderOffers {
             lender,
             start_after,
             limit,
         } => to_json_binary(&query_lender_offers(deps, lender, start_after, limit)?),
+        QueryMsg::WithdrawableOffers { lender } => {
+            to_json_binary(&query_withdrawable_offers(deps, lender)?)
+        }
+        QueryMsg::LenderDefaultedLoans {
+            lender,
+            start_after,
+            limit,
+        } => to_json_binary(&query_lender_defaulted_loans(
+            deps,
+            env,
+            lender,
+            start_after,
+            limit,
+        )?),
     }
 }
 
@@ -203,13 +338,52 @@ pub fn set_fee_distributor(
         ContractError::Unauthorized {}
     );
 
+    let old_distributor = contract_info.fee_distributor;
     contract_info.fee_distributor = deps.api.addr_validate(&new_distributor)?;
     CONTRACT_INFO.save(deps.storage, &contract_info)?;
 
-    Ok(Response::default()
+    Ok(Response::new()
         .add_attribute("action", "changed-contract-parameter")
         .add_attribute("parameter", "fee_distributor")
-        .add_attribute("value", new_distributor))
+        .add_attribute("old_value", old_distributor)
+        .add_attribute("value", contract_info.fee_distributor))
+}
+
+/// Owner only function
+/// Routes the fees of a single loan to another fee-distributor contract, for partnerships
+pub fn set_loan_fee_distributor(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    borrower: String,
+    loan_id: u64,
+    fee_distributor: Option<String>,
+) -> Result<Response, ContractError> {
+    let contract_info = CONTRACT_INFO.load(deps.storage)?;
+    ensure_eq!(
+        info.sender,
+        contract_info.owner,
+        ContractError::Unauthorized {}
+    );
+
+    let borrower = deps.api.addr_validate(&borrower)?;
+    let mut collateral = COLLATERAL_INFO.load(deps.storage, (borrower.clone(), loan_id))?;
+    collateral.fee_distributor_override = fee_distributor
+        .map(|fee_distributor| deps.api.addr_validate(&fee_distributor))
+        .transpose()?;
+    COLLATERAL_INFO.save(deps.storage, (borrower.clone(), loan_id), &collateral)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "changed-contract-parameter")
+        .add_attribute("parameter", "loan_fee_distributor")
+        .add_attribute("borrower", borrower)
+        .add_attribute("loan_id", loan_id.to_string())
+        .add_attribute(
+            "value",
+            collateral
+                .fee_distributor_override
+                .map_or_else(|| "none".to_string(), |addr| addr.to_string()),
+        ))
 }
 
 /// Owner only function
@@ -242,3 +416,179 @@ pub fn set_fee_rate(
         .add_attribute("value", new_fee_rate.to_string()))
 }
 
+/// Owner only function
+/// Sets the minimum interest every new offer has to carry
+/// This prevents zero-interest loans that would bypass the protocol fees
+/// Setting it to None removes the limit
+pub fn set_min_interest(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    min_interest: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let mut contract_info = CONTRACT_INFO.load(deps.storage)?;
+    ensure_eq!(
+        info.sender,
+        contract_info.owner,
+        ContractError::Unauthorized {}
+    );
+
+    contract_info.min_interest = min_interest;
+    CONTRACT_INFO.save(deps.storage, &contract_info)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "changed-contract-parameter")
+        .add_attribute("parameter", "min_interest")
+        .add_attribute(
+            "value",
+            min_interest
+                .map(|min_interest| min_interest.to_string())
+                .unwrap_or_else(|| "none".to_string()),
+        ))
+}
+
+/// Owner only function
+/// Restricts the collections accepted as loan collateral
+/// An empty list or None allows every collection
+pub fn set_allowed_collections(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    allowed_collections: Option<Vec<String>>,
+) -> Result<Response, ContractError> {
+    let mut contract_info = CONTRACT_INFO.load(deps.storage)?;
+    ensure_eq!(
+        info.sender,
+        contract_info.owner,
+        ContractError::Unauthorized {}
+    );
+
+    // An empty list allows all collections, same as no list
+    let allowed_collections = allowed_collections
+        .filter(|collections| !collections.is_empty())
+        .map(|collections| {
+            collections
+                .iter()
+                .map(|collection| Ok(deps.api.addr_validate(collection)?.to_string()))
+                .collect::<StdResult<Vec<String>>>()
+        })
+        .transpose()?;
+    contract_info.allowed_collections = allowed_collections.clone();
+    CONTRACT_INFO.save(deps.storage, &contract_info)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "changed-contract-parameter")
+        .add_attribute("parameter", "allowed_collections")
+        .add_attribute(
+            "value",
+            allowed_collections
+                .map(|collections| collections.join(","))
+                .unwrap_or_else(|| "none".to_string()),
+        ))
+}
+
+/// Owner only function
+/// Sets the window during which a defaulted loan can still be cured, in blocks and in seconds
+/// depending on how the loan duration is evaluated, along with the penalty paid to the lender when curing.
+/// The penalty is a share of the principle and can't exceed the principle itself
+pub fn set_cure_params(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    cure_blocks: u64,
+    cure_seconds: u64,
+    cure_penalty: Decimal,
+) -> Result<Response, ContractError> {
+    let mut contract_info = CONTRACT_INFO.load(deps.storage)?;
+    ensure_eq!(
+        info.sender,
+        contract_info.owner,
+        ContractError::Unauthorized {}
+    );
+
+    if cure_penalty > Decimal::one() {
+        return Err(ContractError::NotAcceptable {});
+    }
+    contract_info.cure_blocks = cure_blocks;
+    contract_info.cure_seconds = cure_seconds;
+    contract_info.cure_penalty = cure_penalty;
+    CONTRACT_INFO.save(deps.storage, &contract_info)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "changed-contract-parameter")
+        .add_attribute("parameter", "cure_params")
+        .add_attribute("cure_blocks", cure_blocks.to_string())
+        .add_attribute("cure_seconds", cure_seconds.to_string())
+        .add_attribute("cure_penalty", cure_penalty.to_string()))
+}
+
+/// Owner only function
+/// Makes the deposits check that the borrower owns every listed asset
+pub fn set_verify_ownership_on_deposit(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    verify_ownership_on_deposit: bool,
+) -> Result<Response, ContractError> {
+    let mut contract_info = CONTRACT_INFO.load(deps.storage)?;
+    ensure_eq!(
+        info.sender,
+        contract_info.owner,
+        ContractError::Unauthorized {}
+    );
+
+    contract_info.verify_ownership_on_deposit = verify_ownership_on_deposit;
+    CONTRACT_INFO.save(deps.storage, &contract_info)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "changed-contract-parameter")
+        .add_attribute("parameter", "verify_ownership_on_deposit")
+        .add_attribute("value", verify_ownership_on_deposit.to_string()))
+}
+
+/// Owner only function
+/// Sets the addresses the fee cut of the interests is split between on repayment
+/// The weights have to sum to 1. An empty list sends the whole fee cut to the fee distributor again
+pub fn set_interest_beneficiaries(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    beneficiaries: Vec<(String, Decimal)>,
+) -> Result<Response, ContractError> {
+    let mut contract_info = CONTRACT_INFO.load(deps.storage)?;
+    ensure_eq!(
+        info.sender,
+        contract_info.owner,
+        ContractError::Unauthorized {}
+    );
+
+    if !beneficiaries.is_empty()
+        && beneficiaries
+            .iter()
+            .map(|(_, weight)| *weight)
+            .sum::<Decimal>()
+            != Decimal::one()
+    {
+        return Err(ContractError::InvalidBeneficiaryWeights {});
+    }
+    contract_info.interest_beneficiaries = beneficiaries
+        .into_iter()
+        .map(|(address, weight)| Ok((deps.api.addr_validate(&address)?, weight)))
+        .collect::<StdResult<Vec<_>>>()?;
+    CONTRACT_INFO.save(deps.storage, &contract_info)?;
+
+    let value = if contract_info.interest_beneficiaries.is_empty() {
+        "none".to_string()
+    } else {
+        contract_info
+            .interest_beneficiaries
+            .iter()
+            .map(|(address, weight)| format!("{address}:{weight}"))
+            .collect::<Vec<String>>()
+            .join(",")
+    };
+    Ok(Response::new()
+        .add_attribute("action", "changed-contract-parameter")
+        .add_attribute("parameter", "interest_beneficiaries")
+        .add_attribute("value", value))
+}
@@ -1,4 +1,4 @@
-use cosmwasm_std::{StdError, Uint128};
+use cosmwasm_std::{Coin, StdError, Uint128};
 use thiserror::Error;
 
 use crate::state::{OfferState, LoanState};
@@ -23,12 +23,27 @@ pub enum ContractError {
     #[error("Please include at least one asset when creating a loan")]
     NoAssets {},
 
+    #[error("The asset at index {index} is already part of this loan")]
+    DuplicateAsset { index: usize },
+
+    #[error("The groups must split the assets of the loan in at least two non-empty parts")]
+    InvalidSplit {},
+
     #[error("You need to send exactly one coin with this transaction")]
     MultipleCoins {},
 
     #[error("Fund sent do not match the loan terms")]
     FundsDontMatchTerms {},
 
+    #[error("The funds sent to accept the loan don't match its terms, expected {expected}, received {received:?}")]
+    LoanFundsMismatch { expected: Coin, received: Vec<Coin> },
+
+    #[error("This listing has expired, it doesn't accept new offers")]
+    ListingExpired {},
+
+    #[error("This loan is reserved for another lender")]
+    LoanReserved {},
+
     #[error("Fund sent do not match the loan terms, {0}, {1}")]
     FundsDontMatchTermsAndPrinciple(Uint128, Uint128),
 
@@ -56,9 +71,15 @@ pub enum ContractError {
     #[error("This loan doesn't have any terms")]
     NoTermsSpecified {},
 
+    #[error("The interest of a loan can only be raised, it's currently {current}")]
+    InterestDecrease { current: Uint128 },
+
     #[error("Sorry, this loan doesn't exist :/")]
     LoanNotFound {},
 
+    #[error("This loan is over, it was archived")]
+    LoanArchived {},
+
     #[error("Sorry, this offer doesn't exist :/")]
     OfferNotFound {},
 
@@ -74,6 +95,27 @@ pub enum ContractError {
     #[error("The loan has already been defaulted, you can't withdraw the funds again")]
     LoanAlreadyDefaulted {},
 
+    #[error("The cure period of this loan is over")]
+    CureWindowClosed {},
+
     #[error("You can't set a preview of an asset not associated with the loan")]
     AssetNotInLoan {},
+
+    #[error("The borrower hasn't countered this offer")]
+    NoCounterTerms {},
+
+    #[error("The collection {collection} is not accepted as collateral")]
+    CollectionNotAllowed { collection: String },
+
+    #[error("The offered interest ({interest}) is lower than the minimum accepted ({min_interest})")]
+    InterestTooLow {
+        min_interest: Uint128,
+        interest: Uint128,
+    },
+
+    #[error("The interest beneficiaries weights must sum to 1")]
+    InvalidBeneficiaryWeights {},
+
+    #[error("The collateral couldn't be transferred to the contract : {error}")]
+    CollateralTransferFailed { error: String },
 }
@@ -1,13 +1,13 @@
-use cosmwasm_std::{DepsMut, Env, MessageInfo, Addr, Storage, BankMsg, Empty, coins, StdResult, StdError, Decimal};
+use cosmwasm_std::{Deps, DepsMut, Env, MessageInfo, Addr, Storage, BankMsg, Empty, coins, StdResult, StdError, Decimal, Coin, Uint128, Order, Timestamp, Reply, SubMsgResult};
 
 use cw721::Cw721ExecuteMsg;
 use cw721_base::Extension;
 use fee_contract_export::state::FeeType;
-use sg_std::{ Response, CosmosMsg};
+use sg_std::{ Response, CosmosMsg, SubMsg};
 use sg721::ExecuteMsg as Sg721ExecuteMsg;
-use utils::state::{AssetInfo, Cw721Coin, Sg721Token, into_cosmos_msg};
+use utils::state::{AssetInfo, Cw721Coin, Sg721Token, find_duplicate_nft, into_cosmos_msg};
 
-use crate::{state::{ LoanTerms, COLLATERAL_INFO, BorrowerInfo, BORROWER_INFO, CollateralInfo, is_loan_modifiable, LoanState, is_collateral_withdrawable, is_loan_counterable, CONTRACT_INFO, lender_offers, OfferInfo, OfferState, is_loan_acceptable, get_offer, save_offer, is_offer_borrower, is_lender, is_offer_refusable, is_loan_defaulted, is_active_lender, can_repay_loan, get_active_loan}, error::{self, ContractError}, query::is_nft_owner};
+use crate::{state::{ LoanTerms, COLLATERAL_INFO, BorrowerInfo, BORROWER_INFO, CollateralInfo, is_loan_modifiable, LoanState, is_collateral_withdrawable, is_loan_counterable, CONTRACT_INFO, lender_offers, OfferInfo, OfferState, is_loan_acceptable, get_offer, save_offer, is_offer_borrower, is_lender, is_offer_refusable, is_loan_defaulted, is_active_lender, can_repay_loan, get_active_loan, is_interest_sufficient, is_collection_allowed, ContractInfo, load_collateral, load_live_collateral, archive_loan, PENDING_FUND_RELEASES}, error::{self, ContractError}, query::{is_nft_owner, is_sg721_owner}};
 use fee_distributor_export::msg::ExecuteMsg as FeeDistributorMsg;
 
 
@@ -21,6 +21,7 @@ use fee_distributor_export::msg::ExecuteMsg as FeeDistributorMsg;
 /// If terms are specified, fund lenders can accept the loan directly.
 /// If not, lenders can propose terms than may be accepted by the borrower in return to start the loan
 /// This deposit function allows CW721 and CW1155 tokens to be deposited
+#[allow(clippy::too_many_arguments)]
 pub fn deposit_collaterals(
     deps: DepsMut,
     env: Env,
@@ -29,6 +30,7 @@ pub fn deposit_collaterals(
     terms: Option<LoanTerms>,
     comment: Option<String>,
     loan_preview: Option<AssetInfo>,
+    listing_expiry: Option<Timestamp>,
 ) -> Result<Response, ContractError> {
     // set the borrower
     let borrower = info.sender;
@@ -37,24 +39,32 @@ pub fn deposit_collaterals(
     if tokens.is_empty() {
         return Err(ContractError::NoAssets {});
     }
+    // The same NFT can't be deposited twice
+    if let Some(index) = find_duplicate_nft(&tokens) {
+        return Err(ContractError::DuplicateAsset { index });
+    }
 
     // We save the collateral info in our internal structure
     // First we update the number of collateral a user has deposited (to make sure the id assigned is unique)
-    let loan_id = BORROWER_INFO
-        .update::<_, error::ContractError>(deps.storage, &borrower, |x| match x {
-            Some(mut info) => {
-                info.last_collateral_id += 1;
-                Ok(info)
-            }
-            None => Ok(BorrowerInfo::default()),
-        })?
-        .last_collateral_id;
+    let loan_id = _next_loan_id(deps.storage, &borrower)?;
+
+    // The assets have to come from collections accepted as collateral
+    let contract_info = CONTRACT_INFO.load(deps.storage)?;
+    for token in &tokens {
+        is_collection_allowed(&contract_info, token)?;
+        // Listings of NFTs the borrower doesn't own can be rejected early instead of failing on accept
+        if contract_info.verify_ownership_on_deposit {
+            _check_asset_owner(deps.as_ref(), &borrower, token)?;
+        }
+    }
 
     // Then we verify we can set the asset as preview
     if let Some(preview) = loan_preview.clone() {
-        if !tokens.iter().any(|r| *r == preview) {
+        if !tokens.contains(&preview) {
             return Err(ContractError::AssetNotInLoan {});
         }
+        // The preview is the most visible asset of the listing, so the borrower has to own it
+        _check_asset_owner(deps.as_ref(), &borrower, &preview)?;
     }
 
     // Finally we save an collateral info object
@@ -67,6 +77,7 @@ pub fn deposit_collaterals(
             list_date: env.block.time,
             comment,
             loan_preview,
+            listing_expiry,
             ..Default::default()
         },
     )?;
@@ -77,6 +88,32 @@ pub fn deposit_collaterals(
         .add_attribute("loan_id", loan_id.to_string()))
 }
 
+/// Makes sure `owner` owns an NFT asset. Coins aren't checked
+fn _check_asset_owner(deps: Deps, owner: &Addr, asset: &AssetInfo) -> Result<(), ContractError> {
+    match asset {
+        AssetInfo::Cw721Coin(Cw721Coin { address, token_id }) => {
+            is_nft_owner(deps, owner.clone(), address.clone(), token_id.clone())
+        }
+        AssetInfo::Sg721Token(Sg721Token { address, token_id }) => {
+            is_sg721_owner(deps, owner.clone(), address.clone(), token_id.clone())
+        }
+        AssetInfo::Coin(_) => Ok(()),
+    }
+}
+
+fn _next_loan_id(storage: &mut dyn Storage, borrower: &Addr) -> Result<u64, ContractError> {
+    Ok(BORROWER_INFO
+        .update::<_, error::ContractError>(storage, borrower, |x| match x {
+            Some(mut info) => {
+                info.last_collateral_id += 1;
+                Ok(info)
+            }
+            None => Ok(BorrowerInfo::default()),
+        })?
+        .last_collateral_id)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn modify_collaterals(
     deps: DepsMut,
     env: Env,
@@ -85,36 +122,35 @@ pub fn modify_collaterals(
     terms: Option<LoanTerms>,
     comment: Option<String>,
     loan_preview: Option<AssetInfo>,
+    listing_expiry: Option<Timestamp>,
 ) -> Result<Response, ContractError> {
     let borrower = info.sender;
 
-    COLLATERAL_INFO.update(
-        deps.storage,
-        (borrower.clone(), loan_id),
-        |collateral| match collateral {
-            None => return Err(ContractError::LoanNotFound {}),
-            Some(mut collateral) => {
-                is_loan_modifiable(&collateral)?;
-
-                if terms.is_some() {
-                    collateral.terms = terms;
-                }
-                if comment.is_some() {
-                    collateral.comment = comment;
-                }
-                // Then we verify we can set the asset as preview
-                if let Some(preview) = loan_preview.clone() {
-                    if !collateral.associated_assets.iter().any(|r| *r == preview) {
-                        return Err(ContractError::AssetNotInLoan {});
-                    }
-                    collateral.loan_preview = loan_preview;
-                }
-                collateral.list_date = env.block.time;
-
-                Ok(collateral)
-            }
-        },
-    )?;
+    let mut collateral = COLLATERAL_INFO
+        .may_load(deps.storage, (borrower.clone(), loan_id))?
+        .ok_or(ContractError::LoanNotFound {})?;
+    is_loan_modifiable(&collateral)?;
+
+    if terms.is_some() {
+        collateral.terms = terms;
+    }
+    if comment.is_some() {
+        collateral.comment = comment;
+    }
+    if listing_expiry.is_some() {
+        collateral.listing_expiry = listing_expiry;
+    }
+    // Then we verify we can set the asset as preview
+    if let Some(preview) = loan_preview.clone() {
+        if !collateral.associated_assets.contains(&preview) {
+            return Err(ContractError::AssetNotInLoan {});
+        }
+        // Same as at deposit, the borrower has to own the asset they put forward
+        _check_asset_owner(deps.as_ref(), &borrower, &preview)?;
+        collateral.loan_preview = loan_preview;
+    }
+    collateral.list_date = env.block.time;
+    COLLATERAL_INFO.save(deps.storage, (borrower.clone(), loan_id), &collateral)?;
 
     Ok(Response::new()
         .add_attribute("action", "modify_collaterals")
@@ -134,7 +170,7 @@ pub fn withdraw_collateral(
 ) -> Result<Response, ContractError> {
     // We query the loan info
     let borrower = info.sender;
-    let mut collateral = COLLATERAL_INFO.load(deps.storage, (borrower.clone(), loan_id))?;
+    let mut collateral = load_collateral(deps.storage, (borrower.clone(), loan_id))?;
     is_collateral_withdrawable(&collateral)?;
 
     // We update the internal state, the loan proposal is no longer valid
@@ -148,6 +184,279 @@ pub fn withdraw_collateral(
         .add_attribute("loan_id", loan_id.to_string()))
 }
 
+/// Reserves a published loan for a lender the borrower negotiated with off-chain
+/// Other lenders can't make offers on or accept the loan until the reservation is lifted (`lender: None`)
+pub fn reserve_for_lender(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    loan_id: u64,
+    lender: Option<String>,
+) -> Result<Response, ContractError> {
+    let borrower = info.sender;
+    let mut collateral = load_collateral(deps.storage, (borrower.clone(), loan_id))?;
+    is_loan_modifiable(&collateral)?;
+
+    collateral.reserved_lender = lender
+        .map(|lender| deps.api.addr_validate(&lender))
+        .transpose()?;
+    COLLATERAL_INFO.save(deps.storage, (borrower.clone(), loan_id), &collateral)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "reserve_for_lender")
+        .add_attribute("borrower", borrower)
+        .add_attribute("loan_id", loan_id.to_string())
+        .add_attribute(
+            "lender",
+            collateral
+                .reserved_lender
+                .map(|lender| lender.to_string())
+                .unwrap_or_else(|| "none".to_string()),
+        ))
+}
+
+/// Changes the duration of the terms of a loan that wasn't accepted yet, leaving the other terms untouched
+/// The duration is updated in the unit the loan expiry is evaluated in
+pub fn set_loan_duration(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    loan_id: u64,
+    duration: u64,
+) -> Result<Response, ContractError> {
+    let borrower = info.sender;
+    let mut collateral = load_collateral(deps.storage, (borrower.clone(), loan_id))?;
+    is_loan_modifiable(&collateral)?;
+
+    let terms = collateral
+        .terms
+        .as_mut()
+        .ok_or(ContractError::NoTermsSpecified {})?;
+    let duration_unit = match terms.duration_in_seconds.as_mut() {
+        Some(duration_in_seconds) => {
+            *duration_in_seconds = duration;
+            "duration_in_seconds"
+        }
+        None => {
+            terms.duration_in_blocks = duration;
+            "duration_in_blocks"
+        }
+    };
+    COLLATERAL_INFO.save(deps.storage, (borrower.clone(), loan_id), &collateral)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_loan_duration")
+        .add_attribute("borrower", borrower)
+        .add_attribute("loan_id", loan_id.to_string())
+        .add_attribute(duration_unit, duration.to_string()))
+}
+
+/// Raises the interest of the terms of a loan that wasn't accepted yet, leaving the other terms untouched
+/// Lowering it isn't possible, lenders looking at the listing only ever see it get more attractive
+pub fn sweeten_terms(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    loan_id: u64,
+    interest: Uint128,
+) -> Result<Response, ContractError> {
+    let borrower = info.sender;
+    let mut collateral = load_collateral(deps.storage, (borrower.clone(), loan_id))?;
+    is_loan_modifiable(&collateral)?;
+
+    let terms = collateral
+        .terms
+        .as_mut()
+        .ok_or(ContractError::NoTermsSpecified {})?;
+    if interest < terms.interest {
+        return Err(ContractError::InterestDecrease {
+            current: terms.interest,
+        });
+    }
+    terms.interest = interest;
+    COLLATERAL_INFO.save(deps.storage, (borrower.clone(), loan_id), &collateral)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "sweeten_terms")
+        .add_attribute("borrower", borrower)
+        .add_attribute("loan_id", loan_id.to_string())
+        .add_attribute("interest", interest))
+}
+
+/// Cancels a listing like `withdraw_collateral` and marks all its published offers as refused
+/// The lenders can then withdraw their funds right away
+pub fn withdraw_collateral_and_refuse_all(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    loan_id: u64,
+) -> Result<Response, ContractError> {
+    let borrower = info.sender.clone();
+    let res = withdraw_collateral(deps.branch(), env, info, loan_id)?;
+
+    let offers = lender_offers()
+        .idx
+        .loan
+        .prefix((borrower, loan_id))
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<(String, OfferInfo)>>>()?;
+    let mut refused: Vec<String> = vec![];
+    for (global_offer_id, mut offer_info) in offers {
+        if offer_info.state == OfferState::Published {
+            offer_info.state = OfferState::Refused;
+            save_offer(deps.storage, &global_offer_id, offer_info)?;
+            refused.push(global_offer_id);
+        }
+    }
+
+    Ok(res.add_attribute(
+        "refused_offers",
+        if refused.is_empty() {
+            "none".to_string()
+        } else {
+            refused.join(",")
+        },
+    ))
+}
+
+/// Removes stale listings of the sender in a batch
+/// Only `Published` listings that never had a live offer are removed, the others are skipped.
+/// Cancelled offers on the removed listings are removed along with them
+pub fn abandon_listings(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    loan_ids: Vec<u64>,
+) -> Result<Response, ContractError> {
+    let borrower = info.sender;
+    let mut abandoned: Vec<String> = vec![];
+    let mut skipped: Vec<String> = vec![];
+
+    for loan_id in loan_ids {
+        let collateral = match COLLATERAL_INFO.may_load(deps.storage, (borrower.clone(), loan_id))? {
+            Some(collateral) => collateral,
+            None => {
+                skipped.push(loan_id.to_string());
+                continue;
+            }
+        };
+        let offers = lender_offers()
+            .idx
+            .loan
+            .prefix((borrower.clone(), loan_id))
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<(String, OfferInfo)>>>()?;
+
+        if collateral.state != LoanState::Published
+            || offers
+                .iter()
+                .any(|(_, offer)| offer.state != OfferState::Cancelled)
+        {
+            skipped.push(loan_id.to_string());
+            continue;
+        }
+
+        for (global_offer_id, _) in offers {
+            lender_offers().remove(deps.storage, &global_offer_id)?;
+        }
+        COLLATERAL_INFO.remove(deps.storage, (borrower.clone(), loan_id));
+        abandoned.push(loan_id.to_string());
+    }
+
+    // Attribute values can't be empty
+    let list_attribute = |loan_ids: Vec<String>| {
+        if loan_ids.is_empty() {
+            "none".to_string()
+        } else {
+            loan_ids.join(",")
+        }
+    };
+
+    Ok(Response::new()
+        .add_attribute("action", "abandon_listings")
+        .add_attribute("borrower", borrower)
+        .add_attribute("abandoned", list_attribute(abandoned))
+        .add_attribute("skipped", list_attribute(skipped)))
+}
+
+/// Splits a published listing into several listings, one per group of assets
+/// Each new listing gets a fresh id and keeps the terms, comment and expiry of the original one.
+/// The original listing is removed. If it received offers, it's made inactive instead, so that those offers are refused and their funds can be withdrawn
+pub fn split_listing(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    loan_id: u64,
+    groups: Vec<Vec<usize>>,
+) -> Result<Response, ContractError> {
+    let borrower = info.sender;
+    let mut collateral = load_collateral(deps.storage, (borrower.clone(), loan_id))?;
+    is_loan_modifiable(&collateral)?;
+
+    // The groups have to be a partition of the loan assets
+    let mut grouped = vec![false; collateral.associated_assets.len()];
+    if groups.len() < 2 || groups.iter().any(|group| group.is_empty()) {
+        return Err(ContractError::InvalidSplit {});
+    }
+    for &index in groups.iter().flatten() {
+        match grouped.get_mut(index) {
+            Some(seen) if !*seen => *seen = true,
+            _ => return Err(ContractError::InvalidSplit {}),
+        }
+    }
+    if grouped.contains(&false) {
+        return Err(ContractError::InvalidSplit {});
+    }
+
+    let mut new_loan_ids = vec![];
+    for group in groups {
+        let associated_assets: Vec<AssetInfo> = group
+            .into_iter()
+            .map(|index| collateral.associated_assets[index].clone())
+            .collect();
+        let new_loan_id = _next_loan_id(deps.storage, &borrower)?;
+        COLLATERAL_INFO.save(
+            deps.storage,
+            (borrower.clone(), new_loan_id),
+            &CollateralInfo {
+                terms: collateral.terms.clone(),
+                loan_preview: collateral
+                    .loan_preview
+                    .clone()
+                    .filter(|preview| associated_assets.contains(preview)),
+                associated_assets,
+                list_date: env.block.time,
+                comment: collateral.comment.clone(),
+                listing_expiry: collateral.listing_expiry,
+                fee_distributor_override: collateral.fee_distributor_override.clone(),
+                reserved_lender: collateral.reserved_lender.clone(),
+                ..Default::default()
+            },
+        )?;
+        new_loan_ids.push(new_loan_id.to_string());
+    }
+
+    let has_offers = lender_offers()
+        .idx
+        .loan
+        .prefix((borrower.clone(), loan_id))
+        .range(deps.storage, None, None, Order::Ascending)
+        .next()
+        .is_some();
+    if has_offers {
+        collateral.state = LoanState::Inactive;
+        COLLATERAL_INFO.save(deps.storage, (borrower.clone(), loan_id), &collateral)?;
+    } else {
+        COLLATERAL_INFO.remove(deps.storage, (borrower.clone(), loan_id));
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "split_listing")
+        .add_attribute("borrower", borrower)
+        .add_attribute("loan_id", loan_id.to_string())
+        .add_attribute("new_loan_ids", new_loan_ids.join(",")))
+}
+
 /// Accept a loan and its terms directly
 /// As soon as the lender executes this messages, the loan starts and the borrower will need to repay the loan before the term
 pub fn accept_loan(
@@ -160,10 +469,17 @@ pub fn accept_loan(
 ) -> Result<Response, ContractError> {
     // We query the loan info
     let borrower_addr = deps.api.addr_validate(&borrower)?;
-    let collateral = COLLATERAL_INFO.load(deps.storage, (borrower_addr.clone(), loan_id))?;
+    let collateral = load_live_collateral(deps.storage, (borrower_addr.clone(), loan_id))?;
 
     // We start by making an offer with exactly the same terms as the depositor specified
     let terms: LoanTerms = collateral.terms.ok_or(ContractError::NoTermsSpecified {})?;
+    // The lender has to send exactly the principle the borrower asked for
+    if info.funds != [terms.principle.clone()] {
+        return Err(ContractError::LoanFundsMismatch {
+            expected: terms.principle,
+            received: info.funds,
+        });
+    }
     let (global_offer_id, _offer_id) = _make_offer_raw(
         deps.storage,
         env.clone(),
@@ -172,6 +488,7 @@ pub fn accept_loan(
         loan_id,
         terms,
         comment,
+        None,
     )?;
 
     // Then we make the borrower accept the loan
@@ -184,6 +501,7 @@ pub fn accept_loan(
 // It verifies an offer can be made for the current loan
 // It verifies the sent funds match the principle indicated in the terms
 // And then saves the new offer in the internal storage
+#[allow(clippy::too_many_arguments)]
 fn _make_offer_raw(
     storage: &mut dyn Storage,
     env: Env,
@@ -192,10 +510,21 @@ fn _make_offer_raw(
     loan_id: u64,
     terms: LoanTerms,
     comment: Option<String>,
+    memo: Option<String>,
 ) -> Result<(String, u64), ContractError> {
     let mut collateral: CollateralInfo =
-        COLLATERAL_INFO.load(storage, (borrower.clone(), loan_id))?;
+        load_collateral(storage, (borrower.clone(), loan_id))?;
     is_loan_counterable(&collateral)?;
+    if collateral.is_listing_expired(&env) {
+        return Err(ContractError::ListingExpired {});
+    }
+    if collateral
+        .reserved_lender
+        .as_ref()
+        .is_some_and(|lender| *lender != info.sender)
+    {
+        return Err(ContractError::LoanReserved {});
+    }
 
     // Make sure the transaction contains funds that match the principle indicated in the terms
     if info.funds.len() != 1 {
@@ -204,13 +533,16 @@ fn _make_offer_raw(
         return Err(ContractError::FundsDontMatchTerms {});
     }
 
+    // Every offer has to carry some interest, otherwise the fee distributor doesn't get its cut
+    let mut contract_config = CONTRACT_INFO.load(storage)?;
+    is_interest_sufficient(&contract_config, &terms)?;
+
     // We add the new offer to the collateral object
     collateral.offer_amount += 1;
     COLLATERAL_INFO.save(storage, (borrower.clone(), loan_id), &collateral)?;
     let offer_id = collateral.offer_amount;
 
     // We save this new offer
-    let mut contract_config = CONTRACT_INFO.load(storage)?;
     contract_config.global_offer_index += 1;
     let global_offers = lender_offers();
     global_offers.save(
@@ -226,6 +558,8 @@ fn _make_offer_raw(
             list_date: env.block.time,
             deposited_funds: Some(terms.principle),
             comment,
+            memo,
+            counter_terms: None,
         },
     )?;
 
@@ -244,7 +578,7 @@ fn _accept_offer_raw(
 
     let borrower = offer_info.borrower.clone();
     let loan_id = offer_info.loan_id;
-    let mut collateral = COLLATERAL_INFO.load(deps.storage, (borrower.clone(), loan_id))?;
+    let mut collateral = load_live_collateral(deps.storage, (borrower.clone(), loan_id))?;
     is_loan_acceptable(&collateral)?;
 
     // We verify the offer is still valid
@@ -252,6 +586,7 @@ fn _accept_offer_raw(
         // We can start the loan now !
         collateral.state = LoanState::Started;
         collateral.start_block = Some(env.block.height);
+        collateral.start_time = Some(env.block.time);
         collateral.active_offer = Some(global_offer_id.clone());
         offer_info.state = OfferState::Accepted;
 
@@ -263,11 +598,8 @@ fn _accept_offer_raw(
         });
     };
 
-    // We transfer the funds directly when the offer is accepted
-    let fund_messages = _withdraw_offer_unsafe(borrower.clone(), &offer_info)?;
-
     // We transfer the nfts directly from the owner's wallets when the offer is accepted
-    let asset_messages: Vec<CosmosMsg> = collateral
+    let mut asset_messages: Vec<SubMsg> = collateral
         .associated_assets
         .iter()
         .map(|token| match token {
@@ -311,11 +643,20 @@ fn _accept_offer_raw(
             }
             _ => Err(ContractError::WrongAssetDeposited {}),
         })
-        .collect::<Result<Vec<CosmosMsg>, ContractError>>()?;
+        .map(|msg: Result<CosmosMsg, ContractError>| msg.map(SubMsg::new))
+        .collect::<Result<Vec<SubMsg>, ContractError>>()?;
+
+    // The funds are only released to the borrower in the reply to the last collateral transfer,
+    // once the contract is sure to have the custody of the collateral
+    let last_transfer = asset_messages.pop().ok_or(ContractError::NoAssets {})?;
+    let global_offer_index: u64 = global_offer_id
+        .parse()
+        .map_err(|_| ContractError::OfferNotFound {})?;
+    asset_messages.push(SubMsg::reply_always(last_transfer.msg, global_offer_index));
+    PENDING_FUND_RELEASES.save(deps.storage, &global_offer_id, &true)?;
 
     Ok(Response::new()
-        .add_message(fund_messages)
-        .add_messages(asset_messages)
+        .add_submessages(asset_messages)
         .add_attribute("action", "start_loan")
         .add_attribute("denom_borrowed", offer_info.terms.principle.denom)
         .add_attribute(
@@ -328,6 +669,46 @@ fn _accept_offer_raw(
         .add_attribute("global_offer_id", global_offer_id))
 }
 
+/// Releases the funds of the accepted offer to the borrower once the collateral is in the contract's custody
+/// The reply id is the global offer index of the accepted offer
+pub fn reply_accept_offer(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    let global_offer_id = msg.id.to_string();
+    if !PENDING_FUND_RELEASES.has(deps.storage, &global_offer_id) {
+        return Err(ContractError::Unreachable {});
+    }
+    PENDING_FUND_RELEASES.remove(deps.storage, &global_offer_id);
+    if let SubMsgResult::Err(error) = msg.result {
+        return Err(ContractError::CollateralTransferFailed { error });
+    }
+
+    let offer_info = get_offer(deps.storage, &global_offer_id)?;
+    let collateral = load_live_collateral(
+        deps.storage,
+        (offer_info.borrower.clone(), offer_info.loan_id),
+    )?;
+    for token in collateral.associated_assets.iter() {
+        match token {
+            AssetInfo::Cw721Coin(Cw721Coin { address, token_id })
+            | AssetInfo::Sg721Token(Sg721Token { address, token_id }) => is_nft_owner(
+                deps.as_ref(),
+                env.contract.address.clone(),
+                address.to_string(),
+                token_id.to_string(),
+            )?,
+            _ => return Err(ContractError::WrongAssetDeposited {}),
+        }
+    }
+
+    let fund_message = _withdraw_offer_unsafe(offer_info.borrower.clone(), &offer_info)?;
+
+    Ok(Response::new()
+        .add_message(fund_message)
+        .add_attribute("action", "release_loan_funds")
+        .add_attribute("borrower", offer_info.borrower)
+        .add_attribute("loan_id", offer_info.loan_id.to_string())
+        .add_attribute("global_offer_id", global_offer_id))
+}
+
 /// This creates withdraw messages to withdraw the funds from an offer (to the lender of the borrower depending on the situation
 /// This function does not do any checks on the validity of the procedure
 /// Be careful when using this internal function
@@ -347,6 +728,50 @@ pub fn _withdraw_offer_unsafe(
     })
 }
 
+/// This reconciles the funds deposited with an offer with a new principle
+/// The missing funds have to be sent along with the message, the surplus is sent back to the lender
+/// This function does not do any checks on the validity of the procedure
+pub fn _reconcile_offer_funds(
+    lender: Addr,
+    offer_info: &OfferInfo,
+    sent_funds: &[Coin],
+    new_principle: &Coin,
+) -> Result<Option<BankMsg>, ContractError> {
+    let deposited_funds = offer_info
+        .deposited_funds
+        .clone()
+        .ok_or(ContractError::NoFundsToWithdraw {})?;
+    if deposited_funds.denom != new_principle.denom {
+        return Err(ContractError::FundsDontMatchTerms {});
+    }
+
+    // We pull the additional funds from the message
+    let sent_amount = match sent_funds {
+        [] => Uint128::zero(),
+        [sent] if sent.denom == deposited_funds.denom => sent.amount,
+        [_] => return Err(ContractError::FundsDontMatchTerms {}),
+        _ => return Err(ContractError::MultipleCoins {}),
+    };
+    let available_funds = deposited_funds.amount + sent_amount;
+    if available_funds < new_principle.amount {
+        return Err(ContractError::FundsDontMatchTermsAndPrinciple(
+            new_principle.amount,
+            available_funds,
+        ));
+    }
+
+    // And refund what's left
+    let surplus = available_funds - new_principle.amount;
+    if surplus.is_zero() {
+        Ok(None)
+    } else {
+        Ok(Some(BankMsg::Send {
+            to_address: lender.to_string(),
+            amount: coins(surplus.u128(), deposited_funds.denom),
+        }))
+    }
+}
+
 /// Accept an offer someone made for your collateral
 /// As soon as the borrower executes this messages, the loan starts and the they will need to repay the loan before the term
 pub fn accept_offer(
@@ -364,8 +789,85 @@ pub fn accept_offer(
     Ok(res.add_attribute("action_type", "accept_offer"))
 }
 
+/// Counter an offer someone made for your collateral with new terms
+/// The lender can then accept those terms using `accept_counter`, which starts the loan directly
+pub fn counter_offer(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    global_offer_id: String,
+    terms: LoanTerms,
+) -> Result<Response, ContractError> {
+    // We make sure the caller is the borrower
+    let borrower = info.sender;
+    let mut offer_info = is_offer_borrower(deps.storage, borrower.clone(), &global_offer_id)?;
+    let collateral = load_collateral(deps.storage, (borrower.clone(), offer_info.loan_id))?;
+    is_loan_counterable(&collateral)?;
+    if offer_info.state != OfferState::Published {
+        return Err(ContractError::WrongOfferState {
+            state: offer_info.state,
+        });
+    }
+
+    // The countered terms have to respect the same rules as any other offer
+    let contract_info = CONTRACT_INFO.load(deps.storage)?;
+    is_interest_sufficient(&contract_info, &terms)?;
+    if offer_info.terms.principle.denom != terms.principle.denom {
+        return Err(ContractError::FundsDontMatchTerms {});
+    }
+
+    offer_info.counter_terms = Some(terms);
+    save_offer(deps.storage, &global_offer_id, offer_info.clone())?;
+
+    Ok(Response::new()
+        .add_attribute("action", "counter_offer")
+        .add_attribute("borrower", borrower)
+        .add_attribute("lender", offer_info.lender)
+        .add_attribute("loan_id", offer_info.loan_id.to_string())
+        .add_attribute("global_offer_id", global_offer_id))
+}
+
+/// Accept the terms the borrower countered your offer with
+/// If the countered principle is higher than the deposited funds, the difference has to be sent with this message
+/// If it's lower, the surplus is sent back to the lender
+/// As soon as the lender executes this messages, the loan starts
+pub fn accept_counter(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    global_offer_id: String,
+) -> Result<Response, ContractError> {
+    // We make sure the caller is the lender
+    let lender = info.sender;
+    let mut offer_info = is_lender(deps.storage, lender.clone(), &global_offer_id)?;
+    let counter_terms = offer_info
+        .counter_terms
+        .clone()
+        .ok_or(ContractError::NoCounterTerms {})?;
+
+    // We adjust the offer funds to the new principle
+    let surplus_message = _reconcile_offer_funds(
+        lender,
+        &offer_info,
+        &info.funds,
+        &counter_terms.principle,
+    )?;
+    offer_info.deposited_funds = Some(counter_terms.principle.clone());
+    offer_info.terms = counter_terms;
+    offer_info.counter_terms = None;
+    save_offer(deps.storage, &global_offer_id, offer_info)?;
+
+    // And the loan starts with the countered terms
+    let res = _accept_offer_raw(deps, env, global_offer_id)?;
+
+    Ok(res
+        .add_messages(surplus_message)
+        .add_attribute("action_type", "accept_counter"))
+}
+
 /// Make an offer (offer some terms) to lend some money against someone's collateral
 /// The borrower will then be able to accept those terms if they please them
+#[allow(clippy::too_many_arguments)]
 pub fn make_offer(
     deps: DepsMut,
     env: Env,
@@ -374,6 +876,7 @@ pub fn make_offer(
     loan_id: u64,
     terms: LoanTerms,
     comment: Option<String>,
+    memo: Option<String>,
 ) -> Result<Response, ContractError> {
     // We query the loan info
 
@@ -386,6 +889,7 @@ pub fn make_offer(
         loan_id,
         terms,
         comment,
+        memo,
     )?;
 
     Ok(Response::new()
@@ -418,7 +922,7 @@ pub fn cancel_offer(
     // We query the loan info
     let borrower = offer_info.borrower.clone();
     let loan_id = offer_info.loan_id;
-    let collateral = COLLATERAL_INFO.load(deps.storage, (borrower.clone(), loan_id))?;
+    let collateral = load_collateral(deps.storage, (borrower.clone(), loan_id))?;
     // We can cancel an offer only if the Borrower is still searching for a loan (the loan is modifyable)
     is_loan_modifiable(&collateral)?;
 
@@ -457,7 +961,7 @@ pub fn refuse_offer(
 
     // We load the offer and collateral info
     let mut offer_info = is_offer_borrower(deps.storage, borrower.clone(), &global_offer_id)?;
-    let collateral = COLLATERAL_INFO.load(
+    let collateral = load_collateral(
         deps.storage,
         (offer_info.clone().borrower, offer_info.loan_id),
     )?;
@@ -517,6 +1021,7 @@ pub fn withdraw_refused_offer(
 /// This function receives principle + interest funds to end the loan and unlock the collateral
 /// This effectively puts an end to the loan.
 /// Loans can only be repaid before the period ends.
+/// Repaying before the minimum duration of the loan may cost an additional penalty, paid to the lender
 /// There is not takebacks, no failesafe
 pub fn repay_borrowed_funds(
     deps: DepsMut,
@@ -527,33 +1032,133 @@ pub fn repay_borrowed_funds(
     let contract_info = CONTRACT_INFO.load(deps.storage)?;
     // We query the loan info
     let borrower = info.sender;
-    let mut collateral = COLLATERAL_INFO.load(deps.storage, (borrower.clone(), loan_id))?;
+    let mut collateral = load_live_collateral(deps.storage, (borrower.clone(), loan_id))?;
     can_repay_loan(deps.storage, env.clone(), &collateral)?;
     let offer_info = get_active_loan(deps.storage, &collateral)?;
 
-    // We verify the sent funds correspond to the principle + interests
-    let interests = offer_info.terms.interest;
-    if info.funds.len() != 1 {
+    // We verify the sent funds correspond to the principle + interests (+ a penalty when repaying early)
+    let penalty = offer_info.terms.early_repay_penalty_due(&env, &collateral);
+    let funds = _check_repayment_funds(&offer_info, &info.funds, penalty)?;
+
+    // The loan is over, we archive it
+    collateral.state = LoanState::Ended;
+    archive_loan(deps.storage, (borrower.clone(), loan_id), &collateral)?;
+
+    let res = _repayment_response(
+        env,
+        contract_info,
+        borrower.clone(),
+        collateral,
+        &offer_info,
+        funds,
+        penalty,
+    )?;
+
+    Ok(res
+        .add_attribute("action", "repay_loan")
+        .add_attribute("borrower", borrower)
+        .add_attribute("lender", offer_info.lender)
+        .add_attribute("loan_id", loan_id.to_string())
+        .add_attribute("early_repay_penalty", penalty))
+}
+
+/// Cure a defaulted loan and get back your collateral
+/// Within `cure_blocks` blocks (`cure_seconds` seconds for the loans evaluated in seconds) after the loan defaulted,
+/// and as long as the lender hasn't withdrawn the collateral,
+/// the borrower can still repay the principle + interest + a penalty (a share of the principle going to the lender).
+pub fn cure_default(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    loan_id: u64,
+) -> Result<Response, ContractError> {
+    let contract_info = CONTRACT_INFO.load(deps.storage)?;
+    // We query the loan info
+    let borrower = info.sender;
+    let mut collateral = load_collateral(deps.storage, (borrower.clone(), loan_id))?;
+    is_loan_defaulted(deps.storage, env.clone(), &collateral)?;
+    if collateral.state == LoanState::Defaulted {
+        return Err(ContractError::LoanAlreadyDefaulted {});
+    }
+    let offer_info = get_active_loan(deps.storage, &collateral)?;
+    if !offer_info.terms.is_curable(
+        &env,
+        &collateral,
+        contract_info.cure_blocks,
+        contract_info.cure_seconds,
+    ) {
+        return Err(ContractError::CureWindowClosed {});
+    }
+
+    // We verify the sent funds correspond to the principle + interests + penalty
+    let penalty = offer_info.terms.principle.amount * contract_info.cure_penalty;
+    let funds = _check_repayment_funds(&offer_info, &info.funds, penalty)?;
+
+    // The loan is over, we archive it
+    collateral.state = LoanState::Ended;
+    archive_loan(deps.storage, (borrower.clone(), loan_id), &collateral)?;
+
+    let res = _repayment_response(
+        env,
+        contract_info,
+        borrower.clone(),
+        collateral,
+        &offer_info,
+        funds,
+        penalty,
+    )?;
+
+    Ok(res
+        .add_attribute("action", "cure_default")
+        .add_attribute("borrower", borrower)
+        .add_attribute("lender", offer_info.lender)
+        .add_attribute("loan_id", loan_id.to_string())
+        .add_attribute("penalty", penalty))
+}
+
+/// Verifies the funds sent to repay a loan cover the principle + interests + `penalty`
+pub fn _check_repayment_funds(
+    offer_info: &OfferInfo,
+    funds: &[Coin],
+    penalty: Uint128,
+) -> Result<Coin, ContractError> {
+    let amount_due = offer_info.terms.principle.amount + offer_info.terms.interest + penalty;
+    if funds.len() != 1 {
         return Err(ContractError::MultipleCoins {});
-    } else if offer_info.terms.principle.denom != info.funds[0].denom.clone() {
+    } else if offer_info.terms.principle.denom != funds[0].denom {
         return Err(ContractError::FundsDontMatchTerms {});
-    } else if offer_info.terms.principle.amount + interests > info.funds[0].amount {
+    } else if amount_due > funds[0].amount {
         return Err(ContractError::FundsDontMatchTermsAndPrinciple(
-            offer_info.terms.principle.amount + interests,
-            info.funds[0].amount,
+            amount_due,
+            funds[0].amount,
         ));
     }
+    Ok(funds[0].clone())
+}
 
-    // We save the collateral state
-    collateral.state = LoanState::Ended;
-    COLLATERAL_INFO.save(deps.storage, (borrower.clone(), loan_id), &collateral)?;
+/// Pays back the lender (principle + interests + `penalty`), the fee distributor (the fee on interests and any surplus)
+/// and sends the collateral back to the borrower
+pub fn _repayment_response(
+    env: Env,
+    contract_info: ContractInfo,
+    borrower: Addr,
+    collateral: CollateralInfo,
+    offer_info: &OfferInfo,
+    funds: Coin,
+    penalty: Uint128,
+) -> Result<Response, ContractError> {
+    // Every payment below is made in the repayment denom, it has to be the one the loan was made in
+    if funds.denom != offer_info.terms.principle.denom {
+        return Err(ContractError::FundsDontMatchTerms {});
+    }
 
     // We prepare the funds to send back to the lender
-    let lender_payback =
-        offer_info.terms.principle.amount + interests * (Decimal::one() - contract_info.fee_rate);
+    let lender_payback = offer_info.terms.principle.amount
+        + offer_info.terms.interest * (Decimal::one() - contract_info.fee_rate)
+        + penalty;
 
     // And the funds to send to the fee_depositor contract
-    let fee_depositor_payback = info.funds[0].amount - lender_payback;
+    let fee_depositor_payback = funds.amount - lender_payback;
 
     // The fee depositor needs to know which assets where involved in the transaction
     let collateral_addresses = collateral
@@ -562,16 +1167,18 @@ pub fn repay_borrowed_funds(
         .map(|collateral| match collateral {
             AssetInfo::Sg721Token(sg721) => Ok(sg721.address.clone()),
             AssetInfo::Cw721Coin(cw721) => Ok(cw721.address.clone()),
-            _ => return Err(ContractError::Unreachable {}),
+            _ => Err(ContractError::Unreachable {}),
         })
         .collect::<Result<Vec<String>, ContractError>>()?;
 
+    let fee_distributor_override = collateral.fee_distributor_override.clone();
+
     let mut res = Response::new();
     // We get the funds back to the lender
     if lender_payback.u128() > 0u128 {
         res = res.add_message(BankMsg::Send {
             to_address: offer_info.lender.to_string(),
-            amount: coins(lender_payback.u128(), info.funds[0].denom.clone()),
+            amount: coins(lender_payback.u128(), funds.denom.clone()),
         })
     }
 
@@ -579,32 +1186,46 @@ pub fn repay_borrowed_funds(
     res = res.add_messages(_withdraw_loan(
         collateral,
         env.contract.address,
-        borrower.clone(),
+        borrower,
     )?);
 
-    // And we pay the fee to the treasury
-    if fee_depositor_payback.u128() > 0u128 {
+    // And we pay the fee to the treasury, or split it between the beneficiaries when some are set
+    // A loan routed to a partner distributor pays its whole fee there
+    if fee_depositor_payback.u128() > 0u128
+        && fee_distributor_override.is_none()
+        && !contract_info.interest_beneficiaries.is_empty()
+    {
+        let mut remaining = fee_depositor_payback;
+        let last = contract_info.interest_beneficiaries.len() - 1;
+        for (i, (beneficiary, weight)) in contract_info.interest_beneficiaries.iter().enumerate() {
+            // The last beneficiary gets the rounding leftovers
+            let share = if i == last {
+                remaining
+            } else {
+                fee_depositor_payback * *weight
+            };
+            remaining -= share;
+            if share.u128() > 0u128 {
+                res = res.add_message(BankMsg::Send {
+                    to_address: beneficiary.to_string(),
+                    amount: coins(share.u128(), funds.denom.clone()),
+                })
+            }
+        }
+    } else if fee_depositor_payback.u128() > 0u128 {
         res = res.add_message(into_cosmos_msg(
             FeeDistributorMsg::DepositFees {
                 addresses: collateral_addresses,
                 fee_type: FeeType::Funds,
             },
-            contract_info.fee_distributor,
-            Some(coins(
-                fee_depositor_payback.u128(),
-                info.funds[0].denom.clone(),
-            )),
+            fee_distributor_override.unwrap_or(contract_info.fee_distributor),
+            Some(coins(fee_depositor_payback.u128(), funds.denom)),
         )?);
     }
 
-    Ok(res
-        .add_attribute("action", "repay_loan")
-        .add_attribute("borrower", borrower)
-        .add_attribute("lender", offer_info.lender)
-        .add_attribute("loan_id", loan_id.to_string()))
+    Ok(res)
 }
 
-
 /// Withdraw the collateral from a defaulted loan
 /// If the loan duration has exceeded, the collateral can be withdrawn by the lender
 /// This closes the loan and puts it in a defaulted state
@@ -615,20 +1236,43 @@ pub fn withdraw_defaulted_loan(
     borrower: String,
     loan_id: u64,
 ) -> Result<Response, ContractError> {
+    let (borrower, collateral, offer) =
+        _check_defaulted_loan(deps.as_ref(), &env, info.sender, &borrower, loan_id)?;
+    _withdraw_defaulted_loan(deps, env, borrower, loan_id, collateral, offer)
+}
+
+/// Makes sure `lender` can withdraw the collateral of a defaulted loan, without writing anything
+fn _check_defaulted_loan(
+    deps: Deps,
+    env: &Env,
+    lender: Addr,
+    borrower: &str,
+    loan_id: u64,
+) -> Result<(Addr, CollateralInfo, OfferInfo), ContractError> {
     // We query the loan info
-    let borrower = deps.api.addr_validate(&borrower)?;
-    let mut collateral = COLLATERAL_INFO.load(deps.storage, (borrower.clone(), loan_id))?;
+    let borrower = deps.api.addr_validate(borrower)?;
+    let collateral = load_collateral(deps.storage, (borrower.clone(), loan_id))?;
     is_loan_defaulted(deps.storage, env.clone(), &collateral)?;
-    let offer = is_active_lender(deps.storage, info.sender, &collateral)?;
+    let offer = is_active_lender(deps.storage, lender, &collateral)?;
 
     // We need to test if the loan hasn't already been defaulted
     if collateral.state == LoanState::Defaulted {
         return Err(ContractError::LoanAlreadyDefaulted {});
     }
+    Ok((borrower, collateral, offer))
+}
 
+fn _withdraw_defaulted_loan(
+    deps: DepsMut,
+    env: Env,
+    borrower: Addr,
+    loan_id: u64,
+    mut collateral: CollateralInfo,
+    offer: OfferInfo,
+) -> Result<Response, ContractError> {
     // Saving the collateral state, the loan is defaulted, we can't default it again
     collateral.state = LoanState::Defaulted;
-    COLLATERAL_INFO.save(deps.storage, (borrower.clone(), loan_id), &collateral)?;
+    archive_loan(deps.storage, (borrower.clone(), loan_id), &collateral)?;
 
     // We create the collateral withdrawal message
     let withdraw_messages = _withdraw_loan(collateral, env.contract.address, offer.lender.clone())?;
@@ -641,6 +1285,55 @@ pub fn withdraw_defaulted_loan(
         .add_attribute("loan_id", loan_id.to_string()))
 }
 
+/// Withdraws the collateral of several defaulted loans of the sender in a batch
+/// Loans that can't be withdrawn (unknown, not defaulted, already withdrawn, or lent by someone else) are skipped and reported
+pub fn withdraw_defaulted_loans(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    loans: Vec<(String, u64)>,
+) -> Result<Response, ContractError> {
+    let mut withdraw_messages = vec![];
+    let mut withdrawn: Vec<String> = vec![];
+    let mut skipped: Vec<String> = vec![];
+
+    for (borrower, loan_id) in loans {
+        let loan = format!("{borrower}:{loan_id}");
+        // Every check happens before anything is written, so the loans failing them can simply be skipped
+        match _check_defaulted_loan(deps.as_ref(), &env, info.sender.clone(), &borrower, loan_id) {
+            Ok((borrower, collateral, offer)) => {
+                let res = _withdraw_defaulted_loan(
+                    deps.branch(),
+                    env.clone(),
+                    borrower,
+                    loan_id,
+                    collateral,
+                    offer,
+                )?;
+                withdraw_messages.extend(res.messages);
+                withdrawn.push(loan);
+            }
+            Err(_) => skipped.push(loan),
+        }
+    }
+
+    // Attribute values can't be empty
+    let list_attribute = |loans: Vec<String>| {
+        if loans.is_empty() {
+            "none".to_string()
+        } else {
+            loans.join(",")
+        }
+    };
+
+    Ok(Response::new()
+        .add_submessages(withdraw_messages)
+        .add_attribute("action", "default_loans")
+        .add_attribute("lender", info.sender)
+        .add_attribute("withdrawn", list_attribute(withdrawn))
+        .add_attribute("skipped", list_attribute(skipped)))
+}
+
 pub fn _withdraw_loan(
     collateral: CollateralInfo,
     sender: Addr,
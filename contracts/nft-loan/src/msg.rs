@@ -1,9 +1,9 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Decimal, StdError, StdResult};
+use cosmwasm_std::{Addr, Coin, Decimal, Order, StdError, StdResult, Timestamp, Uint128, Int128};
 
 use utils::state::{is_valid_name, AssetInfo};
 
-use crate::state::{ LoanTerms, ContractInfo, BorrowerInfo, CollateralInfo, OfferInfo};
+use crate::state::{ LoanTerms, ContractInfo, BorrowerInfo, CollateralInfo, OfferInfo, LoanState, OfferState};
 
 #[cw_serde]
 pub struct InstantiateMsg {
@@ -11,6 +11,7 @@ pub struct InstantiateMsg {
     pub owner: Option<String>,
     pub fee_distributor: String,
     pub fee_rate: Decimal,
+    pub min_interest: Option<Uint128>,
 }
 
 impl InstantiateMsg {
@@ -40,25 +41,58 @@ pub enum ExecuteMsg {
         tokens: Vec<AssetInfo>,
         terms: Option<LoanTerms>,
         comment: Option<String>,
-        loan_preview: Option<AssetInfo>
+        loan_preview: Option<AssetInfo>,
+        listing_expiry: Option<Timestamp>,
     },
     /// Used to modify the loan terms and the associated comment
     ModifyCollaterals {
         loan_id: u64,
         terms: Option<LoanTerms>,
         comment: Option<String>,
-        loan_preview: Option<AssetInfo>
+        loan_preview: Option<AssetInfo>,
+        listing_expiry: Option<Timestamp>,
     },
     /// Used to withdraw the collateral before the loan starts
     WithdrawCollaterals {
         loan_id: u64,
     },
+    /// Used by the borrower to only let a specific lender make offers on or accept the loan
+    ReserveForLender {
+        loan_id: u64,
+        lender: Option<String>,
+    },
+    /// Used by the borrower to only change the duration of the loan terms before the loan starts
+    /// The duration is in seconds for loans whose terms are evaluated in seconds, in blocks otherwise
+    SetLoanDuration {
+        loan_id: u64,
+        duration: u64,
+    },
+    /// Used by the borrower to raise the interest of the loan terms before the loan starts
+    SweetenTerms {
+        loan_id: u64,
+        interest: Uint128,
+    },
+    /// Used to withdraw the collateral before the loan starts, refusing all the offers at once
+    WithdrawCollateralAndRefuseAll {
+        loan_id: u64,
+    },
+    /// Used to clean up stale listings that don't have any live offer
+    AbandonListings {
+        loan_ids: Vec<u64>,
+    },
+    /// Used to split a published listing into several ones, each `groups` item lists the indexes of the assets of a new listing
+    SplitListing {
+        loan_id: u64,
+        groups: Vec<Vec<usize>>,
+    },
     /// Make an offer to deposited collaterals
+    /// The `memo` is a message to the borrower, it's returned with the offer along with the comment
     MakeOffer {
         borrower: String,
         loan_id: u64,
         terms: LoanTerms,
         comment: Option<String>,
+        memo: Option<String>,
     },
     CancelOffer {
         global_offer_id: String,
@@ -72,6 +106,15 @@ pub enum ExecuteMsg {
     AcceptOffer {
         global_offer_id: String,
     },
+    /// Used by the borrower to propose new terms on an offer
+    CounterOffer {
+        global_offer_id: String,
+        terms: LoanTerms,
+    },
+    /// Used by the lender to accept the countered terms, topping up or getting back the principle difference
+    AcceptCounter {
+        global_offer_id: String,
+    },
     AcceptLoan {
         borrower: String,
         loan_id: u64,
@@ -84,6 +127,14 @@ pub enum ExecuteMsg {
         borrower: String,
         loan_id: u64,
     },
+    /// Used by the lender to withdraw the collateral of several defaulted loans at once, as (borrower, loan_id) pairs
+    WithdrawDefaultedLoans {
+        loans: Vec<(String, u64)>,
+    },
+    /// Used by the borrower to repay a defaulted loan with a penalty, before the lender withdraws the collateral
+    CureDefault {
+        loan_id: u64,
+    },
 
     /// Internal state
     SetOwner {
@@ -92,9 +143,32 @@ pub enum ExecuteMsg {
     SetFeeDistributor {
         fee_depositor: String,
     },
+    /// Routes the fees of a loan to another distributor, None goes back to the contract fee distributor
+    SetLoanFeeDistributor {
+        borrower: String,
+        loan_id: u64,
+        fee_distributor: Option<String>,
+    },
     SetFeeRate {
         fee_rate: Decimal,
     },
+    SetMinInterest {
+        min_interest: Option<Uint128>,
+    },
+    SetAllowedCollections {
+        allowed_collections: Option<Vec<String>>,
+    },
+    SetCureParams {
+        cure_blocks: u64,
+        cure_seconds: u64,
+        cure_penalty: Decimal,
+    },
+    SetInterestBeneficiaries {
+        beneficiaries: Vec<(String, Decimal)>,
+    },
+    SetVerifyOwnershipOnDeposit {
+        verify_ownership_on_deposit: bool,
+    },
 }
 
 #[cw_serde]
@@ -115,14 +189,44 @@ pub enum QueryMsg {
         limit: Option<u32>,
     },
 
+    /// Lists the ended and defaulted loans of a borrower, they don't appear in the other collateral listings
+    #[returns(MultipleCollateralsResponse)]
+    ArchivedLoans {
+        borrower: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+
     #[returns(MultipleCollateralsAllResponse)]
     AllCollaterals {
         start_after: Option<(String, u64)>,
         limit: Option<u32>,
+        order: Option<QueryOrder>,
+    },
+
+    /// Lists the collaterals of the contract matching a stored state and/or a computed default status, the most recent first
+    #[returns(MultipleCollateralsAllResponse)]
+    CollateralsFiltered {
+        state: Option<LoanState>,
+        defaulted: Option<bool>,
+        start_after: Option<(String, u64)>,
+        limit: Option<u32>,
     },
 
     #[returns(OfferResponse)]
     OfferInfo { global_offer_id: String },
+    #[returns(OfferYieldResponse)]
+    OfferYield { global_offer_id: String },
+    /// The loan an offer was made on
+    #[returns(OfferLocationResponse)]
+    OfferLocation { global_offer_id: String },
+    /// The principle of the active loan over the collateral floor price provided by the caller
+    #[returns(Decimal)]
+    LoanToValue {
+        borrower: String,
+        loan_id: u64,
+        collateral_floor: Uint128,
+    },
 
     #[returns(MultipleOffersResponse)]
     Offers {
@@ -132,11 +236,61 @@ pub enum QueryMsg {
         limit: Option<u32>,
     },
     #[returns(MultipleOffersResponse)]
+    BestOffers {
+        borrower: String,
+        loan_id: u64,
+        limit: Option<u32>,
+    },
+    #[returns(Vec<String>)]
+    OfferIdsForLoan { borrower: String, loan_id: u64 },
+    #[returns(OfferStatsResponse)]
+    OfferStats { borrower: String, loan_id: u64 },
+    /// Lists every offer of a loan, oldest first, with their state and funds as of now
+    #[returns(Vec<LoanOfferResponse>)]
+    OffersForLoan { borrower: String, loan_id: u64 },
+    /// Sums the lender funds of the published offers and of the started loans, per denom
+    /// The offers are scanned by pages, the totals of all the pages add up to the contract TVL
+    #[returns(TvlResponse)]
+    Tvl {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Compares the funds held by the contract with the funds it owes to the lenders, per denom
+    /// Every offer is scanned, this is meant for audits
+    #[returns(Vec<SolvencyResponse>)]
+    SolvencyCheck {},
+    #[returns(MultipleOffersResponse)]
     LenderOffers {
         lender: String,
         start_after: Option<String>,
         limit: Option<u32>,
     },
+    /// Lists the refused offers of a lender whose funds weren't withdrawn yet
+    #[returns(Vec<OfferResponse>)]
+    WithdrawableOffers { lender: String },
+    /// Lists the offers of a lender that funded a defaulted loan, whose collateral wasn't withdrawn yet
+    #[returns(MultipleOffersResponse)]
+    LenderDefaultedLoans {
+        lender: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+}
+
+/// Order in which the paginated queries return their results
+#[cw_serde]
+pub enum QueryOrder {
+    Ascending,
+    Descending,
+}
+
+impl From<QueryOrder> for Order {
+    fn from(order: QueryOrder) -> Self {
+        match order {
+            QueryOrder::Ascending => Order::Ascending,
+            QueryOrder::Descending => Order::Descending,
+        }
+    }
 }
 
 #[cw_serde]
@@ -144,6 +298,7 @@ pub struct CollateralResponse {
     pub borrower: String,
     pub loan_id: u64,
     pub collateral: CollateralInfo,
+    pub listing_expired: bool,
 }
 
 #[cw_serde]
@@ -164,6 +319,55 @@ pub struct OfferResponse {
     pub offer_info: OfferInfo,
 }
 
+/// An offer of a loan, with its state and the funds it holds computed at query time
+#[cw_serde]
+pub struct LoanOfferResponse {
+    pub global_offer_id: String,
+    pub lender: Addr,
+    pub terms: LoanTerms,
+    pub state: OfferState,
+    pub deposited_funds: Option<Coin>, // The funds the contract still holds for the offer, None once lent out or withdrawn
+}
+
+/// What the lender earns if the loan of an offer is repaid
+#[cw_serde]
+pub struct OfferYieldResponse {
+    pub global_offer_id: String,
+    pub net_interest: Uint128, // The interest left to the lender after the fee cut
+    pub apr: Decimal, // The net interest over the principle, annualized over the loan duration
+}
+
+/// Coordinates of the loan an offer was made on
+#[cw_serde]
+pub struct OfferLocationResponse {
+    pub borrower: Addr,
+    pub loan_id: u64,
+    pub offer_id: u64,
+}
+
+/// Lender funds of a page of offers, sorted by denom
+#[cw_serde]
+pub struct TvlResponse {
+    pub tvl: Vec<Coin>,
+    pub next_offer: Option<String>, // None once every offer was scanned
+}
+
+/// Funds held by the contract in a denom, against the offer funds it holds in escrow
+#[cw_serde]
+pub struct SolvencyResponse {
+    pub denom: String,
+    pub balance: Uint128,
+    pub escrowed: Uint128, // Funds of the offers that were neither lent out nor withdrawn
+    pub difference: Int128, // Negative if the contract can't pay back every lender
+}
+
+/// How many offers a loan received
+#[cw_serde]
+pub struct OfferStatsResponse {
+    pub total_offers: u64, // Every offer ever made on the loan, whatever its current state
+    pub live_offers: u64, // The offers that can still be accepted
+}
+
 #[cw_serde]
 pub struct MultipleOffersResponse {
     pub offers: Vec<OfferResponse>,
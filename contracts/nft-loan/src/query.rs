@@ -1,16 +1,23 @@
-use cosmwasm_std::{Deps, Addr, QueryRequest, WasmQuery, to_json_binary, StdResult, StdError, Order};
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{Deps, Env, Addr, QueryRequest, WasmQuery, to_json_binary, StdResult, StdError, Order, Decimal, Uint128, Coin, Int128};
 use cw721::{OwnerOfResponse, Cw721QueryMsg};
-use cw_storage_plus::Bound;
+use cw_storage_plus::{Bound, Map};
 use sg721_base::QueryMsg as Sg721QueryMsg;
 
-use crate::{ state::{CONTRACT_INFO, ContractInfo, BORROWER_INFO, BorrowerInfo, CollateralInfo, COLLATERAL_INFO, get_offer, get_actual_state, lender_offers}, msg::{MultipleCollateralsResponse, CollateralResponse, OfferResponse, MultipleOffersResponse, MultipleCollateralsAllResponse}, error::ContractError};
+use crate::{ state::{CONTRACT_INFO, ContractInfo, BORROWER_INFO, BorrowerInfo, CollateralInfo, COLLATERAL_INFO, ARCHIVED_LOANS, load_collateral, get_offer, get_actual_state, lender_offers, OfferInfo, OfferState, LoanState, is_loan_defaulted, get_active_loan}, msg::{MultipleCollateralsResponse, CollateralResponse, OfferResponse, MultipleOffersResponse, MultipleCollateralsAllResponse, QueryOrder, OfferYieldResponse, OfferLocationResponse, OfferStatsResponse, LoanOfferResponse, TvlResponse, SolvencyResponse}, error::ContractError};
 
 // settings for pagination
 const MAX_QUERY_LIMIT: u32 = 150;
 const DEFAULT_QUERY_LIMIT: u32 = 10;
+// Offers are not stored by amount, so sorted queries load at most this many offers in memory
+const MAX_SORTED_OFFERS: usize = 300;
+// Used to convert loan durations in blocks to seconds when computing yields
+const ASSUMED_BLOCK_TIME: u64 = 6;
+const SECONDS_PER_YEAR: u64 = 365 * 24 * 3600;
 
 pub fn query_contract_info(deps: Deps) -> StdResult<ContractInfo> {
-    CONTRACT_INFO.load(deps.storage).map_err(|err| err)
+    CONTRACT_INFO.load(deps.storage)
 }
 
 pub fn is_nft_owner(
@@ -68,13 +75,34 @@ pub fn query_collateral_info(
     loan_id: u64,
 ) -> StdResult<CollateralInfo> {
     let borrower = deps.api.addr_validate(&borrower)?;
-    COLLATERAL_INFO
-        .load(deps.storage, (borrower, loan_id))
-        .map_err(|err| err)
+    load_collateral(deps.storage, (borrower, loan_id))
 }
 
 pub fn query_collaterals(
     deps: Deps,
+    env: Env,
+    borrower: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<MultipleCollateralsResponse> {
+    _borrower_collaterals(deps, env, COLLATERAL_INFO, borrower, start_after, limit)
+}
+
+/// Lists the ended and defaulted loans of a borrower, the most recent first
+pub fn query_archived_loans(
+    deps: Deps,
+    env: Env,
+    borrower: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<MultipleCollateralsResponse> {
+    _borrower_collaterals(deps, env, ARCHIVED_LOANS, borrower, start_after, limit)
+}
+
+fn _borrower_collaterals(
+    deps: Deps,
+    env: Env,
+    loans: Map<(Addr, u64), CollateralInfo>,
     borrower: String,
     start_after: Option<u64>,
     limit: Option<u32>,
@@ -83,7 +111,7 @@ pub fn query_collaterals(
     let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
     let start = start_after.map(Bound::exclusive);
 
-    let collaterals: Vec<CollateralResponse> = COLLATERAL_INFO
+    let collaterals: Vec<CollateralResponse> = loans
         .prefix(borrower.clone())
         .range(deps.storage, None, start, Order::Descending)
         .map(|result| {
@@ -91,9 +119,9 @@ pub fn query_collaterals(
                 .map(|(loan_id, el)| CollateralResponse {
                     borrower: borrower.to_string(),
                     loan_id,
+                    listing_expired: el.is_listing_expired(&env),
                     collateral: el,
                 })
-                .map_err(|err| err)
         })
         .take(limit)
         .collect::<Result<Vec<CollateralResponse>, StdError>>()?;
@@ -117,11 +145,74 @@ pub fn query_offer_info(deps: Deps, global_offer_id: String) -> StdResult<OfferR
     })
 }
 
+pub fn query_offer_location(
+    deps: Deps,
+    global_offer_id: String,
+) -> StdResult<OfferLocationResponse> {
+    let offer_info = get_offer(deps.storage, &global_offer_id)?;
+
+    Ok(OfferLocationResponse {
+        borrower: offer_info.borrower,
+        loan_id: offer_info.loan_id,
+        offer_id: offer_info.offer_id,
+    })
+}
+
+/// Computes the net interest the lender gets from an offer, and the APR it corresponds to
+/// Durations in blocks are converted to seconds with an assumed block time, so the APR is only an estimate
+pub fn query_offer_yield(deps: Deps, global_offer_id: String) -> StdResult<OfferYieldResponse> {
+    let contract_info = CONTRACT_INFO.load(deps.storage)?;
+    let terms = get_offer(deps.storage, &global_offer_id)?.terms;
+
+    let net_interest = terms.interest * (Decimal::one() - contract_info.fee_rate);
+    let duration_in_seconds = terms
+        .duration_in_seconds
+        .unwrap_or(terms.duration_in_blocks * ASSUMED_BLOCK_TIME);
+    let apr = if terms.principle.amount.is_zero() || duration_in_seconds == 0 {
+        Decimal::zero()
+    } else {
+        Decimal::from_ratio(
+            net_interest * Uint128::from(SECONDS_PER_YEAR),
+            terms.principle.amount * Uint128::from(duration_in_seconds),
+        )
+    };
 
+    Ok(OfferYieldResponse {
+        global_offer_id,
+        net_interest,
+        apr,
+    })
+}
+
+/// Computes the loan-to-value ratio of a started loan
+/// The contract doesn't know market prices, so the value of the collateral is provided by the caller
+pub fn query_loan_to_value(
+    deps: Deps,
+    borrower: String,
+    loan_id: u64,
+    collateral_floor: Uint128,
+) -> StdResult<Decimal> {
+    let borrower = deps.api.addr_validate(&borrower)?;
+    let collateral = load_collateral(deps.storage, (borrower, loan_id))?;
+    if collateral.state != LoanState::Started {
+        return Err(StdError::generic_err("LoanNotStarted"));
+    }
+    if collateral_floor.is_zero() {
+        return Err(StdError::generic_err("ZeroCollateralFloor"));
+    }
+    let offer = get_active_loan(deps.storage, &collateral)
+        .map_err(|_| StdError::generic_err("NoActiveLoan"))?;
+
+    Ok(Decimal::from_ratio(offer.terms.principle.amount, collateral_floor))
+}
+
+/// Lists all the collaterals of the contract, the most recent first by default
 pub fn query_all_collaterals(
     deps: Deps,
+    env: Env,
     start_after: Option<(String, u64)>,
     limit: Option<u32>,
+    order: Option<QueryOrder>,
 ) -> StdResult<MultipleCollateralsAllResponse> {
     let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
     let start = start_after
@@ -131,16 +222,22 @@ pub fn query_all_collaterals(
         })
         .transpose()?;
 
+    let order: Order = order.unwrap_or(QueryOrder::Descending).into();
+    let (min, max) = match order {
+        Order::Ascending => (start, None),
+        Order::Descending => (None, start),
+    };
+
     let collaterals: Vec<CollateralResponse> = COLLATERAL_INFO
-        .range(deps.storage, None, start, Order::Descending)
+        .range(deps.storage, min, max, order)
         .map(|result| {
             result
                 .map(|(loan_id, el)| CollateralResponse {
                     borrower: loan_id.0.to_string(),
                     loan_id: loan_id.1,
+                    listing_expired: el.is_listing_expired(&env),
                     collateral: el,
                 })
-                .map_err(|err| err)
         })
         .take(limit)
         .collect::<Result<Vec<CollateralResponse>, StdError>>()?;
@@ -153,6 +250,59 @@ pub fn query_all_collaterals(
     })
 }
 
+/// Lists the collaterals matching a stored loan state and/or a default status, the most recent first
+/// The default status is computed at query time, so a started loan past its duration counts as defaulted
+pub fn query_collaterals_filtered(
+    deps: Deps,
+    env: Env,
+    state: Option<LoanState>,
+    defaulted: Option<bool>,
+    start_after: Option<(String, u64)>,
+    limit: Option<u32>,
+) -> StdResult<MultipleCollateralsAllResponse> {
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+    let start = start_after
+        .map::<Result<Bound<_>, StdError>, _>(|start_after| {
+            let borrower = deps.api.addr_validate(&start_after.0)?;
+            Ok(Bound::exclusive((borrower, start_after.1)))
+        })
+        .transpose()?;
+
+    let collaterals: Vec<CollateralResponse> = COLLATERAL_INFO
+        .range(deps.storage, None, start, Order::Descending)
+        .filter(|result| match result {
+            Ok((_, collateral)) => {
+                state.as_ref().is_none_or(|state| *state == collateral.state)
+                    && defaulted.is_none_or(|defaulted| {
+                        defaulted
+                            == is_loan_defaulted(deps.storage, env.clone(), collateral).is_ok()
+                    })
+            }
+            Err(_) => true,
+        })
+        .map(|result| {
+            result.map(|(loan_id, el)| CollateralResponse {
+                borrower: loan_id.0.to_string(),
+                loan_id: loan_id.1,
+                listing_expired: el.is_listing_expired(&env),
+                collateral: el,
+            })
+        })
+        .take(limit)
+        .collect::<Result<Vec<CollateralResponse>, StdError>>()?;
+
+    Ok(MultipleCollateralsAllResponse {
+        next_collateral: if collaterals.len() == limit {
+            collaterals
+                .last()
+                .map(|last| (last.borrower.clone(), last.loan_id))
+        } else {
+            None
+        },
+        collaterals,
+    })
+}
+
 pub fn query_offers(
     deps: Deps,
     borrower: String,
@@ -188,6 +338,214 @@ pub fn query_offers(
     })
 }
 
+/// Lists the ids of all the offers made on a loan, whatever their state, oldest first
+pub fn query_offer_ids_for_loan(
+    deps: Deps,
+    borrower: String,
+    loan_id: u64,
+) -> StdResult<Vec<String>> {
+    let borrower = deps.api.addr_validate(&borrower)?;
+    let mut global_offer_ids = lender_offers()
+        .idx
+        .loan
+        .prefix((borrower, loan_id))
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<String>>>()?;
+
+    // The ids are stringified integers, they are sorted as such
+    global_offer_ids.sort_by_key(|id| (id.len(), id.clone()));
+    Ok(global_offer_ids)
+}
+
+/// Lists all the offers of a loan, oldest first
+/// The state of each offer is computed against the current loan state, and accepted offers don't hold funds anymore
+pub fn query_offers_for_loan(
+    deps: Deps,
+    borrower: String,
+    loan_id: u64,
+) -> StdResult<Vec<LoanOfferResponse>> {
+    let borrower = deps.api.addr_validate(&borrower)?;
+    let mut offers = lender_offers()
+        .idx
+        .loan
+        .prefix((borrower, loan_id))
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|x| {
+            let (global_offer_id, offer_info) = x?;
+            let state = get_actual_state(&offer_info, deps.storage)?;
+            let deposited_funds = if state == OfferState::Accepted {
+                None
+            } else {
+                offer_info.deposited_funds
+            };
+            Ok(LoanOfferResponse {
+                global_offer_id,
+                lender: offer_info.lender,
+                terms: offer_info.terms,
+                state,
+                deposited_funds,
+            })
+        })
+        .collect::<StdResult<Vec<LoanOfferResponse>>>()?;
+
+    // The ids are stringified integers, they are sorted as such
+    offers.sort_by_key(|offer| (offer.global_offer_id.len(), offer.global_offer_id.clone()));
+    Ok(offers)
+}
+
+/// Counts the offers a loan received since it was listed, and the ones still published
+pub fn query_offer_stats(
+    deps: Deps,
+    borrower: String,
+    loan_id: u64,
+) -> StdResult<OfferStatsResponse> {
+    let borrower = deps.api.addr_validate(&borrower)?;
+    let collateral = load_collateral(deps.storage, (borrower.clone(), loan_id))?;
+
+    let live_offers = lender_offers()
+        .idx
+        .loan
+        .prefix((borrower, loan_id))
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|x| {
+            let (_, offer_info) = x?;
+            get_actual_state(&offer_info, deps.storage)
+        })
+        .collect::<StdResult<Vec<OfferState>>>()?
+        .into_iter()
+        .filter(|state| *state == OfferState::Published)
+        .count() as u64;
+
+    Ok(OfferStatsResponse {
+        total_offers: collateral.offer_amount,
+        live_offers,
+    })
+}
+
+/// Sums the funds of the published offers and of the offers funding a started loan, over a page of offers
+pub fn query_tvl(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<TvlResponse> {
+    let limit = limit.unwrap_or(MAX_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
+
+    let offers = lender_offers()
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<(String, OfferInfo)>>>()?;
+
+    let mut tvl: BTreeMap<String, Uint128> = BTreeMap::new();
+    for (global_offer_id, offer_info) in &offers {
+        let collateral =
+            load_collateral(deps.storage, (offer_info.borrower.clone(), offer_info.loan_id))?;
+        // Published offers on a loan that isn't published anymore are refused
+        let counted = match offer_info.state {
+            OfferState::Published => collateral.state == LoanState::Published,
+            OfferState::Accepted => {
+                collateral.state == LoanState::Started
+                    && collateral.active_offer.as_ref() == Some(global_offer_id)
+            }
+            _ => false,
+        };
+        if let (true, Some(funds)) = (counted, &offer_info.deposited_funds) {
+            *tvl.entry(funds.denom.clone()).or_default() += funds.amount;
+        }
+    }
+
+    Ok(TvlResponse {
+        tvl: tvl
+            .into_iter()
+            .map(|(denom, amount)| Coin { denom, amount })
+            .collect(),
+        next_offer: if offers.len() == limit {
+            offers.last().map(|(global_offer_id, _)| global_offer_id.clone())
+        } else {
+            None
+        },
+    })
+}
+
+/// Compares the contract balances with the funds escrowed for the offers, per denom
+/// The funds of accepted offers were lent out, only the other offers that weren't withdrawn yet are owed
+pub fn query_solvency_check(deps: Deps, env: Env) -> StdResult<Vec<SolvencyResponse>> {
+    let mut solvency: BTreeMap<String, (Uint128, Uint128)> = BTreeMap::new();
+    for balance in deps.querier.query_all_balances(env.contract.address)? {
+        solvency.entry(balance.denom).or_default().0 += balance.amount;
+    }
+    for offer in lender_offers().range(deps.storage, None, None, Order::Ascending) {
+        let (_, offer_info) = offer?;
+        if offer_info.state == OfferState::Accepted {
+            continue;
+        }
+        if let Some(funds) = offer_info.deposited_funds {
+            solvency.entry(funds.denom).or_default().1 += funds.amount;
+        }
+    }
+
+    solvency
+        .into_iter()
+        .map(|(denom, (balance, escrowed))| {
+            Ok(SolvencyResponse {
+                denom,
+                balance,
+                escrowed,
+                difference: Int128::try_from(balance)?.checked_sub(Int128::try_from(escrowed)?)?,
+            })
+        })
+        .collect()
+}
+
+/// Lists the published offers of a loan, the highest principle first
+/// Offers with the same principle are ranked by lowest interest
+pub fn query_best_offers(
+    deps: Deps,
+    borrower: String,
+    loan_id: u64,
+    limit: Option<u32>,
+) -> StdResult<MultipleOffersResponse> {
+    let borrower = deps.api.addr_validate(&borrower)?;
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+
+    let mut offers: Vec<OfferResponse> = lender_offers()
+        .idx
+        .loan
+        .prefix((borrower, loan_id))
+        .range(deps.storage, None, None, Order::Descending)
+        .take(MAX_SORTED_OFFERS)
+        .map(|x| {
+            let (global_offer_id, mut offer_info) = x?;
+            offer_info.state = get_actual_state(&offer_info, deps.storage)?;
+            Ok(OfferResponse {
+                offer_info,
+                global_offer_id,
+            })
+        })
+        .filter(|offer: &StdResult<OfferResponse>| {
+            offer
+                .as_ref()
+                .map(|offer| offer.offer_info.state == OfferState::Published)
+                .unwrap_or(true)
+        })
+        .collect::<StdResult<Vec<OfferResponse>>>()?;
+
+    offers.sort_by(|a, b| {
+        b.offer_info
+            .terms
+            .principle
+            .amount
+            .cmp(&a.offer_info.terms.principle.amount)
+            .then(a.offer_info.terms.interest.cmp(&b.offer_info.terms.interest))
+    });
+    offers.truncate(limit);
+
+    Ok(MultipleOffersResponse {
+        offers,
+        next_offer: None,
+    })
+}
+
 pub fn query_lender_offers(
     deps: Deps,
     lender: String,
@@ -208,7 +566,6 @@ pub fn query_lender_offers(
                 offer_info,
                 global_offer_id: key,
             })
-            .map_err(|err| err)
         })
         .take(limit)
         .collect::<StdResult<Vec<OfferResponse>>>()?;
@@ -217,4 +574,81 @@ pub fn query_lender_offers(
         next_offer: offers.last().map(|last| last.global_offer_id.clone()),
         offers,
     })
-}
\ No newline at end of file
+}
+
+/// Lists the offers of a lender that were refused and whose funds can still be withdrawn, the most recent first
+pub fn query_withdrawable_offers(deps: Deps, lender: String) -> StdResult<Vec<OfferResponse>> {
+    let lender = deps.api.addr_validate(&lender)?;
+
+    lender_offers()
+        .idx
+        .lender
+        .prefix(lender)
+        .range(deps.storage, None, None, Order::Descending)
+        .filter_map(|x| {
+            let (global_offer_id, mut offer_info) = match x {
+                Ok(offer) => offer,
+                Err(err) => return Some(Err(err)),
+            };
+            match get_actual_state(&offer_info, deps.storage) {
+                Ok(OfferState::Refused) if offer_info.deposited_funds.is_some() => {
+                    offer_info.state = OfferState::Refused;
+                    Some(Ok(OfferResponse {
+                        global_offer_id,
+                        offer_info,
+                    }))
+                }
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            }
+        })
+        .collect()
+}
+
+/// Lists the offers of a lender that funded a loan that defaulted and can be withdrawn
+/// Loans whose collateral was already withdrawn by the lender are not listed
+pub fn query_lender_defaulted_loans(
+    deps: Deps,
+    env: Env,
+    lender: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<MultipleOffersResponse> {
+    let lender = deps.api.addr_validate(&lender)?;
+    let limit = limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let offers: Vec<OfferResponse> = lender_offers()
+        .idx
+        .lender
+        .prefix(lender)
+        .range(deps.storage, None, start, Order::Descending)
+        .filter_map(|x| match x {
+            Ok((key, offer_info)) if offer_info.state == OfferState::Accepted => {
+                match load_collateral(deps.storage, (offer_info.borrower.clone(), offer_info.loan_id))
+                {
+                    Ok(collateral)
+                        if collateral.active_offer.as_ref() == Some(&key)
+                            && collateral.state == LoanState::Started
+                            && is_loan_defaulted(deps.storage, env.clone(), &collateral).is_ok() =>
+                    {
+                        Some(Ok(OfferResponse {
+                            offer_info,
+                            global_offer_id: key,
+                        }))
+                    }
+                    Ok(_) => None,
+                    Err(err) => Some(Err(err)),
+                }
+            }
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        })
+        .take(limit)
+        .collect::<StdResult<Vec<OfferResponse>>>()?;
+
+    Ok(MultipleOffersResponse {
+        next_offer: offers.last().map(|last| last.global_offer_id.clone()),
+        offers,
+    })
+}
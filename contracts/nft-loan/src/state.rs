@@ -7,7 +7,12 @@ use crate::error::ContractError;
 
 pub const CONTRACT_INFO: Item<ContractInfo> = Item::new("contract_info");
 pub const COLLATERAL_INFO: Map<(Addr, u64), CollateralInfo> = Map::new("collateral_info");
+// Ended and defaulted loans, kept out of `COLLATERAL_INFO` so that they don't slow down the scans of live loans
+pub const ARCHIVED_LOANS: Map<(Addr, u64), CollateralInfo> = Map::new("archived_loans");
 pub const BORROWER_INFO: Map<&Addr, BorrowerInfo> = Map::new("borrower_info");
+// Accepted offers whose funds are only released once the collateral transfer reply confirms the custody, by global offer id
+// The reply to the last collateral transfer of an offer has the global offer index as reply id
+pub const PENDING_FUND_RELEASES: Map<&str, bool> = Map::new("pending_fund_releases");
 
 #[cw_serde]
 pub struct OwnerStruct{
@@ -23,6 +28,18 @@ pub struct ContractInfo {
     pub fee_distributor: Addr,
     pub fee_rate: Decimal,
     pub global_offer_index: u64,
+    pub min_interest: Option<Uint128>, // The minimum interest an offer has to carry, so that the fee distributor always gets a cut
+    pub allowed_collections: Option<Vec<String>>, // The only collections accepted as collateral. None or empty allows all collections
+    #[serde(default)]
+    pub cure_blocks: u64, // The number of blocks after a default during which the borrower can still repay the loan
+    #[serde(default)]
+    pub cure_seconds: u64, // The same window for the loans whose duration is evaluated in seconds
+    #[serde(default)]
+    pub cure_penalty: Decimal, // The share of the principle the borrower pays to the lender on top of the loan when curing a default
+    #[serde(default)]
+    pub interest_beneficiaries: Vec<(Addr, Decimal)>, // When not empty, the fee cut is split between those addresses instead of going to the fee distributor
+    #[serde(default)]
+    pub verify_ownership_on_deposit: bool, // When set, the borrower has to own every asset they list, not only when the loan is accepted
 }
 
 #[cw_serde]
@@ -34,8 +51,22 @@ pub struct CollateralInfo {
     pub offer_amount: u64,
     pub active_offer: Option<String>,
     pub start_block: Option<u64>,
+    pub start_time: Option<Timestamp>,
     pub comment: Option<String>,
     pub loan_preview: Option<AssetInfo>, // The preview can only be a CW1155 or a CW721 token.
+    pub listing_expiry: Option<Timestamp>, // A published listing doesn't accept new offers after this time
+    pub fee_distributor_override: Option<Addr>, // Receives the fees of this loan instead of the contract fee distributor, set by the owner
+    pub reserved_lender: Option<Addr>, // Only this lender can make offers on or accept the loan, set by the borrower
+}
+
+impl CollateralInfo {
+    /// A listing is expired once it's past its expiry and still waiting for a lender
+    pub fn is_listing_expired(&self, env: &Env) -> bool {
+        self.state == LoanState::Published
+            && self
+                .listing_expiry
+                .is_some_and(|listing_expiry| env.block.time >= listing_expiry)
+    }
 }
 
 impl Default for CollateralInfo {
@@ -49,7 +80,11 @@ impl Default for CollateralInfo {
             offer_amount: 0u64,
             active_offer: None,
             start_block: None,
+            start_time: None,
             loan_preview: None,
+            listing_expiry: None,
+            fee_distributor_override: None,
+            reserved_lender: None,
         }
     }
 }
@@ -71,6 +106,8 @@ pub struct OfferInfo {
     pub list_date: Timestamp,
     pub deposited_funds: Option<Coin>,
     pub comment: Option<String>,
+    pub memo: Option<String>, // Message from the lender to the borrower, distinct from the public comment
+    pub counter_terms: Option<LoanTerms>, // Terms proposed back by the borrower, the lender can accept them with `AcceptCounter`
 }
 
 
@@ -79,6 +116,60 @@ pub struct LoanTerms {
     pub principle: Coin,
     pub interest: Uint128,
     pub duration_in_blocks: u64,
+    pub duration_in_seconds: Option<u64>, // When specified, the loan duration is evaluated in seconds instead of blocks
+    pub early_repay_penalty: Option<Uint128>, // Paid to the lender on top of the loan when repaying before `min_duration_blocks`
+    pub min_duration_blocks: Option<u64>,
+}
+
+impl LoanTerms {
+    /// Returns whether the loan duration is exceeded
+    /// The duration is evaluated against the block time when specified in seconds, against the block height otherwise
+    pub fn is_expired(&self, env: &Env, collateral: &CollateralInfo) -> bool {
+        match (self.duration_in_seconds, collateral.start_time) {
+            (Some(duration_in_seconds), Some(start_time)) => {
+                start_time.plus_seconds(duration_in_seconds) < env.block.time
+            }
+            _ => collateral.start_block.unwrap() + self.duration_in_blocks < env.block.height,
+        }
+    }
+
+    /// Returns the penalty due when repaying the loan now
+    /// A penalty is only due before the minimum duration of the loan is reached
+    pub fn early_repay_penalty_due(&self, env: &Env, collateral: &CollateralInfo) -> Uint128 {
+        match (self.early_repay_penalty, self.min_duration_blocks, collateral.start_block) {
+            (Some(penalty), Some(min_duration_blocks), Some(start_block))
+                if env.block.height < start_block + min_duration_blocks =>
+            {
+                penalty
+            }
+            _ => Uint128::zero(),
+        }
+    }
+
+    /// Returns whether a defaulted loan is still within its cure window
+    /// The window starts right after the loan duration, it is `cure_seconds` long when the duration is evaluated in seconds
+    /// and `cure_blocks` long otherwise
+    pub fn is_curable(
+        &self,
+        env: &Env,
+        collateral: &CollateralInfo,
+        cure_blocks: u64,
+        cure_seconds: u64,
+    ) -> bool {
+        match (
+            self.duration_in_seconds,
+            collateral.start_time,
+            collateral.start_block,
+        ) {
+            (Some(duration_in_seconds), Some(start_time), _) => {
+                env.block.time <= start_time.plus_seconds(duration_in_seconds + cure_seconds)
+            }
+            (None, _, Some(start_block)) => {
+                env.block.height <= start_block + self.duration_in_blocks + cure_blocks
+            }
+            _ => false,
+        }
+    }
 }
 
 #[cw_serde]
@@ -138,20 +229,49 @@ pub fn lender_offers<'a>() -> IndexedMap<'a, &'a str, OfferInfo, LenderOfferInde
 pub fn is_loan_modifiable(collateral: &CollateralInfo) -> Result<(), ContractError> {
     match collateral.state {
         LoanState::Published => Ok(()),
-        _ => return Err(ContractError::NotModifiable {}),
+        _ => Err(ContractError::NotModifiable {}),
     }
 }
 pub fn is_loan_acceptable(collateral: &CollateralInfo) -> Result<(), ContractError> {
     match collateral.state {
         LoanState::Published => Ok(()),
-        _ => return Err(ContractError::NotAcceptable {}),
+        _ => Err(ContractError::NotAcceptable {}),
     }
 }
 
 pub fn is_loan_counterable(collateral: &CollateralInfo) -> Result<(), ContractError> {
     match collateral.state {
         LoanState::Published => Ok(()),
-        _ => return Err(ContractError::NotCounterable {}),
+        _ => Err(ContractError::NotCounterable {}),
+    }
+}
+
+pub fn is_interest_sufficient(contract_info: &ContractInfo, terms: &LoanTerms) -> Result<(), ContractError> {
+    match contract_info.min_interest {
+        Some(min_interest) if terms.interest < min_interest => Err(ContractError::InterestTooLow {
+            min_interest,
+            interest: terms.interest,
+        }),
+        _ => Ok(()),
+    }
+}
+
+pub fn is_collection_allowed(contract_info: &ContractInfo, asset: &AssetInfo) -> Result<(), ContractError> {
+    let allowed_collections = match &contract_info.allowed_collections {
+        Some(allowed_collections) if !allowed_collections.is_empty() => allowed_collections,
+        _ => return Ok(()),
+    };
+    let collection = match asset {
+        AssetInfo::Sg721Token(sg721) => &sg721.address,
+        AssetInfo::Cw721Coin(cw721) => &cw721.address,
+        AssetInfo::Coin(coin) => &coin.denom,
+    };
+    if allowed_collections.contains(collection) {
+        Ok(())
+    } else {
+        Err(ContractError::CollectionNotAllowed {
+            collection: collection.clone(),
+        })
     }
 }
 
@@ -159,17 +279,17 @@ pub fn is_offer_refusable(collateral: &CollateralInfo, offer_info: &OfferInfo) -
     is_loan_counterable(collateral).map_err(|_| ContractError::NotRefusable {  })?;
     match offer_info.state {
         OfferState::Published => Ok(()),
-        _ => return Err(ContractError::NotRefusable {}),
+        _ => Err(ContractError::NotRefusable {}),
     }
 }
 
 pub fn can_repay_loan(storage: &dyn Storage, env: Env, collateral: &CollateralInfo) -> Result<(), ContractError> {
     if is_loan_defaulted(storage, env, collateral).is_ok() {
-        return Err(ContractError::WrongLoanState {
+        Err(ContractError::WrongLoanState {
             state: LoanState::Defaulted {},
         })
     } else if collateral.state != LoanState::Started {
-        return Err(ContractError::WrongLoanState {
+        Err(ContractError::WrongLoanState {
             state: collateral.state.clone(),
         })
     } else {
@@ -186,16 +306,16 @@ pub fn is_loan_defaulted(
     let offer: OfferInfo = get_active_loan(storage, collateral)?;
     match &collateral.state {
         LoanState::Started => {
-            if collateral.start_block.unwrap() + offer.terms.duration_in_blocks < env.block.height {
+            if offer.terms.is_expired(&env, collateral) {
                 Ok(())
             } else {
-                return Err(ContractError::WrongLoanState {
+                Err(ContractError::WrongLoanState {
                     state: LoanState::Started,
                 })
             }
         }
         LoanState::Defaulted => Ok(()),
-        _ => return Err(ContractError::WrongLoanState {
+        _ => Err(ContractError::WrongLoanState {
             state: collateral.state.clone(),
         }),
     }
@@ -221,7 +341,7 @@ pub fn is_lender(storage: &dyn Storage, lender: Addr, global_offer_id: &str) ->
 pub fn is_collateral_withdrawable(collateral: &CollateralInfo) -> Result<(), ContractError> {
     match collateral.state {
         LoanState::Published => Ok(()),
-        _ => return Err(ContractError::NotWithdrawable {}),
+        _ => Err(ContractError::NotWithdrawable {}),
     }
 }
 
@@ -269,7 +389,7 @@ pub fn get_offer(storage: &dyn Storage, global_offer_id: &str) -> StdResult<Offe
 
 pub fn get_actual_state(offer_info: &OfferInfo, storage: &dyn Storage) -> StdResult<OfferState>{
     let collateral_info =
-        COLLATERAL_INFO.load(storage, (offer_info.borrower.clone(), offer_info.loan_id))?;
+        load_collateral(storage, (offer_info.borrower.clone(), offer_info.loan_id))?;
 
     // We check the status of the offer.
     // A refused offer isn't marked as such but depends on the overlying collateral info state
@@ -285,3 +405,34 @@ pub fn get_actual_state(offer_info: &OfferInfo, storage: &dyn Storage) -> StdRes
         _ => offer_info.state.clone(),
     })
 }
+
+/// Loads a loan, whether it is live or archived
+pub fn load_collateral(storage: &dyn Storage, key: (Addr, u64)) -> StdResult<CollateralInfo> {
+    match COLLATERAL_INFO.may_load(storage, key.clone())? {
+        Some(collateral) => Ok(collateral),
+        None => ARCHIVED_LOANS.load(storage, key),
+    }
+}
+
+/// Loads a loan that didn't reach a terminal state yet
+/// Archived loans and ids that never existed are told apart
+pub fn load_live_collateral(
+    storage: &dyn Storage,
+    key: (Addr, u64),
+) -> Result<CollateralInfo, ContractError> {
+    match COLLATERAL_INFO.may_load(storage, key.clone())? {
+        Some(collateral) => Ok(collateral),
+        None if ARCHIVED_LOANS.has(storage, key) => Err(ContractError::LoanArchived {}),
+        None => Err(ContractError::LoanNotFound {}),
+    }
+}
+
+/// Moves a loan that reached a terminal state (ended or defaulted) to the archive
+pub fn archive_loan(
+    storage: &mut dyn Storage,
+    key: (Addr, u64),
+    collateral: &CollateralInfo,
+) -> StdResult<()> {
+    COLLATERAL_INFO.remove(storage, key.clone());
+    ARCHIVED_LOANS.save(storage, key, collateral)
+}
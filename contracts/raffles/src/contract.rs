@@ -1,20 +1,30 @@
 use cosmwasm_std::{
-    ensure_eq, entry_point, to_json_binary, Decimal, Deps, DepsMut, Empty, Env, MessageInfo,
-    QueryResponse, StdResult, Uint128,
+    ensure_eq, entry_point, to_json_binary, Addr, Decimal, Deps, DepsMut, Empty, Env, MessageInfo,
+    Order, QueryResponse, Reply, StdResult, Uint128,
 };
 use sg_std::StargazeMsgWrapper;
+use std::collections::BTreeMap;
+use utils::state::{AssetInfo, Cw721Coin, Sg721Token};
 
 use crate::error::ContractError;
 use crate::execute::{
-    execute_buy_tickets, execute_cancel_raffle, execute_claim, execute_create_raffle,
-    execute_modify_raffle, execute_receive, execute_receive_nois, execute_update_randomness,
+    execute_admin_settle, execute_buy_tickets, execute_cancel_and_unwind, execute_cancel_raffle, execute_claim, execute_claim_and_relist, execute_claim_consolation,
+    execute_create_raffle, execute_modify_raffle, execute_realign_nois_fees, execute_reassign_randomness, execute_recount_tickets, execute_sweep_dust, execute_receive, execute_refund_tickets, execute_receive_nois,
+    execute_update_randomness, reply_claim,
+};
+use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, RaffleResponse, RandomnessModeMsg};
+use crate::query::{
+    query_all_raffles, query_all_tickets, query_can_buy, query_config, query_ticket_holders,
+    query_ticket_number, query_verify_winner, query_raffle_infos, query_price_stats, query_randomness_status, query_sale_time_remaining, query_raffles_by_collection, query_raffle_fees, query_ticket_owner_at, query_has_entered, query_awaiting_claim, query_collection_participation, query_raffle_randomness, query_prize_value, query_treasury_earned,
 };
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, RaffleResponse};
-use crate::query::{query_all_raffles, query_all_tickets, query_config, query_ticket_number};
 use crate::state::{
-    get_raffle_state, load_raffle, Config, RandomnessParams, CONFIG, MINIMUM_CREATION_FEE_AMOUNT,
-    MINIMUM_RAFFLE_DURATION, MINIMUM_RAFFLE_TIMEOUT, NOIS_RANDOMNESS, MINIMUM_CREATION_FEE_DENOM,
+    get_raffle_state, load_raffle, Config, CONFIG, MINIMUM_CREATION_FEE_AMOUNT,
+    MINIMUM_RAFFLE_DURATION, MINIMUM_RAFFLE_TIMEOUT, MINIMUM_CREATION_FEE_DENOM, MINIMUM_RAND_FEE,
+    NoisFeeSource, RandomnessMode, MAX_BPS, MAX_TICKET_MULTIPLIER, TICKET_MULTIPLIERS,
+    RaffleFeeParams, COLLECTION_RAFFLES, LIVE_RAFFLES, PAID_TICKETS, RAFFLE_INFO, TICKET_HOLDERS,
+    USER_TICKETS, BUYER_PRICES,
 };
+use crate::utils::{default_job_id_prefix, validate_job_id_prefix};
 use cw2::set_contract_version;
 
 pub type Response = cosmwasm_std::Response<StargazeMsgWrapper>;
@@ -22,7 +32,7 @@ pub type Response = cosmwasm_std::Response<StargazeMsgWrapper>;
 #[entry_point]
 pub fn instantiate(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
@@ -30,13 +40,13 @@ pub fn instantiate(
         .api
         .addr_validate(&msg.nois_proxy_addr)
         .map_err(|_| ContractError::InvalidProxyAddress)?;
-    NOIS_RANDOMNESS.save(
-        deps.storage,
-        &RandomnessParams {
-            nois_randomness: None,
-            requested: false,
-        },
-    )?;
+    // A zero amount or an empty denom would make the proxy reject every randomness request
+    if msg.nois_proxy_amount.is_zero() {
+        return Err(ContractError::InvalidProxyAmount {});
+    }
+    if msg.nois_proxy_denom.is_empty() {
+        return Err(ContractError::InvalidProxyDenom {});
+    }
 
     let creation_fee_amount = match msg.creation_fee_amount {
         Some(int) => int,
@@ -48,6 +58,11 @@ pub fn instantiate(
         None => MINIMUM_CREATION_FEE_DENOM.to_string(),
     };
 
+    let job_id_prefix = msg
+        .job_id_prefix
+        .unwrap_or_else(|| default_job_id_prefix(&env.contract.address));
+    validate_job_id_prefix(&job_id_prefix)?;
+
     let config = Config {
         name: msg.name,
         owner: deps
@@ -68,17 +83,29 @@ pub fn instantiate(
         raffle_fee: msg.raffle_fee.unwrap_or(Decimal::zero()),
         creation_fee_denom,
         creation_fee_amount,
-        // rand_fee: msg
-        //     .rand_fee
-        //     .unwrap_or(MINIMUM_RAND_FEE)
-        //     .max(MINIMUM_RAND_FEE),
+        // The minimum is only the default, the owner can waive the randomness provider fee
+        rand_fee: msg.rand_fee.unwrap_or(MINIMUM_RAND_FEE),
         lock: false,
+        full_pause: false,
         nois_proxy_addr,
         nois_proxy_denom: msg.nois_proxy_denom,
         nois_proxy_amount: msg.nois_proxy_amount,
+        job_id_prefix,
+        max_live_raffles: None,
+        cancel_lockout_seconds: msg.cancel_lockout_seconds.unwrap_or(0),
+        trusted_minters: vec![],
+        max_nois_proxy_amount: None,
+        randomness_mode: RandomnessMode::Nois,
+        consolation_bps: None,
+        nois_fee_source: NoisFeeSource::Contract,
+        min_assets_per_raffle: 1,
+        price_oracle: None,
     };
 
     // TODO: add fair-burn module?
+    if config.rand_fee >= Decimal::one() {
+        return Err(ContractError::InvalidRandFee {});
+    }
     config.validate_fee()?;
 
     CONFIG.save(deps.storage, &config)?;
@@ -90,14 +117,74 @@ pub fn instantiate(
     Ok(Response::default())
 }
 
+/// Brings the state written by the previous contract versions up to date
+/// The config is saved back with the defaults of its new fields.
+/// Raffles created before the fee snapshot get the current fee parameters, and their new indexes are rebuilt
 #[cfg_attr(not(feature = "library"), ::cosmwasm_std::entry_point)]
 pub fn migrate(deps: DepsMut, _env: Env, _msg: Empty) -> StdResult<Response> {
+    let config = CONFIG.load(deps.storage)?;
+    CONFIG.save(deps.storage, &config)?;
+
+    let raffles = RAFFLE_INFO
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut live_raffles = 0u32;
+    let mut migrated_raffles = BTreeMap::new();
+    for (raffle_id, mut raffle_info) in raffles {
+        if !raffle_info.is_cancelled && raffle_info.winner.is_none() {
+            live_raffles += 1;
+        }
+        // Raffles created since the fee snapshot are already up to date
+        if !raffle_info.fee_params.fee_addr.as_str().is_empty() {
+            continue;
+        }
+        raffle_info.fee_params = RaffleFeeParams::from_config(&config);
+        // There were no ticket multipliers, every ticket slot was paid for
+        raffle_info.tickets_paid = raffle_info.number_of_tickets;
+        for asset in &raffle_info.assets {
+            match asset {
+                AssetInfo::Cw721Coin(Cw721Coin { address, .. })
+                | AssetInfo::Sg721Token(Sg721Token { address, .. }) => {
+                    COLLECTION_RAFFLES.save(deps.storage, (address, raffle_id), &true)?
+                }
+                AssetInfo::Coin(_) => {}
+            }
+        }
+        RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
+        migrated_raffles.insert(raffle_id, raffle_info.raffle_ticket_price);
+    }
+
+    let user_tickets = USER_TICKETS
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for ((owner, raffle_id), tickets) in user_tickets {
+        if let Some(ticket_price) = migrated_raffles.get(&raffle_id) {
+            TICKET_HOLDERS.save(deps.storage, (raffle_id, &owner), &tickets)?;
+            PAID_TICKETS.save(deps.storage, (raffle_id, &owner), &tickets)?;
+            // There were no acceptable prices, every ticket was paid with the raffle ticket price
+            if let AssetInfo::Coin(ticket_price) = ticket_price {
+                BUYER_PRICES.save(deps.storage, (raffle_id, &owner), ticket_price)?;
+            }
+        }
+    }
+    if LIVE_RAFFLES.may_load(deps.storage)?.is_none() {
+        LIVE_RAFFLES.save(deps.storage, &live_raffles)?;
+    }
+
     set_contract_version(
         deps.storage,
         env!("CARGO_PKG_NAME"),
         env!("CARGO_PKG_VERSION"),
     )?;
-    Ok(Response::default())
+    Ok(Response::default()
+        .add_attribute("action", "migrate")
+        .add_attribute("migrated_raffles", migrated_raffles.len().to_string()))
+}
+
+#[entry_point]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    // The only submessages with a reply are the last prize transfers of the claims
+    reply_claim(deps, env, msg.id)
 }
 
 #[entry_point]
@@ -117,9 +204,13 @@ pub fn execute(
             creation_fee_denom,
             creation_fee_amount,
             raffle_fee,
+            rand_fee,
             nois_proxy_addr,
             nois_proxy_denom,
             nois_proxy_amount,
+            cancel_lockout_seconds,
+            max_nois_proxy_amount,
+            clear_max_nois_proxy_amount,
         } => execute_update_config(
             deps,
             env,
@@ -132,9 +223,13 @@ pub fn execute(
             creation_fee_denom,
             creation_fee_amount,
             raffle_fee,
+            rand_fee,
             nois_proxy_addr,
             nois_proxy_denom,
             nois_proxy_amount,
+            cancel_lockout_seconds,
+            max_nois_proxy_amount,
+            clear_max_nois_proxy_amount.unwrap_or(false),
         ),
         ExecuteMsg::CreateRaffle {
             owner,
@@ -167,15 +262,78 @@ pub fn execute(
             raffle_id,
             ticket_number,
             sent_assets,
-        } => execute_buy_tickets(deps, env, info, raffle_id, ticket_number, sent_assets),
+            proof,
+        } => execute_buy_tickets(deps, env, info, raffle_id, ticket_number, sent_assets, proof),
+        ExecuteMsg::RefundTicket {
+            raffle_id,
+            ticket_number,
+        } => execute_refund_tickets(deps, env, info, raffle_id, ticket_number),
         ExecuteMsg::Receive(msg) => execute_receive(deps, env, info, msg),
         ExecuteMsg::ClaimNft { raffle_id } => execute_claim(deps, env, info, raffle_id),
+        ExecuteMsg::ClaimAndRelist {
+            raffle_id,
+            new_raffle_options,
+            new_ticket_price,
+        } => execute_claim_and_relist(
+            deps,
+            env,
+            info,
+            raffle_id,
+            new_raffle_options,
+            new_ticket_price,
+        ),
+        ExecuteMsg::ClaimConsolation { raffle_id } => {
+            execute_claim_consolation(deps, env, info, raffle_id)
+        }
         ExecuteMsg::UpdateRandomness { raffle_id } => {
-            execute_update_randomness(deps, env, info, raffle_id)
+            execute_update_randomness(deps, env, info, raffle_id, None)
         }
+        ExecuteMsg::SubmitDrandRandomness {
+            raffle_id,
+            randomness,
+        } => execute_update_randomness(deps, env, info, raffle_id, Some(randomness)),
         ExecuteMsg::NoisReceive { callback } => execute_receive_nois(deps, env, info, callback),
         // Admin messages
-        ExecuteMsg::ToggleLock { lock } => execute_toggle_lock(deps, env, info, lock),
+        ExecuteMsg::ToggleLock { lock, full_pause } => {
+            execute_toggle_lock(deps, env, info, lock, full_pause)
+        }
+        ExecuteMsg::RealignNoisFees {} => execute_realign_nois_fees(deps, env, info),
+        ExecuteMsg::ReassignRandomness { raffle_id } => {
+            execute_reassign_randomness(deps, env, info, raffle_id)
+        }
+        ExecuteMsg::RecountTickets { raffle_id } => {
+            execute_recount_tickets(deps, env, info, raffle_id)
+        }
+        ExecuteMsg::SweepDust { denom } => execute_sweep_dust(deps, env, info, denom),
+        ExecuteMsg::SetMaxLiveRaffles { max_live_raffles } => {
+            execute_set_max_live_raffles(deps, env, info, max_live_raffles)
+        }
+        ExecuteMsg::SetPriceOracle { price_oracle } => {
+            execute_set_price_oracle(deps, env, info, price_oracle)
+        }
+        ExecuteMsg::SetMinAssetsPerRaffle {
+            min_assets_per_raffle,
+        } => execute_set_min_assets_per_raffle(deps, env, info, min_assets_per_raffle),
+        ExecuteMsg::SetTicketMultiplier {
+            address,
+            multiplier,
+        } => execute_set_ticket_multiplier(deps, env, info, address, multiplier),
+        ExecuteMsg::SetConsolationBps { consolation_bps } => {
+            execute_set_consolation_bps(deps, env, info, consolation_bps)
+        }
+        ExecuteMsg::SetTrustedMinters { trusted_minters } => {
+            execute_set_trusted_minters(deps, env, info, trusted_minters)
+        }
+        ExecuteMsg::CancelAndUnwind { raffle_id } => {
+            execute_cancel_and_unwind(deps, env, info, raffle_id)
+        }
+        ExecuteMsg::AdminSettle { raffle_id } => execute_admin_settle(deps, env, info, raffle_id),
+        ExecuteMsg::SetNoisFeeSource { source } => {
+            execute_set_nois_fee_source(deps, env, info, source)
+        }
+        ExecuteMsg::SetRandomnessMode { mode } => {
+            execute_set_randomness_mode(deps, env, info, mode)
+        }
     }
 }
 
@@ -191,6 +349,9 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<QueryResponse> {
                 raffle_info: Some(raffle_info),
             })?
         }
+        QueryMsg::RaffleInfos { raffle_ids } => {
+            to_json_binary(&query_raffle_infos(deps, env, raffle_ids)?)?
+        }
         QueryMsg::AllRaffles {
             start_after,
             limit,
@@ -210,10 +371,79 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<QueryResponse> {
         QueryMsg::TicketNumber { owner, raffle_id } => {
             to_json_binary(&query_ticket_number(deps, env, raffle_id, owner)?)?
         }
+        QueryMsg::HasEntered { raffle_id, address } => {
+            to_json_binary(&query_has_entered(deps, raffle_id, address)?)?
+        }
+        QueryMsg::TicketOwnerAt {
+            raffle_id,
+            ticket_id,
+        } => to_json_binary(&query_ticket_owner_at(deps, raffle_id, ticket_id)?)?,
+        QueryMsg::TicketHolders {
+            raffle_id,
+            start_after,
+            limit,
+        } => to_json_binary(&query_ticket_holders(
+            deps,
+            env,
+            raffle_id,
+            start_after,
+            limit,
+        )?)?,
+        QueryMsg::CanBuy {
+            raffle_id,
+            buyer,
+            ticket_number,
+            proof,
+        } => to_json_binary(&query_can_buy(
+            deps,
+            env,
+            raffle_id,
+            buyer,
+            ticket_number,
+            proof,
+        )?)?,
+        QueryMsg::VerifyWinner { raffle_id } => {
+            to_json_binary(&query_verify_winner(deps, env, raffle_id)?)?
+        }
+        QueryMsg::PriceStats { denom } => to_json_binary(&query_price_stats(deps, env, denom)?)?,
+        QueryMsg::RandomnessStatus { raffle_id } => {
+            to_json_binary(&query_randomness_status(deps, raffle_id)?)?
+        }
+        QueryMsg::SaleTimeRemaining { raffle_id } => {
+            to_json_binary(&query_sale_time_remaining(deps, env, raffle_id)?)?
+        }
+        QueryMsg::RafflesByCollection {
+            address,
+            start_after,
+            limit,
+        } => to_json_binary(&query_raffles_by_collection(
+            deps,
+            env,
+            address,
+            start_after,
+            limit,
+        )?)?,
+        QueryMsg::CollectionParticipation { address } => {
+            to_json_binary(&query_collection_participation(deps, address)?)?
+        }
+        QueryMsg::AwaitingClaim { start_after, limit } => {
+            to_json_binary(&query_awaiting_claim(deps, env, start_after, limit)?)?
+        }
+        QueryMsg::RaffleRandomness { raffle_id } => {
+            to_json_binary(&query_raffle_randomness(deps, raffle_id)?)?
+        }
+        QueryMsg::PrizeValue { raffle_id } => {
+            to_json_binary(&query_prize_value(deps, raffle_id)?)?
+        }
+        QueryMsg::RaffleFees { raffle_id } => {
+            to_json_binary(&query_raffle_fees(deps, raffle_id)?)?
+        }
+        QueryMsg::TreasuryEarned {} => to_json_binary(&query_treasury_earned(deps)?)?,
     };
     Ok(response)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn execute_update_config(
     deps: DepsMut,
     _env: Env,
@@ -226,9 +456,13 @@ fn execute_update_config(
     creation_fee_denom: Option<String>,
     creation_fee_amount: Option<Uint128>,
     raffle_fee: Option<Decimal>,
+    rand_fee: Option<Decimal>,
     nois_proxy_addr: Option<String>,
     nois_proxy_denom: Option<String>,
     nois_proxy_amount: Option<Uint128>,
+    cancel_lockout_seconds: Option<u64>,
+    max_nois_proxy_amount: Option<Uint128>,
+    clear_max_nois_proxy_amount: bool,
 ) -> Result<Response, ContractError> {
     //TODO: let mut config
     let config = CONFIG.load(deps.storage)?;
@@ -236,6 +470,9 @@ fn execute_update_config(
     ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
     // TODO: check if new value is_valid_name
     let name = config.name;
+    // The previous addresses are logged when they change, for an on-chain audit trail
+    let previous_owner = config.owner.clone();
+    let previous_fee_addr = config.fee_addr.clone();
     let owner = match owner {
         Some(ow) => deps.api.addr_validate(&ow)?,
         None => config.owner,
@@ -256,15 +493,17 @@ fn execute_update_config(
         Some(rf) => rf,
         None => config.raffle_fee,
     };
-    // let rand_fee = match rand_fee {
-    //     Some(raf) => raf,
-    //     None => config.rand_fee,
-    // };
+    let rand_fee = match rand_fee {
+        Some(raf) if raf >= Decimal::one() => return Err(ContractError::InvalidRandFee {}),
+        Some(raf) => raf,
+        None => config.rand_fee,
+    };
     let nois_proxy_addr = match nois_proxy_addr {
         Some(prx) => deps.api.addr_validate(&prx)?,
         None => config.nois_proxy_addr,
     };
     let nois_proxy_denom = match nois_proxy_denom {
+        Some(npr) if npr.is_empty() => return Err(ContractError::InvalidProxyDenom {}),
         Some(npr) => npr,
         None => config.nois_proxy_denom,
     };
@@ -272,6 +511,24 @@ fn execute_update_config(
         Some(npa) => npa,
         None => config.nois_proxy_amount,
     };
+    let max_nois_proxy_amount = if clear_max_nois_proxy_amount {
+        None
+    } else {
+        max_nois_proxy_amount.or(config.max_nois_proxy_amount)
+    };
+    // A zero fee can't pay for the draws and a runaway one would drain the contract
+    if nois_proxy_amount.is_zero() {
+        return Err(ContractError::InvalidProxyAmount {});
+    }
+    if let Some(max) = max_nois_proxy_amount {
+        if nois_proxy_amount > max {
+            return Err(ContractError::ProxyAmountTooHigh { max });
+        }
+    }
+    let cancel_lockout_seconds = match cancel_lockout_seconds {
+        Some(cls) => cls,
+        None => config.cancel_lockout_seconds,
+    };
     let creation_fee_denom = match creation_fee_denom {
         Some(crf) => crf,
         None => config.creation_fee_denom,
@@ -285,46 +542,288 @@ fn execute_update_config(
     // we do not want to be able to manually update the last raffle id.
     let last_raffle_id = config.last_raffle_id;
 
-    CONFIG.save(
-        deps.storage,
-        &Config {
-            name,
-            owner,
-            fee_addr,
-            last_raffle_id,
-            minimum_raffle_duration,
-            minimum_raffle_timeout,
-            creation_fee_amount,
-            creation_fee_denom,
-            raffle_fee,
-            // rand_fee,
-            lock,
-            nois_proxy_addr,
-            nois_proxy_denom,
-            nois_proxy_amount,
-        },
-    )?;
+    let new_config = Config {
+        name,
+        owner,
+        fee_addr,
+        last_raffle_id,
+        minimum_raffle_duration,
+        minimum_raffle_timeout,
+        creation_fee_amount,
+        creation_fee_denom,
+        raffle_fee,
+        rand_fee,
+        lock,
+        full_pause: config.full_pause,
+        nois_proxy_addr,
+        nois_proxy_denom,
+        nois_proxy_amount,
+        job_id_prefix: config.job_id_prefix,
+        max_live_raffles: config.max_live_raffles,
+        cancel_lockout_seconds,
+        trusted_minters: config.trusted_minters,
+        max_nois_proxy_amount,
+        randomness_mode: config.randomness_mode,
+        consolation_bps: config.consolation_bps,
+        nois_fee_source: config.nois_fee_source,
+        min_assets_per_raffle: config.min_assets_per_raffle,
+        price_oracle: config.price_oracle,
+    };
+    new_config.validate_fee()?;
+    CONFIG.save(deps.storage, &new_config)?;
 
-    Ok(Response::new().add_attribute("action", "update_config"))
+    let mut res = Response::new().add_attribute("action", "update_config");
+    if new_config.owner != previous_owner {
+        res = res
+            .add_attribute("old_owner", previous_owner)
+            .add_attribute("new_owner", new_config.owner);
+    }
+    if new_config.fee_addr != previous_fee_addr {
+        res = res
+            .add_attribute("old_fee_addr", previous_fee_addr)
+            .add_attribute("new_fee_addr", new_config.fee_addr);
+    }
+    Ok(res)
 }
 
 /// Locking the contract (lock=true) means preventing the creation of new raffles
 /// Tickets can still be bought and NFTs retrieved when a contract is locked
+/// A full pause additionally blocks ticket purchases and randomness requests.
+/// Claims, cancellations and refunds stay available so that no funds are trapped during an emergency
 pub fn execute_toggle_lock(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
     lock: bool,
+    full_pause: Option<bool>,
 ) -> Result<Response, ContractError> {
     let mut config = CONFIG.load(deps.storage)?;
     // check the calling address is the authorised multisig
     ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
 
     config.lock = lock;
+    if let Some(full_pause) = full_pause {
+        config.full_pause = full_pause;
+    }
     CONFIG.save(deps.storage, &config)?;
 
     Ok(Response::new()
         .add_attribute("action", "modify_parameter")
         .add_attribute("parameter", "contract_lock")
-        .add_attribute("value", lock.to_string()))
+        .add_attribute("value", lock.to_string())
+        .add_attribute("full_pause", config.full_pause.to_string()))
+}
+
+/// Caps the number of live raffles, to bound the contract storage growth
+/// Lowering the cap below the current number of live raffles only prevents new raffles from being created
+pub fn execute_set_max_live_raffles(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    max_live_raffles: Option<u32>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+
+    config.max_live_raffles = max_live_raffles;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "modify_parameter")
+        .add_attribute("parameter", "max_live_raffles")
+        .add_attribute(
+            "value",
+            max_live_raffles.map_or_else(|| "none".to_string(), |max| max.to_string()),
+        ))
+}
+
+/// Sets the minimum number of assets of the new raffles
+/// The raffles that already exist are not affected
+pub fn execute_set_min_assets_per_raffle(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    min_assets_per_raffle: u32,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+
+    config.min_assets_per_raffle = min_assets_per_raffle;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "modify_parameter")
+        .add_attribute("parameter", "min_assets_per_raffle")
+        .add_attribute("value", min_assets_per_raffle.to_string()))
+}
+
+/// Sets the contract queried for the floor prices of the prize collections. None disables the prize valuation
+pub fn execute_set_price_oracle(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    price_oracle: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+
+    config.price_oracle = price_oracle
+        .map(|price_oracle| deps.api.addr_validate(&price_oracle))
+        .transpose()?;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "modify_parameter")
+        .add_attribute("parameter", "price_oracle")
+        .add_attribute(
+            "value",
+            config
+                .price_oracle
+                .map_or_else(|| "none".to_string(), |price_oracle| price_oracle.to_string()),
+        ))
+}
+
+/// Sets the number of ticket slots recorded for every ticket an address buys, for loyalty programs
+/// The address still pays for the tickets it asked for, only its odds change
+pub fn execute_set_ticket_multiplier(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    address: String,
+    multiplier: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+    let address = deps.api.addr_validate(&address)?;
+
+    match multiplier {
+        Some(multiplier) => {
+            if multiplier == 0 || multiplier > MAX_TICKET_MULTIPLIER {
+                return Err(ContractError::InvalidTicketMultiplier {
+                    max: MAX_TICKET_MULTIPLIER,
+                });
+            }
+            TICKET_MULTIPLIERS.save(deps.storage, &address, &multiplier)?;
+        }
+        None => TICKET_MULTIPLIERS.remove(deps.storage, &address),
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "modify_parameter")
+        .add_attribute("parameter", "ticket_multiplier")
+        .add_attribute("address", address)
+        .add_attribute(
+            "value",
+            multiplier.map_or_else(|| "none".to_string(), |multiplier| multiplier.to_string()),
+        ))
+}
+
+/// Sets the share of the treasury cut split between the buyers who didn't win, when a raffle is claimed
+/// The raffle owners are not affected, the consolation only comes out of the treasury cut
+pub fn execute_set_consolation_bps(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    consolation_bps: Option<u16>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+    if consolation_bps.unwrap_or_default() > MAX_BPS {
+        return Err(ContractError::InvalidConsolationBps {});
+    }
+
+    config.consolation_bps = consolation_bps;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "modify_parameter")
+        .add_attribute("parameter", "consolation_bps")
+        .add_attribute(
+            "value",
+            consolation_bps.map_or_else(|| "none".to_string(), |bps| bps.to_string()),
+        ))
+}
+
+/// Sets who pays the nois fee of the randomness requests
+/// Requiring it from the caller keeps the contract balance from being drained by the requests
+pub fn execute_set_nois_fee_source(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    source: NoisFeeSource,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+
+    let value = match source {
+        NoisFeeSource::Contract => "contract",
+        NoisFeeSource::Caller => "caller",
+    };
+    config.nois_fee_source = source;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "modify_parameter")
+        .add_attribute("parameter", "nois_fee_source")
+        .add_attribute("value", value))
+}
+
+/// Switches between nois and drand randomness
+/// Raffles that already requested their nois randomness keep waiting for the nois beacon
+pub fn execute_set_randomness_mode(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    mode: RandomnessModeMsg,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+
+    let (randomness_mode, value) = match mode {
+        RandomnessModeMsg::Nois => (RandomnessMode::Nois, "nois".to_string()),
+        RandomnessModeMsg::DrandVerifier { contract, pubkey } => (
+            RandomnessMode::DrandVerifier {
+                contract: deps.api.addr_validate(&contract)?,
+                pubkey,
+            },
+            format!("drand:{contract}"),
+        ),
+    };
+    config.randomness_mode = randomness_mode;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "modify_parameter")
+        .add_attribute("parameter", "randomness_mode")
+        .add_attribute("value", value))
+}
+
+/// Sets the addresses whose raffles skip the per-token ownership queries at creation
+/// This saves gas on large bundles of freshly minted NFTs, those addresses are trusted to only raffle NFTs they own
+pub fn execute_set_trusted_minters(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    trusted_minters: Vec<String>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+
+    config.trusted_minters = trusted_minters
+        .iter()
+        .map(|minter| deps.api.addr_validate(minter))
+        .collect::<StdResult<Vec<Addr>>>()?;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "modify_parameter")
+        .add_attribute("parameter", "trusted_minters")
+        .add_attribute(
+            "value",
+            if trusted_minters.is_empty() {
+                "none".to_string()
+            } else {
+                trusted_minters.join(",")
+            },
+        ))
 }
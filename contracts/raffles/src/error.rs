@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-use cosmwasm_std::{StdError, Timestamp, Coin};
+use cosmwasm_std::{Coin, StdError, Timestamp, Uint128};
 use utils::state::AssetInfo;
 
 use crate::state::RaffleState;
@@ -16,6 +16,24 @@ pub enum ContractError {
     #[error("Proxy address is not valid")]
     InvalidProxyAddress,
 
+    #[error("The nois proxy payment amount must be greater than zero")]
+    InvalidProxyAmount {},
+
+    #[error("The randomness provider fee rate should be lower than 1")]
+    InvalidRandFee {},
+
+    #[error("The consolation can't exceed 10000 basis points")]
+    InvalidConsolationBps {},
+
+    #[error("The nois fee ({fee}) has to be sent along with the randomness request")]
+    NoisFeeRequired { fee: Coin },
+
+    #[error("The nois proxy payment amount can't be above {max}")]
+    ProxyAmountTooHigh { max: Uint128 },
+
+    #[error("The nois proxy payment denom can't be empty")]
+    InvalidProxyDenom {},
+
     #[error("Merkle is immutable.")]
     MerkleImmutable,
 
@@ -62,9 +80,30 @@ pub enum ContractError {
     #[error("Received invalid randomness")]
     InvalidRandomness,
 
+    #[error("The buyer isn't part of the raffle allowlist")]
+    InvalidAllowlistProof {},
+
     #[error("Immutable Randomness")]
     ImmutableRandomness,
 
+    #[error("Randomness was already requested for this raffle")]
+    RandomnessAlreadyRequested {},
+
+    #[error("This randomness source is not the one the contract is set to use")]
+    WrongRandomnessMode {},
+
+    #[error("Only the drand round {expected} can be used for this raffle")]
+    WrongDrandRound { expected: u64 },
+
+    #[error("No randomness was requested for this raffle")]
+    RandomnessNotRequested {},
+
+    #[error("The job id {0} doesn't correspond to any raffle")]
+    InvalidJobId(String),
+
+    #[error("The job id prefix can't be longer than {max} characters")]
+    JobIdPrefixTooLong { max: usize },
+
     #[error("Unreachable code, something weird happened")]
     Unreachable {},
 
@@ -83,6 +122,9 @@ pub enum ContractError {
     #[error("This action is not allowed, the contract is locked")]
     ContractIsLocked {},
 
+    #[error("This action is not allowed, the contract is paused")]
+    ContractIsPaused {},
+
     #[error("Key already exists in RaffleInfo")]
     ExistsInRaffleInfo {},
 
@@ -92,18 +134,51 @@ pub enum ContractError {
     #[error("You can't buy tickets on this raffle anymore")]
     CantBuyTickets {},
 
+    #[error("You can't buy tickets on this raffle yet, it hasn't started")]
+    RaffleNotStarted {},
+
+    #[error("The preview index {index} is out of range, the raffle only has {assets_len} assets")]
+    InvalidPreviewIndex { index: u32, assets_len: usize },
+
+    #[error("The prize bundles have to contain each raffle asset exactly once")]
+    InvalidPrizeBundles {},
+
     #[error("A raffle can only be done with CW721 or SG721 assets")]
     WrongAssetType {},
 
+    #[error("The asset at index {index} can't be raffled, only CW721 and SG721 tokens are supported")]
+    UnsupportedPrizeType { index: usize },
+
+    #[error("Couldn't create the transfer message for the asset at index {index} : {error}")]
+    PrizeTransferMessage { index: usize, error: StdError },
+
+    #[error("The prize at index {index} is no longer held by the raffle contract, it can't be sent to the winner")]
+    PrizeNoLongerHeld { index: usize },
+
     #[error("Tickets to a raffle can only be bought with native assets.")]
     WrongFundsType {},
 
     #[error("The sent asset doesn't match the asset in the message sent along with it")]
     AssetMismatch {},
 
+    #[error("There can't be more than {max} live raffles at the same time")]
+    TooManyLiveRaffles { max: u32 },
+
     #[error("Please include at least one asset when creating a raffle")]
     NoAssets {},
 
+    #[error("A raffle needs at least {min} assets")]
+    TooFewAssets { min: u32 },
+
+    #[error("The asset at index {index} is already part of this raffle")]
+    DuplicateAsset { index: usize },
+
+    #[error("The acceptable prices of a raffle have to be native coins, each in its own denom")]
+    InvalidAcceptablePrices {},
+
+    #[error("Your tickets on this raffle are paid with {price}, the next ones have to be too")]
+    BuyerPriceMismatch { price: Coin },
+
     #[error("The sent assets ({assets_received:?}) don't match the required assets ({assets_wanted:?}) for this raffle")]
     PaymentNotSufficient {
         assets_wanted: AssetInfo,
@@ -124,21 +199,54 @@ pub enum ContractError {
         nb_after: u32,
     },
 
+    #[error("Too much tickets in a single purchase. Max : {max:?}, Number purchased : {nb:?}")]
+    TooMuchTicketsInTx { max: u32, nb: u32 },
+
+    #[error("Wait until {until} before buying tickets again")]
+    PurchaseCooldownActive { until: Timestamp },
+
+    #[error("Tickets of this raffle can't be refunded anymore")]
+    RefundWindowClosed {},
+
+    #[error("Only {owned} tickets can be refunded")]
+    NotEnoughTicketsToRefund { owned: u32 },
+
+    #[error("A ticket multiplier must be between 1 and {max}")]
+    InvalidTicketMultiplier { max: u32 },
+
+    #[error("This purchase would overflow the ticket count")]
+    TicketOverflow {},
+
     #[error("The provided randomness is invalid current round : {current_round:?}")]
     RandomnessNotAccepted { current_round: u64 },
 
     #[error("This raffle is not ready to accept new randomness. Only Closed raffles can be decided upon. Current status : {status:?}")]
     WrongStateForRandmness { status: RaffleState },
 
+    #[error("This raffle is already being claimed")]
+    ClaimInProgress {},
+
+    #[error("There is no consolation for you to withdraw on this raffle")]
+    NoConsolation {},
+
     #[error("This raffle is not ready to be claimed.  Current status : {status:?}")]
     WrongStateForClaim { status: RaffleState },
 
     #[error("This raffle cannot be cancelled anymore,   Current status : {status:?}")]
     WrongStateForCancel { status: RaffleState },
 
+    #[error("This raffle can only be settled once its randomness timed out. Current status : {status:?}")]
+    WrongStateForSettle { status: RaffleState },
+
+    #[error("This raffle can't be cancelled this close to the end of its sale, at {sale_end}")]
+    CancelLockout { sale_end: Timestamp },
+
     #[error("This raffle has already started.")]
     RaffleAlreadyStarted {},
 
+    #[error("The ticket price can't be changed to another asset type")]
+    CannotChangePriceAssetType {},
+
     #[error("The public key you indicated is invalid")]
     InvalidPubkey {},
 
@@ -1,16 +1,19 @@
-use cosmwasm_std::{Addr, DepsMut, Empty, Env, MessageInfo, StdError, StdResult, ensure_eq, Uint128, from_json};
+use std::mem::discriminant;
+
+use cosmwasm_std::{Addr, BankMsg, Deps, DepsMut, Empty, Env, MessageInfo, Order, StdResult, Storage, ensure_eq, Uint128, coin, Coin, from_json};
 use cw721::{Cw721ExecuteMsg, Cw721ReceiveMsg};
 use cw721_base::Extension;
 use nois::NoisCallback;
 use sg721::ExecuteMsg as Sg721ExecuteMsg;
 use sg_std::{CosmosMsg, StargazeMsgWrapper};
-use utils::state::{AssetInfo, Cw721Coin, Sg721Token, into_cosmos_msg};
+use sha2::{Digest, Sha256};
+use utils::state::{AssetInfo, Cw721Coin, Sg721Token, find_duplicate_nft, into_cosmos_msg};
 
 use crate::{
     error::ContractError,
-    msg::ExecuteMsg,
-    query::is_nft_owner,
-    state::{ RaffleInfo, RaffleOptions, RaffleOptionsMsg, CONFIG, RAFFLE_INFO, RaffleState, get_raffle_state, USER_TICKETS, RAFFLE_TICKETS, NOIS_RANDOMNESS, RandomnessParams}, utils::{get_raffle_winner_messages, get_raffle_owner_finished_messages, get_raffle_winner, get_nois_randomness, can_buy_ticket, ticket_cost, is_raffle_owner, get_raffle_owner_messages},
+    msg::{DrandRandomness, ExecuteMsg, VerifierQueryMsg, VerifyResponse},
+    query::{is_nft_owner, is_sg721_owner},
+    state::{ RaffleInfo, RaffleOptions, RaffleOptionsMsg, CONFIG, RAFFLE_INFO, RaffleState, get_raffle_state, RAFFLE_TICKETS, RandomnessParams, RaffleFeeParams, USER_TICKETS, try_auto_extend, increment_live_raffles, decrement_live_raffles, validate_prize_bundles, CLAIMING, COLLECTION_RAFFLES, RandomnessMode, NoisFeeSource, drand_round_after, PAID_TICKETS, LAST_PURCHASE, BUYER_PRICES, PRICE_TICKETS_PAID, validate_acceptable_prices, TICKET_HOLDERS, CONSOLATIONS, CONSOLATIONS_WITHDRAWN, CONSOLATIONS_OWED}, utils::{get_raffle_winner_messages, get_raffle_owner_finished_messages, get_raffle_winner, get_raffle_bundle_winners, get_nois_randomness, check_ticket_purchase, ticket_cost, is_raffle_owner, get_raffle_owner_messages, raffle_id_from_job_id, get_ticket_refund_messages, raffle_sales, add_user_tickets, remove_user_tickets},
 };
 
 pub type Response = cosmwasm_std::Response<StargazeMsgWrapper>;
@@ -27,6 +30,9 @@ pub fn execute_create_raffle(
 ) -> Result<Response, ContractError> {
     let contract_info = CONFIG.load(deps.storage)?;
 
+    if contract_info.full_pause {
+        return Err(ContractError::ContractIsPaused {});
+    }
     if contract_info.lock {
         return Err(ContractError::ContractIsLocked {});
     }
@@ -37,20 +43,35 @@ pub fn execute_create_raffle(
     if all_assets.is_empty() {
         return Err(ContractError::NoAssets {});
     }
+    if all_assets.len() < contract_info.min_assets_per_raffle as usize {
+        return Err(ContractError::TooFewAssets {
+            min: contract_info.min_assets_per_raffle,
+        });
+    }
+    // The same NFT can't be transferred twice
+    if let Some(index) = find_duplicate_nft(&all_assets) {
+        return Err(ContractError::DuplicateAsset { index });
+    }
+
+    // Trusted minters skip the ownership queries, they are trusted to only raffle NFTs they own
+    let trusted_minter = contract_info.trusted_minters.contains(&info.sender);
 
     // Then we physcially transfer all the assets
     let transfer_messages: Vec<CosmosMsg> = all_assets
         .iter()
-        .map(|asset| match &asset {
+        .enumerate()
+        .map(|(index, asset)| match &asset {
             AssetInfo::Cw721Coin(token) => {
                 // Before the transfer, verify current NFT owner
                 // Otherwise, this would cause anyone to be able to create loans in the name of the owner if a bad approval was done
-                is_nft_owner(
-                    deps.as_ref(),
-                    info.sender.clone(),
-                    token.address.to_string(),
-                    token.token_id.to_string(),
-                )?;
+                if !trusted_minter {
+                    is_nft_owner(
+                        deps.as_ref(),
+                        info.sender.clone(),
+                        token.address.to_string(),
+                        token.token_id.to_string(),
+                    )?;
+                }
 
                 let message = Cw721ExecuteMsg::TransferNft {
                     recipient: env.contract.address.clone().into(),
@@ -58,14 +79,17 @@ pub fn execute_create_raffle(
                 };
 
                 into_cosmos_msg(message, token.address.clone(),None,)
+                    .map_err(|error| ContractError::PrizeTransferMessage { index, error })
             }
             AssetInfo::Sg721Token(token) => {
-                is_nft_owner(
-                    deps.as_ref(),
-                    info.sender.clone(),
-                    token.address.to_string(),
-                    token.token_id.to_string(),
-                )?;
+                if !trusted_minter {
+                    is_sg721_owner(
+                        deps.as_ref(),
+                        info.sender.clone(),
+                        token.address.to_string(),
+                        token.token_id.to_string(),
+                    )?;
+                }
 
                 let message = Sg721ExecuteMsg::<Extension, Empty>::TransferNft {
                     recipient: env.contract.address.clone().into(),
@@ -73,12 +97,11 @@ pub fn execute_create_raffle(
                 };
 
                 into_cosmos_msg(message, token.address.clone(),None,)
+                    .map_err(|error| ContractError::PrizeTransferMessage { index, error })
             }
-            _ => Err(StdError::generic_err(
-                "Error generating transfer_messages: Vec<CosmosMsg>",
-            )),
+            _ => Err(ContractError::UnsupportedPrizeType { index }),
         })
-        .collect::<Result<Vec<CosmosMsg>, StdError>>()?;
+        .collect::<Result<Vec<CosmosMsg>, ContractError>>()?;
     // Then we create the internal raffle structure
     let owner = owner.map(|x| deps.api.addr_validate(&x)).transpose()?;
     let raffle_id = _create_raffle(
@@ -106,6 +129,11 @@ pub fn _create_raffle(
     raffle_options: RaffleOptionsMsg,
 ) -> Result<u64, ContractError> {
     let contract_info = CONFIG.load(deps.storage)?;
+    validate_acceptable_prices(
+        &raffle_ticket_price,
+        raffle_options.acceptable_prices.as_deref().unwrap_or_default(),
+    )?;
+    increment_live_raffles(deps.storage, &contract_info)?;
 
     // We start by creating a new trade_id (simply incremented from the last id)
     let raffle_id: u64 = CONFIG
@@ -116,6 +144,16 @@ pub fn _create_raffle(
         .last_raffle_id
         .unwrap(); // This is safe because of the function architecture just there
 
+    for asset in &all_assets {
+        match asset {
+            AssetInfo::Cw721Coin(Cw721Coin { address, .. })
+            | AssetInfo::Sg721Token(Sg721Token { address, .. }) => {
+                COLLECTION_RAFFLES.save(deps.storage, (address, raffle_id), &true)?
+            }
+            AssetInfo::Coin(_) => {}
+        }
+    }
+
     RAFFLE_INFO.update(deps.storage, raffle_id, |trade| match trade {
         // If the trade id already exists, the contract is faulty
         // Or an external error happened, or whatever...
@@ -127,15 +165,18 @@ pub fn _create_raffle(
             assets: all_assets.clone(),
             raffle_ticket_price: raffle_ticket_price.clone(), // No checks for the assetInfo type, the worst thing that can happen is an error when trying to buy a raffle ticket
             number_of_tickets: 0u32,
+            tickets_paid: 0u32,
             randomness: None,
             winner: None,
+            bundle_winners: vec![],
             is_cancelled: false,
+            fee_params: RaffleFeeParams::from_config(&contract_info),
             raffle_options: RaffleOptions::new(
                 env,
                 all_assets.len(),
                 raffle_options,
                 contract_info,
-            ),
+            )?,
         }),
     })?;
     Ok(raffle_id)
@@ -170,9 +211,22 @@ pub fn execute_cancel_raffle(
         return Err(ContractError::RaffleAlreadyStarted {});
     }
 
+    // Close to the end of the sale, buyers may already count on the raffle happening
+    let sale_end = raffle_info
+        .raffle_options
+        .raffle_start_timestamp
+        .plus_seconds(raffle_info.raffle_options.raffle_duration);
+    let contract_info = CONFIG.load(deps.storage)?;
+    if env.block.time < sale_end
+        && env.block.time.plus_seconds(contract_info.cancel_lockout_seconds) >= sale_end
+    {
+        return Err(ContractError::CancelLockout { sale_end });
+    }
+
     // Then notify the raffle is ended
     raffle_info.is_cancelled = true;
     RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
+    decrement_live_raffles(deps.storage)?;
 
     // Then we transfer the assets back to the owner
     let transfer_messages = get_raffle_owner_messages(env, raffle_info)?;
@@ -182,6 +236,126 @@ pub fn execute_cancel_raffle(
         .add_attribute("raffle_id", raffle_id.to_string()))
 }
 
+/// Cancels a raffle and unwinds everything that happened on it in a single transaction
+/// Admin only. Ticket buyers are refunded, the raffled assets go back to the raffle owner
+/// and the nois fee is given back to whoever paid for the randomness request.
+/// This is not possible anymore once a winner was drawn
+pub fn execute_cancel_and_unwind(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    raffle_id: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+    let mut raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
+
+    // The raffle can only be unwound if it wasn't previously cancelled and no winner was drawn
+    let raffle_state = get_raffle_state(env.clone(), raffle_info.clone());
+    if raffle_state == RaffleState::Claimed
+        || raffle_state == RaffleState::Cancelled
+        || raffle_info.winner.is_some()
+    {
+        return Err(ContractError::WrongStateForCancel {
+            status: raffle_state,
+        });
+    }
+
+    raffle_info.is_cancelled = true;
+    Ok(_unwind_raffle(deps.storage, env, raffle_id, raffle_info)?
+        .add_attribute("action", "cancel_and_unwind")
+        .add_attribute("raffle_id", raffle_id.to_string()))
+}
+
+/// Settles a raffle whose randomness never arrived before its timeout
+/// Admin only. If the randomness eventually arrived, the raffle is claimed as usual.
+/// Otherwise, the raffle is cancelled and unwound: buyers are refunded and the assets go back to the raffle owner
+pub fn execute_admin_settle(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    raffle_id: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+    let mut raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
+
+    let raffle_state = get_raffle_state(env.clone(), raffle_info.clone());
+    let timeout = raffle_info
+        .raffle_options
+        .raffle_start_timestamp
+        .plus_seconds(raffle_info.raffle_options.raffle_duration)
+        .plus_seconds(raffle_info.raffle_options.raffle_timeout);
+    match raffle_state {
+        RaffleState::Finished => {
+            let (raffle_info, no_participants) =
+                _claim_raffle(deps.branch(), env.clone(), raffle_id)?;
+            _claim_response(deps.storage, env, raffle_id, raffle_info, no_participants)
+        }
+        RaffleState::Closed if env.block.time >= timeout => {
+            raffle_info.is_cancelled = true;
+            Ok(_unwind_raffle(deps.storage, env, raffle_id, raffle_info)?
+                .add_attribute("action", "admin_settle")
+                .add_attribute("raffle_id", raffle_id.to_string())
+                .add_attribute("outcome", "refunded"))
+        }
+        _ => Err(ContractError::WrongStateForSettle {
+            status: raffle_state,
+        }),
+    }
+}
+
+/// Saves a cancelled raffle and prepares the messages unwinding it
+/// Ticket buyers are refunded, the raffled assets go back to the raffle owner
+/// The nois fee is only given back to whoever paid for it while the contract still holds it.
+/// Once the randomness request was sent, the fee belongs to the proxy and is recorded as lost
+fn _unwind_raffle(
+    storage: &mut dyn Storage,
+    env: Env,
+    raffle_id: u64,
+    raffle_info: RaffleInfo,
+) -> Result<Response, ContractError> {
+    RAFFLE_INFO.save(storage, raffle_id, &raffle_info)?;
+    decrement_live_raffles(storage)?;
+
+    // We refund the ticket buyers
+    let refund_messages = get_ticket_refund_messages(storage, raffle_id, &raffle_info)?;
+
+    // We refund the nois fee if it wasn't paid by the contract and wasn't forwarded to the proxy yet
+    let mut nois_refund_messages: Vec<CosmosMsg> = vec![];
+    let mut lost_nois_fee = None;
+    if let Some(RandomnessParams {
+        nois_fee: Some(nois_fee),
+        nois_fee_payer,
+        requested,
+        ..
+    }) = raffle_info.randomness.clone()
+    {
+        match (nois_fee_payer, requested) {
+            (_, true) => lost_nois_fee = Some(nois_fee),
+            (Some(payer), false) => nois_refund_messages.push(
+                BankMsg::Send {
+                    to_address: payer.to_string(),
+                    amount: vec![nois_fee],
+                }
+                .into(),
+            ),
+            (None, false) => {}
+        }
+    }
+
+    // Then we transfer the assets back to the owner
+    let transfer_messages = get_raffle_owner_messages(env, raffle_info)?;
+    let mut res = Response::new()
+        .add_messages(refund_messages)
+        .add_messages(transfer_messages)
+        .add_messages(nois_refund_messages);
+    if let Some(lost_nois_fee) = lost_nois_fee {
+        res = res.add_attribute("nois_fee_lost", lost_nois_fee.to_string());
+    }
+    Ok(res)
+}
+
 /// Modify the raffle characteristics
 /// A parameter is only modified if it is specified in the called message
 /// If None is provided, nothing changes for the parameter
@@ -200,6 +374,9 @@ pub fn execute_modify_raffle(
     if raffle_info.number_of_tickets != 0 {
         return Err(ContractError::RaffleAlreadyStarted {});
     }
+    if let Some(prize_bundles) = &raffle_options.prize_bundles {
+        validate_prize_bundles(prize_bundles, raffle_info.assets.len())?;
+    }
 
     // Then modify the raffle characteristics
     raffle_info.raffle_options = RaffleOptions::new_from(
@@ -208,10 +385,17 @@ pub fn execute_modify_raffle(
         raffle_options,
         contract_info,
     );
-    // Then modify the ticket price
+    // Then modify the ticket price, only its amount or denom can change
     if let Some(raffle_ticket_price) = raffle_ticket_price {
+        if discriminant(&raffle_ticket_price) != discriminant(&raffle_info.raffle_ticket_price) {
+            return Err(ContractError::CannotChangePriceAssetType {});
+        }
         raffle_info.raffle_ticket_price = raffle_ticket_price;
     }
+    validate_acceptable_prices(
+        &raffle_info.raffle_ticket_price,
+        &raffle_info.raffle_options.acceptable_prices,
+    )?;
     RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
 
     Ok(Response::new()
@@ -219,6 +403,100 @@ pub fn execute_modify_raffle(
         .add_attribute("raffle_id", raffle_id.to_string()))
 }
 
+/// Refunds `ticket_number` tickets of the sender on a raffle
+/// This is only possible while the raffle is selling tickets and before its `refundable_until` time.
+/// The refunded tickets are replaced by the last tickets of the raffle, so that ticket ids stay contiguous for the draw
+pub fn execute_refund_tickets(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    raffle_id: u64,
+    ticket_number: u32,
+) -> Result<Response, ContractError> {
+    let mut raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
+    let refundable = match raffle_info.raffle_options.refundable_until {
+        Some(refundable_until) => {
+            env.block.time < refundable_until
+                && get_raffle_state(env.clone(), raffle_info.clone()) == RaffleState::Started
+        }
+        None => false,
+    };
+    if !refundable {
+        return Err(ContractError::RefundWindowClosed {});
+    }
+    let owned_slots = USER_TICKETS
+        .may_load(deps.storage, (&info.sender, raffle_id))?
+        .unwrap_or(0);
+    let owned = PAID_TICKETS
+        .may_load(deps.storage, (raffle_id, &info.sender))?
+        .unwrap_or(0);
+    if ticket_number == 0 || ticket_number > owned {
+        return Err(ContractError::NotEnoughTicketsToRefund { owned });
+    }
+    // Weighted buyers give back the extra slots of the tickets they get refunded
+    let ticket_slots = (owned_slots as u64 * ticket_number as u64 / owned as u64) as u32;
+
+    for _ in 0..ticket_slots {
+        // We take the last ticket of the sender and move the last ticket of the raffle in its place
+        let ticket_id = RAFFLE_TICKETS
+            .prefix(raffle_id)
+            .range(deps.storage, None, None, Order::Descending)
+            .find_map(|ticket| match ticket {
+                Ok((ticket_id, owner)) if owner == info.sender => Some(Ok(ticket_id)),
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            })
+            .ok_or(ContractError::ContractBug {})??;
+        let last_ticket_id = raffle_info.number_of_tickets - 1;
+        if ticket_id != last_ticket_id {
+            let last_owner = RAFFLE_TICKETS.load(deps.storage, (raffle_id, last_ticket_id))?;
+            RAFFLE_TICKETS.save(deps.storage, (raffle_id, ticket_id), &last_owner)?;
+        }
+        RAFFLE_TICKETS.remove(deps.storage, (raffle_id, last_ticket_id));
+        raffle_info.number_of_tickets = last_ticket_id;
+    }
+    remove_user_tickets(deps.storage, &info.sender, raffle_id, ticket_slots)?;
+    // The tickets are refunded with the price they were paid with
+    let ticket_price = BUYER_PRICES.load(deps.storage, (raffle_id, &info.sender))?;
+    // Acceptable prices all have their own denom, apart from the raffle ticket price one
+    let paid_with_raffle_price = matches!(
+        &raffle_info.raffle_ticket_price,
+        AssetInfo::Coin(price) if price.denom == ticket_price.denom
+    );
+    if !paid_with_raffle_price {
+        PRICE_TICKETS_PAID.update(
+            deps.storage,
+            (raffle_id, &ticket_price.denom),
+            |tickets| -> StdResult<_> { Ok(tickets.unwrap_or_default() - ticket_number) },
+        )?;
+    }
+    if owned == ticket_number {
+        PAID_TICKETS.remove(deps.storage, (raffle_id, &info.sender));
+        BUYER_PRICES.remove(deps.storage, (raffle_id, &info.sender));
+    } else {
+        PAID_TICKETS.save(deps.storage, (raffle_id, &info.sender), &(owned - ticket_number))?;
+    }
+    raffle_info.tickets_paid -= ticket_number;
+    RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
+
+    let refund = match ticket_cost(AssetInfo::Coin(ticket_price), ticket_number)? {
+        AssetInfo::Coin(refund) => refund,
+        _ => return Err(ContractError::WrongFundsType {}),
+    };
+    let mut res = Response::new()
+        .add_attribute("action", "refund_ticket")
+        .add_attribute("raffle_id", raffle_id.to_string())
+        .add_attribute("owner", info.sender.clone())
+        .add_attribute("ticket_number", ticket_number.to_string());
+    if !refund.amount.is_zero() {
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![refund],
+        });
+    }
+    Ok(res)
+}
+
 /// Buy a ticket for a specific raffle.
 ///
 /// `raffle_id`: The id of the raffle you want to buy a ticket to/
@@ -229,13 +507,25 @@ pub fn execute_modify_raffle(
 /// This function needs the sender to approve token transfer (for CW20 tokens) priori to the transaction
 /// The next function provides a receiver message implementation if you prefer
 pub fn execute_buy_tickets(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     raffle_id: u64,
     ticket_number: u32,
     assets: AssetInfo,
+    proof: Option<Vec<String>>,
 ) -> Result<Response, ContractError> {
+    // The first purchase attempt after the sale ended may request the randomness instead
+    // No ticket is bought in that case, the funds sent along are refunded
+    if let Some(mut res) = _auto_request_randomness(deps.branch(), &env, raffle_id)? {
+        if !info.funds.is_empty() {
+            res = res.add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: info.funds,
+            });
+        }
+        return Ok(res);
+    }
     // First we physcially transfer the AssetInfo
     let transfer_messages = match &assets {
         AssetInfo::Cw721Coin(token) => {
@@ -274,6 +564,7 @@ pub fn execute_buy_tickets(
         raffle_id,
         ticket_number,
         assets,
+        proof,
     )?;
 
     Ok(Response::new()
@@ -293,47 +584,53 @@ pub fn _buy_tickets(
     raffle_id: u64,
     ticket_number: u32,
     assets: AssetInfo,
+    proof: Option<Vec<String>>,
 ) -> Result<(), ContractError> {
     let mut raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
 
-    // We first check the sent assets match the raffle assets
-    if ticket_cost(raffle_info.clone(), ticket_number)? != assets {
-        return Err(ContractError::PaymentNotSufficient {
-            assets_wanted: raffle_info.raffle_ticket_price,
-            assets_received: assets,
-        });
-    }
-
-    // We then check the raffle is in the right state
-    can_buy_ticket(env, raffle_info.clone())?;
+    // We check the contract, the raffle state, the allowlist and the ticket limits
+    let ticket_slots = check_ticket_purchase(
+        deps.storage,
+        env.clone(),
+        raffle_id,
+        &raffle_info,
+        &owner,
+        ticket_number,
+        proof,
+    )?;
 
-    // Then we check the user has the right to buy `ticket_number` more tickets
-    if let Some(max_ticket_per_address) = raffle_info.raffle_options.max_ticket_per_address {
-        let current_ticket_number = USER_TICKETS
-            .load(deps.storage, (&owner, raffle_id))
-            .unwrap_or(0);
-        if current_ticket_number + ticket_number > max_ticket_per_address {
-            return Err(ContractError::TooMuchTicketsForUser {
-                max: max_ticket_per_address,
-                nb_before: current_ticket_number,
-                nb_after: current_ticket_number + ticket_number,
-            });
+    // We first check the sent assets match the raffle ticket price or one of its acceptable prices
+    let mut ticket_price = None;
+    for price in std::iter::once(&raffle_info.raffle_ticket_price)
+        .chain(&raffle_info.raffle_options.acceptable_prices)
+    {
+        if ticket_cost(price.clone(), ticket_number)? == assets {
+            ticket_price = Some(price.clone());
+            break;
         }
     }
-
-    // Then we check there are some ticket left to buy
-    if let Some(max_participant_number) = raffle_info.raffle_options.max_participant_number {
-        if raffle_info.number_of_tickets + ticket_number > max_participant_number {
-            return Err(ContractError::TooMuchTickets {
-                max: max_participant_number,
-                nb_before: raffle_info.number_of_tickets,
-                nb_after: raffle_info.number_of_tickets + ticket_number,
-            });
+    let ticket_price = match ticket_price {
+        Some(AssetInfo::Coin(ticket_price)) => ticket_price,
+        _ => {
+            return Err(ContractError::PaymentNotSufficient {
+                assets_wanted: ticket_cost(raffle_info.raffle_ticket_price, ticket_number)?,
+                assets_received: assets,
+            })
         }
     };
+    // A buyer keeps paying with the same price, so that their tickets can be refunded in a single denom
+    match BUYER_PRICES.may_load(deps.storage, (raffle_id, &owner))? {
+        Some(previous_price) if previous_price != ticket_price => {
+            return Err(ContractError::BuyerPriceMismatch {
+                price: previous_price,
+            })
+        }
+        Some(_) => {}
+        None => BUYER_PRICES.save(deps.storage, (raffle_id, &owner), &ticket_price)?,
+    }
 
     // Then we save the sender to the bought tickets
-    for n in 0..ticket_number {
+    for n in 0..ticket_slots {
         RAFFLE_TICKETS.save(
             deps.storage,
             (raffle_id, raffle_info.number_of_tickets + n),
@@ -341,11 +638,39 @@ pub fn _buy_tickets(
         )?;
     }
 
-    USER_TICKETS.update::<_, ContractError>(deps.storage, (&owner, raffle_id), |x| match x {
-        Some(current_ticket_number) => Ok(current_ticket_number + ticket_number),
-        None => Ok(ticket_number),
-    })?;
-    raffle_info.number_of_tickets += ticket_number;
+    add_user_tickets(deps.storage, &owner, raffle_id, ticket_slots)?;
+    raffle_info.number_of_tickets = raffle_info
+        .number_of_tickets
+        .checked_add(ticket_slots)
+        .ok_or(ContractError::TicketOverflow {})?;
+
+    // The payments are tracked apart from the slots, they are what the refunds and the proceeds are based on
+    let paid_tickets = PAID_TICKETS
+        .may_load(deps.storage, (raffle_id, &owner))?
+        .unwrap_or(0)
+        .checked_add(ticket_number)
+        .ok_or(ContractError::TicketOverflow {})?;
+    PAID_TICKETS.save(deps.storage, (raffle_id, &owner), &paid_tickets)?;
+    raffle_info.tickets_paid = raffle_info
+        .tickets_paid
+        .checked_add(ticket_number)
+        .ok_or(ContractError::TicketOverflow {})?;
+    if AssetInfo::Coin(ticket_price.clone()) != raffle_info.raffle_ticket_price {
+        PRICE_TICKETS_PAID.update(
+            deps.storage,
+            (raffle_id, &ticket_price.denom),
+            |tickets| {
+                tickets
+                    .unwrap_or_default()
+                    .checked_add(ticket_number)
+                    .ok_or(ContractError::TicketOverflow {})
+            },
+        )?;
+    }
+
+    if raffle_info.raffle_options.purchase_cooldown_seconds.is_some() {
+        LAST_PURCHASE.save(deps.storage, (raffle_id, &owner), &env.block.time)?;
+    }
 
     RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
 
@@ -364,6 +689,7 @@ pub fn execute_receive(
             raffle_id,
             ticket_number,
             sent_assets,
+            proof,
         } => {
             // First we make sure the received Asset is the one specified in the message
             match sent_assets.clone() {
@@ -380,6 +706,7 @@ pub fn execute_receive(
                             raffle_id,
                             ticket_number,
                             sent_assets,
+                            proof,
                         )?;
 
                         Ok(Response::new()
@@ -403,6 +730,7 @@ pub fn execute_receive(
                             raffle_id,
                             ticket_number,
                             sent_assets,
+                            proof,
                         )?;
 
                         Ok(Response::new()
@@ -427,10 +755,6 @@ pub fn execute_receive_nois(
     callback: NoisCallback,
 ) -> Result<Response, ContractError> {
     let config = CONFIG.load(deps.storage)?;
-    let RandomnessParams {
-        nois_randomness,
-        requested,
-    } = NOIS_RANDOMNESS.load(deps.storage)?;
 
     // callback should only be allowed to be called by the proxy contract
     // otherwise anyone can cut the randomness workflow and cheat the randomness by sending the randomness directly to this contract
@@ -443,80 +767,632 @@ pub fn execute_receive_nois(
         .randomness
         .to_array()
         .map_err(|_| ContractError::InvalidRandomness)?;
-    // Make sure the randomness does not exist yet
 
-    match nois_randomness {
-        None => NOIS_RANDOMNESS.save(
-            deps.storage,
-            &RandomnessParams {
-                nois_randomness: Some(randomness),
-                requested,
-            },
-        ),
-        Some(_randomness) => return Err(ContractError::ImmutableRandomness),
-    }?;
+    // We find the raffle the randomness was requested for
+    let raffle_id = raffle_id_from_job_id(&callback.job_id, &config.job_id_prefix)?;
+    let mut raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
 
-    Ok(Response::default())
+    // Make sure the randomness was requested and does not exist yet
+    let mut randomness_params = raffle_info
+        .randomness
+        .clone()
+        .ok_or(ContractError::RandomnessNotRequested {})?;
+    if randomness_params.nois_randomness.is_some() {
+        return Err(ContractError::ImmutableRandomness);
+    }
+    randomness_params.nois_randomness = Some(randomness);
+    raffle_info.randomness = Some(randomness_params);
+    RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "receive_randomness")
+        .add_attribute("raffle_id", raffle_id.to_string()))
 }
 
 pub fn execute_claim(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     _info: MessageInfo,
     raffle_id: u64,
 ) -> Result<Response, ContractError> {
+    // Raffles that sold too few tickets may get more time instead
+    if let Some(res) = _auto_extend(deps.branch(), &env, raffle_id)? {
+        return Ok(res);
+    }
+    let (raffle_info, no_participants) = _claim_raffle(deps.branch(), env.clone(), raffle_id)?;
+    _claim_response(deps.storage, env, raffle_id, raffle_info, no_participants)
+}
+
+/// Sends the consolations of a claimed raffle to a buyer who didn't win anything on it
+/// Each buyer can only withdraw them once
+pub fn execute_claim_consolation(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    raffle_id: u64,
+) -> Result<Response, ContractError> {
+    let raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
+    let buyer = info.sender;
+    if !TICKET_HOLDERS.has(deps.storage, (raffle_id, &buyer))
+        || raffle_info.winner.as_ref() == Some(&buyer)
+        || raffle_info.bundle_winners.contains(&buyer)
+        || CONSOLATIONS_WITHDRAWN.has(deps.storage, (raffle_id, &buyer))
+    {
+        return Err(ContractError::NoConsolation {});
+    }
+    let consolations = CONSOLATIONS
+        .prefix(raffle_id)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|kv_item| kv_item.map(|(denom, amount)| coin(amount.u128(), denom)))
+        .collect::<StdResult<Vec<Coin>>>()?;
+    if consolations.is_empty() {
+        return Err(ContractError::NoConsolation {});
+    }
+
+    CONSOLATIONS_WITHDRAWN.save(deps.storage, (raffle_id, &buyer), &true)?;
+    for consolation in &consolations {
+        CONSOLATIONS_OWED.update(deps.storage, &consolation.denom, |owed| -> StdResult<_> {
+            Ok(owed.unwrap_or_default().saturating_sub(consolation.amount))
+        })?;
+    }
+
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: buyer.to_string(),
+            amount: consolations,
+        })
+        .add_attribute("action", "claim_consolation")
+        .add_attribute("raffle_id", raffle_id.to_string())
+        .add_attribute("buyer", buyer))
+}
+
+/// Claims a raffle and, if the caller is the winner, lists the prize in a new raffle right away
+/// The prize never leaves the contract, so the winner doesn't need to approve it again
+/// If the caller is not the winner, or the prize is split in bundles between several winners, this behaves exactly like a claim
+pub fn execute_claim_and_relist(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    raffle_id: u64,
+    new_raffle_options: RaffleOptionsMsg,
+    new_ticket_price: AssetInfo,
+) -> Result<Response, ContractError> {
+    let (raffle_info, no_participants) = _claim_raffle(deps.branch(), env.clone(), raffle_id)?;
+    let winner = raffle_info.winner.clone().unwrap();
+    if winner != info.sender || !raffle_info.bundle_winners.is_empty() {
+        return _claim_response(deps.storage, env, raffle_id, raffle_info, no_participants);
+    }
+
+    let contract_info = CONFIG.load(deps.storage)?;
+    if contract_info.full_pause {
+        return Err(ContractError::ContractIsPaused {});
+    }
+    if contract_info.lock {
+        return Err(ContractError::ContractIsLocked {});
+    }
+
+    // The ticket prices are distributed as usual
+    let funds_transfer_messages = get_raffle_owner_finished_messages(
+        deps.storage,
+        env.clone(),
+        raffle_id,
+        raffle_info.clone(),
+    )?;
+
+    // And the prize is put back in a new raffle, owned by the winner
+    let new_raffle_id = _create_raffle(
+        deps,
+        env,
+        winner.clone(),
+        raffle_info.assets,
+        new_ticket_price,
+        new_raffle_options,
+    )?;
+
+    Ok(Response::new()
+        .add_messages(funds_transfer_messages)
+        .add_attribute("action", "claim_and_relist")
+        .add_attribute("raffle_id", raffle_id.to_string())
+        .add_attribute("winner", winner)
+        .add_attribute("no_participants", no_participants.to_string())
+        .add_attribute("new_raffle_id", new_raffle_id.to_string()))
+}
+
+/// Extends the raffle sale once if it ended below its minimum number of tickets
+/// Returns the response to send back when the raffle was extended
+fn _auto_extend(
+    deps: DepsMut,
+    env: &Env,
+    raffle_id: u64,
+) -> Result<Option<Response>, ContractError> {
+    let mut raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
+    if !try_auto_extend(env, &mut raffle_info) {
+        return Ok(None);
+    }
+    RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
+
+    Ok(Some(
+        Response::new()
+            .add_attribute("action", "auto_extend")
+            .add_attribute("raffle_id", raffle_id.to_string())
+            .add_attribute(
+                "sale_end",
+                raffle_info
+                    .raffle_options
+                    .raffle_start_timestamp
+                    .plus_seconds(raffle_info.raffle_options.raffle_duration)
+                    .to_string(),
+            ),
+    ))
+}
+
+/// Requests the nois randomness of a closed raffle that opted into it, when the contract pays the nois fee
+/// Returns the response to send back when the request was issued
+fn _auto_request_randomness(
+    deps: DepsMut,
+    env: &Env,
+    raffle_id: u64,
+) -> Result<Option<Response>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let mut raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
+    // A paused contract doesn't dispatch paid randomness requests, the purchase is rejected instead
+    if config.full_pause
+        || raffle_info.raffle_options.auto_request_randomness != Some(true)
+        || config.randomness_mode != RandomnessMode::Nois
+        || config.nois_fee_source != NoisFeeSource::Contract
+        || get_raffle_state(env.clone(), raffle_info.clone()) != RaffleState::Closed
+        // The randomness can only be requested once per raffle
+        || raffle_info.randomness.is_some()
+        // Raffles that get more time are left to the auto extension
+        || try_auto_extend(env, &mut raffle_info.clone())
+    {
+        return Ok(None);
+    }
+
+    let nois_fee = coin(config.nois_proxy_amount.u128(), config.nois_proxy_denom);
+    raffle_info.randomness = Some(RandomnessParams {
+        nois_randomness: None,
+        requested: true,
+        nois_fee: Some(nois_fee.clone()),
+        nois_fee_payer: None,
+    });
+    RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
+
+    Ok(Some(
+        get_nois_randomness(deps.as_ref(), raffle_id, nois_fee)?
+            .add_attribute("action", "auto_request_randomness")
+            .add_attribute("raffle_id", raffle_id.to_string()),
+    ))
+}
+
+/// Selects the winner of a finished raffle and saves it
+/// Returns the claimed raffle and whether it had no participants
+fn _claim_raffle(
+    deps: DepsMut,
+    env: Env,
+    raffle_id: u64,
+) -> Result<(RaffleInfo, bool), ContractError> {
     // Loading the raffle object
     let mut raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
 
     // We make sure the raffle is ended
     let raffle_state = get_raffle_state(env.clone(), raffle_info.clone());
     if raffle_state != RaffleState::Finished {
+        // A raffle claimed again while its prizes are being transferred is already claimed
+        if CLAIMING.has(deps.storage, raffle_id) {
+            return Err(ContractError::ClaimInProgress {});
+        }
         return Err(ContractError::WrongStateForClaim {
             status: raffle_state,
         });
     }
 
+    // The prizes have to still be in the contract to be sent to the winner
+    check_prizes_held(deps.as_ref(), &env, &raffle_info)?;
+
     // If there was no participant, the winner is the raffle owner and we pay no fees whatsoever
-    if raffle_info.number_of_tickets == 0u32 {
+    let no_participants = raffle_info.number_of_tickets == 0u32;
+    if no_participants {
         raffle_info.winner = Some(raffle_info.owner.clone());
     } else {
         // We get the winner of the raffle and save it to the contract. The raffle is now claimed !
         let winner = get_raffle_winner(deps.as_ref(), env.clone(), raffle_id, raffle_info.clone())?;
         raffle_info.winner = Some(winner);
+        raffle_info.bundle_winners =
+            get_raffle_bundle_winners(deps.as_ref(), env, raffle_id, raffle_info.clone())?;
     }
     RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
+    decrement_live_raffles(deps.storage)?;
+
+    Ok((raffle_info, no_participants))
+}
 
+/// Verifies the contract still owns every prize of a raffle
+fn check_prizes_held(deps: Deps, env: &Env, raffle_info: &RaffleInfo) -> Result<(), ContractError> {
+    for (index, asset) in raffle_info.assets.iter().enumerate() {
+        let held = match asset {
+            AssetInfo::Cw721Coin(token) => is_nft_owner(
+                deps,
+                env.contract.address.clone(),
+                token.address.to_string(),
+                token.token_id.to_string(),
+            ),
+            AssetInfo::Sg721Token(token) => is_sg721_owner(
+                deps,
+                env.contract.address.clone(),
+                token.address.to_string(),
+                token.token_id.to_string(),
+            ),
+            // Only NFTs can be raffled, this is checked at creation
+            _ => Ok(()),
+        };
+        if held.is_err() {
+            return Err(ContractError::PrizeNoLongerHeld { index });
+        }
+    }
+    Ok(())
+}
+
+/// Sends the prize to the winner and distributes the ticket prices of a claimed raffle
+fn _claim_response(
+    storage: &mut dyn Storage,
+    env: Env,
+    raffle_id: u64,
+    raffle_info: RaffleInfo,
+    no_participants: bool,
+) -> Result<Response, ContractError> {
     // We send the assets to the winner
-    let winner_transfer_messages = get_raffle_winner_messages(env.clone(), raffle_info.clone())?;
+    // The raffle is flagged as being claimed until the reply to the last transfer, so that a recipient can't re-enter the claim
+    let mut winner_transfer_messages: Vec<SubMsg> =
+        get_raffle_winner_messages(env.clone(), raffle_info.clone())?
+            .into_iter()
+            .map(SubMsg::new)
+            .collect();
+    if let Some(last_transfer) = winner_transfer_messages.pop() {
+        CLAIMING.save(storage, raffle_id, &true)?;
+        winner_transfer_messages.push(SubMsg::reply_on_success(last_transfer.msg, raffle_id));
+    }
     let funds_transfer_messages =
-        get_raffle_owner_finished_messages(deps.storage, env, raffle_info.clone())?;
+        get_raffle_owner_finished_messages(storage, env, raffle_id, raffle_info.clone())?;
     // We distribute the ticket prices to the owner and in part to the treasury
-    Ok(Response::new()
-        .add_messages(winner_transfer_messages)
+    let mut res = Response::new()
+        .add_submessages(winner_transfer_messages)
         .add_messages(funds_transfer_messages)
         .add_attribute("action", "claim")
         .add_attribute("raffle_id", raffle_id.to_string())
-        .add_attribute("winner", raffle_info.winner.unwrap()))
+        .add_attribute("winner", raffle_info.winner.unwrap())
+        .add_attribute("no_participants", no_participants.to_string());
+
+    // Indexers should not mistake a raffle returned to its owner for a won one
+    if no_participants {
+        res = res.add_attribute("event", "returned_to_owner");
+    }
+    Ok(res)
 }
 
 /// Update the randomness assigned to a raffle
 /// This allows trustless and un-predictable randomness to the raffle contract.
-/// The randomness providers will get a small cut of the raffle tickets (to reimburse the tx fees and incentivize adding randomness)
+/// In nois mode, the nois fee is paid by the contract, unless the caller sends it along with the message
+/// or the config requires the caller to pay it
+/// In drand mode, the caller provides the beacon (`drand_randomness`), which is checked by the verifier contract
 pub fn execute_update_randomness(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
     raffle_id: u64,
+    drand_randomness: Option<DrandRandomness>,
 ) -> Result<Response, ContractError> {
+    if CONFIG.load(deps.storage)?.full_pause {
+        return Err(ContractError::ContractIsPaused {});
+    }
+    // Raffles that sold too few tickets may get more time instead
+    // No fee is taken in that case, the funds sent along are refunded
+    if let Some(mut res) = _auto_extend(deps.branch(), &env, raffle_id)? {
+        if !info.funds.is_empty() {
+            res = res.add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: info.funds,
+            });
+        }
+        return Ok(res);
+    }
+    let config = CONFIG.load(deps.storage)?;
     // We check the raffle can receive randomness (good state)
-    let raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
-    let raffle_state = get_raffle_state(env, raffle_info);
+    let mut raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
+    let raffle_state = get_raffle_state(env, raffle_info.clone());
     if raffle_state != RaffleState::Closed {
         return Err(ContractError::WrongStateForRandmness {
             status: raffle_state,
         });
     }
-    // We assert the randomness is correct
-    get_nois_randomness(deps.as_ref(), raffle_id)
+    // The randomness can only be requested once per raffle
+    if raffle_info.randomness.is_some() {
+        return Err(ContractError::RandomnessAlreadyRequested {});
+    }
+
+    let drand_randomness = match (&config.randomness_mode, drand_randomness) {
+        (RandomnessMode::Nois, None) => None,
+        (RandomnessMode::DrandVerifier { contract, pubkey }, Some(randomness)) => {
+            Some((contract, pubkey, randomness))
+        }
+        _ => return Err(ContractError::WrongRandomnessMode {}),
+    };
+    if let Some((verifier, pubkey, randomness)) = drand_randomness {
+        if !info.funds.is_empty() {
+            return Err(ContractError::AssetMismatch {});
+        }
+        let sale_end = raffle_info
+            .raffle_options
+            .raffle_start_timestamp
+            .plus_seconds(raffle_info.raffle_options.raffle_duration);
+        let expected_round = drand_round_after(sale_end);
+        if randomness.round != expected_round {
+            return Err(ContractError::WrongDrandRound {
+                expected: expected_round,
+            });
+        }
+        let verification: VerifyResponse = deps.querier.query_wasm_smart(
+            verifier,
+            &VerifierQueryMsg::Verify {
+                pubkey: pubkey.clone(),
+                round: randomness.round,
+                previous_signature: randomness.previous_signature,
+                signature: randomness.signature.clone(),
+            },
+        )?;
+        if !verification.valid {
+            return Err(ContractError::InvalidRandomness);
+        }
+
+        raffle_info.randomness = Some(RandomnessParams {
+            nois_randomness: Some(Sha256::digest(randomness.signature.as_slice()).into()),
+            requested: true,
+            nois_fee: None,
+            nois_fee_payer: None,
+        });
+        RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
+
+        return Ok(Response::new()
+            .add_attribute("action", "update_randomness")
+            .add_attribute("raffle_id", raffle_id.to_string())
+            .add_attribute("drand_round", randomness.round.to_string()));
+    }
+
+    // If the caller sends funds, they need to match the nois fee exactly
+    let nois_fee = coin(config.nois_proxy_amount.u128(), config.nois_proxy_denom);
+    let nois_fee_payer = if info.funds.is_empty() {
+        if config.nois_fee_source == NoisFeeSource::Caller {
+            return Err(ContractError::NoisFeeRequired { fee: nois_fee });
+        }
+        None
+    } else if info.funds.len() == 1 && info.funds[0] == nois_fee {
+        Some(info.sender.clone())
+    } else {
+        return Err(ContractError::AssetMismatch {});
+    };
+
+    raffle_info.randomness = Some(RandomnessParams {
+        nois_randomness: None,
+        requested: true,
+        nois_fee: Some(nois_fee.clone()),
+        nois_fee_payer,
+    });
+    RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
+
     // get randomness from nois.network
-}
\ No newline at end of file
+    Ok(get_nois_randomness(deps.as_ref(), raffle_id, nois_fee)?
+        .add_attribute("action", "update_randomness")
+        .add_attribute("raffle_id", raffle_id.to_string()))
+}
+
+/// Requests the randomness again for the raffles still waiting for it with a nois fee that differs from the current config
+/// Admin only. After the nois proxy fee changed, the requests sent with the old fee may never be answered.
+/// The owner sends the current fee for each of those raffles, the fees sent with the previous requests stay with the proxy
+pub fn execute_realign_nois_fees(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+    let nois_fee = coin(config.nois_proxy_amount.u128(), config.nois_proxy_denom);
+
+    let pending_raffles = RAFFLE_INFO
+        .range(deps.storage, None, None, Order::Ascending)
+        .filter_map(|kv_item| match kv_item {
+            Ok((raffle_id, raffle_info)) => match &raffle_info.randomness {
+                Some(randomness)
+                    if randomness.nois_randomness.is_none()
+                        && !raffle_info.is_cancelled
+                        && randomness.nois_fee.as_ref() != Some(&nois_fee) =>
+                {
+                    Some(Ok((raffle_id, raffle_info)))
+                }
+                _ => None,
+            },
+            Err(err) => Some(Err(err)),
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    // The owner pays the current fee of every request sent again
+    let total_fee = coin(
+        (nois_fee.amount * Uint128::from(pending_raffles.len() as u64)).u128(),
+        nois_fee.denom.clone(),
+    );
+    let expected_funds = if total_fee.amount.is_zero() {
+        vec![]
+    } else {
+        vec![total_fee.clone()]
+    };
+    if info.funds != expected_funds {
+        return Err(ContractError::NoisFeeRequired { fee: total_fee });
+    }
+
+    let mut res = Response::new();
+    let mut realigned = vec![];
+    for (raffle_id, mut raffle_info) in pending_raffles {
+        raffle_info.randomness = Some(RandomnessParams {
+            nois_randomness: None,
+            requested: true,
+            nois_fee: Some(nois_fee.clone()),
+            nois_fee_payer: Some(info.sender.clone()),
+        });
+        RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
+        res = res.add_submessages(
+            get_nois_randomness(deps.as_ref(), raffle_id, nois_fee.clone())?.messages,
+        );
+        realigned.push(raffle_id.to_string());
+    }
+
+    Ok(res
+        .add_attribute("action", "realign_nois_fees")
+        .add_attribute("nois_fee", nois_fee.to_string())
+        .add_attribute(
+            "raffle_ids",
+            if realigned.is_empty() {
+                "none".to_string()
+            } else {
+                realigned.join(",")
+            },
+        ))
+}
+
+/// Resets the randomness request of a raffle still waiting for its beacon, so it can be requested again
+/// Admin only. This is meant for requests stuck on a nois proxy that was replaced in the config.
+/// The nois fee sent with the stuck request is lost with the old proxy
+pub fn execute_reassign_randomness(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    raffle_id: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+
+    let mut raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
+    if raffle_info.is_cancelled {
+        return Err(ContractError::WrongStateForRandmness {
+            status: RaffleState::Cancelled,
+        });
+    }
+    // A received beacon may already have decided the winner, it can't be replaced
+    match &raffle_info.randomness {
+        None => return Err(ContractError::RandomnessNotRequested {}),
+        Some(randomness) if randomness.nois_randomness.is_some() => {
+            return Err(ContractError::ImmutableRandomness)
+        }
+        Some(_) => {}
+    }
+    raffle_info.randomness = None;
+    RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "reassign_randomness")
+        .add_attribute("raffle_id", raffle_id.to_string()))
+}
+
+/// Rewrites the ticket count of a raffle from the tickets actually recorded for it
+/// Admin only. This repairs a raffle whose count went out of sync with its tickets, before it's drawn
+pub fn execute_recount_tickets(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    raffle_id: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+
+    let mut raffle_info = RAFFLE_INFO.load(deps.storage, raffle_id)?;
+    // Once the beacon is there, the ticket count decides the winner
+    if raffle_info
+        .randomness
+        .as_ref()
+        .is_some_and(|randomness| randomness.nois_randomness.is_some())
+    {
+        return Err(ContractError::ImmutableRandomness);
+    }
+
+    let previous_count = raffle_info.number_of_tickets;
+    raffle_info.number_of_tickets = RAFFLE_TICKETS
+        .prefix(raffle_id)
+        .keys(deps.storage, None, None, Order::Ascending)
+        .count()
+        .try_into()
+        .map_err(|_| ContractError::TicketOverflow {})?;
+    RAFFLE_INFO.save(deps.storage, raffle_id, &raffle_info)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "recount_tickets")
+        .add_attribute("raffle_id", raffle_id.to_string())
+        .add_attribute("previous_count", previous_count.to_string())
+        .add_attribute("number_of_tickets", raffle_info.number_of_tickets.to_string()))
+}
+
+/// Sends the contract balance of `denom` that isn't owed to anyone to the treasury
+/// Admin only. The ticket sales of the raffles that were neither claimed nor cancelled are kept in escrow,
+/// as well as the nois fee of those still awaiting randomness when the contract pays for it.
+/// Everything above that (rounding leftovers, stray transfers, unused nois fee provisions) is swept
+pub fn execute_sweep_dust(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denom: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_eq!(info.sender, config.owner, ContractError::Unauthorized);
+
+    let reserves_nois_fee = config.randomness_mode == RandomnessMode::Nois
+        && config.nois_fee_source == NoisFeeSource::Contract
+        && config.nois_proxy_denom == denom;
+    let escrow = RAFFLE_INFO
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|kv_item| {
+            let (raffle_id, raffle_info) = kv_item?;
+            if raffle_info.is_cancelled || raffle_info.winner.is_some() {
+                return Ok(Uint128::zero());
+            }
+            let sales: Uint128 = raffle_sales(deps.storage, raffle_id, &raffle_info)?
+                .into_iter()
+                .filter(|sales| sales.denom == denom)
+                .map(|sales| sales.amount)
+                .sum();
+            // The contract balance pays the randomness requests that weren't issued yet
+            if reserves_nois_fee && raffle_info.randomness.is_none() {
+                return Ok(sales + config.nois_proxy_amount);
+            }
+            Ok(sales)
+        })
+        .sum::<Result<Uint128, ContractError>>()?;
+    // The consolations of the claimed raffles are held until their buyers withdraw them
+    let escrow = escrow
+        + CONSOLATIONS_OWED
+            .may_load(deps.storage, &denom)?
+            .unwrap_or_default();
+
+    let balance = deps
+        .querier
+        .query_balance(env.contract.address, denom.clone())?
+        .amount;
+    let dust = balance.saturating_sub(escrow);
+
+    let mut res = Response::new()
+        .add_attribute("action", "sweep_dust")
+        .add_attribute("denom", denom.clone())
+        .add_attribute("amount", dust);
+    if !dust.is_zero() {
+        res = res.add_message(BankMsg::Send {
+            to_address: config.fee_addr.to_string(),
+            amount: vec![coin(dust.u128(), denom)],
+        });
+    }
+    Ok(res)
+}
+
+/// Clears the claim guard of a raffle once its prizes were transferred to the winners
+pub fn reply_claim(deps: DepsMut, _env: Env, raffle_id: u64) -> Result<Response, ContractError> {
+    if !CLAIMING.has(deps.storage, raffle_id) {
+        return Err(ContractError::Unreachable {});
+    }
+    CLAIMING.remove(deps.storage, raffle_id);
+    Ok(Response::new())
+}
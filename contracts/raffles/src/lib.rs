@@ -1,3 +1,5 @@
+#![allow(clippy::result_large_err)]
+
 pub mod contract;
 pub mod error;
 pub mod execute;
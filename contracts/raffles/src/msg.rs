@@ -3,7 +3,7 @@ use cosmwasm_std::{HexBinary, Uint128, Decimal, StdResult, StdError, Addr, Coin}
 use nois::NoisCallback;
 use utils::state::AssetInfo;
 
-use crate::state::{ RaffleOptionsMsg, RaffleState, RaffleInfo};
+use crate::state::{ RaffleOptionsMsg, RaffleState, RaffleInfo, RandomnessMode, NoisFeeSource};
 
 #[cw_serde]
 pub struct InstantiateMsg {
@@ -20,6 +20,8 @@ pub struct InstantiateMsg {
     pub max_participant_number: Option<u32>,
     pub raffle_fee: Option<Decimal>,
     pub rand_fee: Option<Decimal>,
+    pub job_id_prefix: Option<String>,
+    pub cancel_lockout_seconds: Option<u64>,
 }
 
 impl InstantiateMsg {
@@ -63,9 +65,14 @@ pub enum ExecuteMsg {
         creation_fee_denom: Option<String>,
         creation_fee_amount: Option<Uint128>,
         raffle_fee: Option<Decimal>,
+        rand_fee: Option<Decimal>,
         nois_proxy_addr: Option<String>,
         nois_proxy_denom: Option<String>,
         nois_proxy_amount: Option<Uint128>,
+        cancel_lockout_seconds: Option<u64>,
+        max_nois_proxy_amount: Option<Uint128>,
+        // Removes the ceiling on the nois fee, `max_nois_proxy_amount` is ignored when set
+        clear_max_nois_proxy_amount: Option<bool>,
     },
     ModifyRaffle {
         raffle_id: u64,
@@ -76,22 +83,143 @@ pub enum ExecuteMsg {
         raffle_id: u64,
         ticket_number: u32,
         sent_assets: AssetInfo,
+        // Merkle proof of the buyer address, hex encoded, for raffles with an allowlist
+        proof: Option<Vec<String>>,
+    },
+    // Gives back `ticket_number` tickets of the sender, before the raffle `refundable_until` time
+    RefundTicket {
+        raffle_id: u64,
+        ticket_number: u32,
     },
     Receive(cw721::Cw721ReceiveMsg),
     ClaimNft {
         raffle_id: u64,
     },
+    // Claims a raffle and, if the sender is the winner, puts the prize in a new raffle right away
+    ClaimAndRelist {
+        raffle_id: u64,
+        new_raffle_options: RaffleOptionsMsg,
+        new_ticket_price: AssetInfo,
+    },
+    // Withdraws the consolation of a claimed raffle, for the buyers who didn't win it
+    ClaimConsolation {
+        raffle_id: u64,
+    },
     NoisReceive {
         callback: NoisCallback,
     },
     // Admin messages
     ToggleLock {
         lock: bool,
+        // Also blocks ticket purchases and randomness requests. None keeps the current pause
+        full_pause: Option<bool>,
+    },
+    // Requests the randomness again with the current nois fee for the raffles still waiting for it, after the nois fee changed
+    // The owner sends the current fee for each of those raffles
+    RealignNoisFees {},
+    // Resets a randomness request still waiting for its beacon, so it can be sent again to the current nois proxy
+    ReassignRandomness {
+        raffle_id: u64,
+    },
+    // Rewrites the ticket count of a raffle from the tickets recorded for it, in case they went out of sync
+    RecountTickets {
+        raffle_id: u64,
+    },
+    // Sends the balance of a denom that no unclaimed raffle accounts for to the treasury
+    SweepDust {
+        denom: String,
+    },
+    // Caps the number of raffles that can be live at the same time. None removes the cap
+    SetMaxLiveRaffles {
+        max_live_raffles: Option<u32>,
+    },
+    // Sets the contract giving the floor price of the prize collections. None disables the prize valuation
+    SetPriceOracle {
+        price_oracle: Option<String>,
+    },
+    // Requires new raffles to bundle at least this number of assets
+    SetMinAssetsPerRaffle {
+        min_assets_per_raffle: u32,
+    },
+    // Records `multiplier` ticket slots for every ticket `address` buys. None removes the multiplier
+    SetTicketMultiplier {
+        address: String,
+        multiplier: Option<u32>,
+    },
+    // Gives back a share of the treasury cut to the buyers who didn't win, in basis points. None disables it
+    SetConsolationBps {
+        consolation_bps: Option<u16>,
+    },
+    // Sets the addresses trusted to own the prizes they raffle, typically collection minters raffling fresh mints
+    SetTrustedMinters {
+        trusted_minters: Vec<String>,
+    },
+    // Cancels a raffle at any point before the draw, refunding everyone involved
+    CancelAndUnwind {
+        raffle_id: u64,
+    },
+    // Settles a raffle whose randomness timed out, refunding everyone if it never arrived
+    AdminSettle {
+        raffle_id: u64,
+    },
+    // Sets who pays the nois fee of the randomness requests
+    SetNoisFeeSource {
+        source: NoisFeeSource,
+    },
+    // Switches the source of randomness, drand is a fallback for chains without nois
+    SetRandomnessMode {
+        mode: RandomnessModeMsg,
     },
      // provide job_id for randomness contract
      UpdateRandomness {
         raffle_id: u64,
     },
+    // Provides the drand beacon of a raffle, when the contract is in drand mode
+    SubmitDrandRandomness {
+        raffle_id: u64,
+        randomness: DrandRandomness,
+    },
+}
+
+#[cw_serde]
+pub enum RandomnessModeMsg {
+    Nois,
+    DrandVerifier { contract: String, pubkey: HexBinary },
+}
+
+/// A drand beacon, the randomness is the sha256 hash of the signature
+#[cw_serde]
+pub struct DrandRandomness {
+    pub round: u64,
+    pub previous_signature: HexBinary,
+    pub signature: HexBinary,
+}
+
+/// Query interface of the drand verifier contract
+#[cw_serde]
+pub enum VerifierQueryMsg {
+    Verify {
+        pubkey: HexBinary,
+        round: u64,
+        previous_signature: HexBinary,
+        signature: HexBinary,
+    },
+}
+
+#[cw_serde]
+pub struct VerifyResponse {
+    pub valid: bool,
+}
+
+/// Query interface of the price oracle contract
+#[cw_serde]
+pub enum OracleQueryMsg {
+    Floor { collection: String },
+}
+
+#[cw_serde]
+pub struct FloorResponse {
+    pub floor: Option<Coin>, // None when the oracle has no data on the collection
 }
 
 #[cw_serde]
@@ -101,6 +229,8 @@ pub enum QueryMsg {
     Config {},
     #[returns(RaffleResponse)]
     RaffleInfo { raffle_id: u64 },
+    #[returns(Vec<RaffleResponse>)]
+    RaffleInfos { raffle_ids: Vec<u64> },
     #[returns(AllRafflesResponse)]
     AllRaffles {
         start_after: Option<u64>,
@@ -115,6 +245,63 @@ pub enum QueryMsg {
     },
     #[returns(u32)]
     TicketNumber { owner: String, raffle_id: u64 },
+    /// Whether `address` holds tickets of the raffle. Unlike `TicketNumber`, this doesn't fail for addresses that didn't enter
+    #[returns(bool)]
+    HasEntered { raffle_id: u64, address: String },
+    /// Owner of the ticket at index `ticket_id`, so that anyone can walk the tickets and verify a draw
+    #[returns(Addr)]
+    TicketOwnerAt { raffle_id: u64, ticket_id: u32 },
+    #[returns(Vec<(Addr, u32)>)]
+    TicketHolders {
+        raffle_id: u64,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Whether `BuyTicket` would accept the purchase, the payment aside. Allowlisted raffles need the buyer `proof`
+    #[returns(CanBuyResponse)]
+    CanBuy {
+        raffle_id: u64,
+        buyer: String,
+        ticket_number: u32,
+        proof: Option<Vec<String>>,
+    },
+    #[returns(bool)]
+    VerifyWinner { raffle_id: u64 },
+    #[returns(PriceStatsResponse)]
+    PriceStats { denom: Option<String> },
+    #[returns(RandomnessStatusResponse)]
+    RandomnessStatus { raffle_id: u64 },
+    /// Randomness beacon that decided the winner, along with the winning ticket id, to reproduce the draw offline
+    #[returns(RaffleRandomnessResponse)]
+    RaffleRandomness { raffle_id: u64 },
+    /// Estimated value of each prize asset, from the floor prices given by the price oracle
+    #[returns(PrizeValueResponse)]
+    PrizeValue { raffle_id: u64 },
+    /// Seconds left to buy tickets. None if the sale hasn't started yet, 0 once it's over
+    #[returns(Option<u64>)]
+    SaleTimeRemaining { raffle_id: u64 },
+    /// Lists the raffles with a prize from the collection at `address`, the most recent first
+    #[returns(AllRafflesResponse)]
+    RafflesByCollection {
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Number of distinct ticket buyers across the raffles with a prize from the collection at `address`
+    #[returns(u32)]
+    CollectionParticipation { address: String },
+    /// Lists the raffles drawn but not claimed yet, the most recent first
+    #[returns(AllRafflesResponse)]
+    AwaitingClaim {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Fee parameters bound to a raffle when it was created, which apply whatever the current config
+    #[returns(RaffleFeesResponse)]
+    RaffleFees { raffle_id: u64 },
+    /// Treasury cut of the ticket sales of all the claimed raffles, by denom
+    #[returns(Vec<Coin>)]
+    TreasuryEarned {},
 }
 
 #[cw_serde]
@@ -125,6 +312,8 @@ pub struct QueryFilters {
     pub contains_token: Option<String>,
 }
 
+/// Public view of the contract config
+/// It is decoupled from the stored `Config`, so that the storage layout can change without breaking clients
 #[cw_serde]
 pub struct ConfigResponse {
     pub name: String,
@@ -133,11 +322,26 @@ pub struct ConfigResponse {
     pub last_raffle_id: u64,
     pub minimum_raffle_duration: u64, // The minimum interval in which users can buy raffle tickets
     pub minimum_raffle_timeout: u64, // The minimum interval during which users can provide entropy to the contract
+    pub creation_fee_denom: String, // The static fee denom to create a new raffle.
+    pub creation_fee_amount: Uint128, // The static fee amount to create a new raffle.
     pub raffle_fee: Decimal, // The percentage of the resulting ticket-tokens that will go to the treasury
+    pub rand_fee: Decimal, // The percentage of the resulting ticket-tokens that will go to the randomness provider
     pub lock: bool,        // Wether the contract can accept new raffles
+    pub full_pause: bool,
     pub nois_proxy_addr: Addr,
     pub nois_proxy_denom: String, 
     pub nois_proxy_amount: Uint128,
+    pub job_id_prefix: String,
+    pub max_live_raffles: Option<u32>,
+    pub live_raffles: u32, // Number of raffles that were neither claimed nor cancelled yet
+    pub cancel_lockout_seconds: u64, // Raffles can't be cancelled during this interval before the end of their sale
+    pub trusted_minters: Vec<Addr>,
+    pub max_nois_proxy_amount: Option<Uint128>,
+    pub randomness_mode: RandomnessMode,
+    pub consolation_bps: Option<u16>,
+    pub nois_fee_source: NoisFeeSource,
+    pub min_assets_per_raffle: u32,
+    pub price_oracle: Option<Addr>,
 }
 
 #[cw_serde]
@@ -152,6 +356,62 @@ pub struct AllRafflesResponse {
     pub raffles: Vec<RaffleResponse>,
 }
 
+#[cw_serde]
+pub enum CantBuyReason {
+    Paused,
+    NotAllowlisted,
+    NotStarted,
+    Closed,
+    MaxTicketsPerAddress,
+    MaxTicketsPerTx,
+    PurchaseCooldown,
+    MaxTickets,
+}
+
+/// Ticket prices of the latest raffles that are not claimed or cancelled, in a single denom
+#[cw_serde]
+pub struct PriceStatsResponse {
+    pub denom: String,
+    pub raffles_sampled: u32,
+    pub min: Option<Uint128>,
+    pub max: Option<Uint128>,
+    pub most_common: Option<Uint128>, // The lowest price wins ties
+}
+
+/// Where a raffle stands in the nois randomness workflow
+#[cw_serde]
+pub struct RandomnessStatusResponse {
+    pub requested: bool,
+    pub received: bool,
+}
+
+#[cw_serde]
+pub struct RaffleRandomnessResponse {
+    pub randomness: Option<HexBinary>, // None until the beacon is received
+    pub winner_ticket_id: Option<u32>, // None until the beacon is received, or if no ticket was bought
+}
+
+#[cw_serde]
+pub struct PrizeValueResponse {
+    pub assets: Vec<Option<Coin>>, // In the order of the raffle assets, None when the value is unknown
+    pub total: Vec<Coin>, // Sum of the known values, per denom
+}
+
+/// Where the ticket proceeds of a raffle go
+#[cw_serde]
+pub struct RaffleFeesResponse {
+    pub raffle_fee: Decimal,
+    pub rand_fee: Decimal,
+    pub fee_addr: Addr,
+    pub treasury_addr: Addr,
+}
+
+#[cw_serde]
+pub struct CanBuyResponse {
+    pub can_buy: bool,
+    pub reason: Option<CantBuyReason>,
+}
+
 
 #[cw_serde]
 pub struct IsLuckyResponse {
@@ -1,9 +1,13 @@
-use cosmwasm_std::{Deps, Addr, QueryRequest, WasmQuery, to_json_binary, StdError, StdResult, Env, Order, Api};
+use std::collections::BTreeSet;
+
+use cosmwasm_std::{Deps, Addr, QueryRequest, WasmQuery, to_json_binary, StdError, StdResult, Env, Order, Api, Uint128, HexBinary, Coin};
 use cw721::{Cw721QueryMsg, OwnerOfResponse};
 use cw_storage_plus::Bound;
+use sg721_base::QueryMsg as Sg721QueryMsg;
+use sg_std::NATIVE_DENOM;
 use utils::state::AssetInfo;
 
-use crate::{msg::{ConfigResponse, AllRafflesResponse, QueryFilters, RaffleResponse}, state::{CONFIG, RAFFLE_INFO, USER_TICKETS, load_raffle, RaffleState, get_raffle_state, RaffleInfo, RAFFLE_TICKETS}};
+use crate::{error::ContractError, msg::{ConfigResponse, AllRafflesResponse, QueryFilters, RaffleResponse, CanBuyResponse, CantBuyReason, PriceStatsResponse, RandomnessStatusResponse, RaffleFeesResponse, RaffleRandomnessResponse, PrizeValueResponse, OracleQueryMsg, FloorResponse}, utils::{check_ticket_purchase, get_raffle_winner, get_winner_ticket_id}, state::{CONFIG, RAFFLE_INFO, USER_TICKETS, TICKET_HOLDERS, load_raffle, RaffleState, get_raffle_state, RaffleInfo, RAFFLE_TICKETS, load_live_raffles, COLLECTION_RAFFLES, TREASURY_EARNED}};
 
 // settings for pagination
 const MAX_LIMIT: u32 = 100;
@@ -19,15 +23,104 @@ pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
         last_raffle_id: config.last_raffle_id.unwrap_or(0),
         minimum_raffle_duration: config.minimum_raffle_duration,
         minimum_raffle_timeout: config.minimum_raffle_timeout,
+        creation_fee_denom: config.creation_fee_denom,
+        creation_fee_amount: config.creation_fee_amount,
         raffle_fee: config.raffle_fee,
+        rand_fee: config.rand_fee,
         lock: config.lock,
+        full_pause: config.full_pause,
         nois_proxy_addr: config.nois_proxy_addr,
         nois_proxy_denom: config.nois_proxy_denom,
         nois_proxy_amount: config.nois_proxy_amount,
-        
+        job_id_prefix: config.job_id_prefix,
+        max_live_raffles: config.max_live_raffles,
+        live_raffles: load_live_raffles(deps.storage)?,
+        cancel_lockout_seconds: config.cancel_lockout_seconds,
+        trusted_minters: config.trusted_minters,
+        max_nois_proxy_amount: config.max_nois_proxy_amount,
+        randomness_mode: config.randomness_mode,
+        consolation_bps: config.consolation_bps,
+        nois_fee_source: config.nois_fee_source,
+        min_assets_per_raffle: config.min_assets_per_raffle,
+        price_oracle: config.price_oracle,
+    })
+}
+
+/// Query the min, max and most common ticket price among the latest live raffles priced in `denom`
+/// Only the last `BASE_LIMIT` raffles are scanned
+pub fn query_price_stats(deps: Deps, env: Env, denom: Option<String>) -> StdResult<PriceStatsResponse> {
+    let denom = denom.unwrap_or_else(|| NATIVE_DENOM.to_string());
+
+    let mut prices: Vec<Uint128> = RAFFLE_INFO
+        .range(deps.storage, None, None, Order::Descending)
+        .take(BASE_LIMIT)
+        .filter_map(|kv_item| match kv_item {
+            Ok((_, raffle_info)) => {
+                let raffle_state = get_raffle_state(env.clone(), raffle_info.clone());
+                match raffle_info.raffle_ticket_price {
+                    AssetInfo::Coin(price)
+                        if price.denom == denom
+                            && raffle_state != RaffleState::Claimed
+                            && raffle_state != RaffleState::Cancelled =>
+                    {
+                        Some(Ok(price.amount))
+                    }
+                    _ => None,
+                }
+            }
+            Err(err) => Some(Err(err)),
+        })
+        .collect::<StdResult<Vec<Uint128>>>()?;
+    prices.sort();
+
+    // The prices are sorted, so equal prices are contiguous
+    let mut most_common: Option<(Uint128, usize)> = None;
+    for run in prices.chunk_by(|a, b| a == b) {
+        if most_common.is_none_or(|(_, count)| run.len() > count) {
+            most_common = Some((run[0], run.len()));
+        }
+    }
+
+    Ok(PriceStatsResponse {
+        denom,
+        raffles_sampled: prices.len() as u32,
+        min: prices.first().copied(),
+        max: prices.last().copied(),
+        most_common: most_common.map(|(price, _)| price),
     })
 }
 
+/// Query multiple raffles at once, in the order of `raffle_ids`
+/// Missing raffles are returned with `raffle_info: None`, their `raffle_state` is meaningless
+pub fn query_raffle_infos(
+    deps: Deps,
+    env: Env,
+    raffle_ids: Vec<u64>,
+) -> StdResult<Vec<RaffleResponse>> {
+    if raffle_ids.len() > MAX_LIMIT as usize {
+        return Err(StdError::generic_err(format!(
+            "Can't query more than {MAX_LIMIT} raffles at once"
+        )));
+    }
+    raffle_ids
+        .into_iter()
+        .map(|raffle_id| {
+            Ok(match RAFFLE_INFO.may_load(deps.storage, raffle_id)? {
+                Some(raffle_info) => RaffleResponse {
+                    raffle_id,
+                    raffle_state: get_raffle_state(env.clone(), raffle_info.clone()),
+                    raffle_info: Some(raffle_info),
+                },
+                None => RaffleResponse {
+                    raffle_id,
+                    raffle_state: RaffleState::Created,
+                    raffle_info: None,
+                },
+            })
+        })
+        .collect()
+}
+
 pub fn query_all_raffles(
     deps: Deps,
     env: Env,
@@ -83,11 +176,7 @@ pub fn query_all_raffles_by_depositor(
             .last();
         if let Some(raffle_id) = last_raffle_id {
             if raffle_id != 0 {
-                raffles = vec![RaffleResponse {
-                    raffle_id,
-                    raffle_state: RaffleState::Claimed,
-                    raffle_info: None,
-                }]
+                raffles = vec![scan_cursor(deps, env, raffle_id)?]
             }
         }
     }
@@ -108,6 +197,16 @@ fn parse_raffles(
     })
 }
 
+/// Entry returned when none of the scanned raffles matched, so that the scan can resume after the last scanned raffle
+/// Its info is left out, but its state is the actual state of that raffle
+fn scan_cursor(deps: Deps, env: Env, raffle_id: u64) -> StdResult<RaffleResponse> {
+    Ok(RaffleResponse {
+        raffle_id,
+        raffle_state: get_raffle_state(env, load_raffle(deps.storage, raffle_id)?),
+        raffle_info: None,
+    })
+}
+
 /// Query all ticket onwers within a raffle
 ///
 pub fn query_all_tickets(
@@ -155,17 +254,84 @@ pub fn query_all_raffles_raw(
 
         if let Some(Ok(raffle_id)) = raffle_id {
             if raffle_id != 0 {
-                raffles = vec![RaffleResponse {
-                    raffle_id,
-                    raffle_state: RaffleState::Claimed,
-                    raffle_info: None,
-                }]
+                raffles = vec![scan_cursor(deps, env, raffle_id)?]
             }
         }
     }
     Ok(AllRafflesResponse { raffles })
 }
 
+/// Lists the raffles that were drawn but not claimed yet, the most recent first
+/// Only the `BASE_LIMIT` raffles after `start_after` are scanned
+pub fn query_awaiting_claim(
+    deps: Deps,
+    env: Env,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<AllRafflesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let end = start_after.map(Bound::exclusive);
+
+    let raffles = RAFFLE_INFO
+        .range(deps.storage, None, end, Order::Descending)
+        .take(BASE_LIMIT)
+        .map(|kv_item| parse_raffles(deps.api, env.clone(), kv_item))
+        .filter(|response| {
+            response
+                .as_ref()
+                .map_or(true, |raffle| raffle.raffle_state == RaffleState::Finished)
+        })
+        .take(limit)
+        .collect::<StdResult<Vec<RaffleResponse>>>()?;
+    Ok(AllRafflesResponse { raffles })
+}
+
+/// Query the raffles with a prize from a given collection, the most recent first
+pub fn query_raffles_by_collection(
+    deps: Deps,
+    env: Env,
+    address: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<AllRafflesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let end = start_after.map(Bound::exclusive);
+
+    let raffles = COLLECTION_RAFFLES
+        .prefix(&address)
+        .keys(deps.storage, None, end, Order::Descending)
+        .take(limit)
+        .map(|raffle_id| {
+            let raffle_id = raffle_id?;
+            parse_raffles(
+                deps.api,
+                env.clone(),
+                load_raffle(deps.storage, raffle_id).map(|raffle| (raffle_id, raffle)),
+            )
+        })
+        .collect::<StdResult<Vec<RaffleResponse>>>()?;
+    Ok(AllRafflesResponse { raffles })
+}
+
+/// Counts the distinct ticket buyers across the raffles with a prize from the collection at `address`
+/// Only the last `BASE_LIMIT` raffles of the collection are scanned
+pub fn query_collection_participation(deps: Deps, address: String) -> StdResult<u32> {
+    let mut participants = BTreeSet::new();
+    for raffle_id in COLLECTION_RAFFLES
+        .prefix(&address)
+        .keys(deps.storage, None, None, Order::Descending)
+        .take(BASE_LIMIT)
+    {
+        for holder in TICKET_HOLDERS
+            .prefix(raffle_id?)
+            .keys(deps.storage, None, None, Order::Ascending)
+        {
+            participants.insert(holder?);
+        }
+    }
+    Ok(participants.len() as u32)
+}
+
 pub fn raffle_filter(
     _api: &dyn Api,
     env: Env,
@@ -225,6 +391,49 @@ pub fn is_nft_owner(
     Ok(())
 }
 
+/// Same as `is_nft_owner`, using the sg721 query interface
+pub fn is_sg721_owner(
+    deps: Deps,
+    sender: Addr,
+    nft_address: String,
+    token_id: String,
+) -> Result<(), StdError> {
+    let owner_response: OwnerOfResponse =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: nft_address,
+            msg: to_json_binary(&Sg721QueryMsg::OwnerOf {
+                token_id,
+                include_expired: None,
+            })?,
+        }))?;
+
+    if owner_response.owner != sender {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+    Ok(())
+}
+
+/// Query the ticket holders of a raffle, along with the number of tickets they hold
+pub fn query_ticket_holders(
+    deps: Deps,
+    _env: Env,
+    raffle_id: u64,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Vec<(Addr, u32)>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after
+        .map(|holder| deps.api.addr_validate(&holder))
+        .transpose()?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+
+    TICKET_HOLDERS
+        .prefix(raffle_id)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect()
+}
+
 /// Query the number of tickets a ticket_depositor bought in a specific raffle, designated by a raffle_id
 pub fn query_ticket_number(
     deps: Deps,
@@ -232,8 +441,202 @@ pub fn query_ticket_number(
     raffle_id: u64,
     ticket_depositor: String,
 ) -> StdResult<u32> {
-    Ok(USER_TICKETS.load(
+    USER_TICKETS.load(
         deps.storage,
         (&deps.api.addr_validate(&ticket_depositor)?, raffle_id),
-    )?)
-}
\ No newline at end of file
+    )
+}
+
+/// Query whether an address holds tickets of a raffle
+pub fn query_has_entered(deps: Deps, raffle_id: u64, address: String) -> StdResult<bool> {
+    let address = deps.api.addr_validate(&address)?;
+    Ok(USER_TICKETS
+        .may_load(deps.storage, (&address, raffle_id))?
+        .is_some_and(|tickets| tickets > 0))
+}
+
+/// Query the owner of a ticket of a raffle, tickets are indexed from 0 to `number_of_tickets - 1`
+pub fn query_ticket_owner_at(deps: Deps, raffle_id: u64, ticket_id: u32) -> StdResult<Addr> {
+    let raffle_info = load_raffle(deps.storage, raffle_id)?;
+    if ticket_id >= raffle_info.number_of_tickets {
+        return Err(StdError::generic_err(format!(
+            "Ticket {ticket_id} is out of range, the raffle has {} tickets",
+            raffle_info.number_of_tickets
+        )));
+    }
+    RAFFLE_TICKETS.load(deps.storage, (raffle_id, ticket_id))
+}
+
+/// Query whether `buyer` can buy `ticket_number` more tickets on a raffle
+/// This runs the same checks as a purchase (pause, allowlist, multiplier, state, cooldown and limits),
+/// only the payment isn't checked. If the purchase would fail, the reason why is returned along with the answer
+pub fn query_can_buy(
+    deps: Deps,
+    env: Env,
+    raffle_id: u64,
+    buyer: String,
+    ticket_number: u32,
+    proof: Option<Vec<String>>,
+) -> StdResult<CanBuyResponse> {
+    let buyer = deps.api.addr_validate(&buyer)?;
+    let raffle_info = load_raffle(deps.storage, raffle_id)?;
+
+    let reason = match check_ticket_purchase(
+        deps.storage,
+        env,
+        raffle_id,
+        &raffle_info,
+        &buyer,
+        ticket_number,
+        proof,
+    ) {
+        Ok(_) => None,
+        Err(ContractError::ContractIsPaused {}) => Some(CantBuyReason::Paused),
+        Err(ContractError::InvalidAllowlistProof {}) => Some(CantBuyReason::NotAllowlisted),
+        Err(ContractError::RaffleNotStarted {}) => Some(CantBuyReason::NotStarted),
+        Err(ContractError::CantBuyTickets {}) => Some(CantBuyReason::Closed),
+        Err(ContractError::TooMuchTicketsInTx { .. }) => Some(CantBuyReason::MaxTicketsPerTx),
+        Err(ContractError::PurchaseCooldownActive { .. }) => Some(CantBuyReason::PurchaseCooldown),
+        Err(ContractError::TooMuchTicketsForUser { .. }) => {
+            Some(CantBuyReason::MaxTicketsPerAddress)
+        }
+        Err(ContractError::TooMuchTickets { .. } | ContractError::TicketOverflow {}) => {
+            Some(CantBuyReason::MaxTickets)
+        }
+        Err(err) => return Err(StdError::generic_err(err.to_string())),
+    };
+
+    Ok(CanBuyResponse {
+        can_buy: reason.is_none(),
+        reason,
+    })
+}
+
+/// Recomputes the winner of a claimed raffle from its randomness beacon and number of tickets
+/// Returns whether it matches the recorded winner. A raffle that wasn't claimed yet can't be verified
+pub fn query_verify_winner(deps: Deps, env: Env, raffle_id: u64) -> StdResult<bool> {
+    let raffle_info = load_raffle(deps.storage, raffle_id)?;
+    let recorded_winner = match raffle_info.winner.clone() {
+        Some(winner) => winner,
+        None => return Ok(false),
+    };
+
+    // Without participants, the prize goes back to the raffle owner
+    let expected_winner = if raffle_info.number_of_tickets == 0 {
+        raffle_info.owner.clone()
+    } else {
+        get_raffle_winner(deps, env, raffle_id, raffle_info)
+            .map_err(|err| StdError::generic_err(err.to_string()))?
+    };
+
+    Ok(expected_winner == recorded_winner)
+}
+
+/// Query the number of seconds left before the ticket sale of a raffle closes
+/// Returns None if the sale hasn't started yet and 0 once the sale is over or the raffle was cancelled
+pub fn query_sale_time_remaining(deps: Deps, env: Env, raffle_id: u64) -> StdResult<Option<u64>> {
+    let raffle_info = load_raffle(deps.storage, raffle_id)?;
+    Ok(match get_raffle_state(env.clone(), raffle_info.clone()) {
+        RaffleState::Created => None,
+        RaffleState::Started => {
+            let sale_end = raffle_info
+                .raffle_options
+                .raffle_start_timestamp
+                .plus_seconds(raffle_info.raffle_options.raffle_duration);
+            Some(sale_end.seconds() - env.block.time.seconds())
+        }
+        _ => Some(0),
+    })
+}
+
+/// Query whether the randomness of a raffle was requested to the nois proxy and whether it was received
+pub fn query_randomness_status(deps: Deps, raffle_id: u64) -> StdResult<RandomnessStatusResponse> {
+    let raffle_info = load_raffle(deps.storage, raffle_id)?;
+    Ok(match raffle_info.randomness {
+        Some(randomness) => RandomnessStatusResponse {
+            requested: randomness.requested,
+            received: randomness.nois_randomness.is_some(),
+        },
+        None => RandomnessStatusResponse {
+            requested: false,
+            received: false,
+        },
+    })
+}
+
+/// Query the randomness beacon of a raffle and the ticket id it designates as the winner
+pub fn query_raffle_randomness(deps: Deps, raffle_id: u64) -> StdResult<RaffleRandomnessResponse> {
+    let raffle_info = load_raffle(deps.storage, raffle_id)?;
+    let randomness = raffle_info
+        .randomness
+        .and_then(|randomness| randomness.nois_randomness);
+    Ok(RaffleRandomnessResponse {
+        randomness: randomness.map(|randomness| HexBinary::from(randomness.as_slice())),
+        winner_ticket_id: randomness
+            .filter(|_| raffle_info.number_of_tickets > 0)
+            .map(|randomness| get_winner_ticket_id(randomness, raffle_info.number_of_tickets)),
+    })
+}
+
+/// Query the value of each prize asset of a raffle, along with their sum per denom
+/// NFTs are valued at the floor of their collection given by the price oracle.
+/// An NFT the oracle has no floor for (or any NFT, without an oracle) has no value and isn't counted in the total
+pub fn query_prize_value(deps: Deps, raffle_id: u64) -> StdResult<PrizeValueResponse> {
+    let raffle_info = load_raffle(deps.storage, raffle_id)?;
+    let price_oracle = CONFIG.load(deps.storage)?.price_oracle;
+
+    let assets: Vec<Option<Coin>> = raffle_info
+        .assets
+        .iter()
+        .map(|asset| {
+            let collection = match asset {
+                AssetInfo::Coin(coin) => return Some(coin.clone()),
+                AssetInfo::Cw721Coin(cw721) => &cw721.address,
+                AssetInfo::Sg721Token(sg721) => &sg721.address,
+            };
+            let price_oracle = price_oracle.as_ref()?;
+            // The valuation is informative, an oracle failure only leaves the asset without a value
+            deps.querier
+                .query_wasm_smart::<FloorResponse>(
+                    price_oracle,
+                    &OracleQueryMsg::Floor {
+                        collection: collection.clone(),
+                    },
+                )
+                .ok()
+                .and_then(|response| response.floor)
+        })
+        .collect();
+
+    let mut total: Vec<Coin> = vec![];
+    for value in assets.iter().flatten() {
+        match total.iter_mut().find(|coin| coin.denom == value.denom) {
+            Some(coin) => coin.amount += value.amount,
+            None => total.push(value.clone()),
+        }
+    }
+
+    Ok(PrizeValueResponse { assets, total })
+}
+
+/// Query the fee parameters snapshotted on a raffle at creation
+pub fn query_raffle_fees(deps: Deps, raffle_id: u64) -> StdResult<RaffleFeesResponse> {
+    let fee_params = load_raffle(deps.storage, raffle_id)?.fee_params;
+    Ok(RaffleFeesResponse {
+        raffle_fee: fee_params.raffle_fee,
+        rand_fee: fee_params.rand_fee,
+        fee_addr: fee_params.fee_addr,
+        treasury_addr: fee_params.treasury_addr,
+    })
+}
+
+/// Query the treasury cut of the ticket sales of all the claimed raffles, by denom
+pub fn query_treasury_earned(deps: Deps) -> StdResult<Vec<Coin>> {
+    TREASURY_EARNED
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|kv_item| {
+            let (denom, amount) = kv_item?;
+            Ok(Coin { denom, amount })
+        })
+        .collect()
+}
@@ -1,19 +1,25 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Decimal, StdError, StdResult, Coin, Timestamp, Env, Storage, coin, Uint128};
+use cosmwasm_std::{Addr, Decimal, StdError, StdResult, Coin, Timestamp, Env, Storage, Uint128, HexBinary};
 
 use cw_storage_plus::{Item, Map};
 use sg_std::NATIVE_DENOM;
 use utils::state::AssetInfo;
 
+use crate::error::ContractError;
+use crate::utils::JOB_ID_PREFIX;
+
 //TODO: add to contract config
 pub const ATLAS_DAO_STARGAZE_TREASURY: &str = "stars1jyg4j6t4kdptgsx6q55mu0f434zqcfppkx6ww9gs7p4x7clgfrjq29sgmc";
-pub const NOIS_AMOUNT: u128 = 500000;
 pub const MINIMUM_RAFFLE_DURATION: u64 = 1;
 pub const MINIMUM_RAFFLE_TIMEOUT: u64 = 120; // The raffle timeout is a least 2 minutes
 pub const DECIMAL_FRACTIONAL: u128 = 1_000_000_000_000_000_000u128; // 1*10**18
-pub const MINIMUM_RAND_FEE: Decimal = Decimal::raw(DECIMAL_FRACTIONAL/10_000u128); // The randomness provider gets at least 1/10_000 of the total raffle price
+pub const MINIMUM_RAND_FEE: Decimal = Decimal::raw(DECIMAL_FRACTIONAL/10_000u128); // By default, the randomness provider gets 1/10_000 of the total raffle price
 pub const MINIMUM_CREATION_FEE_AMOUNT: u128 = 69;
 pub const MINIMUM_CREATION_FEE_DENOM: &str = NATIVE_DENOM;
+pub const MAX_BPS: u16 = 10_000;
+pub const MAX_TICKET_MULTIPLIER: u32 = 10; // A weighted buyer gets at most 10 ticket slots per ticket bought
+pub const DRAND_GENESIS: u64 = 1595431050; // Genesis time of the drand mainnet chain, in seconds
+pub const DRAND_PERIOD: u64 = 30; // Seconds between two drand mainnet rounds
 
 
 #[cw_serde]
@@ -27,16 +33,36 @@ pub struct Config {
     pub creation_fee_denom: String, // The static fee denom to create a new raffle.
     pub creation_fee_amount: Uint128, // The static fee amount to create a new raffle.
     pub raffle_fee: Decimal, // The percentage of the resulting ticket-tokens that will go to the treasury
+    #[serde(default)]
+    pub rand_fee: Decimal, // The percentage of the resulting ticket-tokens that will go to the randomness provider
     pub lock: bool,        // Wether the contract can accept new raffles
+    #[serde(default)]
+    pub full_pause: bool, // Emergency pause, also blocks ticket purchases and randomness requests. Claims, cancellations and refunds keep working
     pub nois_proxy_addr: Addr,
     pub nois_proxy_denom: String, // https://nois.network proxy address
-    pub nois_proxy_amount: Uint128
+    pub nois_proxy_amount: Uint128,
+    #[serde(default = "legacy_job_id_prefix")]
+    pub job_id_prefix: String, // Namespace of the nois job ids, used to route the beacons back to their raffle
+    pub max_live_raffles: Option<u32>, // Maximum number of raffles that can be live at the same time, None means unbounded
+    #[serde(default)]
+    pub cancel_lockout_seconds: u64, // Raffles can't be cancelled during this interval before the end of their sale
+    #[serde(default)]
+    pub trusted_minters: Vec<Addr>, // Raffles created by those addresses skip the prize ownership checks
+    pub max_nois_proxy_amount: Option<Uint128>, // Ceiling on the nois fee the contract can be set to pay per request, None means unbounded
+    #[serde(default)]
+    pub randomness_mode: RandomnessMode, // Where the raffles get their randomness from
+    pub consolation_bps: Option<u16>, // Share of the treasury cut given back to the buyers who didn't win, in basis points
+    #[serde(default)]
+    pub nois_fee_source: NoisFeeSource, // Who pays the nois proxy for the randomness requests
+    #[serde(default = "default_min_assets_per_raffle")]
+    pub min_assets_per_raffle: u32, // New raffles need at least this number of assets
+    pub price_oracle: Option<Addr>, // Gives the floor price of the prize collections, only used to display the prize value
 }
 
 impl Config{
     pub fn validate_fee(&self) -> Result<(), StdError>{
         // Check the fee distribution
-        if self.raffle_fee >= Decimal::one(){
+        if self.raffle_fee + self.rand_fee >= Decimal::one(){
             return Err(StdError::generic_err(
                 "The Total Fee rate should be lower than 1"
             ))
@@ -45,12 +71,56 @@ impl Config{
     }
 }
 
+// Contracts instantiated before the job id namespacing requested their randomness as "raffle-<raffle_id>"
+fn legacy_job_id_prefix() -> String {
+    JOB_ID_PREFIX.to_string()
+}
+
+fn default_min_assets_per_raffle() -> u32 {
+    1
+}
+
+/// Source of the randomness used to draw the raffles
+#[cw_serde]
+#[derive(Default)]
+pub enum RandomnessMode {
+    // Beacons requested to the nois proxy
+    #[default]
+    Nois,
+    // Drand beacons submitted by anyone and checked by an external verifier contract, for chains without nois
+    DrandVerifier { contract: Addr, pubkey: HexBinary },
+}
+
+/// Who pays the nois fee when the randomness of a raffle is requested
+#[cw_serde]
+#[derive(Default)]
+pub enum NoisFeeSource {
+    // The contract pays from its own balance, unless the caller sends the fee along
+    #[default]
+    Contract,
+    // The caller has to send the fee along with the request
+    Caller,
+}
+
+/// First drand round published at or after `time`
+/// Only this round is accepted for a raffle, so that whoever submits the beacon can't pick a favorable one
+pub fn drand_round_after(time: Timestamp) -> u64 {
+    let elapsed = time.seconds().saturating_sub(DRAND_GENESIS);
+    elapsed.div_ceil(DRAND_PERIOD) + 1
+}
+
 #[cw_serde]
 pub struct RandomnessParams {
-    // The randomness beacon received from the proxy
+    // The randomness beacon received from the proxy, or derived from the drand signature in drand mode
     pub nois_randomness: Option<[u8; 32]>,
     // If the randomness has already been requested
     pub requested: bool,
+    // The fee sent to the proxy with the randomness request
+    #[serde(default)]
+    pub nois_fee: Option<Coin>,
+    // Who paid the nois fee. None means the contract paid it from its own balance
+    #[serde(default)]
+    pub nois_fee_payer: Option<Addr>,
 }
 
 #[cw_serde]
@@ -66,7 +136,33 @@ pub const CONFIG: Item<Config> = Item::new(CONFIG_KEY);
 pub const RAFFLE_INFO: Map<u64, RaffleInfo> = Map::new("raffle_info");
 pub const RAFFLE_TICKETS: Map<(u64, u32), Addr> = Map::new("raffle_tickets");
 pub const USER_TICKETS: Map<(&Addr, u64), u32> = Map::new("user_tickets");
-pub const NOIS_RANDOMNESS: Item<RandomnessParams> = Item::new("nois_randomness");
+// Mirrors USER_TICKETS, keyed by raffle first to list the ticket holders of a raffle
+pub const TICKET_HOLDERS: Map<(u64, &Addr), u32> = Map::new("ticket_holders");
+// Tickets each buyer paid for on a raffle. Lower than USER_TICKETS for the buyers with a ticket multiplier
+pub const PAID_TICKETS: Map<(u64, &Addr), u32> = Map::new("paid_tickets");
+// Price each buyer paid their tickets with, recorded on their first purchase. The refunds are paid from it
+pub const BUYER_PRICES: Map<(u64, &Addr), Coin> = Map::new("buyer_prices");
+// Tickets paid with each of the raffle acceptable prices, by denom. The rest were paid with the raffle ticket price
+pub const PRICE_TICKETS_PAID: Map<(u64, &str), u32> = Map::new("price_tickets_paid");
+// Time of the last ticket purchase of each buyer on a raffle, only recorded for raffles with a purchase cooldown
+pub const LAST_PURCHASE: Map<(u64, &Addr), Timestamp> = Map::new("last_purchase");
+// Owner-managed number of ticket slots recorded for every ticket an address buys
+pub const TICKET_MULTIPLIERS: Map<&Addr, u32> = Map::new("ticket_multipliers");
+// Index of the raffles by the collections of their prizes
+pub const COLLECTION_RAFFLES: Map<(&str, u64), bool> = Map::new("collection_raffles");
+// Number of raffles that were neither claimed nor cancelled yet
+pub const LIVE_RAFFLES: Item<u32> = Item::new("live_raffles");
+// Treasury cut of the ticket sales of all the claimed raffles, by denom
+pub const TREASURY_EARNED: Map<&str, Uint128> = Map::new("treasury_earned");
+// Consolation each buyer who didn't win a claimed raffle can withdraw, by raffle and denom
+pub const CONSOLATIONS: Map<(u64, &str), Uint128> = Map::new("consolations");
+// Buyers who already withdrew their consolation of a raffle
+pub const CONSOLATIONS_WITHDRAWN: Map<(u64, &Addr), bool> = Map::new("consolations_withdrawn");
+// Consolations recorded but not withdrawn yet, by denom. They are kept out of the swept dust
+pub const CONSOLATIONS_OWED: Map<&str, Uint128> = Map::new("consolations_owed");
+// Raffles whose prizes are being sent to their winners. Entries only live until the claim reply
+// The reply to the last prize transfer of a claim has the raffle id as reply id
+pub const CLAIMING: Map<u64, bool> = Map::new("claiming");
 
 
 // RAFFLES
@@ -75,16 +171,80 @@ pub fn load_raffle(storage: &dyn Storage, raffle_id: u64) -> StdResult<RaffleInf
     RAFFLE_INFO.load(storage, raffle_id)
 }
 
+pub fn load_live_raffles(storage: &dyn Storage) -> StdResult<u32> {
+    Ok(LIVE_RAFFLES.may_load(storage)?.unwrap_or_default())
+}
+
+/// Registers a new live raffle, making sure the contract doesn't go over its live raffle cap
+pub fn increment_live_raffles(
+    storage: &mut dyn Storage,
+    contract_info: &Config,
+) -> Result<(), ContractError> {
+    let live_raffles = load_live_raffles(storage)?;
+    if let Some(max) = contract_info.max_live_raffles {
+        if live_raffles >= max {
+            return Err(ContractError::TooManyLiveRaffles { max });
+        }
+    }
+    LIVE_RAFFLES.save(storage, &(live_raffles + 1))?;
+    Ok(())
+}
+
+/// Called once a raffle is claimed or cancelled
+pub fn decrement_live_raffles(storage: &mut dyn Storage) -> StdResult<()> {
+    let live_raffles = load_live_raffles(storage)?;
+    LIVE_RAFFLES.save(storage, &live_raffles.saturating_sub(1))
+}
+
 #[cw_serde]
 pub struct RaffleInfo {
     pub owner: Addr,
     pub assets: Vec<AssetInfo>,
     pub raffle_ticket_price: AssetInfo,
     pub number_of_tickets: u32,
+    #[serde(default)]
+    pub tickets_paid: u32, // Tickets the buyers paid for, without the extra slots of the weighted buyers
     pub randomness: Option<RandomnessParams>,
     pub winner: Option<Addr>,
+    #[serde(default)]
+    pub bundle_winners: Vec<Addr>, // Winner of each prize bundle, in bundle order. Empty if the raffle has no prize bundles
     pub is_cancelled: bool,
     pub raffle_options: RaffleOptions,
+    #[serde(default)]
+    pub fee_params: RaffleFeeParams, // Fee parameters bound to the raffle when it was created
+}
+
+/// Snapshot of the contract fee parameters, taken at raffle creation
+/// so that config changes don't alter the economics of existing raffles
+#[cw_serde]
+pub struct RaffleFeeParams {
+    pub raffle_fee: Decimal,
+    pub rand_fee: Decimal,
+    pub fee_addr: Addr,
+    pub treasury_addr: Addr, // Receives the randomness provider cut
+}
+
+impl RaffleFeeParams {
+    pub fn from_config(contract_info: &Config) -> Self {
+        Self {
+            raffle_fee: contract_info.raffle_fee,
+            rand_fee: contract_info.rand_fee,
+            fee_addr: contract_info.fee_addr.clone(),
+            treasury_addr: Addr::unchecked(ATLAS_DAO_STARGAZE_TREASURY),
+        }
+    }
+}
+
+// Raffles created before the fee snapshot are stored without fee parameters, until the migration fills them from the config
+impl Default for RaffleFeeParams {
+    fn default() -> Self {
+        Self {
+            raffle_fee: Decimal::zero(),
+            rand_fee: Decimal::zero(),
+            fee_addr: Addr::unchecked(""),
+            treasury_addr: Addr::unchecked(ATLAS_DAO_STARGAZE_TREASURY),
+        }
+    }
 }
 
 
@@ -128,14 +288,13 @@ pub fn get_raffle_state(env: Env, raffle_info: RaffleInfo) -> RaffleState {
             .plus_seconds(raffle_info.raffle_options.raffle_duration)
     {
         RaffleState::Started
-    } else if env.block.time
-        < raffle_info
-            .raffle_options
-            .raffle_start_timestamp
-            .plus_seconds(raffle_info.raffle_options.raffle_duration)
-            .plus_seconds(raffle_info.raffle_options.raffle_timeout)
-        || raffle_info.randomness.is_none()
+    } else if raffle_info
+        .randomness
+        .as_ref()
+        .and_then(|randomness| randomness.nois_randomness)
+        .is_none()
     {
+        // Once the beacon has arrived the raffle can be drawn right away, whether or not its timeout has passed
         RaffleState::Closed
     } else if raffle_info.winner.is_none() {
         RaffleState::Finished
@@ -152,7 +311,16 @@ pub struct RaffleOptions {
     pub comment: Option<String>,
     pub max_participant_number: Option<u32>,
     pub max_ticket_per_address: Option<u32>,
+    pub max_tickets_per_tx: Option<u32>, // Caps the number of tickets bought in a single purchase
     pub raffle_preview: u32,
+    pub auto_extend_if_below: Option<(u32, u64)>, // (min tickets, extension in seconds), the sale is extended once if it ends below the minimum
+    pub prize_bundles: Option<Vec<Vec<usize>>>, // Asset indices won by each winner, one winner is drawn per bundle
+    pub refundable_until: Option<Timestamp>, // Buyers can get their tickets refunded until then
+    pub merkle_root: Option<HexBinary>, // Only the addresses of this allowlist tree can buy tickets, sha256 leaves and sorted pairs
+    pub purchase_cooldown_seconds: Option<u64>, // Minimum time between two purchases of the same buyer
+    pub auto_request_randomness: Option<bool>, // The first purchase attempt after the sale ends requests the nois randomness
+    #[serde(default)]
+    pub acceptable_prices: Vec<AssetInfo>, // Ticket prices accepted on top of the raffle ticket price, each in its own denom
 }
 
 #[cw_serde]
@@ -163,17 +331,38 @@ pub struct RaffleOptionsMsg {
     pub comment: Option<String>,
     pub max_participant_number: Option<u32>,
     pub max_ticket_per_address: Option<u32>,
+    pub max_tickets_per_tx: Option<u32>,
     pub raffle_preview: Option<u32>,
+    pub auto_extend_if_below: Option<(u32, u64)>,
+    pub prize_bundles: Option<Vec<Vec<usize>>>,
+    pub refundable_until: Option<Timestamp>,
+    pub merkle_root: Option<HexBinary>,
+    pub purchase_cooldown_seconds: Option<u64>,
+    pub auto_request_randomness: Option<bool>,
+    pub acceptable_prices: Option<Vec<AssetInfo>>,
 }
 
 impl RaffleOptions {
+    /// Creates the options of a new raffle
+    /// An explicit preview index has to point to one of the raffle assets
     pub fn new(
         env: Env,
         assets_len: usize,
         raffle_options: RaffleOptionsMsg,
         contract_info: Config,
-    ) -> Self {
-        Self {
+    ) -> Result<Self, ContractError> {
+        if let Some(preview) = raffle_options.raffle_preview {
+            if preview as usize >= assets_len {
+                return Err(ContractError::InvalidPreviewIndex {
+                    index: preview,
+                    assets_len,
+                });
+            }
+        }
+        if let Some(prize_bundles) = &raffle_options.prize_bundles {
+            validate_prize_bundles(prize_bundles, assets_len)?;
+        }
+        Ok(Self {
             raffle_start_timestamp: raffle_options
                 .raffle_start_timestamp
                 .unwrap_or(env.block.time)
@@ -189,19 +378,20 @@ impl RaffleOptions {
             comment: raffle_options.comment,
             max_participant_number: raffle_options.max_participant_number,
             max_ticket_per_address: raffle_options.max_ticket_per_address,
-            raffle_preview: raffle_options
-                .raffle_preview
-                .map(|preview| {
-                    if preview >= assets_len.try_into().unwrap() {
-                        0u32
-                    } else {
-                        preview
-                    }
-                })
-                .unwrap_or(0u32),
-        }
+            max_tickets_per_tx: raffle_options.max_tickets_per_tx,
+            raffle_preview: raffle_options.raffle_preview.unwrap_or(0u32),
+            auto_extend_if_below: raffle_options.auto_extend_if_below,
+            prize_bundles: raffle_options.prize_bundles,
+            refundable_until: raffle_options.refundable_until,
+            merkle_root: raffle_options.merkle_root,
+            purchase_cooldown_seconds: raffle_options.purchase_cooldown_seconds,
+            auto_request_randomness: raffle_options.auto_request_randomness,
+            acceptable_prices: raffle_options.acceptable_prices.unwrap_or_default(),
+        })
     }
 
+    /// Modifies the options of an existing raffle
+    /// The preview index is clamped to 0 if it's out of range
     pub fn new_from(
         current_options: RaffleOptions,
         assets_len: usize,
@@ -228,6 +418,9 @@ impl RaffleOptions {
             max_ticket_per_address: raffle_options
                 .max_ticket_per_address
                 .or(current_options.max_ticket_per_address),
+            max_tickets_per_tx: raffle_options
+                .max_tickets_per_tx
+                .or(current_options.max_tickets_per_tx),
             raffle_preview: raffle_options
                 .raffle_preview
                 .map(|preview| {
@@ -238,14 +431,92 @@ impl RaffleOptions {
                     }
                 })
                 .unwrap_or(current_options.raffle_preview),
+            auto_extend_if_below: raffle_options
+                .auto_extend_if_below
+                .or(current_options.auto_extend_if_below),
+            prize_bundles: raffle_options
+                .prize_bundles
+                .or(current_options.prize_bundles),
+            refundable_until: raffle_options
+                .refundable_until
+                .or(current_options.refundable_until),
+            merkle_root: raffle_options.merkle_root.or(current_options.merkle_root),
+            purchase_cooldown_seconds: raffle_options
+                .purchase_cooldown_seconds
+                .or(current_options.purchase_cooldown_seconds),
+            auto_request_randomness: raffle_options
+                .auto_request_randomness
+                .or(current_options.auto_request_randomness),
+            acceptable_prices: raffle_options
+                .acceptable_prices
+                .unwrap_or(current_options.acceptable_prices),
         }
     }
 }
 
+/// Acceptable prices have to be native coins in other denoms than the raffle ticket price and than one another
+pub fn validate_acceptable_prices(
+    raffle_ticket_price: &AssetInfo,
+    acceptable_prices: &[AssetInfo],
+) -> Result<(), ContractError> {
+    if acceptable_prices.is_empty() {
+        return Ok(());
+    }
+    let mut denoms = vec![];
+    for price in std::iter::once(raffle_ticket_price).chain(acceptable_prices) {
+        match price {
+            AssetInfo::Coin(coin) if !denoms.contains(&&coin.denom) => denoms.push(&coin.denom),
+            _ => return Err(ContractError::InvalidAcceptablePrices {}),
+        }
+    }
+    Ok(())
+}
 
+/// Prize bundles have to be non-empty and cover each raffle asset exactly once
+pub fn validate_prize_bundles(
+    prize_bundles: &[Vec<usize>],
+    assets_len: usize,
+) -> Result<(), ContractError> {
+    let mut covered = vec![false; assets_len];
+    for bundle in prize_bundles {
+        if bundle.is_empty() {
+            return Err(ContractError::InvalidPrizeBundles {});
+        }
+        for &index in bundle {
+            match covered.get_mut(index) {
+                Some(is_covered) if !*is_covered => *is_covered = true,
+                _ => return Err(ContractError::InvalidPrizeBundles {}),
+            }
+        }
+    }
+    if prize_bundles.is_empty() || covered.contains(&false) {
+        return Err(ContractError::InvalidPrizeBundles {});
+    }
+    Ok(())
+}
 
 
-
-
-
+/// Extends the sale of a raffle that ended below its minimum number of tickets
+/// The sale then ends `extension` seconds from now. This can only happen once per raffle
+/// Returns whether the raffle was extended
+pub fn try_auto_extend(env: &Env, raffle_info: &mut RaffleInfo) -> bool {
+    if get_raffle_state(env.clone(), raffle_info.clone()) != RaffleState::Closed
+        || raffle_info.randomness.is_some()
+    {
+        return false;
+    }
+    match raffle_info.raffle_options.auto_extend_if_below {
+        Some((min_tickets, extension)) if raffle_info.number_of_tickets < min_tickets => {
+            let elapsed = env
+                .block
+                .time
+                .seconds()
+                .saturating_sub(raffle_info.raffle_options.raffle_start_timestamp.seconds());
+            raffle_info.raffle_options.raffle_duration = elapsed + extension;
+            raffle_info.raffle_options.auto_extend_if_below = None;
+            true
+        }
+        _ => false,
+    }
+}
 
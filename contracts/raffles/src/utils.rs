@@ -1,93 +1,170 @@
-use cosmwasm_std::{Deps, Coin, coin, WasmMsg, to_json_binary, Storage, Env, Uint128, coins, BankMsg, Addr, Empty, StdError, StdResult};
+use cosmwasm_std::{Deps, coin, Coin, Decimal, HexBinary, WasmMsg, to_json_binary, Storage, Env, Uint128, coins, BankMsg, Addr, Empty, StdError, StdResult, Order};
 use cw721::Cw721ExecuteMsg;
-use nois::{ProxyExecuteMsg, int_in_range};
+use sha2::{Digest, Sha256};
+use nois::{ProxyExecuteMsg, int_in_range, sub_randomness, MAX_JOB_ID_LEN};
 use sg721::ExecuteMsg as Sg721ExecuteMsg;
 use sg_std::{Response, CosmosMsg};
 use utils::state::{AssetInfo, into_cosmos_msg};
 use cw721_base::Extension;
-use crate::{error::ContractError, state::{NOIS_AMOUNT, CONFIG, RaffleInfo, RandomnessParams, NOIS_RANDOMNESS, get_raffle_state, RAFFLE_TICKETS, ATLAS_DAO_STARGAZE_TREASURY, RAFFLE_INFO, RaffleState}};
+use crate::{error::ContractError, state::{CONFIG, RaffleInfo, get_raffle_state, RAFFLE_TICKETS, RAFFLE_INFO, RaffleState, USER_TICKETS, TICKET_HOLDERS, MAX_BPS, PAID_TICKETS, LAST_PURCHASE, BUYER_PRICES, PRICE_TICKETS_PAID, TREASURY_EARNED, TICKET_MULTIPLIERS, CONSOLATIONS, CONSOLATIONS_OWED}};
 
+pub const JOB_ID_PREFIX: &str = "raffle-";
+// Leaves room for the longest raffle id (u64::MAX has 20 digits)
+pub const MAX_JOB_ID_PREFIX_LEN: usize = MAX_JOB_ID_LEN - 20;
 
+/// Default job id namespace of a raffle contract
+/// It ends with the contract address, so that a proxy serving multiple raffle contracts doesn't mix up the beacons.
+/// Only the end of the address is used to fit in the proxy job id length limit
+pub fn default_job_id_prefix(contract_address: &Addr) -> String {
+    let address = contract_address.as_str();
+    let suffix = &address[address.len().saturating_sub(8)..];
+    format!("{JOB_ID_PREFIX}{suffix}-")
+}
+
+pub fn validate_job_id_prefix(job_id_prefix: &str) -> Result<(), ContractError> {
+    if job_id_prefix.len() > MAX_JOB_ID_PREFIX_LEN {
+        return Err(ContractError::JobIdPrefixTooLong {
+            max: MAX_JOB_ID_PREFIX_LEN,
+        });
+    }
+    Ok(())
+}
 
 pub fn get_nois_randomness(
     deps: Deps,
     raffle_id: u64,
+    nois_fee: Coin,
 ) -> Result<Response, ContractError> {
-    // let raffle_info = load_raffle(deps.storage, raffle_id)?;
-    // let contract_info = CONFIG.load(deps.storage)?;
     let config = CONFIG.load(deps.storage)?;
     let id = raffle_id.to_string();
-    let nois_fee: Coin = coin(NOIS_AMOUNT, config.nois_proxy_denom);
-
-    // TODO: if raffle already has randomness, error.
-
 
     let response = Response::new().add_message(WasmMsg::Execute {
         contract_addr: config.nois_proxy_addr.into_string(),
         // GetNextRandomness requests the randomness from the proxy
         // The job id is needed to know what randomness we are referring to upon reception in the callback.
         msg: to_json_binary(&ProxyExecuteMsg::GetNextRandomness {
-            job_id: "raffle-".to_string() + id.as_str(), 
+            job_id: config.job_id_prefix + id.as_str(),
         })?,
-        
 
-        funds: vec![nois_fee], // Pay from the contract
+        funds: vec![nois_fee],
     });
     Ok(response)
-}   
+}
+
+/// Retrieves the raffle id from the job id sent back by the nois proxy
+pub fn raffle_id_from_job_id(job_id: &str, job_id_prefix: &str) -> Result<u64, ContractError> {
+    job_id
+        .strip_prefix(job_id_prefix)
+        .and_then(|id| id.parse::<u64>().ok())
+        .ok_or_else(|| ContractError::InvalidJobId(job_id.to_string()))
+}
+
+/// Splits the ticket proceeds `total` between the raffle owner and a `fee` cut, as `(owner_amount, fee_amount)`
+/// The fee cut is rounded down and capped at `total`, so that the two amounts always add up to `total`
+pub fn compute_raffle_split(total: Uint128, fee: Decimal) -> (Uint128, Uint128) {
+    let fee_amount = (total * fee).min(total);
+    (total - fee_amount, fee_amount)
+}
 
 /// Util to get the organizers and helpers messages to return when claiming a Raffle (returns the funds)
+/// The treasury cut is added to the treasury earnings
 pub fn get_raffle_owner_finished_messages(
-    storage: &dyn Storage,
-    env: Env,
+    storage: &mut dyn Storage,
+    _env: Env,
+    raffle_id: u64,
     raffle_info: RaffleInfo,
 ) -> Result<Vec<CosmosMsg>, ContractError> {
-    let contract_info = CONFIG.load(storage)?;
+    // The fee parameters are the ones bound to the raffle at creation
+    let fee_params = raffle_info.fee_params.clone();
 
-    // We start by splitting the fees between owner, treasury and radomness provider
-    let total_paid = match raffle_info.raffle_ticket_price.clone() {
-        AssetInfo::Coin(coin) => coin.amount,
-        _ => return Err(ContractError::WrongFundsType {}),
-    } * Uint128::from(raffle_info.number_of_tickets);
-    let treasury_amount = total_paid * contract_info.raffle_fee;
-    let owner_amount = total_paid  - treasury_amount;
-
-    // Then we craft the messages needed for asset transfers
-    match raffle_info.raffle_ticket_price {
-        AssetInfo::Coin(coin) => {
-            let mut messages: Vec<CosmosMsg> = vec![];
-            // if rand_amount != Uint128::zero() {
-            //     messages.push(
-            //         BankMsg::Send { // TODO: Swap into $NOIS ?
-            //             to_address: ATLAS_DAO_STARGAZE_TREASURY.to_string(),
-            //             amount: coins(rand_amount.u128(), coin.denom.clone()),
-            //         }
-            //         .into(),
-            //     );
-            // };
-            if treasury_amount != Uint128::zero() {
-                messages.push(
-                    BankMsg::Send {
-                        to_address: contract_info.fee_addr.to_string(),
-                        amount: coins(treasury_amount.u128(), coin.denom.clone()),
-                    }
-                    .into(),
-                );
-            };
-            if owner_amount != Uint128::zero() {
-                messages.push(
-                    BankMsg::Send {
-                        to_address: ATLAS_DAO_STARGAZE_TREASURY.to_string(),
-                        amount: coins(owner_amount.u128(), coin.denom),
-                    }
-                    .into(),
-                );
-            };
-
-            Ok(messages)
+    // The ticket sales of each denom are split on their own
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for sales in raffle_sales(storage, raffle_id, &raffle_info)? {
+        // We start by splitting the fees between owner, treasury and radomness provider
+        let total_paid = sales.amount;
+        let (owner_amount, treasury_amount) =
+            compute_raffle_split(total_paid, fee_params.raffle_fee);
+        // The randomness provider cut comes out of the owner share
+        let (_, rand_amount) = compute_raffle_split(total_paid, fee_params.rand_fee);
+        let owner_amount = owner_amount - rand_amount;
+        // Part of the treasury cut may be given back to the buyers who didn't win
+        // They withdraw it themselves, so that the claim doesn't have to pay every one of them
+        let (consolation_share, losers) =
+            get_consolations(storage, raffle_id, &raffle_info, treasury_amount)?;
+        let consolations = consolation_share * Uint128::from(losers);
+        let treasury_amount = treasury_amount - consolations;
+        if !consolations.is_zero() {
+            CONSOLATIONS.save(storage, (raffle_id, &sales.denom), &consolation_share)?;
+            CONSOLATIONS_OWED.update(storage, &sales.denom, |owed| -> StdResult<_> {
+                Ok(owed.unwrap_or_default() + consolations)
+            })?;
         }
-        _ => Err(ContractError::WrongFundsType {}),
+
+        // Then we craft the messages needed for asset transfers
+        if rand_amount != Uint128::zero() {
+            messages.push(
+                BankMsg::Send { // TODO: Swap into $NOIS ?
+                    to_address: fee_params.treasury_addr.to_string(),
+                    amount: coins(rand_amount.u128(), sales.denom.clone()),
+                }
+                .into(),
+            );
+        };
+        if treasury_amount != Uint128::zero() {
+            TREASURY_EARNED.update(storage, &sales.denom, |earned| -> StdResult<_> {
+                Ok(earned.unwrap_or_default() + treasury_amount)
+            })?;
+            messages.push(
+                BankMsg::Send {
+                    to_address: fee_params.fee_addr.to_string(),
+                    amount: coins(treasury_amount.u128(), sales.denom.clone()),
+                }
+                .into(),
+            );
+        };
+        if owner_amount != Uint128::zero() {
+            messages.push(
+                BankMsg::Send {
+                    to_address: raffle_info.owner.to_string(),
+                    amount: coins(owner_amount.u128(), sales.denom),
+                }
+                .into(),
+            );
+        };
     }
+
+    Ok(messages)
+}
+
+/// Splits `consolation_bps` of the treasury cut evenly between the buyers who didn't win anything
+/// Returns the share of each of those buyers, and their number. What can't be split evenly stays with the treasury
+fn get_consolations(
+    storage: &dyn Storage,
+    raffle_id: u64,
+    raffle_info: &RaffleInfo,
+    treasury_amount: Uint128,
+) -> StdResult<(Uint128, u128)> {
+    let consolation_bps = match CONFIG.load(storage)?.consolation_bps {
+        Some(bps) if bps > 0 => bps,
+        _ => return Ok((Uint128::zero(), 0)),
+    };
+    let losers = TICKET_HOLDERS
+        .prefix(raffle_id)
+        .keys(storage, None, None, Order::Ascending)
+        .filter(|holder| match holder {
+            Ok(holder) => {
+                raffle_info.winner.as_ref() != Some(holder)
+                    && !raffle_info.bundle_winners.contains(holder)
+            }
+            Err(_) => true,
+        })
+        .collect::<StdResult<Vec<Addr>>>()?;
+    if losers.is_empty() {
+        return Ok((Uint128::zero(), 0));
+    }
+    let losers = losers.len() as u128;
+    let consolation = treasury_amount.multiply_ratio(consolation_bps, MAX_BPS);
+    Ok((consolation / Uint128::from(losers), losers))
 }
 
 /// Picking the winner of the raffle
@@ -97,36 +174,147 @@ pub fn get_raffle_winner(
     raffle_id: u64,
     raffle_info: RaffleInfo,
 ) -> Result<Addr, ContractError> {
-    let RandomnessParams {
-        nois_randomness,
-        requested: _,
-    } = NOIS_RANDOMNESS.load(deps.storage)?;
-
-    if nois_randomness.is_none() {
-        return Err(ContractError::WrongStateForClaim {
-            status: get_raffle_state(env, raffle_info),
-        });
-    }
+    // We get the randomness beacon the proxy sent for this raffle
+    let nois_randomness = match raffle_info
+        .randomness
+        .as_ref()
+        .and_then(|randomness| randomness.nois_randomness)
+    {
+        Some(nois_randomness) => nois_randomness,
+        None => {
+            return Err(ContractError::WrongStateForClaim {
+                status: get_raffle_state(env, raffle_info),
+            })
+        }
+    };
 
-    // TODO: get_nois_for_raffle(env, raffle_id)
+    // We pick a winner id
+    let winner_id = get_winner_ticket_id(nois_randomness, raffle_info.number_of_tickets);
+    let winner = RAFFLE_TICKETS.load(deps.storage, (raffle_id, winner_id))?;
 
-    // We initiate the random number generator
-    if raffle_info.randomness.is_none() {
-        return Err(ContractError::WrongStateForClaim {
-            status: get_raffle_state(env, raffle_info),
-        });
+    Ok(winner)
+}
+
+/// Derives the id of the winning ticket from a randomness beacon
+/// Tickets are indexed from 0 to `number_of_tickets - 1`
+pub fn get_winner_ticket_id(nois_randomness: [u8; 32], number_of_tickets: u32) -> u32 {
+    int_in_range(nois_randomness, 0, number_of_tickets)
+}
+
+/// Picking a winner for each prize bundle of the raffle
+/// The first bundle goes to the raffle winner, the next ones are drawn from sub-randomness of the same beacon.
+/// Each bundle is drawn independently, so a ticket can win more than one bundle
+pub fn get_raffle_bundle_winners(
+    deps: Deps,
+    env: Env,
+    raffle_id: u64,
+    raffle_info: RaffleInfo,
+) -> Result<Vec<Addr>, ContractError> {
+    let bundle_number = match &raffle_info.raffle_options.prize_bundles {
+        Some(prize_bundles) => prize_bundles.len(),
+        None => return Ok(vec![]),
+    };
+    let nois_randomness = match raffle_info
+        .randomness
+        .as_ref()
+        .and_then(|randomness| randomness.nois_randomness)
+    {
+        Some(nois_randomness) => nois_randomness,
+        None => {
+            return Err(ContractError::WrongStateForClaim {
+                status: get_raffle_state(env, raffle_info),
+            })
+        }
+    };
+
+    let mut winners = vec![get_raffle_winner(deps, env, raffle_id, raffle_info.clone())?];
+    let mut provider = sub_randomness(nois_randomness);
+    for _ in 1..bundle_number {
+        let winner_id = int_in_range(provider.provide(), 0, raffle_info.number_of_tickets);
+        winners.push(RAFFLE_TICKETS.load(deps.storage, (raffle_id, winner_id))?);
     }
-    // let mut rng: Prng = Prng::new(&raffle_info.randomness.unwrap().randomness);
+    Ok(winners)
+}
 
-    // We pick a winner id
-    let winner_id = int_in_range(
-        nois_randomness.expect("expect a value here"),
+/// Ticket sales of a raffle, one coin for its ticket price and one for each of its acceptable prices
+pub fn raffle_sales(
+    storage: &dyn Storage,
+    raffle_id: u64,
+    raffle_info: &RaffleInfo,
+) -> Result<Vec<Coin>, ContractError> {
+    let mut other_tickets = 0u32;
+    let mut sales = vec![];
+    for price in &raffle_info.raffle_options.acceptable_prices {
+        let price = match price {
+            AssetInfo::Coin(price) => price,
+            _ => return Err(ContractError::WrongFundsType {}),
+        };
+        let tickets = PRICE_TICKETS_PAID
+            .may_load(storage, (raffle_id, &price.denom))?
+            .unwrap_or(0);
+        other_tickets += tickets;
+        sales.push(coin(
+            (price.amount * Uint128::from(tickets)).u128(),
+            price.denom.clone(),
+        ));
+    }
+    let price = match &raffle_info.raffle_ticket_price {
+        AssetInfo::Coin(price) => price,
+        _ => return Err(ContractError::WrongFundsType {}),
+    };
+    let tickets = raffle_info.tickets_paid - other_tickets;
+    sales.insert(
         0,
-        raffle_info.number_of_tickets,
+        coin(
+            (price.amount * Uint128::from(tickets)).u128(),
+            price.denom.clone(),
+        ),
     );
-    let winner = RAFFLE_TICKETS.load(deps.storage, (raffle_id, winner_id))?;
+    Ok(sales)
+}
 
-    Ok(winner)
+/// Util to refund every ticket bought on a raffle to its buyer
+/// Buyers are refunded in one message each, whatever the number of tickets they bought
+pub fn get_ticket_refund_messages(
+    storage: &dyn Storage,
+    raffle_id: u64,
+    raffle_info: &RaffleInfo,
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    if raffle_info.number_of_tickets == 0 {
+        return Ok(vec![]);
+    }
+
+    // We list the buyers in the order of their first purchase
+    let mut buyers: Vec<Addr> = vec![];
+    for ticket in RAFFLE_TICKETS
+        .prefix(raffle_id)
+        .range(storage, None, None, Order::Ascending)
+    {
+        let (_, buyer) = ticket?;
+        if !buyers.contains(&buyer) {
+            buyers.push(buyer);
+        }
+    }
+
+    // Each buyer gets back what they paid, whatever the number of ticket slots they got
+    let mut messages = vec![];
+    for buyer in buyers {
+        let ticket_price = BUYER_PRICES.load(storage, (raffle_id, &buyer))?;
+        let paid_tickets = PAID_TICKETS
+            .may_load(storage, (raffle_id, &buyer))?
+            .unwrap_or(0);
+        let refund = ticket_price.amount * Uint128::from(paid_tickets);
+        if !refund.is_zero() {
+            messages.push(
+                BankMsg::Send {
+                    to_address: buyer.to_string(),
+                    amount: coins(refund.u128(), ticket_price.denom),
+                }
+                .into(),
+            );
+        }
+    }
+    Ok(messages)
 }
 
 /// Util to get the raffle creator messages to return when the Raffle is cancelled (returns the raffled asset)
@@ -144,26 +332,30 @@ fn _get_raffle_end_asset_messages(
     raffle_info
         .assets
         .iter()
-        .map(|asset| match asset {
-            AssetInfo::Cw721Coin(nft) => {
-                let message = Cw721ExecuteMsg::TransferNft {
-                    recipient: receiver.clone(),
-                    token_id: nft.token_id.clone(),
-                };
-                into_cosmos_msg(message, nft.address.clone(),None,)
-            }
-            AssetInfo::Sg721Token(sg721_token) => {
-                let message = Sg721ExecuteMsg::<Extension, Empty>::TransferNft {
-                    recipient: receiver.clone(),
-                    token_id: sg721_token.token_id.clone(),
-                };
-                into_cosmos_msg(message, sg721_token.address.clone(),None,)
-            }
-            _ => return Err(StdError::generic_err("unreachable")),
-        })
+        .map(|asset| _get_asset_transfer_message(asset, receiver.clone()))
         .collect()
 }
 
+fn _get_asset_transfer_message(asset: &AssetInfo, receiver: String) -> StdResult<CosmosMsg> {
+    match asset {
+        AssetInfo::Cw721Coin(nft) => {
+            let message = Cw721ExecuteMsg::TransferNft {
+                recipient: receiver,
+                token_id: nft.token_id.clone(),
+            };
+            into_cosmos_msg(message, nft.address.clone(),None,)
+        }
+        AssetInfo::Sg721Token(sg721_token) => {
+            let message = Sg721ExecuteMsg::<Extension, Empty>::TransferNft {
+                recipient: receiver,
+                token_id: sg721_token.token_id.clone(),
+            };
+            into_cosmos_msg(message, sg721_token.address.clone(),None,)
+        }
+        _ => Err(StdError::generic_err("unreachable")),
+    }
+}
+
 pub fn is_raffle_owner(
     storage: &dyn Storage,
     raffle_id: u64,
@@ -177,15 +369,18 @@ pub fn is_raffle_owner(
     }
 }
 
-/// Computes the ticket cost for multiple tickets bought together
+/// Computes the cost of multiple tickets bought together at a ticket price
 pub fn ticket_cost(
-    raffle_info: RaffleInfo,
+    ticket_price: AssetInfo,
     ticket_number: u32,
 ) -> Result<AssetInfo, ContractError> {
-    Ok(match raffle_info.raffle_ticket_price {
+    Ok(match ticket_price {
         AssetInfo::Coin(x) => AssetInfo::Coin(Coin {
             denom: x.denom,
-            amount: Uint128::from(ticket_number) * x.amount,
+            amount: x
+                .amount
+                .checked_mul(Uint128::from(ticket_number))
+                .map_err(StdError::from)?,
         }),
         // TODO: to set cost as Cw721Coin, we expect a possible
         // array of Cw721Coins as price cost.
@@ -200,17 +395,204 @@ pub fn ticket_cost(
 
 /// Can only buy a ticket when the raffle has started and is not closed
 pub fn can_buy_ticket(env: Env, raffle_info: RaffleInfo) -> Result<(), ContractError> {
-    if get_raffle_state(env, raffle_info) == RaffleState::Started {
-        Ok(())
-    } else {
-        return Err(ContractError::CantBuyTickets {});
+    match get_raffle_state(env, raffle_info) {
+        RaffleState::Started => Ok(()),
+        RaffleState::Created => Err(ContractError::RaffleNotStarted {}),
+        _ => Err(ContractError::CantBuyTickets {}),
     }
 }
 
+/// Checks `proof` leads from the `buyer` leaf to the allowlist `merkle_root`
+/// Leaves are the sha256 hash of the address, and each pair of nodes is sorted before being hashed together
+pub fn verify_allowlist_proof(
+    merkle_root: &HexBinary,
+    buyer: &Addr,
+    proof: Vec<String>,
+) -> Result<(), ContractError> {
+    let mut hash: [u8; 32] = Sha256::digest(buyer.as_bytes()).into();
+    for node in proof {
+        let node: [u8; 32] = HexBinary::from_hex(&node)
+            .ok()
+            .and_then(|node| node.to_array().ok())
+            .ok_or(ContractError::InvalidAllowlistProof {})?;
+        let (first, second) = if hash <= node { (hash, node) } else { (node, hash) };
+        hash = Sha256::new()
+            .chain_update(first)
+            .chain_update(second)
+            .finalize()
+            .into();
+    }
+    if hash.as_slice() != merkle_root.as_slice() {
+        return Err(ContractError::InvalidAllowlistProof {});
+    }
+    Ok(())
+}
+
+/// Runs every check a ticket purchase goes through, except the payment itself
+/// Returns the number of ticket slots the purchase gives to `buyer`, once their multiplier is applied
+pub fn check_ticket_purchase(
+    storage: &dyn Storage,
+    env: Env,
+    raffle_id: u64,
+    raffle_info: &RaffleInfo,
+    buyer: &Addr,
+    ticket_number: u32,
+    proof: Option<Vec<String>>,
+) -> Result<u32, ContractError> {
+    if CONFIG.load(storage)?.full_pause {
+        return Err(ContractError::ContractIsPaused {});
+    }
+
+    // Raffles with an allowlist need a proof that the buyer belongs to it
+    if let Some(merkle_root) = &raffle_info.raffle_options.merkle_root {
+        verify_allowlist_proof(merkle_root, buyer, proof.unwrap_or_default())?;
+    }
+
+    // Weighted buyers get more ticket slots for the same payment
+    // The address and raffle ticket limits apply to the slots, so a raffle never holds more tickets than allowed
+    let ticket_slots = TICKET_MULTIPLIERS
+        .may_load(storage, buyer)?
+        .unwrap_or(1)
+        .checked_mul(ticket_number)
+        .ok_or(ContractError::TicketOverflow {})?;
+
+    // We then check the raffle is in the right state and the ticket limits are respected
+    can_buy_tickets(
+        storage,
+        env,
+        raffle_id,
+        raffle_info,
+        buyer,
+        ticket_number,
+        ticket_slots,
+    )?;
+    Ok(ticket_slots)
+}
+
+/// Checks `buyer` can buy `ticket_number` more tickets on a raffle, giving them `ticket_slots` ticket slots
+/// This gathers all the checks on the raffle state and the ticket limits
+pub fn can_buy_tickets(
+    storage: &dyn Storage,
+    env: Env,
+    raffle_id: u64,
+    raffle_info: &RaffleInfo,
+    buyer: &Addr,
+    ticket_number: u32,
+    ticket_slots: u32,
+) -> Result<(), ContractError> {
+    // We check the raffle is in the right state
+    can_buy_ticket(env.clone(), raffle_info.clone())?;
+
+    // Then we check a single purchase doesn't go over the per transaction cap
+    if let Some(max_tickets_per_tx) = raffle_info.raffle_options.max_tickets_per_tx {
+        if ticket_number > max_tickets_per_tx {
+            return Err(ContractError::TooMuchTicketsInTx {
+                max: max_tickets_per_tx,
+                nb: ticket_number,
+            });
+        }
+    }
+
+    // Then we check the buyer waited long enough since their last purchase
+    if let Some(cooldown) = raffle_info.raffle_options.purchase_cooldown_seconds {
+        if let Some(last_purchase) = LAST_PURCHASE.may_load(storage, (raffle_id, buyer))? {
+            let until = last_purchase.plus_seconds(cooldown);
+            if env.block.time < until {
+                return Err(ContractError::PurchaseCooldownActive { until });
+            }
+        }
+    }
+
+    // Then we check the user has the right to hold `ticket_slots` more tickets
+    let current_ticket_number = USER_TICKETS.load(storage, (buyer, raffle_id)).unwrap_or(0);
+    let user_ticket_number = current_ticket_number
+        .checked_add(ticket_slots)
+        .ok_or(ContractError::TicketOverflow {})?;
+    if let Some(max_ticket_per_address) = raffle_info.raffle_options.max_ticket_per_address {
+        if user_ticket_number > max_ticket_per_address {
+            return Err(ContractError::TooMuchTicketsForUser {
+                max: max_ticket_per_address,
+                nb_before: current_ticket_number,
+                nb_after: user_ticket_number,
+            });
+        }
+    }
+
+    // Then we check there are some ticket left to buy
+    let total_ticket_number = raffle_info
+        .number_of_tickets
+        .checked_add(ticket_slots)
+        .ok_or(ContractError::TicketOverflow {})?;
+    if let Some(max_participant_number) = raffle_info.raffle_options.max_participant_number {
+        if total_ticket_number > max_participant_number {
+            return Err(ContractError::TooMuchTickets {
+                max: max_participant_number,
+                nb_before: raffle_info.number_of_tickets,
+                nb_after: total_ticket_number,
+            });
+        }
+    };
+    Ok(())
+}
+
 // RAFFLE WINNER 
 
 /// Util to get the winner messages to return when claiming a Raffle (returns the raffled asset)
+/// When the prize is split in bundles, each bundle goes to its own winner
 pub fn get_raffle_winner_messages(env: Env, raffle_info: RaffleInfo) -> StdResult<Vec<CosmosMsg>> {
     let winner: Addr = raffle_info.winner.clone().unwrap();
-    _get_raffle_end_asset_messages(env, raffle_info, winner.to_string())
-}
\ No newline at end of file
+    match &raffle_info.raffle_options.prize_bundles {
+        Some(prize_bundles) if !raffle_info.bundle_winners.is_empty() => prize_bundles
+            .iter()
+            .zip(raffle_info.bundle_winners.iter())
+            .flat_map(|(bundle, bundle_winner)| {
+                bundle.iter().map(|&index| {
+                    _get_asset_transfer_message(&raffle_info.assets[index], bundle_winner.to_string())
+                })
+            })
+            .collect(),
+        _ => _get_raffle_end_asset_messages(env, raffle_info, winner.to_string()),
+    }
+}
+
+/// Adds `ticket_number` tickets to the count of `owner` on a raffle
+/// Returns the new ticket count of the owner
+pub fn add_user_tickets(
+    storage: &mut dyn Storage,
+    owner: &Addr,
+    raffle_id: u64,
+    ticket_number: u32,
+) -> Result<u32, ContractError> {
+    let user_tickets = USER_TICKETS
+        .may_load(storage, (owner, raffle_id))?
+        .unwrap_or(0)
+        .checked_add(ticket_number)
+        .ok_or(ContractError::TicketOverflow {})?;
+    USER_TICKETS.save(storage, (owner, raffle_id), &user_tickets)?;
+    TICKET_HOLDERS.save(storage, (raffle_id, owner), &user_tickets)?;
+    Ok(user_tickets)
+}
+
+/// Removes `ticket_number` tickets from the count of `owner` on a raffle
+/// The owner isn't listed as a ticket holder anymore once they have no tickets left
+pub fn remove_user_tickets(
+    storage: &mut dyn Storage,
+    owner: &Addr,
+    raffle_id: u64,
+    ticket_number: u32,
+) -> Result<u32, ContractError> {
+    let owned = USER_TICKETS
+        .may_load(storage, (owner, raffle_id))?
+        .unwrap_or(0);
+    let user_tickets = owned
+        .checked_sub(ticket_number)
+        .ok_or(ContractError::NotEnoughTicketsToRefund { owned })?;
+    if user_tickets == 0 {
+        USER_TICKETS.remove(storage, (owner, raffle_id));
+        TICKET_HOLDERS.remove(storage, (raffle_id, owner));
+    } else {
+        USER_TICKETS.save(storage, (owner, raffle_id), &user_tickets)?;
+        TICKET_HOLDERS.save(storage, (raffle_id, owner), &user_tickets)?;
+    }
+    Ok(user_tickets)
+}
@@ -27,6 +27,7 @@ impl InstantiateMsg {
 }
 
 #[cw_serde]
+#[allow(clippy::large_enum_variant)]
 pub enum ExecuteMsg {
     PayFeeAndWithdraw {
         trade_id: u64,
@@ -1,4 +1,4 @@
-use cosmwasm_std::{Addr, Uint128, Coin};
+use cosmwasm_std::{Addr, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -50,6 +50,23 @@ impl AssetInfo {
     }
 }
 
+/// Returns the index of the first NFT that already appears earlier in `assets`
+/// A Cw721 and an Sg721 token with the same collection and token id are the same NFT
+pub fn find_duplicate_nft(assets: &[AssetInfo]) -> Option<usize> {
+    let nft_key = |asset: &AssetInfo| match asset {
+        AssetInfo::Cw721Coin(token) => Some((token.address.clone(), token.token_id.clone())),
+        AssetInfo::Sg721Token(token) => Some((token.address.clone(), token.token_id.clone())),
+        AssetInfo::Coin(_) => None,
+    };
+    assets.iter().enumerate().position(|(index, asset)| {
+        nft_key(asset).is_some_and(|key| {
+            assets[..index]
+                .iter()
+                .any(|previous| nft_key(previous).as_ref() == Some(&key))
+        })
+    })
+}
+
 pub fn is_valid_name(name: &str) -> bool {
     let bytes = name.as_bytes();
     if bytes.len() < 3 || bytes.len() > 50 {
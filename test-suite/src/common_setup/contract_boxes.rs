@@ -1,4 +1,13 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    from_json, to_json_binary, to_json_vec, Binary, Coin, Deps, DepsMut, Empty, Env, MessageInfo,
+    Response, StdError, StdResult, WasmMsg,
+};
+use cw721::{Cw721QueryMsg, OwnerOfResponse};
 use cw_multi_test::{Contract, ContractWrapper, };
+use fee_distributor_export::msg::ExecuteMsg as FeeDistributorMsg;
+use nois::ProxyExecuteMsg;
+use raffles::msg::{FloorResponse, OracleQueryMsg, VerifierQueryMsg, VerifyResponse};
 use sg_multi_test::StargazeApp;
 use sg_std::StargazeMsgWrapper;
 
@@ -11,7 +20,8 @@ pub fn contract_raffles() -> Box<dyn Contract<StargazeMsgWrapper>> {
         raffles::contract::execute,
         raffles::contract::instantiate,
         raffles::contract::query,
-    );
+    )
+    .with_reply(raffles::contract::reply);
     // .with_sudo(vending_factory::contract::sudo);
     Box::new(contract)
 }
@@ -45,12 +55,169 @@ pub fn contract_sg721_base() -> Box<dyn Contract<StargazeMsgWrapper>> {
     Box::new(contract)
 }
 
-// pub fn contract_nft_loans() -> Box<dyn Contract<StargazeMsgWrapper>> {
-//     let contract = ContractWrapper::new(
-//         nft_loans::contract::execute,
-//         nft_loans::contract::instantiate,
-//         nft_loans::contract::query,
-//     );
-//     // .with_sudo(vending_factory::contract::sudo);
-//     Box::new(contract)
-// }
\ No newline at end of file
+pub fn contract_nft_loans() -> Box<dyn Contract<StargazeMsgWrapper>> {
+    let contract = ContractWrapper::new(
+        nft_loans::contract::execute,
+        nft_loans::contract::instantiate,
+        nft_loans::contract::query,
+    )
+    .with_reply(nft_loans::contract::reply);
+    Box::new(contract)
+}
+
+/// Mock fee distributor, it accepts any fee deposit and keeps the funds sent along
+pub fn contract_fee_distributor() -> Box<dyn Contract<StargazeMsgWrapper>> {
+    let contract = ContractWrapper::new_with_empty(
+        |_deps: DepsMut, _env: Env, _info: MessageInfo, _msg: FeeDistributorMsg| -> StdResult<Response> {
+            Ok(Response::default())
+        },
+        |_deps: DepsMut, _env: Env, _info: MessageInfo, _msg: Empty| -> StdResult<Response> {
+            Ok(Response::default())
+        },
+        |_deps: Deps, _env: Env, _msg: Empty| -> StdResult<Binary> {
+            Err(StdError::generic_err("no queries on the mock fee distributor"))
+        },
+    );
+    Box::new(contract)
+}
+
+/// Mock nois proxy, it accepts any randomness request along with its fee
+/// The requested job id is emitted as an attribute
+/// The randomness callback has to be sent manually from the proxy address
+pub fn contract_nois_proxy() -> Box<dyn Contract<StargazeMsgWrapper>> {
+    let contract = ContractWrapper::new_with_empty(
+        |_deps: DepsMut, _env: Env, _info: MessageInfo, msg: ProxyExecuteMsg| -> StdResult<Response> {
+            match msg {
+                ProxyExecuteMsg::GetNextRandomness { job_id } => {
+                    Ok(Response::new().add_attribute("job_id", job_id))
+                }
+                _ => Ok(Response::default()),
+            }
+        },
+        |_deps: DepsMut, _env: Env, _info: MessageInfo, _msg: Empty| -> StdResult<Response> {
+            Ok(Response::default())
+        },
+        |_deps: Deps, _env: Env, _msg: Empty| -> StdResult<Binary> {
+            Err(StdError::generic_err("no queries on the mock nois proxy"))
+        },
+    );
+    Box::new(contract)
+}
+
+/// Mock drand verifier, any beacon with a non empty signature is valid
+pub fn contract_drand_verifier() -> Box<dyn Contract<StargazeMsgWrapper>> {
+    let contract = ContractWrapper::new_with_empty(
+        |_deps: DepsMut, _env: Env, _info: MessageInfo, _msg: Empty| -> StdResult<Response> {
+            Ok(Response::default())
+        },
+        |_deps: DepsMut, _env: Env, _info: MessageInfo, _msg: Empty| -> StdResult<Response> {
+            Ok(Response::default())
+        },
+        |_deps: Deps, _env: Env, msg: VerifierQueryMsg| -> StdResult<Binary> {
+            match msg {
+                VerifierQueryMsg::Verify { signature, .. } => to_json_binary(&VerifyResponse {
+                    valid: !signature.is_empty(),
+                }),
+            }
+        },
+    );
+    Box::new(contract)
+}
+
+/// Mock price oracle, it knows the floors it was instantiated with
+pub fn contract_price_oracle() -> Box<dyn Contract<StargazeMsgWrapper>> {
+    let contract = ContractWrapper::new_with_empty(
+        |_deps: DepsMut, _env: Env, _info: MessageInfo, _msg: Empty| -> StdResult<Response> {
+            Ok(Response::default())
+        },
+        |deps: DepsMut,
+         _env: Env,
+         _info: MessageInfo,
+         floors: Vec<(String, Coin)>|
+         -> StdResult<Response> {
+            for (collection, floor) in floors {
+                deps.storage.set(collection.as_bytes(), &to_json_vec(&floor)?);
+            }
+            Ok(Response::default())
+        },
+        |deps: Deps, _env: Env, msg: OracleQueryMsg| -> StdResult<Binary> {
+            match msg {
+                OracleQueryMsg::Floor { collection } => to_json_binary(&FloorResponse {
+                    floor: deps
+                        .storage
+                        .get(collection.as_bytes())
+                        .map(from_json)
+                        .transpose()?,
+                }),
+            }
+        },
+    );
+    Box::new(contract)
+}
+
+#[cw_serde]
+pub enum ReentrantNftMsg {
+    TransferNft { recipient: String, token_id: String },
+    // When set, transferring a token out of a contract makes it claim this raffle again
+    ReenterClaim { raffle_id: Option<u64> },
+}
+
+const REENTER_CLAIM_KEY: &[u8] = b"reenter_claim";
+
+/// Mock cw721 collection, anyone can transfer any token
+/// It can be set to call back the raffle contract that transfers a prize out, to simulate a re-entrant claim
+pub fn contract_reentrant_nft() -> Box<dyn Contract<StargazeMsgWrapper>> {
+    let contract = ContractWrapper::new_with_empty(
+        |deps: DepsMut, _env: Env, info: MessageInfo, msg: ReentrantNftMsg| -> StdResult<Response> {
+            match msg {
+                ReentrantNftMsg::TransferNft {
+                    recipient,
+                    token_id,
+                } => {
+                    deps.storage.set(token_id.as_bytes(), recipient.as_bytes());
+                    let reenter_claim = deps.storage.get(REENTER_CLAIM_KEY);
+                    match reenter_claim {
+                        Some(raffle_id) if recipient != info.sender.as_str() => {
+                            Ok(Response::new().add_message(WasmMsg::Execute {
+                                contract_addr: info.sender.to_string(),
+                                msg: to_json_binary(&raffles::msg::ExecuteMsg::ClaimNft {
+                                    raffle_id: u64::from_be_bytes(raffle_id.try_into().unwrap()),
+                                })?,
+                                funds: vec![],
+                            }))
+                        }
+                        _ => Ok(Response::default()),
+                    }
+                }
+                ReentrantNftMsg::ReenterClaim { raffle_id } => {
+                    match raffle_id {
+                        Some(raffle_id) => deps
+                            .storage
+                            .set(REENTER_CLAIM_KEY, &raffle_id.to_be_bytes()),
+                        None => deps.storage.remove(REENTER_CLAIM_KEY),
+                    }
+                    Ok(Response::default())
+                }
+            }
+        },
+        |_deps: DepsMut, _env: Env, _info: MessageInfo, _msg: Empty| -> StdResult<Response> {
+            Ok(Response::default())
+        },
+        |deps: Deps, _env: Env, msg: Cw721QueryMsg| -> StdResult<Binary> {
+            match msg {
+                Cw721QueryMsg::OwnerOf { token_id, .. } => {
+                    let owner = deps
+                        .storage
+                        .get(token_id.as_bytes())
+                        .ok_or_else(|| StdError::not_found("token"))?;
+                    to_json_binary(&OwnerOfResponse {
+                        owner: String::from_utf8(owner)?,
+                        approvals: vec![],
+                    })
+                }
+                _ => Err(StdError::generic_err("unsupported query on the mock collection")),
+            }
+        },
+    );
+    Box::new(contract)
+}
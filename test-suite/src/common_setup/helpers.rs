@@ -0,0 +1,582 @@
+use cosmwasm_std::{coin, Addr, BlockInfo, Coin, Decimal, Empty, HexBinary, Timestamp, Uint128};
+use cw721::OwnerOfResponse;
+use cw_multi_test::{AppResponse, BankSudo, Executor, SudoMsg};
+use nft_loans::state::LoanTerms;
+use nois::NoisCallback;
+use raffles::{
+    msg::InstantiateMsg,
+    state::{RaffleOptionsMsg, MINIMUM_RAFFLE_TIMEOUT},
+};
+use sg721::CollectionInfo;
+use sg_multi_test::StargazeApp;
+use sg_std::NATIVE_DENOM;
+use utils::state::AssetInfo;
+use vending_factory::{
+    msg::{VendingMinterCreateMsg, VendingMinterInitMsgExtension},
+    state::{ParamsExtension, VendingMinterParams},
+};
+
+use crate::common_setup::contract_boxes::{
+    contract_fee_distributor, contract_nft_loans, contract_nois_proxy, contract_raffles,
+    contract_sg721_base, contract_vending_factory, contract_vending_minter, custom_mock_app,
+};
+
+pub const OWNER_ADDR: &str = "owner";
+pub const FEE_ADDR: &str = "fee";
+pub const NOIS_AMOUNT: u128 = 50;
+pub const MINT_PRICE: u128 = 100_000;
+pub const GENESIS_TIME: u64 = 1647032400000000000;
+pub const LOAN_FEE_PERCENT: u64 = 5;
+
+pub struct RaffleContracts {
+    pub raffle: Addr,
+    pub nois_proxy: Addr,
+    pub factory: Addr,
+    pub sg721_code_id: u64,
+}
+
+/// Creates a new app, with the block time set at genesis
+pub fn genesis_app() -> StargazeApp {
+    let mut app = custom_mock_app();
+    let chain_id = app.block_info().chain_id;
+    app.set_block(BlockInfo {
+        height: 10000,
+        time: Timestamp::from_nanos(GENESIS_TIME),
+        chain_id,
+    });
+    app
+}
+
+/// Instantiates the raffle contract along with a mock nois proxy and a vending factory
+pub fn setup_raffle_contracts() -> (StargazeApp, RaffleContracts) {
+    setup_raffle_contracts_with(|_| {})
+}
+
+/// Same as `setup_raffle_contracts`, but allows to customize the raffle instantiate message
+pub fn setup_raffle_contracts_with(
+    customize: impl FnOnce(&mut InstantiateMsg),
+) -> (StargazeApp, RaffleContracts) {
+    let mut app = genesis_app();
+    let nois_id = app.store_code(contract_nois_proxy());
+    let raffle_id = app.store_code(contract_raffles());
+
+    let nois_proxy = app
+        .instantiate_contract(
+            nois_id,
+            Addr::unchecked(OWNER_ADDR),
+            &Empty {},
+            &[],
+            "nois-proxy",
+            None,
+        )
+        .unwrap();
+
+    let mut raffle_msg = InstantiateMsg {
+        name: "raffle param name".to_string(),
+        nois_proxy_addr: nois_proxy.to_string(),
+        nois_proxy_denom: NATIVE_DENOM.to_string(),
+        nois_proxy_amount: NOIS_AMOUNT.into(),
+        creation_fee_denom: None,
+        creation_fee_amount: None,
+        owner: Some(OWNER_ADDR.to_string()),
+        fee_addr: Some(FEE_ADDR.to_string()),
+        minimum_raffle_duration: None,
+        minimum_raffle_timeout: None,
+        max_participant_number: None,
+        raffle_fee: None,
+        rand_fee: None,
+        job_id_prefix: None,
+        cancel_lockout_seconds: None,
+    };
+    customize(&mut raffle_msg);
+    let raffle = app
+        .instantiate_contract(
+            raffle_id,
+            Addr::unchecked(OWNER_ADDR),
+            &raffle_msg,
+            &[],
+            "raffle",
+            None,
+        )
+        .unwrap();
+
+    let (factory, sg721_code_id) = instantiate_vending_factory(&mut app);
+
+    (
+        app,
+        RaffleContracts {
+            raffle,
+            nois_proxy,
+            factory,
+            sg721_code_id,
+        },
+    )
+}
+
+pub struct LoanContracts {
+    pub loan: Addr,
+    pub fee_distributor: Addr,
+    pub factory: Addr,
+    pub sg721_code_id: u64,
+}
+
+/// Instantiates the loan contract along with a mock fee distributor and a vending factory
+pub fn setup_loan_contracts() -> (StargazeApp, LoanContracts) {
+    setup_loan_contracts_with(|_| {})
+}
+
+/// Same as `setup_loan_contracts`, but allows to customize the loan instantiate message
+pub fn setup_loan_contracts_with(
+    customize: impl FnOnce(&mut nft_loans::msg::InstantiateMsg),
+) -> (StargazeApp, LoanContracts) {
+    let mut app = genesis_app();
+    let fee_distributor_id = app.store_code(contract_fee_distributor());
+    let loan_id = app.store_code(contract_nft_loans());
+
+    let fee_distributor = app
+        .instantiate_contract(
+            fee_distributor_id,
+            Addr::unchecked(OWNER_ADDR),
+            &Empty {},
+            &[],
+            "fee-distributor",
+            None,
+        )
+        .unwrap();
+
+    let mut loan_msg = nft_loans::msg::InstantiateMsg {
+        name: "loan param name".to_string(),
+        owner: Some(OWNER_ADDR.to_string()),
+        fee_distributor: fee_distributor.to_string(),
+        fee_rate: Decimal::percent(LOAN_FEE_PERCENT),
+        min_interest: None,
+    };
+    customize(&mut loan_msg);
+    let loan = app
+        .instantiate_contract(
+            loan_id,
+            Addr::unchecked(OWNER_ADDR),
+            &loan_msg,
+            &[],
+            "nft-loan",
+            None,
+        )
+        .unwrap();
+
+    let (factory, sg721_code_id) = instantiate_vending_factory(&mut app);
+
+    (
+        app,
+        LoanContracts {
+            loan,
+            fee_distributor,
+            factory,
+            sg721_code_id,
+        },
+    )
+}
+
+/// Instantiates a vending factory that can be used to create new collections
+/// Returns the factory address and the sg721 code id it accepts
+pub fn instantiate_vending_factory(app: &mut StargazeApp) -> (Addr, u64) {
+    let factory_id = app.store_code(contract_vending_factory());
+    let minter_id = app.store_code(contract_vending_minter());
+    let sg721_id = app.store_code(contract_sg721_base());
+
+    let factory = app
+        .instantiate_contract(
+            factory_id,
+            Addr::unchecked(OWNER_ADDR),
+            &vending_factory::msg::InstantiateMsg {
+                params: VendingMinterParams {
+                    code_id: minter_id,
+                    allowed_sg721_code_ids: vec![sg721_id],
+                    frozen: false,
+                    creation_fee: coin(MINT_PRICE, NATIVE_DENOM),
+                    min_mint_price: coin(MINT_PRICE, NATIVE_DENOM),
+                    mint_fee_bps: 10,
+                    max_trading_offset_secs: 0,
+                    extension: ParamsExtension {
+                        max_token_limit: 1000,
+                        max_per_address_limit: 50,
+                        airdrop_mint_price: coin(MINT_PRICE, NATIVE_DENOM),
+                        airdrop_mint_fee_bps: 10,
+                        shuffle_fee: coin(MINT_PRICE, NATIVE_DENOM),
+                    },
+                },
+            },
+            &[],
+            "factory",
+            Some(OWNER_ADDR.to_string()),
+        )
+        .unwrap();
+    (factory, sg721_id)
+}
+
+/// Gives native funds to an address
+pub fn mint_native(app: &mut StargazeApp, recipient: &str, amount: u128) {
+    app.sudo(SudoMsg::Bank(BankSudo::Mint {
+        to_address: recipient.to_string(),
+        amount: vec![coin(amount, NATIVE_DENOM)],
+    }))
+    .unwrap();
+}
+
+/// Overwrites a raw entry in the storage of a contract, to simulate a state corrupted by a bug
+pub fn write_contract_storage(app: &mut StargazeApp, contract: &Addr, key: &[u8], value: &[u8]) {
+    // Contract storages live under the length-prefixed "wasm" and "contract_data/<address>" namespaces
+    let contract_namespace = [b"contract_data/".as_slice(), contract.as_bytes()].concat();
+    let mut full_key = vec![];
+    for namespace in [b"wasm".as_slice(), &contract_namespace] {
+        full_key.extend_from_slice(&(namespace.len() as u16).to_be_bytes());
+        full_key.extend_from_slice(namespace);
+    }
+    full_key.extend_from_slice(key);
+    app.init_modules(|_, _, storage| storage.set(&full_key, value));
+}
+
+/// Returns the value of an attribute emitted in a response
+pub fn find_attribute(response: &AppResponse, key: &str) -> Option<String> {
+    response
+        .events
+        .iter()
+        .flat_map(|event| event.attributes.iter())
+        .find(|attribute| attribute.key == key)
+        .map(|attribute| attribute.value.clone())
+}
+
+/// Creates a new collection through the vending factory
+/// Returns the minter and the sg721 collection addresses
+pub fn create_collection(
+    app: &mut StargazeApp,
+    factory: &Addr,
+    sg721_code_id: u64,
+) -> (Addr, Addr) {
+    mint_native(app, OWNER_ADDR, MINT_PRICE);
+    let start_time = app.block_info().time;
+    let response = app
+        .execute_contract(
+            Addr::unchecked(OWNER_ADDR),
+            factory.clone(),
+            &vending_factory::msg::ExecuteMsg::CreateMinter(VendingMinterCreateMsg {
+                init_msg: VendingMinterInitMsgExtension {
+                    base_token_uri: "ipfs://aldkfjads".to_string(),
+                    payment_address: Some(OWNER_ADDR.to_string()),
+                    start_time,
+                    num_tokens: 100,
+                    mint_price: coin(MINT_PRICE, NATIVE_DENOM),
+                    per_address_limit: 3,
+                    whitelist: None,
+                },
+                collection_params: sg2::msg::CollectionParams {
+                    code_id: sg721_code_id,
+                    name: "Collection Name".to_string(),
+                    symbol: "COL".to_string(),
+                    info: CollectionInfo {
+                        creator: "creator".to_string(),
+                        description: String::from("Stargaze Monkeys"),
+                        image: "https://example.com/image.png".to_string(),
+                        external_link: Some("https://example.com/external.html".to_string()),
+                        start_trading_time: None,
+                        explicit_content: Some(false),
+                        royalty_info: None,
+                    },
+                },
+            }),
+            &[coin(MINT_PRICE, NATIVE_DENOM)],
+        )
+        .unwrap();
+
+    // The minter is instantiated first, it then instantiates the collection
+    let mut instantiated = response
+        .events
+        .iter()
+        .filter(|event| event.ty == "instantiate")
+        .flat_map(|event| event.attributes.iter())
+        .filter(|attribute| attribute.key == "_contract_addr")
+        .map(|attribute| Addr::unchecked(attribute.value.clone()));
+    let minter = instantiated.next().unwrap();
+    let collection = instantiated.next().unwrap();
+    (minter, collection)
+}
+
+/// Mints a token to `owner` and approves `spender` on it
+/// Returns the token id
+pub fn mint_and_approve(
+    app: &mut StargazeApp,
+    minter: &Addr,
+    collection: &Addr,
+    owner: &str,
+    spender: &Addr,
+) -> String {
+    mint_native(app, owner, MINT_PRICE);
+    let response = app
+        .execute_contract(
+            Addr::unchecked(owner),
+            minter.clone(),
+            &vending_minter::msg::ExecuteMsg::Mint {},
+            &[coin(MINT_PRICE, NATIVE_DENOM)],
+        )
+        .unwrap();
+    let token_id = find_attribute(&response, "token_id").unwrap();
+
+    app.execute_contract(
+        Addr::unchecked(owner),
+        collection.clone(),
+        &sg721_base::msg::ExecuteMsg::<Empty, Empty>::Approve {
+            spender: spender.to_string(),
+            token_id: token_id.clone(),
+            expires: None,
+        },
+        &[],
+    )
+    .unwrap();
+    token_id
+}
+
+/// Raffle options with every parameter left to its default value
+pub fn default_raffle_options() -> RaffleOptionsMsg {
+    RaffleOptionsMsg {
+        raffle_start_timestamp: None,
+        raffle_duration: None,
+        raffle_timeout: None,
+        comment: None,
+        max_participant_number: None,
+        max_ticket_per_address: None,
+        max_tickets_per_tx: None,
+        raffle_preview: None,
+        auto_extend_if_below: None,
+        prize_bundles: None,
+        refundable_until: None,
+        merkle_root: None,
+        purchase_cooldown_seconds: None,
+        auto_request_randomness: None,
+        acceptable_prices: None,
+    }
+}
+
+/// Creates a raffle on the given assets, with a ticket price in the native denom
+/// Returns the raffle id
+pub fn create_raffle(
+    app: &mut StargazeApp,
+    raffle: &Addr,
+    owner: &str,
+    assets: Vec<AssetInfo>,
+    ticket_price: u128,
+    raffle_options: RaffleOptionsMsg,
+) -> anyhow::Result<u64> {
+    let response = app.execute_contract(
+        Addr::unchecked(owner),
+        raffle.clone(),
+        &raffles::msg::ExecuteMsg::CreateRaffle {
+            owner: None,
+            assets,
+            raffle_options,
+            raffle_ticket_price: AssetInfo::coin(ticket_price, NATIVE_DENOM),
+        },
+        &[],
+    )?;
+    Ok(find_attribute(&response, "raffle_id").unwrap().parse().unwrap())
+}
+
+/// Buys `ticket_number` tickets for `buyer`, minting the needed funds beforehand
+pub fn buy_tickets(
+    app: &mut StargazeApp,
+    raffle: &Addr,
+    buyer: &str,
+    raffle_id: u64,
+    ticket_number: u32,
+    ticket_price: u128,
+) -> anyhow::Result<AppResponse> {
+    let cost = ticket_price * u128::from(ticket_number);
+    if cost > 0 {
+        mint_native(app, buyer, cost);
+    }
+    app.execute_contract(
+        Addr::unchecked(buyer),
+        raffle.clone(),
+        &raffles::msg::ExecuteMsg::BuyTicket {
+            raffle_id,
+            ticket_number,
+            sent_assets: AssetInfo::coin(ticket_price * u128::from(ticket_number), NATIVE_DENOM),
+            proof: None,
+        },
+        &if cost > 0 {
+            vec![coin(cost, NATIVE_DENOM)]
+        } else {
+            vec![]
+        },
+    )
+}
+
+/// Loan terms with a principle in the native denom
+pub fn loan_terms(principle: u128, interest: u128, duration_in_blocks: u64) -> LoanTerms {
+    LoanTerms {
+        principle: coin(principle, NATIVE_DENOM),
+        interest: Uint128::new(interest),
+        duration_in_blocks,
+        duration_in_seconds: None,
+        early_repay_penalty: None,
+        min_duration_blocks: None,
+    }
+}
+
+/// Lists the assets of `borrower` as a loan collateral
+/// Returns the loan id
+pub fn deposit_collaterals(
+    app: &mut StargazeApp,
+    loan: &Addr,
+    borrower: &str,
+    tokens: Vec<AssetInfo>,
+    terms: Option<LoanTerms>,
+) -> anyhow::Result<u64> {
+    let response = app.execute_contract(
+        Addr::unchecked(borrower),
+        loan.clone(),
+        &nft_loans::msg::ExecuteMsg::DepositCollaterals {
+            tokens,
+            terms,
+            comment: None,
+            loan_preview: None,
+            listing_expiry: None,
+        },
+        &[],
+    )?;
+    Ok(find_attribute(&response, "loan_id").unwrap().parse().unwrap())
+}
+
+/// Makes an offer on a loan, minting the principle to `lender` beforehand
+/// Returns the global offer id
+pub fn make_offer(
+    app: &mut StargazeApp,
+    loan: &Addr,
+    lender: &str,
+    borrower: &str,
+    loan_id: u64,
+    terms: LoanTerms,
+) -> anyhow::Result<String> {
+    mint_native(app, lender, terms.principle.amount.u128());
+    let response = app.execute_contract(
+        Addr::unchecked(lender),
+        loan.clone(),
+        &nft_loans::msg::ExecuteMsg::MakeOffer {
+            borrower: borrower.to_string(),
+            loan_id,
+            terms: terms.clone(),
+            comment: None,
+            memo: None,
+        },
+        &[terms.principle],
+    )?;
+    Ok(find_attribute(&response, "global_offer_id").unwrap())
+}
+
+/// Lists a freshly minted NFT as collateral and has `lender` fund it with `terms`
+/// Returns the loan id, the collection and the token id
+pub fn start_loan(
+    app: &mut StargazeApp,
+    contracts: &LoanContracts,
+    borrower: &str,
+    lender: &str,
+    terms: LoanTerms,
+) -> (u64, Addr, String) {
+    let (minter, collection) = create_collection(app, &contracts.factory, contracts.sg721_code_id);
+    let token_id = mint_and_approve(app, &minter, &collection, borrower, &contracts.loan);
+    let loan_id = deposit_collaterals(
+        app,
+        &contracts.loan,
+        borrower,
+        vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+        None,
+    )
+    .unwrap();
+    let global_offer_id =
+        make_offer(app, &contracts.loan, lender, borrower, loan_id, terms).unwrap();
+    app.execute_contract(
+        Addr::unchecked(borrower),
+        contracts.loan.clone(),
+        &nft_loans::msg::ExecuteMsg::AcceptOffer { global_offer_id },
+        &[],
+    )
+    .unwrap();
+    (loan_id, collection, token_id)
+}
+
+/// Moves the block time forward
+pub fn advance_time(app: &mut StargazeApp, seconds: u64) {
+    app.update_block(|block| {
+        block.time = block.time.plus_seconds(seconds);
+        block.height += seconds / 5;
+    });
+}
+
+/// Sends a randomness beacon to the raffle contract, as the nois proxy would
+pub fn send_nois_randomness(
+    app: &mut StargazeApp,
+    contracts: &RaffleContracts,
+    raffle_id: u64,
+    randomness: [u8; 32],
+) -> anyhow::Result<AppResponse> {
+    let published = app.block_info().time;
+    let config: raffles::msg::ConfigResponse = app
+        .wrap()
+        .query_wasm_smart(contracts.raffle.clone(), &raffles::msg::QueryMsg::Config {})?;
+    app.execute_contract(
+        contracts.nois_proxy.clone(),
+        contracts.raffle.clone(),
+        &raffles::msg::ExecuteMsg::NoisReceive {
+            callback: NoisCallback {
+                job_id: format!("{}{raffle_id}", config.job_id_prefix),
+                published,
+                randomness: HexBinary::from(randomness.to_vec()),
+            },
+        },
+        &[],
+    )
+}
+
+/// Requests and delivers the randomness of a closed raffle, the contract paying the nois fee
+/// The time is then moved past the raffle timeout
+pub fn draw_raffle(
+    app: &mut StargazeApp,
+    contracts: &RaffleContracts,
+    raffle_id: u64,
+    randomness: [u8; 32],
+) {
+    mint_native(app, contracts.raffle.as_str(), NOIS_AMOUNT);
+    app.execute_contract(
+        Addr::unchecked(OWNER_ADDR),
+        contracts.raffle.clone(),
+        &raffles::msg::ExecuteMsg::UpdateRandomness { raffle_id },
+        &[],
+    )
+    .unwrap();
+    send_nois_randomness(app, contracts, raffle_id, randomness).unwrap();
+    advance_time(app, MINIMUM_RAFFLE_TIMEOUT);
+}
+
+/// Returns the native balance of an address
+pub fn native_balance(app: &StargazeApp, address: &str) -> Uint128 {
+    app.wrap()
+        .query_balance(address, NATIVE_DENOM)
+        .unwrap_or(Coin {
+            denom: NATIVE_DENOM.to_string(),
+            amount: Uint128::zero(),
+        })
+        .amount
+}
+
+/// Returns the current owner of an NFT
+pub fn nft_owner(app: &StargazeApp, collection: &Addr, token_id: &str) -> String {
+    let response: OwnerOfResponse = app
+        .wrap()
+        .query_wasm_smart(
+            collection.clone(),
+            &sg721_base::msg::QueryMsg::OwnerOf {
+                token_id: token_id.to_string(),
+                include_expired: None,
+            },
+        )
+        .unwrap();
+    response.owner
+}
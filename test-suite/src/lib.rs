@@ -1,6 +1,6 @@
 pub mod common_setup;
 
-// #[cfg(test)]
-// mod nft_loan;
+#[cfg(test)]
+mod nft_loan;
 #[cfg(test)]
 mod raffle;
\ No newline at end of file
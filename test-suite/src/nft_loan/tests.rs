@@ -0,0 +1,2 @@
+pub mod integration_tests;
+pub mod queries;
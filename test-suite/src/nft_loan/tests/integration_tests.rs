@@ -0,0 +1,2179 @@
+#[cfg(test)]
+mod tests {
+    mod min_interest {
+        use cosmwasm_std::{Addr, Uint128};
+        use cw_multi_test::Executor;
+        use nft_loans::{error::ContractError, msg::ExecuteMsg};
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, deposit_collaterals, loan_terms, make_offer, mint_and_approve,
+            setup_loan_contracts_with, OWNER_ADDR,
+        };
+
+        const BORROWER: &str = "borrower";
+        const LENDER: &str = "lender";
+
+        #[test]
+        fn zero_interest_offer_rejected() {
+            let (mut app, contracts) = setup_loan_contracts_with(|msg| {
+                msg.min_interest = Some(Uint128::new(10));
+            });
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                None,
+            )
+            .unwrap();
+
+            let err = make_offer(
+                &mut app,
+                &contracts.loan,
+                LENDER,
+                BORROWER,
+                loan_id,
+                loan_terms(1_000, 0, 100),
+            )
+            .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::InterestTooLow { min_interest, interest })
+                    if *min_interest == Uint128::new(10) && interest.is_zero()
+            ));
+
+            // An offer carrying the minimum interest goes through
+            make_offer(
+                &mut app,
+                &contracts.loan,
+                LENDER,
+                BORROWER,
+                loan_id,
+                loan_terms(1_000, 10, 100),
+            )
+            .unwrap();
+
+            // Only the owner can lift the limit
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(LENDER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::SetMinInterest { min_interest: None },
+                    &[],
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::Unauthorized {})
+            ));
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.loan.clone(),
+                &ExecuteMsg::SetMinInterest { min_interest: None },
+                &[],
+            )
+            .unwrap();
+            make_offer(
+                &mut app,
+                &contracts.loan,
+                LENDER,
+                BORROWER,
+                loan_id,
+                loan_terms(1_000, 0, 100),
+            )
+            .unwrap();
+        }
+    }
+
+    mod counter {
+        use cosmwasm_std::{coin, Addr, Uint128};
+        use cw_multi_test::Executor;
+        use nft_loans::{error::ContractError, msg::ExecuteMsg};
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, deposit_collaterals, loan_terms, make_offer, mint_and_approve,
+            mint_native, native_balance, nft_owner, setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+        const LENDER: &str = "lender";
+
+        #[test]
+        fn lender_tops_up_countered_principle() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                None,
+            )
+            .unwrap();
+            let global_offer_id = make_offer(
+                &mut app,
+                &contracts.loan,
+                LENDER,
+                BORROWER,
+                loan_id,
+                loan_terms(1_000, 50, 100),
+            )
+            .unwrap();
+            let borrower_balance = native_balance(&app, BORROWER);
+
+            // The borrower asks for more
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::CounterOffer {
+                    global_offer_id: global_offer_id.clone(),
+                    terms: loan_terms(1_500, 80, 100),
+                },
+                &[],
+            )
+            .unwrap();
+
+            // The lender has to send the difference along
+            mint_native(&mut app, LENDER, 500);
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(LENDER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::AcceptCounter {
+                        global_offer_id: global_offer_id.clone(),
+                    },
+                    &[coin(400, NATIVE_DENOM)],
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::FundsDontMatchTermsAndPrinciple(wanted, available))
+                    if *wanted == Uint128::new(1_500) && *available == Uint128::new(1_400)
+            ));
+
+            app.execute_contract(
+                Addr::unchecked(LENDER),
+                contracts.loan.clone(),
+                &ExecuteMsg::AcceptCounter {
+                    global_offer_id: global_offer_id.clone(),
+                },
+                &[coin(500, NATIVE_DENOM)],
+            )
+            .unwrap();
+
+            // The loan started with the countered terms
+            assert_eq!(
+                native_balance(&app, BORROWER),
+                borrower_balance + Uint128::new(1_500)
+            );
+            assert_eq!(native_balance(&app, LENDER), Uint128::zero());
+            assert_eq!(native_balance(&app, contracts.loan.as_str()), Uint128::zero());
+            assert_eq!(
+                nft_owner(&app, &collection, &token_id),
+                contracts.loan.to_string()
+            );
+        }
+
+        #[test]
+        fn surplus_refunded_on_lower_counter() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                None,
+            )
+            .unwrap();
+            let global_offer_id = make_offer(
+                &mut app,
+                &contracts.loan,
+                LENDER,
+                BORROWER,
+                loan_id,
+                loan_terms(1_000, 50, 100),
+            )
+            .unwrap();
+
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::CounterOffer {
+                    global_offer_id: global_offer_id.clone(),
+                    terms: loan_terms(600, 50, 100),
+                },
+                &[],
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(LENDER),
+                contracts.loan.clone(),
+                &ExecuteMsg::AcceptCounter { global_offer_id },
+                &[],
+            )
+            .unwrap();
+
+            assert_eq!(native_balance(&app, LENDER), Uint128::new(400));
+            assert_eq!(native_balance(&app, contracts.loan.as_str()), Uint128::zero());
+        }
+    }
+
+    mod fee_distributor {
+        use cosmwasm_std::{coin, testing::mock_env, Addr, Decimal, Empty, Uint128};
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            error::ContractError,
+            execute::_repayment_response,
+            msg::{ExecuteMsg, OfferResponse, QueryMsg},
+            state::{CollateralInfo, ContractInfo},
+        };
+        use sg_std::NATIVE_DENOM;
+
+        use crate::common_setup::{
+            contract_boxes::contract_fee_distributor,
+            helpers::{
+                loan_terms, mint_native, native_balance, setup_loan_contracts, start_loan,
+                OWNER_ADDR,
+            },
+        };
+
+        const BORROWER: &str = "borrower";
+        const LENDER: &str = "lender";
+
+        #[test]
+        fn repayment_fees_go_to_new_distributor() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (loan_id, _, _) =
+                start_loan(&mut app, &contracts, BORROWER, LENDER, loan_terms(1_000, 100, 100));
+
+            let code_id = app.store_code(contract_fee_distributor());
+            let new_distributor = app
+                .instantiate_contract(
+                    code_id,
+                    Addr::unchecked(OWNER_ADDR),
+                    &Empty {},
+                    &[],
+                    "new-fee-distributor",
+                    None,
+                )
+                .unwrap();
+
+            // Only the owner can change the fee distributor
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(LENDER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::SetFeeDistributor {
+                        fee_depositor: new_distributor.to_string(),
+                    },
+                    &[],
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::Unauthorized {})
+            ));
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.loan.clone(),
+                &ExecuteMsg::SetFeeDistributor {
+                    fee_depositor: new_distributor.to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+
+            mint_native(&mut app, BORROWER, 100);
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::RepayBorrowedFunds { loan_id },
+                &[coin(1_100, NATIVE_DENOM)],
+            )
+            .unwrap();
+
+            // 5% of the interests go to the new distributor
+            assert_eq!(native_balance(&app, new_distributor.as_str()), Uint128::new(5));
+            assert_eq!(
+                native_balance(&app, contracts.fee_distributor.as_str()),
+                Uint128::zero()
+            );
+            assert_eq!(native_balance(&app, LENDER), Uint128::new(1_095));
+        }
+
+        #[test]
+        fn fees_deposited_in_repayment_denom() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (loan_id, _, _) =
+                start_loan(&mut app, &contracts, BORROWER, LENDER, loan_terms(1_000, 100, 100));
+
+            mint_native(&mut app, BORROWER, 100);
+            let response = app
+                .execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::RepayBorrowedFunds { loan_id },
+                    &[coin(1_100, NATIVE_DENOM)],
+                )
+                .unwrap();
+
+            // DepositFees was called with the fee, in the denom the loan was repaid in only
+            assert!(response.events.iter().any(|event| {
+                event.ty == "execute"
+                    && event.attributes.iter().any(|attribute| {
+                        attribute.key == "_contract_addr"
+                            && attribute.value == contracts.fee_distributor.as_str()
+                    })
+            }));
+            assert_eq!(
+                app.wrap().query_all_balances(contracts.fee_distributor.clone()).unwrap(),
+                vec![coin(5, NATIVE_DENOM)]
+            );
+        }
+
+        #[test]
+        fn fees_in_another_denom_rejected() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (loan_id, _, _) =
+                start_loan(&mut app, &contracts, BORROWER, LENDER, loan_terms(1_000, 100, 100));
+            let collateral: CollateralInfo = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::CollateralInfo {
+                        borrower: BORROWER.to_string(),
+                        loan_id,
+                    },
+                )
+                .unwrap();
+            let offer: OfferResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::OfferInfo {
+                        global_offer_id: collateral.active_offer.clone().unwrap(),
+                    },
+                )
+                .unwrap();
+            let contract_info: ContractInfo = app
+                .wrap()
+                .query_wasm_smart(contracts.loan.clone(), &QueryMsg::ContractInfo {})
+                .unwrap();
+
+            let err = _repayment_response(
+                mock_env(),
+                contract_info,
+                Addr::unchecked(BORROWER),
+                collateral,
+                &offer.offer_info,
+                coin(1_100, "uother"),
+                Uint128::zero(),
+            )
+            .unwrap_err();
+            assert!(matches!(err, ContractError::FundsDontMatchTerms {}));
+        }
+
+        #[test]
+        fn loan_fees_routed_to_override() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (loan_id, _, _) =
+                start_loan(&mut app, &contracts, BORROWER, LENDER, loan_terms(1_000, 100, 100));
+
+            let code_id = app.store_code(contract_fee_distributor());
+            let partner_distributor = app
+                .instantiate_contract(
+                    code_id,
+                    Addr::unchecked(OWNER_ADDR),
+                    &Empty {},
+                    &[],
+                    "partner-fee-distributor",
+                    None,
+                )
+                .unwrap();
+            let set_override = ExecuteMsg::SetLoanFeeDistributor {
+                borrower: BORROWER.to_string(),
+                loan_id,
+                fee_distributor: Some(partner_distributor.to_string()),
+            };
+
+            // Only the owner can route the fees of a loan
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &set_override,
+                    &[],
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::Unauthorized {})
+            ));
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.loan.clone(),
+                &set_override,
+                &[],
+            )
+            .unwrap();
+
+            mint_native(&mut app, BORROWER, 100);
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::RepayBorrowedFunds { loan_id },
+                &[coin(1_100, NATIVE_DENOM)],
+            )
+            .unwrap();
+
+            assert_eq!(native_balance(&app, partner_distributor.as_str()), Uint128::new(5));
+            assert_eq!(
+                native_balance(&app, contracts.fee_distributor.as_str()),
+                Uint128::zero()
+            );
+        }
+
+        #[test]
+        fn repayment_fees_split_between_beneficiaries() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (loan_id, _, _) =
+                start_loan(&mut app, &contracts, BORROWER, LENDER, loan_terms(1_000, 1_000, 100));
+
+            // The weights have to sum to 1
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(OWNER_ADDR),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::SetInterestBeneficiaries {
+                        beneficiaries: vec![
+                            ("staking".to_string(), Decimal::percent(60)),
+                            ("dev-fund".to_string(), Decimal::percent(60)),
+                        ],
+                    },
+                    &[],
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::InvalidBeneficiaryWeights {})
+            ));
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.loan.clone(),
+                &ExecuteMsg::SetInterestBeneficiaries {
+                    beneficiaries: vec![
+                        ("staking".to_string(), Decimal::percent(60)),
+                        ("dev-fund".to_string(), Decimal::percent(40)),
+                    ],
+                },
+                &[],
+            )
+            .unwrap();
+
+            mint_native(&mut app, BORROWER, 1_000);
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::RepayBorrowedFunds { loan_id },
+                &[coin(2_000, NATIVE_DENOM)],
+            )
+            .unwrap();
+
+            // The 5% fee cut (50) is split 60/40 instead of going to the distributor
+            assert_eq!(native_balance(&app, "staking"), Uint128::new(30));
+            assert_eq!(native_balance(&app, "dev-fund"), Uint128::new(20));
+            assert_eq!(
+                native_balance(&app, contracts.fee_distributor.as_str()),
+                Uint128::zero()
+            );
+            assert_eq!(native_balance(&app, LENDER), Uint128::new(1_950));
+        }
+    }
+
+    mod default {
+        use cosmwasm_std::{coins, Addr, Decimal, Uint128};
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            error::ContractError,
+            msg::ExecuteMsg,
+            state::{LoanState, LoanTerms},
+        };
+        use sg_multi_test::StargazeApp;
+        use sg_std::NATIVE_DENOM;
+
+        use crate::common_setup::helpers::{
+            advance_time, find_attribute, loan_terms, mint_native, native_balance, nft_owner,
+            setup_loan_contracts, start_loan, LoanContracts, OWNER_ADDR,
+        };
+
+        const BORROWER: &str = "borrower";
+        const LENDER: &str = "lender";
+
+        #[test]
+        fn seconds_based_loan_defaults_at_time_boundary() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let terms = LoanTerms {
+                duration_in_seconds: Some(1_000),
+                ..loan_terms(1_000, 100, 1)
+            };
+            let (loan_id, collection, token_id) =
+                start_loan(&mut app, &contracts, BORROWER, LENDER, terms);
+            let withdraw_defaulted = ExecuteMsg::WithdrawDefaultedLoan {
+                borrower: BORROWER.to_string(),
+                loan_id,
+            };
+
+            // Way more than 1 block passed, but the duration is in seconds
+            advance_time(&mut app, 1_000);
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(LENDER),
+                    contracts.loan.clone(),
+                    &withdraw_defaulted,
+                    &[],
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::WrongLoanState {
+                    state: LoanState::Started
+                })
+            ));
+
+            advance_time(&mut app, 1);
+            app.execute_contract(
+                Addr::unchecked(LENDER),
+                contracts.loan.clone(),
+                &withdraw_defaulted,
+                &[],
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, &token_id), LENDER.to_string());
+        }
+
+        /// Starts a loan of 1_000 + 100 interest over 10 blocks, with a 20 blocks (100 seconds) cure window and a 10% penalty
+        /// The loan is then moved just past its default
+        fn defaulted_loan() -> (StargazeApp, LoanContracts, u64, Addr, String) {
+            let (mut app, contracts) = setup_loan_contracts();
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.loan.clone(),
+                &ExecuteMsg::SetCureParams {
+                    cure_blocks: 20,
+                    cure_seconds: 100,
+                    cure_penalty: Decimal::percent(10),
+                },
+                &[],
+            )
+            .unwrap();
+            let (loan_id, collection, token_id) =
+                start_loan(&mut app, &contracts, BORROWER, LENDER, loan_terms(1_000, 100, 10));
+            // 11 blocks
+            advance_time(&mut app, 55);
+            (app, contracts, loan_id, collection, token_id)
+        }
+
+        #[test]
+        fn borrower_cures_default() {
+            let (mut app, contracts, loan_id, collection, token_id) = defaulted_loan();
+
+            // The penalty has to be paid on top of the loan
+            mint_native(&mut app, BORROWER, 200);
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::CureDefault { loan_id },
+                    &coins(1_100, NATIVE_DENOM),
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::FundsDontMatchTermsAndPrinciple(..))
+            ));
+
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::CureDefault { loan_id },
+                &coins(1_200, NATIVE_DENOM),
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, &token_id), BORROWER.to_string());
+            // principle + interest minus the 5% fee + penalty
+            assert_eq!(native_balance(&app, LENDER), Uint128::new(1_000 + 95 + 100));
+
+            // The lender can't default a cured loan
+            app.execute_contract(
+                Addr::unchecked(LENDER),
+                contracts.loan.clone(),
+                &ExecuteMsg::WithdrawDefaultedLoan {
+                    borrower: BORROWER.to_string(),
+                    loan_id,
+                },
+                &[],
+            )
+            .unwrap_err();
+        }
+
+        #[test]
+        fn cure_fails_after_lender_withdrawal() {
+            let (mut app, contracts, loan_id, collection, token_id) = defaulted_loan();
+            app.execute_contract(
+                Addr::unchecked(LENDER),
+                contracts.loan.clone(),
+                &ExecuteMsg::WithdrawDefaultedLoan {
+                    borrower: BORROWER.to_string(),
+                    loan_id,
+                },
+                &[],
+            )
+            .unwrap();
+
+            mint_native(&mut app, BORROWER, 200);
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::CureDefault { loan_id },
+                    &coins(1_200, NATIVE_DENOM),
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::LoanAlreadyDefaulted {})
+            ));
+            assert_eq!(nft_owner(&app, &collection, &token_id), LENDER.to_string());
+        }
+
+        #[test]
+        fn cure_fails_after_window() {
+            let (mut app, contracts, loan_id, _, _) = defaulted_loan();
+            // 31 blocks after the start
+            advance_time(&mut app, 100);
+
+            mint_native(&mut app, BORROWER, 200);
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::CureDefault { loan_id },
+                    &coins(1_200, NATIVE_DENOM),
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::CureWindowClosed {})
+            ));
+        }
+
+        #[test]
+        fn cure_penalty_over_principle_rejected() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let set_cure_params = |app: &mut StargazeApp, cure_penalty: Decimal| {
+                app.execute_contract(
+                    Addr::unchecked(OWNER_ADDR),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::SetCureParams {
+                        cure_blocks: 20,
+                        cure_seconds: 100,
+                        cure_penalty,
+                    },
+                    &[],
+                )
+            };
+
+            let err = set_cure_params(&mut app, Decimal::percent(101)).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::NotAcceptable {})
+            ));
+            set_cure_params(&mut app, Decimal::one()).unwrap();
+        }
+
+        #[test]
+        fn seconds_based_loan_cured_within_seconds_window() {
+            let (mut app, contracts) = setup_loan_contracts();
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.loan.clone(),
+                &ExecuteMsg::SetCureParams {
+                    cure_blocks: 20,
+                    cure_seconds: 100,
+                    cure_penalty: Decimal::percent(10),
+                },
+                &[],
+            )
+            .unwrap();
+            let terms = LoanTerms {
+                duration_in_seconds: Some(50),
+                ..loan_terms(1_000, 100, 1_000)
+            };
+            let mut loans = vec![];
+            for borrower in [BORROWER, "other_borrower"] {
+                let (loan_id, _, _) =
+                    start_loan(&mut app, &contracts, borrower, LENDER, terms.clone());
+                mint_native(&mut app, borrower, 200);
+                loans.push(loan_id);
+            }
+            let cure = |app: &mut StargazeApp, borrower: &str, loan_id: u64| {
+                app.execute_contract(
+                    Addr::unchecked(borrower),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::CureDefault { loan_id },
+                    &coins(1_200, NATIVE_DENOM),
+                )
+            };
+
+            // 145 seconds after the start, the loan is defaulted but still in its cure window
+            advance_time(&mut app, 145);
+            cure(&mut app, BORROWER, loans[0]).unwrap();
+
+            // 155 seconds after the start, the window is over whatever the number of blocks
+            advance_time(&mut app, 10);
+            let err = cure(&mut app, "other_borrower", loans[1]).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::CureWindowClosed {})
+            ));
+        }
+
+        #[test]
+        fn defaulted_loans_withdrawn_in_batch() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let mut loans = vec![];
+            for (borrower, duration_in_blocks) in
+                [(BORROWER, 10), ("other_borrower", 10), (BORROWER, 1_000)]
+            {
+                let (loan_id, collection, token_id) = start_loan(
+                    &mut app,
+                    &contracts,
+                    borrower,
+                    LENDER,
+                    loan_terms(1_000, 100, duration_in_blocks),
+                );
+                loans.push((borrower, loan_id, collection, token_id));
+            }
+            // 11 blocks
+            advance_time(&mut app, 55);
+
+            // Unknown loans and loans already withdrawn earlier in the batch are skipped too
+            let mut batch: Vec<(String, u64)> = loans
+                .iter()
+                .map(|(borrower, loan_id, _, _)| (borrower.to_string(), *loan_id))
+                .collect();
+            batch.push(("nobody".to_string(), 42));
+            batch.push((BORROWER.to_string(), loans[0].1));
+            let response = app
+                .execute_contract(
+                    Addr::unchecked(LENDER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::WithdrawDefaultedLoans { loans: batch },
+                    &[],
+                )
+                .unwrap();
+            assert_eq!(
+                find_attribute(&response, "withdrawn"),
+                Some(format!("{BORROWER}:{},other_borrower:{}", loans[0].1, loans[1].1))
+            );
+            assert_eq!(
+                find_attribute(&response, "skipped"),
+                Some(format!(
+                    "{BORROWER}:{},nobody:42,{BORROWER}:{}",
+                    loans[2].1, loans[0].1
+                ))
+            );
+            for (_, _, collection, token_id) in &loans[..2] {
+                assert_eq!(nft_owner(&app, collection, token_id), LENDER.to_string());
+            }
+            assert_eq!(nft_owner(&app, &loans[2].2, &loans[2].3), contracts.loan.to_string());
+        }
+    }
+
+    mod abandon {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            msg::{ExecuteMsg, QueryMsg},
+            state::CollateralInfo,
+        };
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, deposit_collaterals, find_attribute, loan_terms, make_offer,
+            mint_and_approve, setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+        const LENDER: &str = "lender";
+
+        #[test]
+        fn stale_listings_removed_and_offered_skipped() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let mut loan_ids = vec![];
+            for _ in 0..3 {
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+                loan_ids.push(
+                    deposit_collaterals(
+                        &mut app,
+                        &contracts.loan,
+                        BORROWER,
+                        vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                        None,
+                    )
+                    .unwrap(),
+                );
+            }
+            make_offer(
+                &mut app,
+                &contracts.loan,
+                LENDER,
+                BORROWER,
+                loan_ids[2],
+                loan_terms(1_000, 10, 100),
+            )
+            .unwrap();
+
+            let response = app
+                .execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::AbandonListings {
+                        loan_ids: loan_ids.clone(),
+                    },
+                    &[],
+                )
+                .unwrap();
+            assert_eq!(
+                find_attribute(&response, "abandoned"),
+                Some(format!("{},{}", loan_ids[0], loan_ids[1]))
+            );
+            assert_eq!(
+                find_attribute(&response, "skipped"),
+                Some(loan_ids[2].to_string())
+            );
+
+            for loan_id in &loan_ids[..2] {
+                assert!(app
+                    .wrap()
+                    .query_wasm_smart::<CollateralInfo>(
+                        contracts.loan.clone(),
+                        &QueryMsg::CollateralInfo {
+                            borrower: BORROWER.to_string(),
+                            loan_id: *loan_id,
+                        },
+                    )
+                    .is_err());
+            }
+            // The listing with a live offer is kept
+            app.wrap()
+                .query_wasm_smart::<CollateralInfo>(
+                    contracts.loan.clone(),
+                    &QueryMsg::CollateralInfo {
+                        borrower: BORROWER.to_string(),
+                        loan_id: loan_ids[2],
+                    },
+                )
+                .unwrap();
+
+            // Nothing left to abandon
+            let response = app
+                .execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::AbandonListings {
+                        loan_ids: vec![loan_ids[2]],
+                    },
+                    &[],
+                )
+                .unwrap();
+            assert_eq!(find_attribute(&response, "abandoned"), Some("none".to_string()));
+        }
+    }
+
+    mod refuse_all {
+        use cosmwasm_std::{Addr, Uint128};
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            msg::{ExecuteMsg, OfferResponse, QueryMsg},
+            state::OfferState,
+        };
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            deposit_collaterals, find_attribute, loan_terms, make_offer, native_balance,
+            setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn withdrawn_listing_refuses_every_offer() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721("collection", "1")],
+                None,
+            )
+            .unwrap();
+            let mut offers = vec![];
+            for lender in ["lender1", "lender2"] {
+                let global_offer_id = make_offer(
+                    &mut app,
+                    &contracts.loan,
+                    lender,
+                    BORROWER,
+                    loan_id,
+                    loan_terms(1_000, 10, 100),
+                )
+                .unwrap();
+                offers.push((lender, global_offer_id));
+            }
+
+            let response = app
+                .execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::WithdrawCollateralAndRefuseAll { loan_id },
+                    &[],
+                )
+                .unwrap();
+            assert_eq!(
+                find_attribute(&response, "refused_offers"),
+                Some(format!("{},{}", offers[0].1, offers[1].1))
+            );
+
+            for (lender, global_offer_id) in offers {
+                let offer: OfferResponse = app
+                    .wrap()
+                    .query_wasm_smart(
+                        contracts.loan.clone(),
+                        &QueryMsg::OfferInfo {
+                            global_offer_id: global_offer_id.clone(),
+                        },
+                    )
+                    .unwrap();
+                assert_eq!(offer.offer_info.state, OfferState::Refused);
+
+                app.execute_contract(
+                    Addr::unchecked(lender),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::WithdrawRefusedOffer { global_offer_id },
+                    &[],
+                )
+                .unwrap();
+                assert_eq!(native_balance(&app, lender), Uint128::new(1_000));
+            }
+        }
+    }
+
+    mod allowed_collections {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use nft_loans::{error::ContractError, msg::ExecuteMsg};
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, deposit_collaterals, mint_and_approve, setup_loan_contracts,
+            LoanContracts, OWNER_ADDR,
+        };
+
+        const BORROWER: &str = "borrower";
+
+        fn set_allowed_collections(
+            app: &mut StargazeApp,
+            contracts: &LoanContracts,
+            allowed_collections: Option<Vec<String>>,
+        ) {
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.loan.clone(),
+                &ExecuteMsg::SetAllowedCollections {
+                    allowed_collections,
+                },
+                &[],
+            )
+            .unwrap();
+        }
+
+        #[test]
+        fn allowed_collection_deposited() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            set_allowed_collections(&mut app, &contracts, Some(vec![collection.to_string()]));
+
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                None,
+            )
+            .unwrap();
+        }
+
+        #[test]
+        fn disallowed_collection_rejected() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (_, allowed) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            set_allowed_collections(&mut app, &contracts, Some(vec![allowed.to_string()]));
+
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            let err = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                None,
+            )
+            .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::CollectionNotAllowed { collection: rejected })
+                    if rejected == collection.as_str()
+            ));
+
+            // An empty list allows every collection again
+            set_allowed_collections(&mut app, &contracts, Some(vec![]));
+            deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                None,
+            )
+            .unwrap();
+        }
+    }
+
+    mod early_repay {
+        use cosmwasm_std::{coins, Addr, Uint128};
+        use cw_multi_test::Executor;
+        use nft_loans::{error::ContractError, msg::ExecuteMsg, state::LoanTerms};
+        use sg_std::NATIVE_DENOM;
+
+        use crate::common_setup::helpers::{
+            advance_time, loan_terms, mint_native, native_balance, nft_owner,
+            setup_loan_contracts, start_loan,
+        };
+
+        const BORROWER: &str = "borrower";
+        const LENDER: &str = "lender";
+
+        /// 1_000 + 100 interest over 100 blocks, with a 50 penalty during the first 20 blocks
+        fn terms() -> LoanTerms {
+            LoanTerms {
+                early_repay_penalty: Some(Uint128::new(50)),
+                min_duration_blocks: Some(20),
+                ..loan_terms(1_000, 100, 100)
+            }
+        }
+
+        #[test]
+        fn early_repayment_pays_penalty() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (loan_id, collection, token_id) =
+                start_loan(&mut app, &contracts, BORROWER, LENDER, terms());
+            // 5 blocks
+            advance_time(&mut app, 25);
+            mint_native(&mut app, BORROWER, 150);
+
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::RepayBorrowedFunds { loan_id },
+                    &coins(1_100, NATIVE_DENOM),
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::FundsDontMatchTermsAndPrinciple(due, _))
+                    if *due == Uint128::new(1_150)
+            ));
+
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::RepayBorrowedFunds { loan_id },
+                &coins(1_150, NATIVE_DENOM),
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, &token_id), BORROWER.to_string());
+            // principle + interest minus the 5% fee + penalty
+            assert_eq!(native_balance(&app, LENDER), Uint128::new(1_000 + 95 + 50));
+        }
+
+        #[test]
+        fn no_penalty_after_min_duration() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (loan_id, _, _) = start_loan(&mut app, &contracts, BORROWER, LENDER, terms());
+            // 25 blocks
+            advance_time(&mut app, 125);
+            mint_native(&mut app, BORROWER, 100);
+
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::RepayBorrowedFunds { loan_id },
+                &coins(1_100, NATIVE_DENOM),
+            )
+            .unwrap();
+            assert_eq!(native_balance(&app, LENDER), Uint128::new(1_000 + 95));
+        }
+    }
+
+    mod accept_loan {
+        use cosmwasm_std::{coin, coins, Addr};
+        use cw_multi_test::Executor;
+        use nft_loans::{error::ContractError, msg::ExecuteMsg};
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, deposit_collaterals, loan_terms, mint_and_approve, mint_native,
+            nft_owner, setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+        const LENDER: &str = "lender";
+
+        #[test]
+        fn wrong_funds_detailed() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                Some(loan_terms(1_000, 100, 100)),
+            )
+            .unwrap();
+            mint_native(&mut app, LENDER, 1_000);
+            let accept_loan = ExecuteMsg::AcceptLoan {
+                borrower: BORROWER.to_string(),
+                loan_id,
+                comment: None,
+            };
+
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(LENDER),
+                    contracts.loan.clone(),
+                    &accept_loan,
+                    &coins(900, NATIVE_DENOM),
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::LoanFundsMismatch { expected, received })
+                    if *expected == coin(1_000, NATIVE_DENOM)
+                        && *received == coins(900, NATIVE_DENOM)
+            ));
+
+            app.execute_contract(
+                Addr::unchecked(LENDER),
+                contracts.loan.clone(),
+                &accept_loan,
+                &coins(1_000, NATIVE_DENOM),
+            )
+            .unwrap();
+            assert_eq!(
+                nft_owner(&app, &collection, &token_id),
+                contracts.loan.to_string()
+            );
+        }
+    }
+    mod listing_expiry {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            error::ContractError,
+            msg::{ExecuteMsg, MultipleCollateralsResponse, QueryMsg},
+        };
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, create_collection, loan_terms, make_offer, mint_and_approve,
+            setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+        const LENDER: &str = "lender";
+
+        #[test]
+        fn expired_listing_refuses_offers() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            let listing_expiry = app.block_info().time.plus_seconds(100);
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::DepositCollaterals {
+                    tokens: vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    terms: None,
+                    comment: None,
+                    loan_preview: None,
+                    listing_expiry: Some(listing_expiry),
+                },
+                &[],
+            )
+            .unwrap();
+
+            let listing_expired = |app: &StargazeApp| -> bool {
+                let response: MultipleCollateralsResponse = app
+                    .wrap()
+                    .query_wasm_smart(
+                        contracts.loan.clone(),
+                        &QueryMsg::Collaterals {
+                            borrower: BORROWER.to_string(),
+                            start_after: None,
+                            limit: None,
+                        },
+                    )
+                    .unwrap();
+                response.collaterals[0].listing_expired
+            };
+
+            // Offers are accepted until the expiry
+            make_offer(
+                &mut app,
+                &contracts.loan,
+                LENDER,
+                BORROWER,
+                0,
+                loan_terms(1_000, 100, 100),
+            )
+            .unwrap();
+            assert!(!listing_expired(&app));
+
+            advance_time(&mut app, 100);
+            assert!(listing_expired(&app));
+            let err = make_offer(
+                &mut app,
+                &contracts.loan,
+                LENDER,
+                BORROWER,
+                0,
+                loan_terms(1_000, 100, 100),
+            )
+            .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::ListingExpired {})
+            ));
+        }
+    }
+
+    mod deposit {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use nft_loans::{error::ContractError, msg::ExecuteMsg};
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, deposit_collaterals, mint_and_approve, setup_loan_contracts,
+            OWNER_ADDR,
+        };
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn preview_of_someone_elses_nft_rejected() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let owned = AssetInfo::sg721(
+                collection.as_str(),
+                &mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan),
+            );
+            let not_owned = AssetInfo::sg721(
+                collection.as_str(),
+                &mint_and_approve(&mut app, &minter, &collection, "someone", &contracts.loan),
+            );
+            let deposit = |app: &mut StargazeApp, loan_preview: &AssetInfo| {
+                app.execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::DepositCollaterals {
+                        tokens: vec![owned.clone(), not_owned.clone()],
+                        terms: None,
+                        comment: None,
+                        loan_preview: Some(loan_preview.clone()),
+                        listing_expiry: None,
+                    },
+                    &[],
+                )
+            };
+
+            let err = deposit(&mut app, &not_owned).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::SenderNotOwner {})
+            ));
+            deposit(&mut app, &owned).unwrap();
+        }
+
+        #[test]
+        fn modified_preview_of_someone_elses_nft_rejected() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let owned = AssetInfo::sg721(
+                collection.as_str(),
+                &mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan),
+            );
+            let not_owned = AssetInfo::sg721(
+                collection.as_str(),
+                &mint_and_approve(&mut app, &minter, &collection, "someone", &contracts.loan),
+            );
+            deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![owned.clone(), not_owned.clone()],
+                None,
+            )
+            .unwrap();
+            let modify = |app: &mut StargazeApp, loan_preview: &AssetInfo| {
+                app.execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::ModifyCollaterals {
+                        loan_id: 0,
+                        terms: None,
+                        comment: None,
+                        loan_preview: Some(loan_preview.clone()),
+                        listing_expiry: None,
+                    },
+                    &[],
+                )
+            };
+
+            let err = modify(&mut app, &not_owned).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::SenderNotOwner {})
+            ));
+            modify(&mut app, &owned).unwrap();
+        }
+
+        #[test]
+        fn unowned_nft_rejected_when_verified() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let not_owned = AssetInfo::sg721(
+                collection.as_str(),
+                &mint_and_approve(&mut app, &minter, &collection, "someone", &contracts.loan),
+            );
+            let set_verify = |app: &mut StargazeApp, sender: &str| {
+                app.execute_contract(
+                    Addr::unchecked(sender),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::SetVerifyOwnershipOnDeposit {
+                        verify_ownership_on_deposit: true,
+                    },
+                    &[],
+                )
+            };
+
+            let err = set_verify(&mut app, BORROWER).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::Unauthorized {})
+            ));
+            // The listing is only checked when the loan is accepted by default
+            deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![not_owned.clone()],
+                None,
+            )
+            .unwrap();
+
+            set_verify(&mut app, OWNER_ADDR).unwrap();
+            let err =
+                deposit_collaterals(&mut app, &contracts.loan, BORROWER, vec![not_owned], None)
+                    .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::SenderNotOwner {})
+            ));
+            let owned = AssetInfo::sg721(
+                collection.as_str(),
+                &mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan),
+            );
+            deposit_collaterals(&mut app, &contracts.loan, BORROWER, vec![owned], None).unwrap();
+        }
+
+        #[test]
+        fn duplicate_collateral_rejected() {
+            let (mut app, contracts) = setup_loan_contracts();
+
+            let err = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![
+                    AssetInfo::sg721("collection", "1"),
+                    AssetInfo::sg721("collection", "2"),
+                    AssetInfo::sg721("collection", "1"),
+                ],
+                None,
+            )
+            .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::DuplicateAsset { index: 2 })
+            ));
+        }
+    }
+
+    mod instantiate {
+        use cosmwasm_std::{Addr, Decimal};
+        use cw_multi_test::Executor;
+        use nft_loans::{error::ContractError, msg::InstantiateMsg};
+
+        use crate::common_setup::{
+            contract_boxes::contract_nft_loans,
+            helpers::{genesis_app, OWNER_ADDR},
+        };
+
+        #[test]
+        fn fee_rate_over_one_rejected() {
+            let mut app = genesis_app();
+            let loan_id = app.store_code(contract_nft_loans());
+            let err = app
+                .instantiate_contract(
+                    loan_id,
+                    Addr::unchecked(OWNER_ADDR),
+                    &InstantiateMsg {
+                        name: "loan param name".to_string(),
+                        owner: None,
+                        fee_distributor: "fee_distributor".to_string(),
+                        fee_rate: Decimal::percent(150),
+                        min_interest: None,
+                    },
+                    &[],
+                    "nft-loan",
+                    None,
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::NotAcceptable {})
+            ));
+        }
+    }
+
+    mod split {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            error::ContractError,
+            msg::{ExecuteMsg, MultipleCollateralsResponse, QueryMsg},
+        };
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{deposit_collaterals, loan_terms, setup_loan_contracts};
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn listing_split_in_two() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let assets = vec![
+                AssetInfo::sg721("collection", "1"),
+                AssetInfo::sg721("collection", "2"),
+                AssetInfo::sg721("collection", "3"),
+            ];
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                assets.clone(),
+                Some(loan_terms(1_000, 100, 1_000)),
+            )
+            .unwrap();
+            let split = |app: &mut StargazeApp, groups: Vec<Vec<usize>>| {
+                app.execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::SplitListing { loan_id, groups },
+                    &[],
+                )
+            };
+
+            // Every asset has to end up in exactly one group
+            for groups in [
+                vec![vec![0, 2]],
+                vec![vec![0, 2], vec![2, 1]],
+                vec![vec![0], vec![1]],
+            ] {
+                let err = split(&mut app, groups).unwrap_err();
+                assert!(matches!(
+                    err.downcast_ref::<ContractError>(),
+                    Some(ContractError::InvalidSplit {})
+                ));
+            }
+
+            split(&mut app, vec![vec![0, 2], vec![1]]).unwrap();
+            let response: MultipleCollateralsResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::Collaterals {
+                        borrower: BORROWER.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            let listings: Vec<(u64, Vec<AssetInfo>)> = response
+                .collaterals
+                .into_iter()
+                .map(|c| (c.loan_id, c.collateral.associated_assets))
+                .collect();
+            assert_eq!(
+                listings,
+                vec![
+                    (loan_id + 2, vec![assets[1].clone()]),
+                    (loan_id + 1, vec![assets[0].clone(), assets[2].clone()]),
+                ]
+            );
+        }
+    }
+
+    mod archived {
+        use cosmwasm_std::{coin, Addr};
+        use cw_multi_test::Executor;
+        use nft_loans::{error::ContractError, msg::ExecuteMsg};
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, deposit_collaterals, loan_terms, make_offer, mint_and_approve,
+            mint_native, setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn archived_loans_told_apart_from_missing_ones() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                None,
+            )
+            .unwrap();
+            let mut offers = vec![];
+            for lender in ["lender1", "lender2"] {
+                offers.push(
+                    make_offer(
+                        &mut app,
+                        &contracts.loan,
+                        lender,
+                        BORROWER,
+                        loan_id,
+                        loan_terms(1_000, 100, 100),
+                    )
+                    .unwrap(),
+                );
+            }
+            let accept = |app: &mut sg_multi_test::StargazeApp, global_offer_id: &String| {
+                app.execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::AcceptOffer {
+                        global_offer_id: global_offer_id.clone(),
+                    },
+                    &[],
+                )
+            };
+            let repay = |app: &mut sg_multi_test::StargazeApp, loan_id: u64| {
+                app.execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::RepayBorrowedFunds { loan_id },
+                    &[coin(1_100, NATIVE_DENOM)],
+                )
+            };
+
+            accept(&mut app, &offers[0]).unwrap();
+            mint_native(&mut app, BORROWER, 100);
+            repay(&mut app, loan_id).unwrap();
+
+            mint_native(&mut app, BORROWER, 2_200);
+            let err = repay(&mut app, loan_id).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::LoanArchived {})
+            ));
+            let err = accept(&mut app, &offers[1]).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::LoanArchived {})
+            ));
+            mint_native(&mut app, "lender2", 1_000);
+            let err = app
+                .execute_contract(
+                    Addr::unchecked("lender2"),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::AcceptLoan {
+                        borrower: BORROWER.to_string(),
+                        loan_id,
+                        comment: None,
+                    },
+                    &[coin(1_000, NATIVE_DENOM)],
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::LoanArchived {})
+            ));
+            let err = repay(&mut app, loan_id + 1).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::LoanNotFound {})
+            ));
+        }
+    }
+
+    mod reserved {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use nft_loans::{error::ContractError, msg::ExecuteMsg};
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, deposit_collaterals, loan_terms, make_offer, mint_and_approve,
+            mint_native, setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+        const RESERVED_LENDER: &str = "reserved_lender";
+        const OTHER_LENDER: &str = "other_lender";
+
+        #[test]
+        fn only_reserved_lender_can_accept() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            let terms = loan_terms(1_000, 100, 100);
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                Some(terms.clone()),
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::ReserveForLender {
+                    loan_id,
+                    lender: Some(RESERVED_LENDER.to_string()),
+                },
+                &[],
+            )
+            .unwrap();
+
+            let accept_loan = |app: &mut StargazeApp, lender: &str| {
+                mint_native(app, lender, 1_000);
+                app.execute_contract(
+                    Addr::unchecked(lender),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::AcceptLoan {
+                        borrower: BORROWER.to_string(),
+                        loan_id,
+                        comment: None,
+                    },
+                    std::slice::from_ref(&terms.principle),
+                )
+            };
+
+            let err = make_offer(
+                &mut app,
+                &contracts.loan,
+                OTHER_LENDER,
+                BORROWER,
+                loan_id,
+                terms.clone(),
+            )
+            .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::LoanReserved {})
+            ));
+            let err = accept_loan(&mut app, OTHER_LENDER).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::LoanReserved {})
+            ));
+
+            accept_loan(&mut app, RESERVED_LENDER).unwrap();
+            assert_eq!(
+                app.wrap()
+                    .query_wasm_smart::<nft_loans::state::CollateralInfo>(
+                        contracts.loan.clone(),
+                        &nft_loans::msg::QueryMsg::CollateralInfo {
+                            borrower: BORROWER.to_string(),
+                            loan_id,
+                        },
+                    )
+                    .unwrap()
+                    .state,
+                nft_loans::state::LoanState::Started
+            );
+        }
+    }
+
+    mod loan_duration {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            error::ContractError,
+            msg::{ExecuteMsg, QueryMsg},
+            state::{CollateralInfo, LoanTerms},
+        };
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, deposit_collaterals, loan_terms, mint_and_approve,
+            setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn only_duration_changed() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let deposit = |app: &mut StargazeApp, terms| {
+                let token_id =
+                    mint_and_approve(app, &minter, &collection, BORROWER, &contracts.loan);
+                deposit_collaterals(
+                    app,
+                    &contracts.loan,
+                    BORROWER,
+                    vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    terms,
+                )
+                .unwrap()
+            };
+            let terms = loan_terms(1_000, 100, 100);
+            let loan_id = deposit(&mut app, Some(terms.clone()));
+            let untermed_loan_id = deposit(&mut app, None);
+
+            let set_duration = |app: &mut StargazeApp, loan_id| {
+                app.execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::SetLoanDuration {
+                        loan_id,
+                        duration: 250,
+                    },
+                    &[],
+                )
+            };
+            let err = set_duration(&mut app, untermed_loan_id).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::NoTermsSpecified {})
+            ));
+            set_duration(&mut app, loan_id).unwrap();
+
+            let collateral: CollateralInfo = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::CollateralInfo {
+                        borrower: BORROWER.to_string(),
+                        loan_id,
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                collateral.terms.unwrap(),
+                LoanTerms {
+                    duration_in_blocks: 250,
+                    ..terms
+                }
+            );
+        }
+
+        #[test]
+        fn seconds_based_duration_changed() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            let terms = LoanTerms {
+                duration_in_seconds: Some(3_600),
+                ..loan_terms(1_000, 100, 100)
+            };
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                Some(terms.clone()),
+            )
+            .unwrap();
+
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::SetLoanDuration {
+                    loan_id,
+                    duration: 7_200,
+                },
+                &[],
+            )
+            .unwrap();
+            let collateral: CollateralInfo = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::CollateralInfo {
+                        borrower: BORROWER.to_string(),
+                        loan_id,
+                    },
+                )
+                .unwrap();
+            // The expiry is evaluated in seconds, so the block duration is left untouched
+            assert_eq!(
+                collateral.terms.unwrap(),
+                LoanTerms {
+                    duration_in_seconds: Some(7_200),
+                    ..terms
+                }
+            );
+        }
+    }
+
+    mod sweeten_terms {
+        use cosmwasm_std::{coins, Addr, Uint128};
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            error::ContractError,
+            msg::{ExecuteMsg, OfferResponse, QueryMsg},
+            state::{CollateralInfo, LoanTerms},
+        };
+        use sg_multi_test::StargazeApp;
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, deposit_collaterals, loan_terms, mint_and_approve, mint_native,
+            setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+        const LENDER: &str = "lender";
+
+        #[test]
+        fn accepted_offer_carries_raised_interest() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            let terms = loan_terms(1_000, 100, 100);
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                Some(terms.clone()),
+            )
+            .unwrap();
+            let sweeten = |app: &mut StargazeApp, interest: u128| {
+                app.execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::SweetenTerms {
+                        loan_id,
+                        interest: Uint128::new(interest),
+                    },
+                    &[],
+                )
+            };
+
+            let err = sweeten(&mut app, 50).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::InterestDecrease { current }) if current.u128() == 100
+            ));
+            sweeten(&mut app, 150).unwrap();
+
+            mint_native(&mut app, LENDER, 1_000);
+            app.execute_contract(
+                Addr::unchecked(LENDER),
+                contracts.loan.clone(),
+                &ExecuteMsg::AcceptLoan {
+                    borrower: BORROWER.to_string(),
+                    loan_id,
+                    comment: None,
+                },
+                &coins(1_000, NATIVE_DENOM),
+            )
+            .unwrap();
+            let collateral: CollateralInfo = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::CollateralInfo {
+                        borrower: BORROWER.to_string(),
+                        loan_id,
+                    },
+                )
+                .unwrap();
+            let offer: OfferResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::OfferInfo {
+                        global_offer_id: collateral.active_offer.unwrap(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                offer.offer_info.terms,
+                LoanTerms {
+                    interest: Uint128::new(150),
+                    ..terms
+                }
+            );
+
+            // The terms are frozen once the loan started
+            let err = sweeten(&mut app, 200).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::NotModifiable {})
+            ));
+        }
+    }
+
+    mod collateral_custody {
+        use cosmwasm_std::{Addr, Empty, Uint128};
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            error::ContractError,
+            msg::{ExecuteMsg, OfferResponse, QueryMsg},
+            state::OfferState,
+        };
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, deposit_collaterals, loan_terms, make_offer, mint_and_approve,
+            native_balance, nft_owner, setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+        const LENDER: &str = "lender";
+
+        #[test]
+        fn failed_transfer_keeps_funds() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            let terms = loan_terms(1_000, 100, 100);
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                None,
+            )
+            .unwrap();
+            let global_offer_id =
+                make_offer(&mut app, &contracts.loan, LENDER, BORROWER, loan_id, terms).unwrap();
+
+            // The borrower revokes the approval, so the collateral can't be transferred anymore
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                collection.clone(),
+                &sg721_base::msg::ExecuteMsg::<Empty, Empty>::Revoke {
+                    spender: contracts.loan.to_string(),
+                    token_id: token_id.clone(),
+                },
+                &[],
+            )
+            .unwrap();
+            let accept_offer = ExecuteMsg::AcceptOffer {
+                global_offer_id: global_offer_id.clone(),
+            };
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &accept_offer,
+                    &[],
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ContractError>(),
+                Some(ContractError::CollateralTransferFailed { .. })
+            ));
+            assert_eq!(native_balance(&app, BORROWER), Uint128::zero());
+            assert_eq!(
+                native_balance(&app, contracts.loan.as_str()),
+                Uint128::new(1_000)
+            );
+            assert_eq!(nft_owner(&app, &collection, &token_id), BORROWER);
+            let offer: OfferResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::OfferInfo {
+                        global_offer_id: global_offer_id.clone(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(offer.offer_info.state, OfferState::Published);
+
+            // Once the collateral can be transferred, the funds are released in the reply
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                collection.clone(),
+                &sg721_base::msg::ExecuteMsg::<Empty, Empty>::Approve {
+                    spender: contracts.loan.to_string(),
+                    token_id: token_id.clone(),
+                    expires: None,
+                },
+                &[],
+            )
+            .unwrap();
+            let response = app
+                .execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &accept_offer,
+                    &[],
+                )
+                .unwrap();
+            assert!(response
+                .events
+                .iter()
+                .flat_map(|event| event.attributes.iter())
+                .any(|attribute| attribute.key == "action"
+                    && attribute.value == "release_loan_funds"));
+            assert_eq!(native_balance(&app, BORROWER), Uint128::new(1_000));
+            assert_eq!(
+                nft_owner(&app, &collection, &token_id),
+                contracts.loan.to_string()
+            );
+        }
+    }
+
+    mod migrate {
+        use cosmwasm_std::{Addr, Decimal};
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            msg::{ExecuteMsg, QueryMsg},
+            state::{ContractInfo, CONTRACT_INFO},
+        };
+
+        use crate::common_setup::helpers::{
+            setup_loan_contracts, write_contract_storage, OWNER_ADDR,
+        };
+
+        #[test]
+        fn legacy_contract_info_loads() {
+            let (mut app, contracts) = setup_loan_contracts();
+            // Contract info written by the contract version deployed before the new parameters
+            let legacy_contract_info = format!(
+                r#"{{"name":"nft-loan","owner":"{OWNER_ADDR}","fee_distributor":"{}","fee_rate":"0.05","global_offer_index":3}}"#,
+                contracts.fee_distributor
+            );
+            write_contract_storage(
+                &mut app,
+                &contracts.loan,
+                CONTRACT_INFO.as_slice(),
+                legacy_contract_info.as_bytes(),
+            );
+
+            let contract_info: ContractInfo = app
+                .wrap()
+                .query_wasm_smart(contracts.loan.clone(), &QueryMsg::ContractInfo {})
+                .unwrap();
+            assert_eq!(contract_info.global_offer_index, 3);
+            assert_eq!(contract_info.min_interest, None);
+            assert_eq!(contract_info.allowed_collections, None);
+            assert_eq!(contract_info.cure_blocks, 0);
+            assert_eq!(contract_info.cure_seconds, 0);
+            assert_eq!(contract_info.cure_penalty, Decimal::zero());
+            assert!(contract_info.interest_beneficiaries.is_empty());
+            assert!(!contract_info.verify_ownership_on_deposit);
+
+            // The owner settings still go through
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.loan.clone(),
+                &ExecuteMsg::SetCureParams {
+                    cure_blocks: 20,
+                    cure_seconds: 100,
+                    cure_penalty: Decimal::percent(10),
+                },
+                &[],
+            )
+            .unwrap();
+        }
+    }
+}
@@ -0,0 +1,1069 @@
+#[cfg(test)]
+mod tests {
+    mod all_collaterals {
+        use cosmwasm_std::Addr;
+        use nft_loans::msg::{MultipleCollateralsAllResponse, QueryMsg, QueryOrder};
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{deposit_collaterals, setup_loan_contracts};
+
+        fn all_collaterals(
+            app: &StargazeApp,
+            loan: &Addr,
+            start_after: Option<(String, u64)>,
+            limit: Option<u32>,
+            order: Option<QueryOrder>,
+        ) -> MultipleCollateralsAllResponse {
+            app.wrap()
+                .query_wasm_smart(
+                    loan.clone(),
+                    &QueryMsg::AllCollaterals {
+                        start_after,
+                        limit,
+                        order,
+                    },
+                )
+                .unwrap()
+        }
+
+        fn ids(response: &MultipleCollateralsAllResponse) -> Vec<(String, u64)> {
+            response
+                .collaterals
+                .iter()
+                .map(|collateral| (collateral.borrower.clone(), collateral.loan_id))
+                .collect()
+        }
+
+        #[test]
+        fn orderings_are_opposite() {
+            let (mut app, contracts) = setup_loan_contracts();
+            for (borrower, token_id) in [("alice", "1"), ("bob", "2"), ("alice", "3"), ("bob", "4")] {
+                deposit_collaterals(
+                    &mut app,
+                    &contracts.loan,
+                    borrower,
+                    vec![AssetInfo::sg721("collection", token_id)],
+                    None,
+                )
+                .unwrap();
+            }
+
+            let descending = all_collaterals(&app, &contracts.loan, None, None, None);
+            let ascending = all_collaterals(
+                &app,
+                &contracts.loan,
+                None,
+                None,
+                Some(QueryOrder::Ascending),
+            );
+            assert_eq!(descending.collaterals.len(), 4);
+            let mut reversed = ids(&ascending);
+            reversed.reverse();
+            assert_eq!(ids(&descending), reversed);
+            // Loans of the same borrower are ordered by id
+            let alice_ids: Vec<u64> = ids(&ascending)
+                .into_iter()
+                .filter(|(borrower, _)| borrower == "alice")
+                .map(|(_, loan_id)| loan_id)
+                .collect();
+            assert_eq!(alice_ids, vec![0, 1]);
+        }
+
+        #[test]
+        fn pagination_in_both_orders() {
+            let (mut app, contracts) = setup_loan_contracts();
+            for (borrower, token_id) in [("alice", "1"), ("bob", "2"), ("alice", "3")] {
+                deposit_collaterals(
+                    &mut app,
+                    &contracts.loan,
+                    borrower,
+                    vec![AssetInfo::sg721("collection", token_id)],
+                    None,
+                )
+                .unwrap();
+            }
+
+            for order in [QueryOrder::Ascending, QueryOrder::Descending] {
+                let full = all_collaterals(&app, &contracts.loan, None, None, Some(order.clone()));
+                let first_page =
+                    all_collaterals(&app, &contracts.loan, None, Some(2), Some(order.clone()));
+                let second_page = all_collaterals(
+                    &app,
+                    &contracts.loan,
+                    first_page.next_collateral.clone(),
+                    Some(2),
+                    Some(order),
+                );
+                assert_eq!(
+                    [ids(&first_page), ids(&second_page)].concat(),
+                    ids(&full)
+                );
+            }
+        }
+    }
+
+    mod offers {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use nft_loans::msg::{ExecuteMsg, MultipleOffersResponse, QueryMsg};
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            deposit_collaterals, loan_terms, mint_native, setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn memo_returned_apart_from_comment() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721("collection", "1")],
+                None,
+            )
+            .unwrap();
+            let terms = loan_terms(1_000, 10, 100);
+            mint_native(&mut app, "lender", 1_000);
+            app.execute_contract(
+                Addr::unchecked("lender"),
+                contracts.loan.clone(),
+                &ExecuteMsg::MakeOffer {
+                    borrower: BORROWER.to_string(),
+                    loan_id,
+                    terms: terms.clone(),
+                    comment: Some("public comment".to_string()),
+                    memo: Some("happy to extend if needed".to_string()),
+                },
+                &[terms.principle],
+            )
+            .unwrap();
+
+            let response: MultipleOffersResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::Offers {
+                        borrower: BORROWER.to_string(),
+                        loan_id,
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            let offer_info = &response.offers[0].offer_info;
+            assert_eq!(offer_info.comment, Some("public comment".to_string()));
+            assert_eq!(offer_info.memo, Some("happy to extend if needed".to_string()));
+        }
+    }
+
+    mod best_offers {
+        use cosmwasm_std::{Addr, Uint128};
+        use cw_multi_test::Executor;
+        use nft_loans::msg::{ExecuteMsg, MultipleOffersResponse, QueryMsg};
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            deposit_collaterals, loan_terms, make_offer, setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn offers_ranked_by_principle() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721("collection", "1")],
+                None,
+            )
+            .unwrap();
+            for (lender, principle) in [("lender1", 500), ("lender2", 1_500), ("lender3", 1_000)] {
+                make_offer(
+                    &mut app,
+                    &contracts.loan,
+                    lender,
+                    BORROWER,
+                    loan_id,
+                    loan_terms(principle, 10, 100),
+                )
+                .unwrap();
+            }
+            // Cancelled offers are not listed
+            let cancelled = make_offer(
+                &mut app,
+                &contracts.loan,
+                "lender4",
+                BORROWER,
+                loan_id,
+                loan_terms(2_000, 10, 100),
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked("lender4"),
+                contracts.loan.clone(),
+                &ExecuteMsg::CancelOffer {
+                    global_offer_id: cancelled,
+                },
+                &[],
+            )
+            .unwrap();
+
+            let response: MultipleOffersResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::BestOffers {
+                        borrower: BORROWER.to_string(),
+                        loan_id,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            let principles: Vec<Uint128> = response
+                .offers
+                .iter()
+                .map(|offer| offer.offer_info.terms.principle.amount)
+                .collect();
+            assert_eq!(
+                principles,
+                vec![
+                    Uint128::new(1_500),
+                    Uint128::new(1_000),
+                    Uint128::new(500)
+                ]
+            );
+        }
+    }
+
+    mod offer_ids_for_loan {
+        use nft_loans::msg::QueryMsg;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            deposit_collaterals, loan_terms, make_offer, setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn all_offer_ids_listed() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let mut loan_ids = vec![];
+            for token_id in ["1", "2"] {
+                loan_ids.push(
+                    deposit_collaterals(
+                        &mut app,
+                        &contracts.loan,
+                        BORROWER,
+                        vec![AssetInfo::sg721("collection", token_id)],
+                        None,
+                    )
+                    .unwrap(),
+                );
+            }
+            // Offers on both loans are interleaved, over more than 10 offers
+            let mut expected = vec![];
+            for i in 0..12 {
+                let loan_id = loan_ids[i % 2];
+                let global_offer_id = make_offer(
+                    &mut app,
+                    &contracts.loan,
+                    &format!("lender{i}"),
+                    BORROWER,
+                    loan_id,
+                    loan_terms(100, 10, 100),
+                )
+                .unwrap();
+                if loan_id == loan_ids[0] {
+                    expected.push(global_offer_id);
+                }
+            }
+
+            let offer_ids: Vec<String> = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::OfferIdsForLoan {
+                        borrower: BORROWER.to_string(),
+                        loan_id: loan_ids[0],
+                    },
+                )
+                .unwrap();
+            assert_eq!(offer_ids, expected);
+        }
+    }
+    mod offers_for_loan {
+        use cosmwasm_std::{coin, Addr};
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            msg::{ExecuteMsg, LoanOfferResponse, QueryMsg},
+            state::OfferState,
+        };
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, deposit_collaterals, loan_terms, make_offer, mint_and_approve,
+            setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn outbid_offers_shown_refused() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, BORROWER, &contracts.loan);
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                None,
+            )
+            .unwrap();
+            let accepted = make_offer(
+                &mut app,
+                &contracts.loan,
+                "lender1",
+                BORROWER,
+                loan_id,
+                loan_terms(1_000, 100, 100),
+            )
+            .unwrap();
+            let outbid = make_offer(
+                &mut app,
+                &contracts.loan,
+                "lender2",
+                BORROWER,
+                loan_id,
+                loan_terms(500, 100, 100),
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::AcceptOffer {
+                    global_offer_id: accepted.clone(),
+                },
+                &[],
+            )
+            .unwrap();
+
+            let offers: Vec<LoanOfferResponse> = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::OffersForLoan {
+                        borrower: BORROWER.to_string(),
+                        loan_id,
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                offers,
+                vec![
+                    LoanOfferResponse {
+                        global_offer_id: accepted,
+                        lender: Addr::unchecked("lender1"),
+                        terms: loan_terms(1_000, 100, 100),
+                        state: OfferState::Accepted,
+                        deposited_funds: None,
+                    },
+                    // The offer is still stored as published, but the loan has started without it
+                    LoanOfferResponse {
+                        global_offer_id: outbid,
+                        lender: Addr::unchecked("lender2"),
+                        terms: loan_terms(500, 100, 100),
+                        state: OfferState::Refused,
+                        deposited_funds: Some(coin(500, NATIVE_DENOM)),
+                    },
+                ]
+            );
+        }
+    }
+    mod withdrawable_offers {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use nft_loans::msg::{ExecuteMsg, OfferResponse, QueryMsg};
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            deposit_collaterals, loan_terms, make_offer, setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+        const LENDER: &str = "lender";
+
+        #[test]
+        fn only_refused_offers_with_funds_listed() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721("collection", "1")],
+                None,
+            )
+            .unwrap();
+            let mut global_offer_ids = vec![];
+            for _ in 0..3 {
+                global_offer_ids.push(
+                    make_offer(
+                        &mut app,
+                        &contracts.loan,
+                        LENDER,
+                        BORROWER,
+                        loan_id,
+                        loan_terms(100, 10, 100),
+                    )
+                    .unwrap(),
+                );
+            }
+            // The first two offers are refused, the funds of the first one are withdrawn
+            for global_offer_id in &global_offer_ids[..2] {
+                app.execute_contract(
+                    Addr::unchecked(BORROWER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::RefuseOffer {
+                        global_offer_id: global_offer_id.clone(),
+                    },
+                    &[],
+                )
+                .unwrap();
+            }
+            app.execute_contract(
+                Addr::unchecked(LENDER),
+                contracts.loan.clone(),
+                &ExecuteMsg::WithdrawRefusedOffer {
+                    global_offer_id: global_offer_ids[0].clone(),
+                },
+                &[],
+            )
+            .unwrap();
+
+            let offers: Vec<OfferResponse> = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::WithdrawableOffers {
+                        lender: LENDER.to_string(),
+                    },
+                )
+                .unwrap();
+            let offer_ids: Vec<String> = offers
+                .into_iter()
+                .map(|offer| offer.global_offer_id)
+                .collect();
+            assert_eq!(offer_ids, vec![global_offer_ids[1].clone()]);
+        }
+    }
+    mod offer_stats {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use nft_loans::msg::{ExecuteMsg, OfferStatsResponse, QueryMsg};
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            deposit_collaterals, loan_terms, make_offer, setup_loan_contracts,
+        };
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn cancelled_offers_still_counted() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721("collection", "1")],
+                None,
+            )
+            .unwrap();
+            let mut global_offer_ids = vec![];
+            for lender in ["lender1", "lender2", "lender3"] {
+                global_offer_ids.push(
+                    make_offer(
+                        &mut app,
+                        &contracts.loan,
+                        lender,
+                        BORROWER,
+                        loan_id,
+                        loan_terms(100, 10, 100),
+                    )
+                    .unwrap(),
+                );
+            }
+            app.execute_contract(
+                Addr::unchecked("lender2"),
+                contracts.loan.clone(),
+                &ExecuteMsg::CancelOffer {
+                    global_offer_id: global_offer_ids[1].clone(),
+                },
+                &[],
+            )
+            .unwrap();
+
+            let stats: OfferStatsResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::OfferStats {
+                        borrower: BORROWER.to_string(),
+                        loan_id,
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                stats,
+                OfferStatsResponse {
+                    total_offers: 3,
+                    live_offers: 2,
+                }
+            );
+        }
+    }
+    mod offer_yield {
+        use cosmwasm_std::{Decimal, Uint128};
+        use nft_loans::msg::{OfferYieldResponse, QueryMsg};
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            deposit_collaterals, loan_terms, make_offer, setup_loan_contracts,
+        };
+
+        #[test]
+        fn net_of_fee() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                "borrower",
+                vec![AssetInfo::sg721("collection", "1")],
+                None,
+            )
+            .unwrap();
+            // A year worth of 6 second blocks
+            let global_offer_id = make_offer(
+                &mut app,
+                &contracts.loan,
+                "lender",
+                "borrower",
+                loan_id,
+                loan_terms(1_000, 100, 5_256_000),
+            )
+            .unwrap();
+
+            let response: OfferYieldResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::OfferYield {
+                        global_offer_id: global_offer_id.clone(),
+                    },
+                )
+                .unwrap();
+            // The fee distributor takes 5% of the interest
+            assert_eq!(
+                response,
+                OfferYieldResponse {
+                    global_offer_id,
+                    net_interest: Uint128::new(95),
+                    apr: Decimal::permille(95),
+                }
+            );
+        }
+    }
+    mod offer_location {
+        use cosmwasm_std::Addr;
+        use nft_loans::msg::{OfferLocationResponse, QueryMsg};
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            deposit_collaterals, loan_terms, make_offer, setup_loan_contracts,
+        };
+
+        #[test]
+        fn known_offer_located() {
+            let (mut app, contracts) = setup_loan_contracts();
+            for token_id in ["1", "2"] {
+                deposit_collaterals(
+                    &mut app,
+                    &contracts.loan,
+                    "borrower",
+                    vec![AssetInfo::sg721("collection", token_id)],
+                    None,
+                )
+                .unwrap();
+            }
+            let mut global_offer_ids = vec![];
+            for lender in ["lender", "other_lender"] {
+                global_offer_ids.push(
+                    make_offer(
+                        &mut app,
+                        &contracts.loan,
+                        lender,
+                        "borrower",
+                        1,
+                        loan_terms(1_000, 100, 100),
+                    )
+                    .unwrap(),
+                );
+            }
+
+            let response: OfferLocationResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::OfferLocation {
+                        global_offer_id: global_offer_ids[1].clone(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                response,
+                OfferLocationResponse {
+                    borrower: Addr::unchecked("borrower"),
+                    loan_id: 1,
+                    offer_id: 2,
+                }
+            );
+            app.wrap()
+                .query_wasm_smart::<OfferLocationResponse>(
+                    contracts.loan.clone(),
+                    &QueryMsg::OfferLocation {
+                        global_offer_id: "unknown".to_string(),
+                    },
+                )
+                .unwrap_err();
+        }
+    }
+    mod loan_to_value {
+        use cosmwasm_std::{Decimal, Uint128};
+        use nft_loans::msg::QueryMsg;
+
+        use crate::common_setup::helpers::{loan_terms, setup_loan_contracts, start_loan};
+
+        #[test]
+        fn principle_over_floor() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (loan_id, _, _) =
+                start_loan(&mut app, &contracts, "borrower", "lender", loan_terms(456, 10, 100));
+
+            let ltv: Decimal = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::LoanToValue {
+                        borrower: "borrower".to_string(),
+                        loan_id,
+                        collateral_floor: Uint128::new(1_000),
+                    },
+                )
+                .unwrap();
+            assert_eq!(ltv, Decimal::permille(456));
+        }
+    }
+    mod collaterals_filtered {
+        use cosmwasm_std::{coins, Addr};
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            msg::{ExecuteMsg, MultipleCollateralsAllResponse, QueryMsg},
+            state::LoanState,
+        };
+        use sg_multi_test::StargazeApp;
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, create_collection, deposit_collaterals, loan_terms, mint_and_approve,
+            mint_native, setup_loan_contracts,
+        };
+
+        #[test]
+        fn healthy_and_defaulted_loans_told_apart() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            // A short loan that defaults, a long one that stays healthy and a loan that never starts
+            for (borrower, duration_in_blocks, lender) in [
+                ("short", 10, Some("lender0")),
+                ("long", 1_000, Some("lender1")),
+                ("unfunded", 10, None),
+            ] {
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, borrower, &contracts.loan);
+                let loan_id = deposit_collaterals(
+                    &mut app,
+                    &contracts.loan,
+                    borrower,
+                    vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    Some(loan_terms(1_000, 100, duration_in_blocks)),
+                )
+                .unwrap();
+                if let Some(lender) = lender {
+                    mint_native(&mut app, lender, 1_000);
+                    app.execute_contract(
+                        Addr::unchecked(lender),
+                        contracts.loan.clone(),
+                        &ExecuteMsg::AcceptLoan {
+                            borrower: borrower.to_string(),
+                            loan_id,
+                            comment: None,
+                        },
+                        &coins(1_000, NATIVE_DENOM),
+                    )
+                    .unwrap();
+                }
+            }
+            advance_time(&mut app, 100);
+
+            let borrowers = |app: &StargazeApp, state: Option<LoanState>, defaulted: Option<bool>| {
+                let response: MultipleCollateralsAllResponse = app
+                    .wrap()
+                    .query_wasm_smart(
+                        contracts.loan.clone(),
+                        &QueryMsg::CollateralsFiltered {
+                            state,
+                            defaulted,
+                            start_after: None,
+                            limit: None,
+                        },
+                    )
+                    .unwrap();
+                response
+                    .collaterals
+                    .into_iter()
+                    .map(|collateral| collateral.borrower)
+                    .collect::<Vec<String>>()
+            };
+            assert_eq!(
+                borrowers(&app, Some(LoanState::Started), Some(false)),
+                vec!["long"]
+            );
+            assert_eq!(
+                borrowers(&app, Some(LoanState::Started), Some(true)),
+                vec!["short"]
+            );
+            assert_eq!(borrowers(&app, None, Some(false)), vec!["unfunded", "long"]);
+            assert_eq!(
+                borrowers(&app, Some(LoanState::Published), None),
+                vec!["unfunded"]
+            );
+        }
+    }
+    mod lender_defaulted_loans {
+        use cosmwasm_std::{coins, Addr};
+        use cw_multi_test::Executor;
+        use nft_loans::msg::{ExecuteMsg, MultipleOffersResponse, QueryMsg};
+        use sg_multi_test::StargazeApp;
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, create_collection, deposit_collaterals, loan_terms, mint_and_approve,
+            mint_native, setup_loan_contracts,
+        };
+
+        const LENDER: &str = "lender";
+
+        #[test]
+        fn only_defaulted_loans_listed() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            for (borrower, duration_in_blocks) in [("short", 10), ("long", 1_000)] {
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, borrower, &contracts.loan);
+                let loan_id = deposit_collaterals(
+                    &mut app,
+                    &contracts.loan,
+                    borrower,
+                    vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    Some(loan_terms(1_000, 100, duration_in_blocks)),
+                )
+                .unwrap();
+                mint_native(&mut app, LENDER, 1_000);
+                app.execute_contract(
+                    Addr::unchecked(LENDER),
+                    contracts.loan.clone(),
+                    &ExecuteMsg::AcceptLoan {
+                        borrower: borrower.to_string(),
+                        loan_id,
+                        comment: None,
+                    },
+                    &coins(1_000, NATIVE_DENOM),
+                )
+                .unwrap();
+            }
+            advance_time(&mut app, 100);
+
+            let defaulted_borrowers = |app: &StargazeApp| -> Vec<String> {
+                let response: MultipleOffersResponse = app
+                    .wrap()
+                    .query_wasm_smart(
+                        contracts.loan.clone(),
+                        &QueryMsg::LenderDefaultedLoans {
+                            lender: LENDER.to_string(),
+                            start_after: None,
+                            limit: None,
+                        },
+                    )
+                    .unwrap();
+                response
+                    .offers
+                    .into_iter()
+                    .map(|offer| offer.offer_info.borrower.to_string())
+                    .collect()
+            };
+            assert_eq!(defaulted_borrowers(&app), vec!["short"]);
+
+            // Once the collateral is withdrawn, there is nothing left to do on the loan
+            app.execute_contract(
+                Addr::unchecked(LENDER),
+                contracts.loan.clone(),
+                &ExecuteMsg::WithdrawDefaultedLoan {
+                    borrower: "short".to_string(),
+                    loan_id: 0,
+                },
+                &[],
+            )
+            .unwrap();
+            assert!(defaulted_borrowers(&app).is_empty());
+        }
+    }
+
+    mod archived_loans {
+        use cosmwasm_std::{coin, Addr};
+        use cw_multi_test::Executor;
+        use nft_loans::{
+            msg::{ExecuteMsg, MultipleCollateralsResponse, QueryMsg},
+            state::{CollateralInfo, LoanState},
+        };
+        use sg_multi_test::StargazeApp;
+        use sg_std::NATIVE_DENOM;
+
+        use crate::common_setup::helpers::{loan_terms, mint_native, setup_loan_contracts, start_loan};
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn repaid_loan_moves_to_archive() {
+            let (mut app, contracts) = setup_loan_contracts();
+            let (loan_id, _, _) =
+                start_loan(&mut app, &contracts, BORROWER, "lender", loan_terms(1_000, 100, 1_000));
+
+            let loan_ids = |app: &StargazeApp, archived: bool| -> Vec<u64> {
+                let msg = if archived {
+                    QueryMsg::ArchivedLoans {
+                        borrower: BORROWER.to_string(),
+                        start_after: None,
+                        limit: None,
+                    }
+                } else {
+                    QueryMsg::Collaterals {
+                        borrower: BORROWER.to_string(),
+                        start_after: None,
+                        limit: None,
+                    }
+                };
+                let response: MultipleCollateralsResponse = app
+                    .wrap()
+                    .query_wasm_smart(contracts.loan.clone(), &msg)
+                    .unwrap();
+                response.collaterals.iter().map(|c| c.loan_id).collect()
+            };
+            assert_eq!(loan_ids(&app, false), vec![loan_id]);
+            assert!(loan_ids(&app, true).is_empty());
+
+            mint_native(&mut app, BORROWER, 100);
+            app.execute_contract(
+                Addr::unchecked(BORROWER),
+                contracts.loan.clone(),
+                &ExecuteMsg::RepayBorrowedFunds { loan_id },
+                &[coin(1_100, NATIVE_DENOM)],
+            )
+            .unwrap();
+
+            assert!(loan_ids(&app, false).is_empty());
+            assert_eq!(loan_ids(&app, true), vec![loan_id]);
+            // The loan can still be looked up directly
+            let collateral: CollateralInfo = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::CollateralInfo {
+                        borrower: BORROWER.to_string(),
+                        loan_id,
+                    },
+                )
+                .unwrap();
+            assert_eq!(collateral.state, LoanState::Ended);
+        }
+    }
+
+    mod tvl {
+        use cosmwasm_std::{coin, Addr, Uint128};
+        use cw_multi_test::Executor;
+        use nft_loans::msg::{ExecuteMsg, QueryMsg, TvlResponse};
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            deposit_collaterals, loan_terms, make_offer, setup_loan_contracts, start_loan,
+        };
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn published_offers_and_started_loans_counted() {
+            let (mut app, contracts) = setup_loan_contracts();
+            start_loan(
+                &mut app,
+                &contracts,
+                "other_borrower",
+                "lender0",
+                loan_terms(1_000, 100, 1_000),
+            );
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721("collection", "1")],
+                None,
+            )
+            .unwrap();
+            make_offer(
+                &mut app,
+                &contracts.loan,
+                "lender1",
+                BORROWER,
+                loan_id,
+                loan_terms(500, 50, 1_000),
+            )
+            .unwrap();
+            // A cancelled offer doesn't hold funds anymore
+            let cancelled = make_offer(
+                &mut app,
+                &contracts.loan,
+                "lender2",
+                BORROWER,
+                loan_id,
+                loan_terms(300, 30, 1_000),
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked("lender2"),
+                contracts.loan.clone(),
+                &ExecuteMsg::CancelOffer {
+                    global_offer_id: cancelled,
+                },
+                &[],
+            )
+            .unwrap();
+
+            let response: TvlResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.loan.clone(),
+                    &QueryMsg::Tvl {
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(response.tvl, vec![coin(1_500, NATIVE_DENOM)]);
+            assert_eq!(response.next_offer, None);
+
+            // Summing the pages gives the same result
+            let mut total = Uint128::zero();
+            let mut start_after = None;
+            loop {
+                let page: TvlResponse = app
+                    .wrap()
+                    .query_wasm_smart(
+                        contracts.loan.clone(),
+                        &QueryMsg::Tvl {
+                            start_after,
+                            limit: Some(1),
+                        },
+                    )
+                    .unwrap();
+                total += page.tvl.iter().map(|c| c.amount).sum::<Uint128>();
+                match page.next_offer {
+                    Some(next) => start_after = Some(next),
+                    None => break,
+                }
+            }
+            assert_eq!(total, Uint128::new(1_500));
+        }
+    }
+
+    mod solvency_check {
+        use cosmwasm_std::{Int128, Uint128};
+        use nft_loans::msg::{QueryMsg, SolvencyResponse};
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            deposit_collaterals, loan_terms, make_offer, setup_loan_contracts, start_loan,
+        };
+
+        const BORROWER: &str = "borrower";
+
+        #[test]
+        fn balance_matches_escrowed_offers() {
+            let (mut app, contracts) = setup_loan_contracts();
+            // The funds of a started loan went to its borrower
+            start_loan(
+                &mut app,
+                &contracts,
+                "other_borrower",
+                "lender0",
+                loan_terms(1_000, 100, 1_000),
+            );
+            let loan_id = deposit_collaterals(
+                &mut app,
+                &contracts.loan,
+                BORROWER,
+                vec![AssetInfo::sg721("collection", "1")],
+                None,
+            )
+            .unwrap();
+            for (lender, principle) in [("lender1", 500), ("lender2", 300)] {
+                make_offer(
+                    &mut app,
+                    &contracts.loan,
+                    lender,
+                    BORROWER,
+                    loan_id,
+                    loan_terms(principle, 10, 1_000),
+                )
+                .unwrap();
+            }
+
+            let response: Vec<SolvencyResponse> = app
+                .wrap()
+                .query_wasm_smart(contracts.loan.clone(), &QueryMsg::SolvencyCheck {})
+                .unwrap();
+            assert_eq!(
+                response,
+                vec![SolvencyResponse {
+                    denom: NATIVE_DENOM.to_string(),
+                    balance: Uint128::new(800),
+                    escrowed: Uint128::new(800),
+                    difference: Int128::zero(),
+                }]
+            );
+        }
+    }
+}
@@ -2,12 +2,12 @@
 
 use cosmwasm_std::{
     coins,
-    testing::{mock_dependencies_with_balance, mock_env, mock_info},
-    Api, Uint128, Coin,
+    testing::{mock_dependencies_with_balance, mock_env, mock_info}, Uint128,
 };
 use raffles::{
     contract::{instantiate, execute},
-    msg::{ExecuteMsg, InstantiateMsg}, state::{RaffleOptions, RaffleOptionsMsg},
+    error::ContractError,
+    msg::{ExecuteMsg, InstantiateMsg}, state::RaffleOptionsMsg,
 };
 use sg_std::NATIVE_DENOM;
 use utils::state::{AssetInfo, Cw721Coin, Sg721Token};
@@ -35,13 +35,15 @@ fn initialization() {
         nois_proxy_addr: "".to_string(),
         nois_proxy_denom: "ibc/717352A5277F3DE916E8FD6B87F4CA6A51F2FBA9CF04ABCFF2DF7202F8A8BC50"
             .to_string(),
-        nois_proxy_amount: AMOUNT.into(),
+        nois_proxy_amount: AMOUNT,
         fee_addr: None,
         minimum_raffle_duration: None,
         minimum_raffle_timeout: None,
         max_participant_number: None,
         raffle_fee: None,
         rand_fee: None,
+        job_id_prefix: None,
+        cancel_lockout_seconds: None,
         creation_fee_denom: Some(NATIVE_DENOM.to_string()),
         creation_fee_amount: AMOUNT.into(),
     };
@@ -49,6 +51,40 @@ fn initialization() {
     instantiate(deps.as_mut(), mock_env(), info, msg).unwrap_err();
 }
 
+#[test]
+fn initialization_zero_proxy_amount() {
+    let mut deps = mock_dependencies_with_balance(&coins(2, "token"));
+    let info = mock_info("creator", &coins(INITIAL_BALANCE, NATIVE_DENOM));
+
+    let mut msg = InstantiateMsg {
+        owner: Some(MANAGER.to_string()),
+        name: NAME.to_string(),
+        nois_proxy_addr: NOIS_PROXY.to_string(),
+        nois_proxy_denom: NATIVE_DENOM.to_string(),
+        nois_proxy_amount: Uint128::zero(),
+        fee_addr: None,
+        minimum_raffle_duration: None,
+        minimum_raffle_timeout: None,
+        max_participant_number: None,
+        raffle_fee: None,
+        rand_fee: None,
+        job_id_prefix: None,
+        cancel_lockout_seconds: None,
+        creation_fee_denom: Some(NATIVE_DENOM.to_string()),
+        creation_fee_amount: AMOUNT.into(),
+    };
+
+    // A zero proxy amount is rejected
+    let err = instantiate(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+    assert_eq!(err, ContractError::InvalidProxyAmount {});
+
+    // So is an empty proxy denom
+    msg.nois_proxy_amount = AMOUNT;
+    msg.nois_proxy_denom = "".to_string();
+    let err = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+    assert_eq!(err, ContractError::InvalidProxyDenom {});
+}
+
 #[test]
 fn execution() {
     // Invalid TicketPrice
@@ -66,13 +102,15 @@ fn execution() {
         nois_proxy_addr: NOIS_PROXY.to_string(),
         nois_proxy_denom: "ibc/717352A5277F3DE916E8FD6B87F4CA6A51F2FBA9CF04ABCFF2DF7202F8A8BC50"
             .to_string(),
-        nois_proxy_amount: AMOUNT.into(),
+        nois_proxy_amount: AMOUNT,
         fee_addr: None,
         minimum_raffle_duration: None,
         minimum_raffle_timeout: None,
         max_participant_number: None,
         raffle_fee: None,
         rand_fee: None,
+        job_id_prefix: None,
+        cancel_lockout_seconds: None,
         creation_fee_denom: Some(NATIVE_DENOM.to_string()),
         creation_fee_amount: AMOUNT.into(),
     };
@@ -99,7 +137,15 @@ fn execution() {
         comment: None,
         max_participant_number: None,
         max_ticket_per_address: None,
+        max_tickets_per_tx: None,
         raffle_preview: None,
+        auto_extend_if_below: None,
+        prize_bundles: None,
+        refundable_until: None,
+        merkle_root: None,
+        purchase_cooldown_seconds: None,
+        auto_request_randomness: None,
+        acceptable_prices: None,
     };
     // define improper raffle ticket price
     let bad_ticket_price = AssetInfo::Sg721Token(
@@ -116,7 +162,7 @@ fn execution() {
         raffle_ticket_price: bad_ticket_price,
     };
     // simulate broadcast, expect to unwrap error
-    execute(deps.as_mut(), mock_env(), info, bad_raffle_msg).unwrap();
+    execute(deps.as_mut(), mock_env(), info, bad_raffle_msg).unwrap_err();
 
 
     // // Invalid CancelRaffle
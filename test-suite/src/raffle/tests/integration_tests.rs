@@ -24,7 +24,7 @@ mod tests {
         let chainid = app.block_info().chain_id.clone();
         app.set_block(BlockInfo {
             height: 10000,
-            time: Timestamp::from_nanos(1647032400000000000),
+            time: Timestamp::from_nanos(GENESIS_TIME),
             chain_id: chainid
         });
         let raffle_id = app.store_code(contract_raffles());
@@ -32,15 +32,15 @@ mod tests {
         let minter_id = app.store_code(contract_vending_minter());
         let sg721_id = app.store_code(contract_sg721_base());
 
-        let mut params = mock_params();
+        let _params = mock_params();
 
         let factory_addr = app
             .instantiate_contract(
                 factory_id,
                 Addr::unchecked(OWNER_ADDR),
                 &vending_factory::msg::InstantiateMsg { params: VendingMinterParams {
-                    code_id: minter_id.clone(),
-                    allowed_sg721_code_ids: vec![sg721_id.clone()],
+                    code_id: minter_id,
+                    allowed_sg721_code_ids: vec![sg721_id],
                     frozen: false,
                     creation_fee: Coin {denom: "ustars".to_string(), amount: Uint128::new(100000u128)},
                     min_mint_price: Coin {denom: "ustars".to_string(), amount: Uint128::new(100000u128)},
@@ -78,6 +78,8 @@ mod tests {
                      max_participant_number: None,
                      raffle_fee: None,
                      rand_fee: None,
+                     job_id_prefix: None,
+                     cancel_lockout_seconds: None,
                      },
                 &[],
                 "raffle",
@@ -90,10 +92,9 @@ mod tests {
 
 
     mod init {
-        use cosmwasm_std::{Coin, coin, Empty, Uint128};
+        use cosmwasm_std::{Coin, coin, Decimal, Empty, Uint128};
         use cw_multi_test::{BankSudo, SudoMsg};
         use sg721::CollectionInfo;
-        use sg_std::GENESIS_MINT_START_TIME;
         use vending_factory::msg::VendingMinterCreateMsg;
         use raffles::state::RaffleOptionsMsg;
         use utils::state::{AssetInfo, Sg721Token};
@@ -110,7 +111,7 @@ mod tests {
                 ).unwrap();
             assert_eq!(query_config.owner, Addr::unchecked("fee"));
 
-            let current_time = app.block_info().time.clone();
+            let current_time = app.block_info().time;
 
             app.sudo(SudoMsg::Bank({
                 BankSudo::Mint {
@@ -123,15 +124,14 @@ mod tests {
             }))
                 .unwrap();
 
-            let exec_outcome = app
+            let _exec_outcome = app
                 .execute_contract(
                     Addr::unchecked(OWNER_ADDR),
                     factory_addr.clone(),
-                    &vending_factory::msg::ExecuteMsg::CreateMinter {
-                        0: VendingMinterCreateMsg { init_msg: vending_factory::msg::VendingMinterInitMsgExtension {
+                    &vending_factory::msg::ExecuteMsg::CreateMinter(VendingMinterCreateMsg { init_msg: vending_factory::msg::VendingMinterInitMsgExtension {
                             base_token_uri: "ipfs://aldkfjads".to_string(),
                             payment_address: Some(OWNER_ADDR.to_string()),
-                            start_time: current_time.clone(),
+                            start_time: current_time,
                             num_tokens: 100,
                             mint_price: coin(Uint128::new(100000u128).u128(), "ustars"),
                             per_address_limit: 3,
@@ -149,11 +149,11 @@ mod tests {
                                 explicit_content: Some(false),
                                 royalty_info: None,
                             },
-                        } } },
+                        } }),
                     &[Coin {denom: "ustars".to_string(), amount: Uint128::new(100000u128)}]);
             // contract2 is minter
 
-            let exec_outcome = app
+            let _exec_outcome = app
                 .execute_contract(
                     Addr::unchecked(OWNER_ADDR),
                     Addr::unchecked("contract2"),
@@ -162,7 +162,7 @@ mod tests {
                 ).unwrap();
             // token id 41
 
-            let exec_outcome = app
+            let _exec_outcome = app
                 .execute_contract(
                     Addr::unchecked(OWNER_ADDR),
                     Addr::unchecked("contract3"),
@@ -187,7 +187,15 @@ mod tests {
                             comment: None,
                             max_participant_number: None,
                             max_ticket_per_address: None,
+                            max_tickets_per_tx: None,
                             raffle_preview: None,
+                            auto_extend_if_below: None,
+                            prize_bundles: None,
+                            refundable_until: None,
+                            merkle_root: None,
+                            purchase_cooldown_seconds: None,
+                            auto_request_randomness: None,
+                            acceptable_prices: None,
                         },
                         raffle_ticket_price: AssetInfo::Coin(Coin { denom: "denom".to_string(), amount: Uint128::new(100u128) }),
                     },
@@ -196,6 +204,3246 @@ mod tests {
             println!("{:#?}", exec_outcome);
 
         }
+
+        #[test]
+        fn config_response_after_instantiate() {
+            use crate::common_setup::helpers;
+
+            let (app, contracts) = helpers::setup_raffle_contracts_with(|msg| {
+                msg.creation_fee_amount = Some(Uint128::new(100));
+                msg.minimum_raffle_duration = Some(60);
+                msg.raffle_fee = Some(Decimal::percent(5));
+                msg.job_id_prefix = Some("atlas-".to_string());
+            });
+            let config: raffles::msg::ConfigResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle, &raffles::msg::QueryMsg::Config {})
+                .unwrap();
+            assert_eq!(
+                config,
+                raffles::msg::ConfigResponse {
+                    name: "raffle param name".to_string(),
+                    owner: Addr::unchecked(helpers::OWNER_ADDR),
+                    fee_addr: Addr::unchecked(helpers::FEE_ADDR),
+                    last_raffle_id: 0,
+                    minimum_raffle_duration: 60,
+                    minimum_raffle_timeout: raffles::state::MINIMUM_RAFFLE_TIMEOUT,
+                    creation_fee_denom: NATIVE_DENOM.to_string(),
+                    creation_fee_amount: Uint128::new(100),
+                    raffle_fee: Decimal::percent(5),
+                    rand_fee: raffles::state::MINIMUM_RAND_FEE,
+                    lock: false,
+                    full_pause: false,
+                    nois_proxy_addr: contracts.nois_proxy,
+                    nois_proxy_denom: NATIVE_DENOM.to_string(),
+                    nois_proxy_amount: Uint128::new(helpers::NOIS_AMOUNT),
+                    job_id_prefix: "atlas-".to_string(),
+                    max_live_raffles: None,
+                    live_raffles: 0,
+                    cancel_lockout_seconds: 0,
+                    trusted_minters: vec![],
+                    max_nois_proxy_amount: None,
+                    randomness_mode: raffles::state::RandomnessMode::Nois,
+                    consolation_bps: None,
+                    nois_fee_source: raffles::state::NoisFeeSource::Contract,
+                    min_assets_per_raffle: 1,
+                    price_oracle: None,
+                }
+            );
+        }
+
+        #[test]
+        fn nois_proxy_amount_bounded() {
+            use crate::common_setup::helpers;
+            use raffles::{error::ContractError, msg::ExecuteMsg};
+
+            let (mut app, contracts) = helpers::setup_raffle_contracts();
+            let update_config =
+                |nois_proxy_amount: u128,
+                 max_nois_proxy_amount: Option<u128>,
+                 clear_max_nois_proxy_amount: bool| {
+                    ExecuteMsg::UpdateConfig {
+                        name: None,
+                        owner: None,
+                        fee_addr: None,
+                        minimum_raffle_duration: None,
+                        minimum_raffle_timeout: None,
+                        creation_fee_denom: None,
+                        creation_fee_amount: None,
+                        raffle_fee: None,
+                        rand_fee: None,
+                        nois_proxy_addr: None,
+                        nois_proxy_denom: None,
+                        nois_proxy_amount: Some(Uint128::new(nois_proxy_amount)),
+                        cancel_lockout_seconds: None,
+                        max_nois_proxy_amount: max_nois_proxy_amount.map(Uint128::new),
+                        clear_max_nois_proxy_amount: Some(clear_max_nois_proxy_amount),
+                    }
+                };
+
+            // A zero fee couldn't pay for any draw
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(helpers::OWNER_ADDR),
+                    contracts.raffle.clone(),
+                    &update_config(0, None, false),
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::InvalidProxyAmount {}
+            );
+
+            app.execute_contract(
+                Addr::unchecked(helpers::OWNER_ADDR),
+                contracts.raffle.clone(),
+                &update_config(100, Some(1_000), false),
+                &[],
+            )
+            .unwrap();
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(helpers::OWNER_ADDR),
+                    contracts.raffle.clone(),
+                    &update_config(1_001, None, false),
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::ProxyAmountTooHigh {
+                    max: Uint128::new(1_000)
+                }
+            );
+
+            // Once the ceiling is cleared, the fee is unbounded again
+            app.execute_contract(
+                Addr::unchecked(helpers::OWNER_ADDR),
+                contracts.raffle.clone(),
+                &update_config(1_001, Some(1_000), true),
+                &[],
+            )
+            .unwrap();
+            let config: raffles::msg::ConfigResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &raffles::msg::QueryMsg::Config {})
+                .unwrap();
+            assert_eq!(config.max_nois_proxy_amount, None);
+            assert_eq!(config.nois_proxy_amount, Uint128::new(1_001));
+        }
+
+        #[test]
+        fn empty_nois_proxy_denom_rejected_on_update() {
+            use crate::common_setup::helpers;
+            use raffles::{error::ContractError, msg::ExecuteMsg};
+
+            let (mut app, contracts) = helpers::setup_raffle_contracts();
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(helpers::OWNER_ADDR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::UpdateConfig {
+                        name: None,
+                        owner: None,
+                        fee_addr: None,
+                        minimum_raffle_duration: None,
+                        minimum_raffle_timeout: None,
+                        creation_fee_denom: None,
+                        creation_fee_amount: None,
+                        raffle_fee: None,
+                        rand_fee: None,
+                        nois_proxy_addr: None,
+                        nois_proxy_denom: Some(String::new()),
+                        nois_proxy_amount: None,
+                        cancel_lockout_seconds: None,
+                        max_nois_proxy_amount: None,
+                        clear_max_nois_proxy_amount: None,
+                    },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::InvalidProxyDenom {}
+            );
+        }
+    }
+
+    mod unwind {
+        use cosmwasm_std::{Addr, Uint128, coin};
+        use cw_multi_test::Executor;
+        use raffles::{
+            error::ContractError,
+            msg::ExecuteMsg,
+            state::{RaffleOptionsMsg, MINIMUM_RAFFLE_TIMEOUT},
+        };
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, buy_tickets, create_collection, create_raffle, default_raffle_options,
+            find_attribute, mint_and_approve, mint_native, native_balance, nft_owner,
+            send_nois_randomness, setup_raffle_contracts, NOIS_AMOUNT, OWNER_ADDR,
+        };
+
+        const CREATOR: &str = "creator";
+        const TICKET_PRICE: u128 = 100;
+
+        #[test]
+        fn cancel_and_unwind_refunds_everyone() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) = create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, &token_id), contracts.raffle.to_string());
+
+            buy_tickets(&mut app, &contracts.raffle, "buyer1", raffle_id, 2, TICKET_PRICE).unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer2", raffle_id, 1, TICKET_PRICE).unwrap();
+
+            // The sale closes and someone pays for the randomness request
+            advance_time(&mut app, 101);
+            mint_native(&mut app, "requester", NOIS_AMOUNT);
+            app.execute_contract(
+                Addr::unchecked("requester"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::UpdateRandomness { raffle_id },
+                &[coin(NOIS_AMOUNT, NATIVE_DENOM)],
+            )
+            .unwrap();
+            assert_eq!(native_balance(&app, "requester"), Uint128::zero());
+            assert_eq!(native_balance(&app, contracts.nois_proxy.as_str()), Uint128::new(NOIS_AMOUNT));
+
+            // Escrow of other raffles
+            mint_native(&mut app, contracts.raffle.as_str(), 1_000);
+
+            // Only the contract owner can unwind a raffle
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(CREATOR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::CancelAndUnwind { raffle_id },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::Unauthorized);
+
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::CancelAndUnwind { raffle_id },
+                &[],
+            )
+            .unwrap();
+
+            // Ticket buyers and raffle owner are refunded
+            assert_eq!(native_balance(&app, "buyer1"), Uint128::new(2 * TICKET_PRICE));
+            assert_eq!(native_balance(&app, "buyer2"), Uint128::new(TICKET_PRICE));
+            // The nois fee already went to the proxy, it isn't refunded from the other raffles escrow
+            assert_eq!(native_balance(&app, "requester"), Uint128::zero());
+            assert_eq!(native_balance(&app, contracts.raffle.as_str()), Uint128::new(1_000));
+            assert_eq!(nft_owner(&app, &collection, &token_id), CREATOR.to_string());
+
+            // A late beacon can't revive the raffle
+            send_nois_randomness(&mut app, &contracts, raffle_id, [1u8; 32]).unwrap();
+            let err = app
+                .execute_contract(
+                    Addr::unchecked("buyer1"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::ClaimNft { raffle_id },
+                    &[],
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::WrongStateForClaim { .. }
+            ));
+        }
+
+        #[test]
+        fn cancel_and_unwind_rejected_after_draw() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) = create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer1", raffle_id, 1, TICKET_PRICE).unwrap();
+
+            // The contract pays for the randomness itself
+            advance_time(&mut app, 101);
+            mint_native(&mut app, contracts.raffle.as_str(), NOIS_AMOUNT);
+            app.execute_contract(
+                Addr::unchecked("buyer1"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::UpdateRandomness { raffle_id },
+                &[],
+            )
+            .unwrap();
+            send_nois_randomness(&mut app, &contracts, raffle_id, [1u8; 32]).unwrap();
+            advance_time(&mut app, 120);
+            app.execute_contract(
+                Addr::unchecked("buyer1"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft { raffle_id },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, &token_id), "buyer1".to_string());
+
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(OWNER_ADDR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::CancelAndUnwind { raffle_id },
+                    &[],
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::WrongStateForCancel { .. }
+            ));
+        }
+
+        #[test]
+        fn admin_settle_refunds_after_timeout() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) = create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer1", raffle_id, 2, TICKET_PRICE).unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer2", raffle_id, 1, TICKET_PRICE).unwrap();
+
+            // The randomness is requested but the beacon never arrives
+            advance_time(&mut app, 101);
+            mint_native(&mut app, "requester", NOIS_AMOUNT);
+            app.execute_contract(
+                Addr::unchecked("requester"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::UpdateRandomness { raffle_id },
+                &[coin(NOIS_AMOUNT, NATIVE_DENOM)],
+            )
+            .unwrap();
+            // Escrow of other raffles
+            mint_native(&mut app, contracts.raffle.as_str(), 1_000);
+
+            // The beacon may still arrive before the timeout
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(OWNER_ADDR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::AdminSettle { raffle_id },
+                    &[],
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::WrongStateForSettle { .. }
+            ));
+
+            advance_time(&mut app, MINIMUM_RAFFLE_TIMEOUT);
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(CREATOR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::AdminSettle { raffle_id },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::Unauthorized);
+            let response = app
+                .execute_contract(
+                    Addr::unchecked(OWNER_ADDR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::AdminSettle { raffle_id },
+                    &[],
+                )
+                .unwrap();
+
+            assert_eq!(native_balance(&app, "buyer1"), Uint128::new(2 * TICKET_PRICE));
+            assert_eq!(native_balance(&app, "buyer2"), Uint128::new(TICKET_PRICE));
+            // The nois fee was already forwarded to the proxy, it isn't taken from the other raffles
+            assert_eq!(native_balance(&app, "requester"), Uint128::zero());
+            assert_eq!(
+                native_balance(&app, contracts.raffle.as_str()),
+                Uint128::new(1_000)
+            );
+            assert_eq!(
+                find_attribute(&response, "nois_fee_lost"),
+                Some(coin(NOIS_AMOUNT, NATIVE_DENOM).to_string())
+            );
+            assert_eq!(nft_owner(&app, &collection, &token_id), CREATOR.to_string());
+        }
+    }
+
+    mod claim {
+        use cosmwasm_std::{Addr, Decimal, Empty, Uint128};
+        use cw_multi_test::Executor;
+        use raffles::{
+            error::ContractError,
+            msg::{ConfigResponse, ExecuteMsg, QueryMsg, RaffleResponse},
+            state::{RaffleOptionsMsg, ATLAS_DAO_STARGAZE_TREASURY},
+            utils::compute_raffle_split,
+        };
+        use sg_multi_test::StargazeApp;
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::contract_boxes::{contract_reentrant_nft, ReentrantNftMsg};
+        use crate::common_setup::helpers::{
+            advance_time, buy_tickets, create_collection, create_raffle, default_raffle_options,
+            draw_raffle, find_attribute, mint_and_approve, mint_native, native_balance, nft_owner,
+            setup_raffle_contracts, setup_raffle_contracts_with, FEE_ADDR, NOIS_AMOUNT, OWNER_ADDR,
+        };
+
+        const CREATOR: &str = "creator";
+        const TICKET_PRICE: u128 = 100;
+
+        #[test]
+        fn zero_ticket_claim_flags_no_participants() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let mut raffle_ids = vec![];
+            for _ in 0..2 {
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+                let raffle_id = create_raffle(
+                    &mut app,
+                    &contracts.raffle,
+                    CREATOR,
+                    vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    TICKET_PRICE,
+                    RaffleOptionsMsg {
+                        raffle_duration: Some(100),
+                        ..default_raffle_options()
+                    },
+                )
+                .unwrap();
+                raffle_ids.push((raffle_id, token_id));
+            }
+            let (empty_raffle, empty_token) = raffle_ids[0].clone();
+            let (sold_raffle, _) = raffle_ids[1].clone();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", sold_raffle, 1, TICKET_PRICE).unwrap();
+
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, empty_raffle, [1u8; 32]);
+            draw_raffle(&mut app, &contracts, sold_raffle, [2u8; 32]);
+
+            let response = app
+                .execute_contract(
+                    Addr::unchecked("anyone"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::ClaimNft {
+                        raffle_id: empty_raffle,
+                    },
+                    &[],
+                )
+                .unwrap();
+            assert_eq!(
+                find_attribute(&response, "no_participants"),
+                Some("true".to_string())
+            );
+            assert_eq!(
+                find_attribute(&response, "event"),
+                Some("returned_to_owner".to_string())
+            );
+            assert_eq!(nft_owner(&app, &collection, &empty_token), CREATOR.to_string());
+
+            // A real draw is flagged as such
+            let response = app
+                .execute_contract(
+                    Addr::unchecked("anyone"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::ClaimNft {
+                        raffle_id: sold_raffle,
+                    },
+                    &[],
+                )
+                .unwrap();
+            assert_eq!(
+                find_attribute(&response, "no_participants"),
+                Some("false".to_string())
+            );
+            assert_eq!(find_attribute(&response, "event"), None);
+        }
+
+        #[test]
+        fn winner_relists_prize() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "winner", raffle_id, 1, TICKET_PRICE).unwrap();
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_id, [1u8; 32]);
+
+            let response = app
+                .execute_contract(
+                    Addr::unchecked("winner"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::ClaimAndRelist {
+                        raffle_id,
+                        new_raffle_options: default_raffle_options(),
+                        new_ticket_price: AssetInfo::coin(2 * TICKET_PRICE, NATIVE_DENOM),
+                    },
+                    &[],
+                )
+                .unwrap();
+            let new_raffle_id: u64 = find_attribute(&response, "new_raffle_id")
+                .unwrap()
+                .parse()
+                .unwrap();
+
+            // The prize stays in the contract, in a raffle owned by the winner
+            assert_eq!(
+                nft_owner(&app, &collection, &token_id),
+                contracts.raffle.to_string()
+            );
+            let new_raffle: RaffleResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::RaffleInfo {
+                        raffle_id: new_raffle_id,
+                    },
+                )
+                .unwrap();
+            let new_raffle = new_raffle.raffle_info.unwrap();
+            assert_eq!(new_raffle.owner, Addr::unchecked("winner"));
+            assert_eq!(
+                new_raffle.assets,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)]
+            );
+            assert_eq!(
+                new_raffle.raffle_ticket_price,
+                AssetInfo::coin(2 * TICKET_PRICE, NATIVE_DENOM)
+            );
+
+            // The ticket sales were still distributed
+            assert_eq!(
+                native_balance(&app, contracts.raffle.as_str()),
+                Uint128::zero()
+            );
+
+            // The relisted prize can be won again
+            buy_tickets(&mut app, &contracts.raffle, "buyer", new_raffle_id, 1, 2 * TICKET_PRICE)
+                .unwrap();
+            advance_time(&mut app, 1);
+            draw_raffle(&mut app, &contracts, new_raffle_id, [2u8; 32]);
+            app.execute_contract(
+                Addr::unchecked("buyer"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft {
+                    raffle_id: new_raffle_id,
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, &token_id), "buyer".to_string());
+        }
+
+        #[test]
+        fn prize_split_in_bundles() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_ids: Vec<String> = (0..3)
+                .map(|_| {
+                    mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle)
+                })
+                .collect();
+            let assets: Vec<AssetInfo> = token_ids
+                .iter()
+                .map(|token_id| AssetInfo::sg721(collection.as_str(), token_id))
+                .collect();
+            let raffle_options = |prize_bundles: Vec<Vec<usize>>| RaffleOptionsMsg {
+                raffle_duration: Some(100),
+                prize_bundles: Some(prize_bundles),
+                ..default_raffle_options()
+            };
+
+            // Each asset has to be in exactly one bundle
+            for invalid_bundles in [
+                vec![vec![0, 1]],
+                vec![vec![0, 1], vec![1, 2]],
+                vec![vec![0, 1, 2], vec![]],
+            ] {
+                let err = create_raffle(
+                    &mut app,
+                    &contracts.raffle,
+                    CREATOR,
+                    assets.clone(),
+                    TICKET_PRICE,
+                    raffle_options(invalid_bundles),
+                )
+                .unwrap_err();
+                assert_eq!(
+                    err.downcast::<ContractError>().unwrap(),
+                    ContractError::InvalidPrizeBundles {}
+                );
+            }
+
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                assets,
+                TICKET_PRICE,
+                raffle_options(vec![vec![0, 1], vec![2]]),
+            )
+            .unwrap();
+            for buyer in ["alice", "bob", "carol"] {
+                buy_tickets(&mut app, &contracts.raffle, buyer, raffle_id, 2, TICKET_PRICE)
+                    .unwrap();
+            }
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_id, [4u8; 32]);
+            app.execute_contract(
+                Addr::unchecked("anyone"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft { raffle_id },
+                &[],
+            )
+            .unwrap();
+
+            let raffle: RaffleResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::RaffleInfo { raffle_id })
+                .unwrap();
+            let raffle_info = raffle.raffle_info.unwrap();
+            assert_eq!(raffle_info.bundle_winners.len(), 2);
+            assert_eq!(raffle_info.winner.as_ref(), Some(&raffle_info.bundle_winners[0]));
+            // The first winner gets the first two assets, the second winner gets the last one
+            for (token_id, bundle) in token_ids.iter().zip([0, 0, 1]) {
+                assert_eq!(
+                    nft_owner(&app, &collection, token_id),
+                    raffle_info.bundle_winners[bundle].to_string()
+                );
+            }
+        }
+
+        #[test]
+        fn reentrant_claim_blocked() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let nft_code_id = app.store_code(contract_reentrant_nft());
+            let collection = app
+                .instantiate_contract(
+                    nft_code_id,
+                    Addr::unchecked(OWNER_ADDR),
+                    &Empty {},
+                    &[],
+                    "reentrant-nft",
+                    None,
+                )
+                .unwrap();
+            let execute_nft = |app: &mut StargazeApp, msg: ReentrantNftMsg| {
+                app.execute_contract(Addr::unchecked(CREATOR), collection.clone(), &msg, &[])
+                    .unwrap();
+            };
+            execute_nft(
+                &mut app,
+                ReentrantNftMsg::TransferNft {
+                    recipient: CREATOR.to_string(),
+                    token_id: "1".to_string(),
+                },
+            );
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::cw721(collection.as_str(), "1")],
+                TICKET_PRICE,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, TICKET_PRICE)
+                .unwrap();
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_id, [1u8; 32]);
+
+            // The collection claims the raffle again when the prize is transferred out
+            execute_nft(
+                &mut app,
+                ReentrantNftMsg::ReenterClaim {
+                    raffle_id: Some(raffle_id),
+                },
+            );
+            let err = app
+                .execute_contract(
+                    Addr::unchecked("buyer"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::ClaimNft { raffle_id },
+                    &[],
+                )
+                .unwrap_err();
+            assert!(err
+                .chain()
+                .any(|cause| cause.downcast_ref::<ContractError>()
+                    == Some(&ContractError::ClaimInProgress {})));
+
+            execute_nft(&mut app, ReentrantNftMsg::ReenterClaim { raffle_id: None });
+            app.execute_contract(
+                Addr::unchecked("buyer"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft { raffle_id },
+                &[],
+            )
+            .unwrap();
+            let owner: cw721::OwnerOfResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    collection,
+                    &cw721::Cw721QueryMsg::OwnerOf {
+                        token_id: "1".to_string(),
+                        include_expired: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(owner.owner, "buyer");
+        }
+
+        #[test]
+        fn missing_prize_reported() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let nft_code_id = app.store_code(contract_reentrant_nft());
+            let collection = app
+                .instantiate_contract(
+                    nft_code_id,
+                    Addr::unchecked(OWNER_ADDR),
+                    &Empty {},
+                    &[],
+                    "mock-nft",
+                    None,
+                )
+                .unwrap();
+            let transfer = |app: &mut StargazeApp, recipient: &str| {
+                app.execute_contract(
+                    Addr::unchecked(recipient),
+                    collection.clone(),
+                    &ReentrantNftMsg::TransferNft {
+                        recipient: recipient.to_string(),
+                        token_id: "1".to_string(),
+                    },
+                    &[],
+                )
+                .unwrap();
+            };
+            transfer(&mut app, CREATOR);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::cw721(collection.as_str(), "1")],
+                TICKET_PRICE,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, TICKET_PRICE)
+                .unwrap();
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_id, [1u8; 32]);
+
+            // The mock collection lets anyone take the prize out of the raffle contract
+            transfer(&mut app, "thief");
+            let err = app
+                .execute_contract(
+                    Addr::unchecked("buyer"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::ClaimNft { raffle_id },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::PrizeNoLongerHeld { index: 0 }
+            );
+        }
+
+        #[test]
+        fn fees_settle_at_creation_rate() {
+            let (mut app, contracts) = setup_raffle_contracts_with(|msg| {
+                msg.raffle_fee = Some(Decimal::percent(10));
+            });
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 10, TICKET_PRICE)
+                .unwrap();
+
+            // The fee is raised after the raffle was created
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::UpdateConfig {
+                    name: None,
+                    owner: None,
+                    fee_addr: Some("new_fee_addr".to_string()),
+                    minimum_raffle_duration: None,
+                    minimum_raffle_timeout: None,
+                    creation_fee_denom: None,
+                    creation_fee_amount: None,
+                    raffle_fee: Some(Decimal::percent(50)),
+                    rand_fee: None,
+                    nois_proxy_addr: None,
+                    nois_proxy_denom: None,
+                    nois_proxy_amount: None,
+                    cancel_lockout_seconds: None,
+                    max_nois_proxy_amount: None,
+                    clear_max_nois_proxy_amount: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_id, [1u8; 32]);
+            let creator_balance = native_balance(&app, CREATOR);
+            app.execute_contract(
+                Addr::unchecked("buyer"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft { raffle_id },
+                &[],
+            )
+            .unwrap();
+
+            // The raffle settles with the fee parameters it was created with
+            assert_eq!(native_balance(&app, FEE_ADDR), Uint128::new(100));
+            assert_eq!(native_balance(&app, "new_fee_addr"), Uint128::zero());
+            assert_eq!(
+                native_balance(&app, CREATOR) - creator_balance,
+                Uint128::new(900)
+            );
+        }
+
+        #[test]
+        fn proceeds_split_three_ways() {
+            let (mut app, contracts) = setup_raffle_contracts_with(|msg| {
+                msg.raffle_fee = Some(Decimal::percent(10));
+                msg.rand_fee = Some(Decimal::percent(5));
+            });
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 10, TICKET_PRICE)
+                .unwrap();
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_id, [1u8; 32]);
+            let creator_balance = native_balance(&app, CREATOR);
+            app.execute_contract(
+                Addr::unchecked("buyer"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft { raffle_id },
+                &[],
+            )
+            .unwrap();
+
+            let rand_amount = native_balance(&app, ATLAS_DAO_STARGAZE_TREASURY);
+            let treasury_amount = native_balance(&app, FEE_ADDR);
+            let owner_amount = native_balance(&app, CREATOR) - creator_balance;
+            assert_eq!(rand_amount, Uint128::new(50));
+            assert_eq!(treasury_amount, Uint128::new(100));
+            assert_eq!(owner_amount, Uint128::new(850));
+            assert_eq!(
+                rand_amount + treasury_amount + owner_amount,
+                Uint128::new(10 * TICKET_PRICE)
+            );
+        }
+
+        #[test]
+        fn losers_share_consolation() {
+            let (mut app, contracts) = setup_raffle_contracts_with(|msg| {
+                msg.raffle_fee = Some(Decimal::percent(10));
+            });
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::SetConsolationBps {
+                    consolation_bps: Some(2_000),
+                },
+                &[],
+            )
+            .unwrap();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            let buyers = ["buyer0", "buyer1", "buyer2"];
+            for buyer in buyers {
+                buy_tickets(&mut app, &contracts.raffle, buyer, raffle_id, 10, TICKET_PRICE)
+                    .unwrap();
+            }
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_id, [1u8; 32]);
+            app.execute_contract(
+                Addr::unchecked(CREATOR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft { raffle_id },
+                &[],
+            )
+            .unwrap();
+
+            let raffle: RaffleResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::RaffleInfo { raffle_id })
+                .unwrap();
+            let winner = raffle.raffle_info.unwrap().winner.unwrap();
+            // 20% of the 300 treasury cut is kept for the two losers, they withdraw it themselves
+            assert_eq!(native_balance(&app, FEE_ADDR), Uint128::new(240));
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::SweepDust {
+                    denom: NATIVE_DENOM.to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+            let claim_consolation = |app: &mut StargazeApp, buyer: &str| {
+                app.execute_contract(
+                    Addr::unchecked(buyer),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::ClaimConsolation { raffle_id },
+                    &[],
+                )
+            };
+            for buyer in buyers {
+                assert_eq!(native_balance(&app, buyer), Uint128::zero());
+                if winner == buyer {
+                    let err = claim_consolation(&mut app, buyer).unwrap_err();
+                    assert_eq!(
+                        err.downcast::<ContractError>().unwrap(),
+                        ContractError::NoConsolation {}
+                    );
+                    continue;
+                }
+                claim_consolation(&mut app, buyer).unwrap();
+                assert_eq!(native_balance(&app, buyer), Uint128::new(30));
+                let err = claim_consolation(&mut app, buyer).unwrap_err();
+                assert_eq!(
+                    err.downcast::<ContractError>().unwrap(),
+                    ContractError::NoConsolation {}
+                );
+            }
+        }
+
+        #[test]
+        fn raffle_split_conserves_total() {
+            let totals = [0u128, 1, 2, 3, 7, 99, 100, 101, 999_999, u64::MAX as u128];
+            let fees = [
+                Decimal::zero(),
+                Decimal::percent(1),
+                Decimal::percent(10),
+                Decimal::percent(33),
+                Decimal::percent(50),
+                Decimal::percent(99),
+                Decimal::from_ratio(1u128, 3u128),
+                Decimal::from_ratio(2u128, 7u128),
+                Decimal::one(),
+                Decimal::percent(150),
+            ];
+            for total in totals {
+                for fee in fees {
+                    let total = Uint128::new(total);
+                    let (owner_amount, fee_amount) = compute_raffle_split(total, fee);
+                    assert_eq!(owner_amount + fee_amount, total, "total {total}, fee {fee}");
+                    assert!(fee_amount <= total * fee, "total {total}, fee {fee}");
+                }
+            }
+        }
+
+        #[test]
+        fn zero_fee_raffle_pays_owner_in_full() {
+            let (mut app, contracts) = setup_raffle_contracts_with(|msg| {
+                msg.raffle_fee = Some(Decimal::zero());
+                msg.rand_fee = Some(Decimal::zero());
+            });
+            // Large enough for any leaked cut to be visible
+            let price = 1_000_000;
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                price,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 10, price).unwrap();
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_id, [1u8; 32]);
+            let creator_balance = native_balance(&app, CREATOR);
+            let response = app
+                .execute_contract(
+                    Addr::unchecked("buyer"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::ClaimNft { raffle_id },
+                    &[],
+                )
+                .unwrap();
+
+            assert_eq!(
+                native_balance(&app, CREATOR) - creator_balance,
+                Uint128::new(10 * price)
+            );
+            assert_eq!(native_balance(&app, FEE_ADDR), Uint128::zero());
+            assert_eq!(
+                native_balance(&app, ATLAS_DAO_STARGAZE_TREASURY),
+                Uint128::zero()
+            );
+            // No empty transfer is sent to the fee address or the treasury
+            let recipients: Vec<&str> = response
+                .events
+                .iter()
+                .filter(|event| event.ty == "transfer")
+                .flat_map(|event| &event.attributes)
+                .filter(|attribute| attribute.key == "recipient")
+                .map(|attribute| attribute.value.as_str())
+                .collect();
+            assert_eq!(recipients, vec![CREATOR]);
+        }
+
+        #[test]
+        fn total_fee_has_to_be_lower_than_one() {
+            let (mut app, contracts) = setup_raffle_contracts_with(|msg| {
+                msg.raffle_fee = Some(Decimal::percent(60));
+            });
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::UpdateConfig {
+                    name: None,
+                    owner: None,
+                    fee_addr: None,
+                    minimum_raffle_duration: None,
+                    minimum_raffle_timeout: None,
+                    creation_fee_denom: None,
+                    creation_fee_amount: None,
+                    raffle_fee: None,
+                    rand_fee: Some(Decimal::percent(40)),
+                    nois_proxy_addr: None,
+                    nois_proxy_denom: None,
+                    nois_proxy_amount: None,
+                    cancel_lockout_seconds: None,
+                    max_nois_proxy_amount: None,
+                    clear_max_nois_proxy_amount: None,
+                },
+                &[],
+            )
+            .unwrap_err();
+        }
+
+        #[test]
+        fn rand_fee_can_be_waived() {
+            let (mut app, contracts) = setup_raffle_contracts_with(|msg| {
+                msg.rand_fee = Some(Decimal::zero());
+            });
+            let config: ConfigResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::Config {})
+                .unwrap();
+            assert_eq!(config.rand_fee, Decimal::zero());
+
+            // A full cut is rejected instead of being clamped
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(OWNER_ADDR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::UpdateConfig {
+                        name: None,
+                        owner: None,
+                        fee_addr: None,
+                        minimum_raffle_duration: None,
+                        minimum_raffle_timeout: None,
+                        creation_fee_denom: None,
+                        creation_fee_amount: None,
+                        raffle_fee: None,
+                        rand_fee: Some(Decimal::one()),
+                        nois_proxy_addr: None,
+                        nois_proxy_denom: None,
+                        nois_proxy_amount: None,
+                        cancel_lockout_seconds: None,
+                        max_nois_proxy_amount: None,
+                        clear_max_nois_proxy_amount: None,
+                    },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::InvalidRandFee {}
+            );
+        }
+
+        #[test]
+        fn fee_addr_change_logged() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let update_fee_addr = |app: &mut StargazeApp, fee_addr: &str| {
+                app.execute_contract(
+                    Addr::unchecked(OWNER_ADDR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::UpdateConfig {
+                        name: None,
+                        owner: None,
+                        fee_addr: Some(fee_addr.to_string()),
+                        minimum_raffle_duration: None,
+                        minimum_raffle_timeout: None,
+                        creation_fee_denom: None,
+                        creation_fee_amount: None,
+                        raffle_fee: None,
+                        rand_fee: None,
+                        nois_proxy_addr: None,
+                        nois_proxy_denom: None,
+                        nois_proxy_amount: None,
+                        cancel_lockout_seconds: None,
+                        max_nois_proxy_amount: None,
+                        clear_max_nois_proxy_amount: None,
+                    },
+                    &[],
+                )
+                .unwrap()
+            };
+
+            let response = update_fee_addr(&mut app, "new_fee_addr");
+            assert_eq!(find_attribute(&response, "old_fee_addr").unwrap(), FEE_ADDR);
+            assert_eq!(
+                find_attribute(&response, "new_fee_addr").unwrap(),
+                "new_fee_addr"
+            );
+            assert_eq!(find_attribute(&response, "old_owner"), None);
+
+            // Nothing is logged when the address stays the same
+            let response = update_fee_addr(&mut app, "new_fee_addr");
+            assert_eq!(find_attribute(&response, "old_fee_addr"), None);
+        }
+
+        #[test]
+        fn dust_swept_to_treasury() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let mut raffle_ids = vec![];
+            for duration in [100, 1_000] {
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+                let raffle_id = create_raffle(
+                    &mut app,
+                    &contracts.raffle,
+                    CREATOR,
+                    vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    TICKET_PRICE,
+                    RaffleOptionsMsg {
+                        raffle_duration: Some(duration),
+                        ..default_raffle_options()
+                    },
+                )
+                .unwrap();
+                buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 2, TICKET_PRICE)
+                    .unwrap();
+                raffle_ids.push(raffle_id);
+            }
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_ids[0], [1u8; 32]);
+            app.execute_contract(
+                Addr::unchecked("buyer"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft {
+                    raffle_id: raffle_ids[0],
+                },
+                &[],
+            )
+            .unwrap();
+            // The nois fee provision of the live raffle and leftovers that no raffle accounts for
+            mint_native(&mut app, contracts.raffle.as_str(), NOIS_AMOUNT + 3);
+
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(CREATOR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::SweepDust {
+                        denom: NATIVE_DENOM.to_string(),
+                    },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::Unauthorized);
+
+            let treasury_before = native_balance(&app, FEE_ADDR);
+            let response = app
+                .execute_contract(
+                    Addr::unchecked(OWNER_ADDR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::SweepDust {
+                        denom: NATIVE_DENOM.to_string(),
+                    },
+                    &[],
+                )
+                .unwrap();
+            assert_eq!(find_attribute(&response, "amount"), Some("3".to_string()));
+            assert_eq!(native_balance(&app, FEE_ADDR), treasury_before + Uint128::new(3));
+            // The ticket sales and the nois fee of the live raffle stay in escrow
+            assert_eq!(
+                native_balance(&app, contracts.raffle.as_str()),
+                Uint128::new(2 * TICKET_PRICE + NOIS_AMOUNT)
+            );
+        }
+    }
+
+    mod cancel {
+        use cosmwasm_std::{Addr, Uint128};
+        use cw_multi_test::Executor;
+        use raffles::{error::ContractError, msg::ExecuteMsg, state::RaffleOptionsMsg};
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, buy_tickets, create_collection, create_raffle, default_raffle_options,
+            draw_raffle, mint_and_approve, native_balance, nft_owner, setup_raffle_contracts,
+            setup_raffle_contracts_with, OWNER_ADDR,
+        };
+
+        const CREATOR: &str = "creator";
+
+        #[test]
+        fn cancel_lockout_before_sale_end() {
+            let (mut app, contracts) = setup_raffle_contracts_with(|msg| {
+                msg.cancel_lockout_seconds = Some(50);
+            });
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let mut raffles = vec![];
+            for _ in 0..2 {
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+                let raffle_id = create_raffle(
+                    &mut app,
+                    &contracts.raffle,
+                    CREATOR,
+                    vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    100,
+                    RaffleOptionsMsg {
+                        raffle_duration: Some(100),
+                        ..default_raffle_options()
+                    },
+                )
+                .unwrap();
+                raffles.push((raffle_id, token_id));
+            }
+            let cancel = |app: &mut StargazeApp, raffle_id: u64| {
+                app.execute_contract(
+                    Addr::unchecked(CREATOR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::CancelRaffle { raffle_id },
+                    &[],
+                )
+            };
+
+            // Outside of the lockout, the raffle can be cancelled
+            advance_time(&mut app, 40);
+            cancel(&mut app, raffles[0].0).unwrap();
+            assert_eq!(nft_owner(&app, &collection, &raffles[0].1), CREATOR);
+
+            // 50 seconds before the end of the sale, it can't anymore
+            advance_time(&mut app, 10);
+            let err = cancel(&mut app, raffles[1].0).unwrap_err();
+            assert!(matches!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::CancelLockout { .. }
+            ));
+        }
+
+        #[test]
+        fn full_pause_keeps_exits_open() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let now = app.block_info().time;
+            let mut raffles = vec![];
+            for duration in [1_000, 10, 1_000] {
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+                let raffle_id = create_raffle(
+                    &mut app,
+                    &contracts.raffle,
+                    CREATOR,
+                    vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    100,
+                    RaffleOptionsMsg {
+                        raffle_duration: Some(duration),
+                        refundable_until: Some(now.plus_seconds(500)),
+                        ..default_raffle_options()
+                    },
+                )
+                .unwrap();
+                raffles.push((raffle_id, token_id));
+            }
+            buy_tickets(&mut app, &contracts.raffle, "alice", raffles[0].0, 1, 100).unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "bob", raffles[1].0, 1, 100).unwrap();
+            advance_time(&mut app, 10);
+            draw_raffle(&mut app, &contracts, raffles[1].0, [1u8; 32]);
+
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ToggleLock {
+                    lock: false,
+                    full_pause: Some(true),
+                },
+                &[],
+            )
+            .unwrap();
+
+            // New raffles, ticket purchases and randomness requests are blocked
+            let err = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &raffles[2].1)],
+                100,
+                default_raffle_options(),
+            )
+            .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::ContractIsPaused {}
+            );
+            let err =
+                buy_tickets(&mut app, &contracts.raffle, "alice", raffles[0].0, 1, 100).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::ContractIsPaused {}
+            );
+            let err = app
+                .execute_contract(
+                    Addr::unchecked("alice"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::UpdateRandomness {
+                        raffle_id: raffles[0].0,
+                    },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::ContractIsPaused {}
+            );
+
+            // Refunds, claims and cancellations still go through
+            let balance_before = native_balance(&app, "alice");
+            app.execute_contract(
+                Addr::unchecked("alice"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::RefundTicket {
+                    raffle_id: raffles[0].0,
+                    ticket_number: 1,
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(
+                native_balance(&app, "alice"),
+                balance_before + Uint128::new(100)
+            );
+
+            app.execute_contract(
+                Addr::unchecked("bob"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft {
+                    raffle_id: raffles[1].0,
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, &raffles[1].1), "bob");
+
+            app.execute_contract(
+                Addr::unchecked(CREATOR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::CancelRaffle {
+                    raffle_id: raffles[2].0,
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, &raffles[2].1), CREATOR);
+        }
+    }
+
+    mod randomness {
+        use cosmwasm_std::{coin, to_json_vec, Addr, Coin, Empty, HexBinary, Uint128};
+        use cw_multi_test::{BankSudo, Executor, SudoMsg};
+        use raffles::{
+            error::ContractError,
+            msg::{
+                ConfigResponse, DrandRandomness, ExecuteMsg, QueryMsg, RaffleResponse,
+                RandomnessModeMsg,
+            },
+            state::{
+                drand_round_after, NoisFeeSource, RaffleOptionsMsg, MINIMUM_RAFFLE_TIMEOUT,
+                RAFFLE_INFO,
+            },
+        };
+        use sg_multi_test::StargazeApp;
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::contract_boxes::{contract_drand_verifier, contract_nois_proxy};
+        use crate::common_setup::helpers::{
+            advance_time, buy_tickets, create_collection, create_raffle, default_raffle_options,
+            draw_raffle, find_attribute, mint_and_approve, mint_native, native_balance, nft_owner,
+            send_nois_randomness, setup_raffle_contracts, write_contract_storage, NOIS_AMOUNT,
+            OWNER_ADDR,
+        };
+
+        #[test]
+        fn job_id_namespaced_by_contract() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, "creator", &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                "creator",
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            advance_time(&mut app, 101);
+
+            mint_native(&mut app, contracts.raffle.as_str(), NOIS_AMOUNT);
+            let response = app
+                .execute_contract(
+                    Addr::unchecked("anyone"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::UpdateRandomness { raffle_id },
+                    &[],
+                )
+                .unwrap();
+
+            let config: ConfigResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::Config {})
+                .unwrap();
+            let address = contracts.raffle.as_str();
+            assert!(config
+                .job_id_prefix
+                .contains(&address[address.len().saturating_sub(8)..]));
+            assert_eq!(
+                find_attribute(&response, "job_id"),
+                Some(format!("{}{raffle_id}", config.job_id_prefix))
+            );
+        }
+
+        #[test]
+        fn caller_funded_nois_fee() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, "creator", &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                "creator",
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 2, 100).unwrap();
+            advance_time(&mut app, 101);
+
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::SetNoisFeeSource {
+                    source: NoisFeeSource::Caller,
+                },
+                &[],
+            )
+            .unwrap();
+
+            // The contract holds enough to pay, but the caller has to
+            mint_native(&mut app, contracts.raffle.as_str(), NOIS_AMOUNT);
+            let contract_balance = native_balance(&app, contracts.raffle.as_str());
+            let err = app
+                .execute_contract(
+                    Addr::unchecked("requester"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::UpdateRandomness { raffle_id },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::NoisFeeRequired {
+                    fee: coin(NOIS_AMOUNT, NATIVE_DENOM)
+                }
+            );
+
+            mint_native(&mut app, "requester", NOIS_AMOUNT);
+            app.execute_contract(
+                Addr::unchecked("requester"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::UpdateRandomness { raffle_id },
+                &[coin(NOIS_AMOUNT, NATIVE_DENOM)],
+            )
+            .unwrap();
+            assert_eq!(native_balance(&app, "requester"), Uint128::zero());
+            assert_eq!(native_balance(&app, contracts.raffle.as_str()), contract_balance);
+            assert_eq!(
+                native_balance(&app, contracts.nois_proxy.as_str()),
+                Uint128::new(NOIS_AMOUNT)
+            );
+        }
+
+        #[test]
+        fn pending_request_reassigned_to_new_proxy() {
+            let (mut app, mut contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, "creator", &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                "creator",
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, 100).unwrap();
+            advance_time(&mut app, 101);
+            mint_native(&mut app, contracts.raffle.as_str(), NOIS_AMOUNT);
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::UpdateRandomness { raffle_id },
+                &[],
+            )
+            .unwrap();
+
+            // The old proxy never answers, a new one is configured
+            let nois_id = app.store_code(contract_nois_proxy());
+            let new_proxy = app
+                .instantiate_contract(
+                    nois_id,
+                    Addr::unchecked(OWNER_ADDR),
+                    &Empty {},
+                    &[],
+                    "new-nois-proxy",
+                    None,
+                )
+                .unwrap();
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::UpdateConfig {
+                    name: None,
+                    owner: None,
+                    fee_addr: None,
+                    minimum_raffle_duration: None,
+                    minimum_raffle_timeout: None,
+                    creation_fee_denom: None,
+                    creation_fee_amount: None,
+                    raffle_fee: None,
+                    rand_fee: None,
+                    nois_proxy_addr: Some(new_proxy.to_string()),
+                    nois_proxy_denom: None,
+                    nois_proxy_amount: None,
+                    cancel_lockout_seconds: None,
+                    max_nois_proxy_amount: None,
+                    clear_max_nois_proxy_amount: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            let reassign = |app: &mut StargazeApp, sender: &str| {
+                app.execute_contract(
+                    Addr::unchecked(sender),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::ReassignRandomness { raffle_id },
+                    &[],
+                )
+            };
+            let err = reassign(&mut app, "creator").unwrap_err();
+            assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::Unauthorized);
+            reassign(&mut app, OWNER_ADDR).unwrap();
+
+            contracts.nois_proxy = new_proxy;
+            draw_raffle(&mut app, &contracts, raffle_id, [3u8; 32]);
+            let err = reassign(&mut app, OWNER_ADDR).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::ImmutableRandomness
+            );
+
+            app.execute_contract(
+                Addr::unchecked("anyone"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft { raffle_id },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, &token_id), "buyer");
+        }
+
+        #[test]
+        fn closed_sale_auto_requests_randomness() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, "creator", &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                "creator",
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    auto_request_randomness: Some(true),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, 100).unwrap();
+            advance_time(&mut app, 101);
+            mint_native(&mut app, contracts.raffle.as_str(), NOIS_AMOUNT);
+            let proxy_balance =
+                |app: &StargazeApp| native_balance(app, contracts.nois_proxy.as_str());
+
+            // The late purchase issues the request and gets its funds back
+            let response =
+                buy_tickets(&mut app, &contracts.raffle, "late", raffle_id, 1, 100).unwrap();
+            assert_eq!(
+                find_attribute(&response, "action").unwrap(),
+                "auto_request_randomness"
+            );
+            assert_eq!(native_balance(&app, "late"), Uint128::new(100));
+            assert_eq!(proxy_balance(&app), Uint128::new(NOIS_AMOUNT));
+            let raffle: RaffleResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::RaffleInfo { raffle_id },
+                )
+                .unwrap();
+            let randomness = raffle.raffle_info.unwrap().randomness.unwrap();
+            assert!(randomness.requested);
+            assert_eq!(randomness.nois_fee, Some(coin(NOIS_AMOUNT, NATIVE_DENOM)));
+
+            // The request isn't issued twice
+            mint_native(&mut app, contracts.raffle.as_str(), NOIS_AMOUNT);
+            let err = app
+                .execute_contract(
+                    Addr::unchecked("anyone"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::UpdateRandomness { raffle_id },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::RandomnessAlreadyRequested {}
+            );
+            buy_tickets(&mut app, &contracts.raffle, "late", raffle_id, 1, 100).unwrap_err();
+            assert_eq!(proxy_balance(&app), Uint128::new(NOIS_AMOUNT));
+
+            send_nois_randomness(&mut app, &contracts, raffle_id, [7u8; 32]).unwrap();
+            app.execute_contract(
+                Addr::unchecked("anyone"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft { raffle_id },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, &token_id), "buyer");
+        }
+
+        #[test]
+        fn paused_contract_doesnt_auto_request_randomness() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, "creator", &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                "creator",
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    auto_request_randomness: Some(true),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, 100).unwrap();
+            advance_time(&mut app, 101);
+            mint_native(&mut app, contracts.raffle.as_str(), NOIS_AMOUNT);
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ToggleLock {
+                    lock: false,
+                    full_pause: Some(true),
+                },
+                &[],
+            )
+            .unwrap();
+
+            let err =
+                buy_tickets(&mut app, &contracts.raffle, "late", raffle_id, 1, 100).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::ContractIsPaused {}
+            );
+            assert_eq!(
+                native_balance(&app, contracts.nois_proxy.as_str()),
+                Uint128::zero()
+            );
+            let raffle: RaffleResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::RaffleInfo { raffle_id },
+                )
+                .unwrap();
+            assert_eq!(raffle.raffle_info.unwrap().randomness, None);
+        }
+
+        #[test]
+        fn corrupted_ticket_count_recounted() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, "creator", &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                "creator",
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 3, 100).unwrap();
+            let raffle_info = |app: &StargazeApp| {
+                app.wrap()
+                    .query_wasm_smart::<RaffleResponse>(
+                        contracts.raffle.clone(),
+                        &QueryMsg::RaffleInfo { raffle_id },
+                    )
+                    .unwrap()
+                    .raffle_info
+                    .unwrap()
+            };
+
+            // The count says 5 tickets while only 3 were recorded, tickets 3 and 4 can't be drawn
+            let mut corrupted = raffle_info(&app);
+            corrupted.number_of_tickets = 5;
+            write_contract_storage(
+                &mut app,
+                &contracts.raffle,
+                &RAFFLE_INFO.key(raffle_id),
+                &to_json_vec(&corrupted).unwrap(),
+            );
+            assert_eq!(raffle_info(&app).number_of_tickets, 5);
+
+            let recount = |app: &mut StargazeApp, sender: &str| {
+                app.execute_contract(
+                    Addr::unchecked(sender),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::RecountTickets { raffle_id },
+                    &[],
+                )
+            };
+            let err = recount(&mut app, "buyer").unwrap_err();
+            assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::Unauthorized);
+            let response = recount(&mut app, OWNER_ADDR).unwrap();
+            assert_eq!(find_attribute(&response, "previous_count"), Some("5".to_string()));
+            assert_eq!(raffle_info(&app).number_of_tickets, 3);
+
+            // The draw can't be changed once the beacon is in
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_id, [3u8; 32]);
+            let err = recount(&mut app, OWNER_ADDR).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::ImmutableRandomness
+            );
+        }
+
+        #[test]
+        fn pending_request_sent_again_after_denom_change() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, "creator", &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                "creator",
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            advance_time(&mut app, 101);
+            mint_native(&mut app, contracts.raffle.as_str(), NOIS_AMOUNT);
+            app.execute_contract(
+                Addr::unchecked("anyone"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::UpdateRandomness { raffle_id },
+                &[],
+            )
+            .unwrap();
+
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::UpdateConfig {
+                    name: None,
+                    owner: None,
+                    fee_addr: None,
+                    minimum_raffle_duration: None,
+                    minimum_raffle_timeout: None,
+                    creation_fee_denom: None,
+                    creation_fee_amount: None,
+                    raffle_fee: None,
+                    rand_fee: None,
+                    nois_proxy_addr: None,
+                    nois_proxy_denom: Some("unois".to_string()),
+                    nois_proxy_amount: Some(Uint128::new(50)),
+                    cancel_lockout_seconds: None,
+                    max_nois_proxy_amount: None,
+                    clear_max_nois_proxy_amount: None,
+                },
+                &[],
+            )
+            .unwrap();
+            let realign = |app: &mut StargazeApp, sender: &str, funds: &[Coin]| {
+                app.execute_contract(
+                    Addr::unchecked(sender),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::RealignNoisFees {},
+                    funds,
+                )
+            };
+            let err = realign(&mut app, "anyone", &[]).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::Unauthorized
+            );
+            // The owner pays the new fee of the request sent again
+            let err = realign(&mut app, OWNER_ADDR, &[]).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::NoisFeeRequired {
+                    fee: coin(50, "unois")
+                }
+            );
+            app.sudo(SudoMsg::Bank(BankSudo::Mint {
+                to_address: OWNER_ADDR.to_string(),
+                amount: vec![coin(50, "unois")],
+            }))
+            .unwrap();
+            let response = realign(&mut app, OWNER_ADDR, &[coin(50, "unois")]).unwrap();
+            assert_eq!(
+                find_attribute(&response, "raffle_ids"),
+                Some(raffle_id.to_string())
+            );
+
+            // The request reached the proxy again, with the new fee
+            let config: ConfigResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::Config {})
+                .unwrap();
+            assert_eq!(
+                find_attribute(&response, "job_id"),
+                Some(format!("{}{raffle_id}", config.job_id_prefix))
+            );
+            assert_eq!(
+                app.wrap()
+                    .query_balance(contracts.nois_proxy.clone(), "unois")
+                    .unwrap(),
+                coin(50, "unois")
+            );
+
+            // The raffle is now up to date with the config, it isn't requested again
+            let response = realign(&mut app, OWNER_ADDR, &[]).unwrap();
+            assert_eq!(
+                find_attribute(&response, "raffle_ids"),
+                Some("none".to_string())
+            );
+        }
+
+        #[test]
+        fn beacon_finishes_raffle_whatever_the_timeout() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let mut raffles = vec![];
+            for _ in 0..2 {
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, "creator", &contracts.raffle);
+                let raffle_id = create_raffle(
+                    &mut app,
+                    &contracts.raffle,
+                    "creator",
+                    vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    100,
+                    RaffleOptionsMsg {
+                        raffle_duration: Some(100),
+                        ..default_raffle_options()
+                    },
+                )
+                .unwrap();
+                buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, 100).unwrap();
+                raffles.push((raffle_id, token_id));
+            }
+            advance_time(&mut app, 101);
+            mint_native(&mut app, contracts.raffle.as_str(), 2 * NOIS_AMOUNT);
+            for (raffle_id, _) in &raffles {
+                app.execute_contract(
+                    Addr::unchecked(OWNER_ADDR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::UpdateRandomness {
+                        raffle_id: *raffle_id,
+                    },
+                    &[],
+                )
+                .unwrap();
+            }
+
+            // A beacon arriving before the timeout can be drawn right away
+            let (early_raffle, early_token) = &raffles[0];
+            send_nois_randomness(&mut app, &contracts, *early_raffle, [1u8; 32]).unwrap();
+            app.execute_contract(
+                Addr::unchecked("buyer"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft {
+                    raffle_id: *early_raffle,
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, early_token), "buyer");
+
+            // A beacon delayed past the timeout still makes the raffle claimable
+            advance_time(&mut app, MINIMUM_RAFFLE_TIMEOUT + 1);
+            let (late_raffle, late_token) = &raffles[1];
+            send_nois_randomness(&mut app, &contracts, *late_raffle, [2u8; 32]).unwrap();
+            app.execute_contract(
+                Addr::unchecked("buyer"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft {
+                    raffle_id: *late_raffle,
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, late_token), "buyer");
+        }
+
+        #[test]
+        fn drand_mode_replaces_nois() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let verifier_id = app.store_code(contract_drand_verifier());
+            let verifier = app
+                .instantiate_contract(
+                    verifier_id,
+                    Addr::unchecked(OWNER_ADDR),
+                    &Empty {},
+                    &[],
+                    "drand verifier",
+                    None,
+                )
+                .unwrap();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, "creator", &contracts.raffle);
+            let sale_end = app.block_info().time.plus_seconds(100);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                "creator",
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, 100).unwrap();
+            advance_time(&mut app, 101);
+
+            let expected_round = drand_round_after(sale_end);
+            let submit = |app: &mut StargazeApp, round: u64, signature: &[u8]| {
+                app.execute_contract(
+                    Addr::unchecked("anyone"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::SubmitDrandRandomness {
+                        raffle_id,
+                        randomness: DrandRandomness {
+                            round,
+                            previous_signature: HexBinary::from(vec![0u8; 96]),
+                            signature: HexBinary::from(signature),
+                        },
+                    },
+                    &[],
+                )
+            };
+            let set_mode = |app: &mut StargazeApp, sender: &str| {
+                app.execute_contract(
+                    Addr::unchecked(sender),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::SetRandomnessMode {
+                        mode: RandomnessModeMsg::DrandVerifier {
+                            contract: verifier.to_string(),
+                            pubkey: HexBinary::from(vec![1u8; 48]),
+                        },
+                    },
+                    &[],
+                )
+            };
+
+            // Drand beacons are refused while the contract uses nois
+            let err = submit(&mut app, expected_round, &[3u8; 96]).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::WrongRandomnessMode {}
+            );
+
+            let err = set_mode(&mut app, "anyone").unwrap_err();
+            assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::Unauthorized);
+            set_mode(&mut app, OWNER_ADDR).unwrap();
+
+            // Nois requests are refused in drand mode
+            let err = app
+                .execute_contract(
+                    Addr::unchecked("anyone"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::UpdateRandomness { raffle_id },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::WrongRandomnessMode {}
+            );
+
+            // Only the first round after the sale end is accepted
+            let err = submit(&mut app, expected_round + 1, &[3u8; 96]).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::WrongDrandRound {
+                    expected: expected_round
+                }
+            );
+            // The beacon has to be validated by the verifier
+            let err = submit(&mut app, expected_round, &[]).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::InvalidRandomness
+            );
+
+            submit(&mut app, expected_round, &[3u8; 96]).unwrap();
+            app.execute_contract(
+                Addr::unchecked("buyer"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft { raffle_id },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(nft_owner(&app, &collection, &token_id), "buyer");
+        }
+    }
+
+    mod buy {
+        use cosmwasm_std::{coin, to_json_vec, Addr, Coin, HexBinary, Uint128};
+        use cw_multi_test::{BankSudo, Executor, SudoMsg};
+        use raffles::{
+            error::ContractError,
+            msg::{ExecuteMsg, QueryMsg, RaffleResponse},
+            state::{RaffleOptionsMsg, RAFFLE_INFO},
+        };
+        use sg_multi_test::StargazeApp;
+        use sg_std::NATIVE_DENOM;
+        use sha2::{Digest, Sha256};
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, buy_tickets, create_collection, create_raffle, default_raffle_options,
+            mint_and_approve, mint_native, native_balance, setup_raffle_contracts,
+            write_contract_storage, OWNER_ADDR,
+        };
+
+        const CREATOR: &str = "creator";
+        const STABLE_DENOM: &str = "uusdc";
+
+        #[test]
+        fn ticket_count_overflow_rejected() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                1,
+                default_raffle_options(),
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, 1).unwrap();
+
+            let err = buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, u32::MAX, 1)
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::TicketOverflow {}
+            );
+        }
+
+        #[test]
+        fn allowlist_proof_checked() {
+            let leaf = |address: &str| -> [u8; 32] { Sha256::digest(address.as_bytes()).into() };
+            let (alice, bob) = (leaf("alice"), leaf("bob"));
+            let (first, second) = if alice <= bob { (alice, bob) } else { (bob, alice) };
+            let root: [u8; 32] = Sha256::new()
+                .chain_update(first)
+                .chain_update(second)
+                .finalize()
+                .into();
+
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                1,
+                RaffleOptionsMsg {
+                    merkle_root: Some(HexBinary::from(root)),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            let buy = |app: &mut StargazeApp, buyer: &str, proof: Option<Vec<String>>| {
+                mint_native(app, buyer, 1);
+                app.execute_contract(
+                    Addr::unchecked(buyer),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::BuyTicket {
+                        raffle_id,
+                        ticket_number: 1,
+                        sent_assets: AssetInfo::coin(1, NATIVE_DENOM),
+                        proof,
+                    },
+                    &[coin(1, NATIVE_DENOM)],
+                )
+            };
+
+            buy(&mut app, "alice", Some(vec![HexBinary::from(bob).to_hex()])).unwrap();
+            // A proof made for someone else or a missing proof are rejected
+            for (buyer, proof) in [
+                ("carol", Some(vec![HexBinary::from(bob).to_hex()])),
+                ("bob", None),
+            ] {
+                let err = buy(&mut app, buyer, proof).unwrap_err();
+                assert_eq!(
+                    err.downcast::<ContractError>().unwrap(),
+                    ContractError::InvalidAllowlistProof {}
+                );
+            }
+        }
+
+        #[test]
+        fn purchase_over_tx_cap_rejected() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                1,
+                RaffleOptionsMsg {
+                    max_tickets_per_tx: Some(5),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+
+            let err = buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 6, 1)
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::TooMuchTicketsInTx { max: 5, nb: 6 }
+            );
+
+            // The cap only applies to a single purchase
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 5, 1).unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 5, 1).unwrap();
+            let raffle: RaffleResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::RaffleInfo { raffle_id })
+                .unwrap();
+            assert_eq!(raffle.raffle_info.unwrap().number_of_tickets, 10);
+        }
+
+        #[test]
+        fn weighted_buyer_capped_on_tickets_bought() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                1,
+                RaffleOptionsMsg {
+                    max_tickets_per_tx: Some(5),
+                    max_ticket_per_address: Some(12),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::SetTicketMultiplier {
+                    address: "weighted".to_string(),
+                    multiplier: Some(2),
+                },
+                &[],
+            )
+            .unwrap();
+
+            // The per transaction cap counts the tickets bought, not the slots they give
+            let err =
+                buy_tickets(&mut app, &contracts.raffle, "weighted", raffle_id, 6, 1).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::TooMuchTicketsInTx { max: 5, nb: 6 }
+            );
+            buy_tickets(&mut app, &contracts.raffle, "weighted", raffle_id, 5, 1).unwrap();
+
+            // The per address cap still counts the slots
+            let err =
+                buy_tickets(&mut app, &contracts.raffle, "weighted", raffle_id, 2, 1).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::TooMuchTicketsForUser {
+                    max: 12,
+                    nb_before: 10,
+                    nb_after: 14,
+                }
+            );
+            buy_tickets(&mut app, &contracts.raffle, "weighted", raffle_id, 1, 1).unwrap();
+        }
+
+        #[test]
+        fn purchases_spaced_by_cooldown() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                1,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(1_000),
+                    purchase_cooldown_seconds: Some(60),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, 1).unwrap();
+            let until = app.block_info().time.plus_seconds(60);
+            advance_time(&mut app, 59);
+            let err =
+                buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, 1).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::PurchaseCooldownActive { until }
+            );
+            // The cooldown is per buyer
+            buy_tickets(&mut app, &contracts.raffle, "other_buyer", raffle_id, 1, 1).unwrap();
+
+            advance_time(&mut app, 1);
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, 1).unwrap();
+        }
+
+        #[test]
+        fn weighted_buyer_gets_extra_slots() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let now = app.block_info().time;
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    refundable_until: Some(now.plus_seconds(50)),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            let set_multiplier = |app: &mut StargazeApp, multiplier: u32| {
+                app.execute_contract(
+                    Addr::unchecked(OWNER_ADDR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::SetTicketMultiplier {
+                        address: "weighted".to_string(),
+                        multiplier: Some(multiplier),
+                    },
+                    &[],
+                )
+            };
+
+            let err = set_multiplier(&mut app, 11).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::InvalidTicketMultiplier { max: 10 }
+            );
+            set_multiplier(&mut app, 2).unwrap();
+
+            buy_tickets(&mut app, &contracts.raffle, "weighted", raffle_id, 1, 100).unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, 100).unwrap();
+            let ticket_number = |app: &StargazeApp, owner: &str| -> u32 {
+                app.wrap()
+                    .query_wasm_smart(
+                        contracts.raffle.clone(),
+                        &QueryMsg::TicketNumber {
+                            owner: owner.to_string(),
+                            raffle_id,
+                        },
+                    )
+                    .unwrap()
+            };
+            assert_eq!(ticket_number(&app, "weighted"), 2);
+            assert_eq!(ticket_number(&app, "buyer"), 1);
+            let raffle: RaffleResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::RaffleInfo { raffle_id })
+                .unwrap();
+            let raffle_info = raffle.raffle_info.unwrap();
+            assert_eq!(raffle_info.number_of_tickets, 3);
+            assert_eq!(raffle_info.tickets_paid, 2);
+
+            // A refund gives back the price of the ticket, along with its extra slot
+            app.execute_contract(
+                Addr::unchecked("weighted"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::RefundTicket {
+                    raffle_id,
+                    ticket_number: 1,
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(native_balance(&app, "weighted"), Uint128::new(100));
+            assert_eq!(native_balance(&app, contracts.raffle.as_str()), Uint128::new(100));
+            assert_eq!(ticket_number(&app, "buyer"), 1);
+        }
+
+        #[test]
+        fn tickets_bought_with_either_acceptable_denom() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    acceptable_prices: Some(vec![AssetInfo::coin(20, STABLE_DENOM)]),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            let buy = |app: &mut StargazeApp, buyer: &str, price: Coin| {
+                app.sudo(SudoMsg::Bank(BankSudo::Mint {
+                    to_address: buyer.to_string(),
+                    amount: vec![price.clone()],
+                }))
+                .unwrap();
+                app.execute_contract(
+                    Addr::unchecked(buyer),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::BuyTicket {
+                        raffle_id,
+                        ticket_number: 1,
+                        sent_assets: AssetInfo::Coin(price.clone()),
+                        proof: None,
+                    },
+                    &[price],
+                )
+            };
+
+            buy(&mut app, "native_buyer", coin(100, NATIVE_DENOM)).unwrap();
+            buy(&mut app, "stable_buyer", coin(20, STABLE_DENOM)).unwrap();
+            let err = buy(&mut app, "other_buyer", coin(20, "uother")).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::PaymentNotSufficient {
+                    assets_wanted: AssetInfo::coin(100, NATIVE_DENOM),
+                    assets_received: AssetInfo::coin(20, "uother"),
+                }
+            );
+            // A buyer sticks to the denom of their first tickets
+            let err = buy(&mut app, "stable_buyer", coin(100, NATIVE_DENOM)).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::BuyerPriceMismatch {
+                    price: coin(20, STABLE_DENOM)
+                }
+            );
+            buy(&mut app, "stable_buyer", coin(20, STABLE_DENOM)).unwrap();
+
+            // Each buyer is refunded in the denom they paid with
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::CancelAndUnwind { raffle_id },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(
+                app.wrap().query_all_balances("native_buyer").unwrap(),
+                vec![coin(100, NATIVE_DENOM)]
+            );
+            assert_eq!(
+                app.wrap().query_all_balances("stable_buyer").unwrap(),
+                vec![coin(100, NATIVE_DENOM), coin(40, STABLE_DENOM)]
+            );
+        }
+
+        #[test]
+        fn tickets_refunded_before_deadline() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let now = app.block_info().time;
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    refundable_until: Some(now.plus_seconds(50)),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "alice", raffle_id, 2, 100).unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "bob", raffle_id, 1, 100).unwrap();
+
+            // Refunding more tickets than owned is refused
+            let err = app
+                .execute_contract(
+                    Addr::unchecked("bob"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::RefundTicket {
+                        raffle_id,
+                        ticket_number: 2,
+                    },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::NotEnoughTicketsToRefund { owned: 1 }
+            );
+
+            app.execute_contract(
+                Addr::unchecked("alice"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::RefundTicket {
+                    raffle_id,
+                    ticket_number: 1,
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(native_balance(&app, "alice"), Uint128::new(100));
+
+            let raffle: RaffleResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::RaffleInfo { raffle_id })
+                .unwrap();
+            assert_eq!(raffle.raffle_info.unwrap().number_of_tickets, 2);
+            let tickets: Vec<String> = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::AllTickets {
+                        raffle_id,
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(tickets, vec!["alice".to_string(), "bob".to_string()]);
+            let owned: u32 = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::TicketNumber {
+                        owner: "alice".to_string(),
+                        raffle_id,
+                    },
+                )
+                .unwrap();
+            assert_eq!(owned, 1);
+
+            advance_time(&mut app, 50);
+            let err = app
+                .execute_contract(
+                    Addr::unchecked("bob"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::RefundTicket {
+                        raffle_id,
+                        ticket_number: 1,
+                    },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::RefundWindowClosed {}
+            );
+        }
+
+        #[test]
+        fn tickets_refunded_at_the_price_paid() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let now = app.block_info().time;
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    refundable_until: Some(now.plus_seconds(50)),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "alice", raffle_id, 2, 100).unwrap();
+
+            // The raffle price doesn't matter to the refund, only what the buyer paid does
+            let raffle: RaffleResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::RaffleInfo { raffle_id },
+                )
+                .unwrap();
+            let mut raffle_info = raffle.raffle_info.unwrap();
+            raffle_info.raffle_ticket_price = AssetInfo::coin(300, NATIVE_DENOM);
+            write_contract_storage(
+                &mut app,
+                &contracts.raffle,
+                &RAFFLE_INFO.key(raffle_id),
+                &to_json_vec(&raffle_info).unwrap(),
+            );
+
+            app.execute_contract(
+                Addr::unchecked("alice"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::RefundTicket {
+                    raffle_id,
+                    ticket_number: 2,
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(native_balance(&app, "alice"), Uint128::new(200));
+            assert_eq!(
+                native_balance(&app, contracts.raffle.as_str()),
+                Uint128::zero()
+            );
+        }
+    }
+
+    mod auto_extend {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use raffles::{
+            msg::{ExecuteMsg, QueryMsg, RaffleResponse},
+            state::{RaffleOptionsMsg, RaffleState},
+        };
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, buy_tickets, create_collection, create_raffle, default_raffle_options,
+            find_attribute, mint_and_approve, mint_native, setup_raffle_contracts, NOIS_AMOUNT,
+        };
+
+        const CREATOR: &str = "creator";
+        const TICKET_PRICE: u128 = 100;
+
+        #[test]
+        fn low_sales_raffle_extends_once() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    auto_extend_if_below: Some((3, 50)),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, TICKET_PRICE).unwrap();
+            mint_native(&mut app, contracts.raffle.as_str(), NOIS_AMOUNT);
+            let raffle_state = |app: &sg_multi_test::StargazeApp| {
+                let response: RaffleResponse = app
+                    .wrap()
+                    .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::RaffleInfo { raffle_id })
+                    .unwrap();
+                response.raffle_state
+            };
+
+            // The sale ended below the minimum, the first randomness request extends it instead
+            advance_time(&mut app, 101);
+            let response = app
+                .execute_contract(
+                    Addr::unchecked("anyone"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::UpdateRandomness { raffle_id },
+                    &[],
+                )
+                .unwrap();
+            assert_eq!(
+                find_attribute(&response, "action"),
+                Some("auto_extend".to_string())
+            );
+            assert_eq!(raffle_state(&app), RaffleState::Started);
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, TICKET_PRICE).unwrap();
+
+            // Still below the minimum, but the raffle can only be extended once
+            advance_time(&mut app, 50);
+            assert_eq!(raffle_state(&app), RaffleState::Closed);
+            let response = app
+                .execute_contract(
+                    Addr::unchecked("anyone"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::UpdateRandomness { raffle_id },
+                    &[],
+                )
+                .unwrap();
+            assert_eq!(
+                find_attribute(&response, "action"),
+                Some("update_randomness".to_string())
+            );
+        }
+    }
+
+    mod create {
+        use cosmwasm_std::{Addr, Empty, StdError};
+        use cw_multi_test::Executor;
+        use raffles::{
+            error::ContractError,
+            msg::{ConfigResponse, ExecuteMsg, QueryMsg},
+            state::RaffleOptionsMsg,
+        };
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::{
+            contract_boxes::contract_reentrant_nft,
+            helpers::{
+                self, create_collection, create_raffle, default_raffle_options, mint_and_approve,
+                nft_owner, setup_raffle_contracts, OWNER_ADDR,
+            },
+        };
+
+        const CREATOR: &str = "creator";
+
+        #[test]
+        fn unsupported_prize_type_rejected() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(CREATOR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::CreateRaffle {
+                        owner: None,
+                        assets: vec![
+                            AssetInfo::sg721(collection.as_str(), &token_id),
+                            AssetInfo::coin(100, NATIVE_DENOM),
+                        ],
+                        raffle_options: default_raffle_options(),
+                        raffle_ticket_price: AssetInfo::coin(100, NATIVE_DENOM),
+                    },
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::UnsupportedPrizeType { index: 1 }
+            );
+        }
+
+        #[test]
+        fn single_asset_rejected_below_minimum() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::SetMinAssetsPerRaffle {
+                    min_assets_per_raffle: 2,
+                },
+                &[],
+            )
+            .unwrap();
+
+            let token_ids: Vec<String> = (0..2)
+                .map(|_| {
+                    mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle)
+                })
+                .collect();
+            let err = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_ids[0])],
+                100,
+                default_raffle_options(),
+            )
+            .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::TooFewAssets { min: 2 }
+            );
+
+            create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                token_ids
+                    .iter()
+                    .map(|token_id| AssetInfo::sg721(collection.as_str(), token_id))
+                    .collect(),
+                100,
+                default_raffle_options(),
+            )
+            .unwrap();
+        }
+
+        #[test]
+        fn duplicate_prize_rejected() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+
+            let err = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![
+                    AssetInfo::sg721(collection.as_str(), &token_id),
+                    AssetInfo::sg721(collection.as_str(), &token_id),
+                ],
+                100,
+                default_raffle_options(),
+            )
+            .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::DuplicateAsset { index: 1 }
+            );
+        }
+
+        #[test]
+        fn trusted_minter_skips_ownership_queries() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            // The mock collection has no owner recorded for fresh tokens, so any ownership query fails
+            let nft_code_id = app.store_code(contract_reentrant_nft());
+            let collection = app
+                .instantiate_contract(
+                    nft_code_id,
+                    Addr::unchecked(OWNER_ADDR),
+                    &Empty {},
+                    &[],
+                    "mock-nft",
+                    None,
+                )
+                .unwrap();
+            let bundle: Vec<AssetInfo> = ["1", "2", "3"]
+                .iter()
+                .map(|token_id| AssetInfo::cw721(collection.as_str(), token_id))
+                .collect();
+
+            create_raffle(
+                &mut app,
+                &contracts.raffle,
+                "minter",
+                bundle.clone(),
+                100,
+                default_raffle_options(),
+            )
+            .unwrap_err();
+
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::SetTrustedMinters {
+                    trusted_minters: vec!["minter".to_string()],
+                },
+                &[],
+            )
+            .unwrap();
+            create_raffle(
+                &mut app,
+                &contracts.raffle,
+                "minter",
+                bundle,
+                100,
+                default_raffle_options(),
+            )
+            .unwrap();
+            for token_id in ["1", "2", "3"] {
+                assert_eq!(
+                    nft_owner(&app, &collection, token_id),
+                    contracts.raffle.to_string()
+                );
+            }
+        }
+
+        #[test]
+        fn out_of_range_preview_rejected() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+
+            let err = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_preview: Some(1),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::InvalidPreviewIndex {
+                    index: 1,
+                    assets_len: 1
+                }
+            );
+        }
+
+        #[test]
+        fn sg721_ownership_checked() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let assets = vec![AssetInfo::sg721(collection.as_str(), &token_id)];
+
+            // The raffle contract is approved, but the sender doesn't own the token
+            let err = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                "thief",
+                assets.clone(),
+                100,
+                default_raffle_options(),
+            )
+            .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::Std(StdError::generic_err("unauthorized"))
+            );
+
+            create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                assets,
+                100,
+                default_raffle_options(),
+            )
+            .unwrap();
+            assert_eq!(
+                nft_owner(&app, &collection, &token_id),
+                contracts.raffle.to_string()
+            );
+        }
+
+        #[test]
+        fn live_raffles_capped() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            app.execute_contract(
+                Addr::unchecked(helpers::OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::SetMaxLiveRaffles {
+                    max_live_raffles: Some(2),
+                },
+                &[],
+            )
+            .unwrap();
+
+            let create = |app: &mut _, creator: &str| {
+                let token_id =
+                    mint_and_approve(app, &minter, &collection, creator, &contracts.raffle);
+                create_raffle(
+                    app,
+                    &contracts.raffle,
+                    creator,
+                    vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    100,
+                    default_raffle_options(),
+                )
+            };
+            let first_raffle = create(&mut app, "creator0").unwrap();
+            create(&mut app, "creator1").unwrap();
+            let err = create(&mut app, "creator2").unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::TooManyLiveRaffles { max: 2 }
+            );
+
+            // Cancelling a raffle frees a slot
+            app.execute_contract(
+                Addr::unchecked("creator0"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::CancelRaffle {
+                    raffle_id: first_raffle,
+                },
+                &[],
+            )
+            .unwrap();
+            create(&mut app, "creator3").unwrap();
+
+            let config: ConfigResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::Config {})
+                .unwrap();
+            assert_eq!(config.max_live_raffles, Some(2));
+            assert_eq!(config.live_raffles, 2);
+        }
+    }
+
+    mod modify {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use raffles::{
+            error::ContractError,
+            msg::{ExecuteMsg, QueryMsg, RaffleResponse},
+        };
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, create_raffle, default_raffle_options, mint_and_approve,
+            setup_raffle_contracts,
+        };
+
+        const CREATOR: &str = "creator";
+
+        #[test]
+        fn price_asset_type_change_rejected() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                default_raffle_options(),
+            )
+            .unwrap();
+            let modify = |app: &mut sg_multi_test::StargazeApp, price: AssetInfo| {
+                app.execute_contract(
+                    Addr::unchecked(CREATOR),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::ModifyRaffle {
+                        raffle_id,
+                        raffle_ticket_price: Some(price),
+                        raffle_options: default_raffle_options(),
+                    },
+                    &[],
+                )
+            };
+
+            let err = modify(&mut app, AssetInfo::sg721(collection.as_str(), "1")).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::CannotChangePriceAssetType {}
+            );
+
+            // The amount and denom can still change
+            modify(&mut app, AssetInfo::coin(200, "other")).unwrap();
+            let raffle: RaffleResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::RaffleInfo { raffle_id })
+                .unwrap();
+            assert_eq!(
+                raffle.raffle_info.unwrap().raffle_ticket_price,
+                AssetInfo::coin(200, "other")
+            );
+            modify(&mut app, AssetInfo::coin(100, NATIVE_DENOM)).unwrap();
+        }
+    }
+
+    mod migrate {
+        use cosmwasm_std::testing::{mock_dependencies, mock_env};
+        use cosmwasm_std::{coin, Addr, Decimal, Empty, Storage};
+        use raffles::{
+            contract::migrate,
+            state::{
+                load_live_raffles, RaffleFeeParams, RandomnessMode, BUYER_PRICES,
+                COLLECTION_RAFFLES, CONFIG, PAID_TICKETS, RAFFLE_INFO, TICKET_HOLDERS,
+                USER_TICKETS,
+            },
+            utils::JOB_ID_PREFIX,
+        };
+
+        // State written by the contract version deployed before the new config and raffle fields
+        const LEGACY_CONFIG: &str = r#"{"name":"raffle param name","owner":"owner","fee_addr":"fee","last_raffle_id":1,"minimum_raffle_duration":1,"minimum_raffle_timeout":120,"creation_fee_denom":"ustars","creation_fee_amount":"50","raffle_fee":"0.1","lock":false,"nois_proxy_addr":"nois","nois_proxy_denom":"ustars","nois_proxy_amount":"50"}"#;
+        const LEGACY_RAFFLE: &str = r#"{"owner":"creator","assets":[{"sg721_token":{"address":"collection","token_id":"1"}}],"raffle_ticket_price":{"coin":{"denom":"ustars","amount":"100"}},"number_of_tickets":3,"randomness":{"nois_randomness":null,"requested":true},"winner":null,"is_cancelled":false,"raffle_options":{"raffle_start_timestamp":"1000","raffle_duration":100,"raffle_timeout":120,"comment":null,"max_participant_number":null,"max_ticket_per_address":null,"raffle_preview":0}}"#;
+        const CLAIMED_LEGACY_RAFFLE: &str = r#"{"owner":"creator","assets":[{"coin":{"denom":"ustars","amount":"10"}}],"raffle_ticket_price":{"coin":{"denom":"ustars","amount":"100"}},"number_of_tickets":0,"randomness":null,"winner":"creator","is_cancelled":false,"raffle_options":{"raffle_start_timestamp":"1000","raffle_duration":100,"raffle_timeout":120,"comment":null,"max_participant_number":null,"max_ticket_per_address":null,"raffle_preview":0}}"#;
+
+        #[test]
+        fn legacy_state_is_migrated() {
+            let mut deps = mock_dependencies();
+            deps.storage.set(CONFIG.as_slice(), LEGACY_CONFIG.as_bytes());
+            deps.storage.set(&RAFFLE_INFO.key(0), CLAIMED_LEGACY_RAFFLE.as_bytes());
+            deps.storage.set(&RAFFLE_INFO.key(1), LEGACY_RAFFLE.as_bytes());
+            let buyer = Addr::unchecked("buyer");
+            USER_TICKETS.save(&mut deps.storage, (&buyer, 1), &3).unwrap();
+
+            // The old entries already load, with the defaults of the new fields
+            let config = CONFIG.load(&deps.storage).unwrap();
+            assert_eq!(config.job_id_prefix, JOB_ID_PREFIX);
+            assert_eq!(config.rand_fee, Decimal::zero());
+            assert_eq!(config.randomness_mode, RandomnessMode::Nois);
+            assert_eq!(config.min_assets_per_raffle, 1);
+            let raffle = RAFFLE_INFO.load(&deps.storage, 1).unwrap();
+            assert_eq!(raffle.randomness.unwrap().nois_fee, None);
+
+            let response = migrate(deps.as_mut(), mock_env(), Empty {}).unwrap();
+            assert_eq!(response.attributes[1].value, "2");
+
+            let config = CONFIG.load(&deps.storage).unwrap();
+            let raffle = RAFFLE_INFO.load(&deps.storage, 1).unwrap();
+            assert_eq!(raffle.fee_params, RaffleFeeParams::from_config(&config));
+            assert_eq!(raffle.fee_params.raffle_fee, Decimal::percent(10));
+            assert_eq!(raffle.tickets_paid, 3);
+            assert_eq!(PAID_TICKETS.load(&deps.storage, (1, &buyer)).unwrap(), 3);
+            assert_eq!(TICKET_HOLDERS.load(&deps.storage, (1, &buyer)).unwrap(), 3);
+            assert_eq!(
+                BUYER_PRICES.load(&deps.storage, (1, &buyer)).unwrap(),
+                coin(100, "ustars")
+            );
+            assert!(COLLECTION_RAFFLES.has(&deps.storage, ("collection", 1)));
+            // Only the raffle that wasn't claimed is live
+            assert_eq!(load_live_raffles(&deps.storage).unwrap(), 1);
+
+            // Migrating again leaves the state untouched
+            let response = migrate(deps.as_mut(), mock_env(), Empty {}).unwrap();
+            assert_eq!(response.attributes[1].value, "0");
+            assert_eq!(RAFFLE_INFO.load(&deps.storage, 1).unwrap(), raffle);
+        }
     }
 }
 
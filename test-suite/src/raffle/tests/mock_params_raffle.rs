@@ -1,9 +1,8 @@
 use cosmwasm_std::{Decimal, Addr, Uint128};
-use raffles::state::{Config as RaffleParams, MINIMUM_RAFFLE_TIMEOUT, MINIMUM_RAFFLE_DURATION};
+use raffles::state::{Config as RaffleParams, MINIMUM_RAFFLE_TIMEOUT, MINIMUM_RAFFLE_DURATION, MINIMUM_RAND_FEE, NoisFeeSource, RandomnessMode};
 use sg_std::NATIVE_DENOM;
 
 const RAFFLE_FEE: u64 = 50; // 50%
-const RAND_FEE: u64 = 5;
 
 const NOIS_PROXY_ADDR: &str = "nois";
 const FEE_ADDR: &str = "fee";
@@ -20,11 +19,23 @@ pub fn mock_params() -> RaffleParams {
          minimum_raffle_duration: MINIMUM_RAFFLE_DURATION, 
          minimum_raffle_timeout: MINIMUM_RAFFLE_TIMEOUT, 
          raffle_fee: Decimal::percent(RAFFLE_FEE), 
+         rand_fee: MINIMUM_RAND_FEE,
          lock: false,        
+         full_pause: false,
          nois_proxy_addr: Addr::unchecked(NOIS_PROXY_ADDR),
          nois_proxy_denom: NATIVE_DENOM.to_owned(),
         creation_fee_denom: NATIVE_DENOM.to_owned(),
         creation_fee_amount: Uint128::new(NOIS_AMOUNT),
         nois_proxy_amount: NOIS_AMOUNT.into(),
+        job_id_prefix: "raffle-".to_string(),
+        max_live_raffles: None,
+        cancel_lockout_seconds: 0,
+        trusted_minters: vec![],
+        max_nois_proxy_amount: None,
+        randomness_mode: RandomnessMode::Nois,
+        consolation_bps: None,
+        nois_fee_source: NoisFeeSource::Contract,
+        min_assets_per_raffle: 1,
+        price_oracle: None,
     }
 }
\ No newline at end of file
@@ -0,0 +1,1292 @@
+#[cfg(test)]
+mod tests {
+    mod can_buy {
+        use cosmwasm_std::{Addr, HexBinary, Timestamp};
+        use cw_multi_test::Executor;
+        use raffles::{
+            msg::{CanBuyResponse, CantBuyReason, ExecuteMsg, QueryMsg},
+            state::RaffleOptionsMsg,
+        };
+        use sg_multi_test::StargazeApp;
+        use sha2::{Digest, Sha256};
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, buy_tickets, create_collection, create_raffle, default_raffle_options,
+            mint_and_approve, setup_raffle_contracts, GENESIS_TIME, OWNER_ADDR,
+        };
+
+        const CREATOR: &str = "creator";
+        const BUYER: &str = "buyer";
+        const TICKET_PRICE: u128 = 100;
+
+        fn setup_raffle(raffle_options: RaffleOptionsMsg) -> (StargazeApp, Addr, u64) {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) = create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                raffle_options,
+            )
+            .unwrap();
+            (app, contracts.raffle, raffle_id)
+        }
+
+        fn can_buy(
+            app: &StargazeApp,
+            raffle: &Addr,
+            raffle_id: u64,
+            ticket_number: u32,
+        ) -> CanBuyResponse {
+            app.wrap()
+                .query_wasm_smart(
+                    raffle.clone(),
+                    &QueryMsg::CanBuy {
+                        raffle_id,
+                        buyer: BUYER.to_string(),
+                        ticket_number,
+                        proof: None,
+                    },
+                )
+                .unwrap()
+        }
+
+        #[test]
+        fn can_buy_on_started_raffle() {
+            let (app, raffle, raffle_id) = setup_raffle(default_raffle_options());
+            assert_eq!(
+                can_buy(&app, &raffle, raffle_id, 10),
+                CanBuyResponse {
+                    can_buy: true,
+                    reason: None,
+                }
+            );
+        }
+
+        #[test]
+        fn cant_buy_before_start() {
+            let (app, raffle, raffle_id) = setup_raffle(RaffleOptionsMsg {
+                raffle_start_timestamp: Some(
+                    Timestamp::from_nanos(GENESIS_TIME).plus_seconds(1_000),
+                ),
+                ..default_raffle_options()
+            });
+            assert_eq!(
+                can_buy(&app, &raffle, raffle_id, 1),
+                CanBuyResponse {
+                    can_buy: false,
+                    reason: Some(CantBuyReason::NotStarted),
+                }
+            );
+        }
+
+        #[test]
+        fn cant_buy_after_close() {
+            let (mut app, raffle, raffle_id) = setup_raffle(RaffleOptionsMsg {
+                raffle_duration: Some(100),
+                ..default_raffle_options()
+            });
+            advance_time(&mut app, 101);
+            assert_eq!(
+                can_buy(&app, &raffle, raffle_id, 1),
+                CanBuyResponse {
+                    can_buy: false,
+                    reason: Some(CantBuyReason::Closed),
+                }
+            );
+        }
+
+        #[test]
+        fn cant_buy_over_address_cap() {
+            let (mut app, raffle, raffle_id) = setup_raffle(RaffleOptionsMsg {
+                max_ticket_per_address: Some(3),
+                ..default_raffle_options()
+            });
+            buy_tickets(&mut app, &raffle, BUYER, raffle_id, 2, TICKET_PRICE).unwrap();
+
+            assert!(can_buy(&app, &raffle, raffle_id, 1).can_buy);
+            assert_eq!(
+                can_buy(&app, &raffle, raffle_id, 2),
+                CanBuyResponse {
+                    can_buy: false,
+                    reason: Some(CantBuyReason::MaxTicketsPerAddress),
+                }
+            );
+            // The query agrees with the actual purchase
+            buy_tickets(&mut app, &raffle, BUYER, raffle_id, 2, TICKET_PRICE).unwrap_err();
+        }
+
+        #[test]
+        fn cant_buy_over_total_cap() {
+            let (mut app, raffle, raffle_id) = setup_raffle(RaffleOptionsMsg {
+                max_participant_number: Some(4),
+                ..default_raffle_options()
+            });
+            buy_tickets(&mut app, &raffle, "other-buyer", raffle_id, 3, TICKET_PRICE).unwrap();
+
+            assert!(can_buy(&app, &raffle, raffle_id, 1).can_buy);
+            assert_eq!(
+                can_buy(&app, &raffle, raffle_id, 2),
+                CanBuyResponse {
+                    can_buy: false,
+                    reason: Some(CantBuyReason::MaxTickets),
+                }
+            );
+            buy_tickets(&mut app, &raffle, BUYER, raffle_id, 2, TICKET_PRICE).unwrap_err();
+        }
+
+        #[test]
+        fn multiplier_counts_against_caps() {
+            let (mut app, raffle, raffle_id) = setup_raffle(RaffleOptionsMsg {
+                max_ticket_per_address: Some(3),
+                ..default_raffle_options()
+            });
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                raffle.clone(),
+                &ExecuteMsg::SetTicketMultiplier {
+                    address: BUYER.to_string(),
+                    multiplier: Some(2),
+                },
+                &[],
+            )
+            .unwrap();
+
+            assert!(can_buy(&app, &raffle, raffle_id, 1).can_buy);
+            assert_eq!(
+                can_buy(&app, &raffle, raffle_id, 2),
+                CanBuyResponse {
+                    can_buy: false,
+                    reason: Some(CantBuyReason::MaxTicketsPerAddress),
+                }
+            );
+            buy_tickets(&mut app, &raffle, BUYER, raffle_id, 2, TICKET_PRICE).unwrap_err();
+        }
+
+        #[test]
+        fn cant_buy_while_paused() {
+            let (mut app, raffle, raffle_id) = setup_raffle(default_raffle_options());
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                raffle.clone(),
+                &ExecuteMsg::ToggleLock {
+                    lock: false,
+                    full_pause: Some(true),
+                },
+                &[],
+            )
+            .unwrap();
+
+            assert_eq!(
+                can_buy(&app, &raffle, raffle_id, 1),
+                CanBuyResponse {
+                    can_buy: false,
+                    reason: Some(CantBuyReason::Paused),
+                }
+            );
+            buy_tickets(&mut app, &raffle, BUYER, raffle_id, 1, TICKET_PRICE).unwrap_err();
+        }
+
+        #[test]
+        fn cant_buy_off_allowlist() {
+            // The allowlist only holds another address
+            let root: [u8; 32] = Sha256::digest("other-buyer".as_bytes()).into();
+            let (mut app, raffle, raffle_id) = setup_raffle(RaffleOptionsMsg {
+                merkle_root: Some(HexBinary::from(root)),
+                ..default_raffle_options()
+            });
+
+            assert_eq!(
+                can_buy(&app, &raffle, raffle_id, 1),
+                CanBuyResponse {
+                    can_buy: false,
+                    reason: Some(CantBuyReason::NotAllowlisted),
+                }
+            );
+            buy_tickets(&mut app, &raffle, BUYER, raffle_id, 1, TICKET_PRICE).unwrap_err();
+        }
+    }
+
+    mod ticket_holders {
+        use cosmwasm_std::Addr;
+        use raffles::msg::QueryMsg;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            buy_tickets, create_collection, create_raffle, default_raffle_options,
+            mint_and_approve, setup_raffle_contracts,
+        };
+
+        const CREATOR: &str = "creator";
+        const TICKET_PRICE: u128 = 100;
+
+        #[test]
+        fn holders_are_aggregated() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                default_raffle_options(),
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 2, TICKET_PRICE).unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 3, TICKET_PRICE).unwrap();
+
+            let holders: Vec<(Addr, u32)> = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::TicketHolders {
+                        raffle_id,
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(holders, vec![(Addr::unchecked("buyer"), 5)]);
+
+            // The holders can be paginated
+            buy_tickets(&mut app, &contracts.raffle, "other", raffle_id, 1, TICKET_PRICE).unwrap();
+            let holders: Vec<(Addr, u32)> = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::TicketHolders {
+                        raffle_id,
+                        start_after: Some("buyer".to_string()),
+                        limit: Some(1),
+                    },
+                )
+                .unwrap();
+            assert_eq!(holders, vec![(Addr::unchecked("other"), 1)]);
+        }
+    }
+
+    mod ticket_owner_at {
+        use cosmwasm_std::{Addr, StdResult};
+        use raffles::msg::QueryMsg;
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            buy_tickets, create_collection, create_raffle, default_raffle_options,
+            mint_and_approve, setup_raffle_contracts,
+        };
+
+        const CREATOR: &str = "creator";
+        const TICKET_PRICE: u128 = 100;
+
+        #[test]
+        fn tickets_indexed_in_purchase_order() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                default_raffle_options(),
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "first", raffle_id, 1, TICKET_PRICE).unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "second", raffle_id, 2, TICKET_PRICE).unwrap();
+
+            let owner_at = |app: &StargazeApp, ticket_id: u32| -> StdResult<Addr> {
+                app.wrap().query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::TicketOwnerAt {
+                        raffle_id,
+                        ticket_id,
+                    },
+                )
+            };
+            assert_eq!(owner_at(&app, 0).unwrap(), Addr::unchecked("first"));
+            assert_eq!(owner_at(&app, 2).unwrap(), Addr::unchecked("second"));
+            let err = owner_at(&app, 3).unwrap_err();
+            assert!(err.to_string().contains("out of range"));
+        }
+    }
+
+    mod has_entered {
+        use raffles::msg::QueryMsg;
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            buy_tickets, create_collection, create_raffle, default_raffle_options,
+            mint_and_approve, setup_raffle_contracts,
+        };
+
+        const CREATOR: &str = "creator";
+        const TICKET_PRICE: u128 = 100;
+
+        #[test]
+        fn only_buyers_entered() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                default_raffle_options(),
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, TICKET_PRICE).unwrap();
+
+            let has_entered = |app: &StargazeApp, address: &str| -> bool {
+                app.wrap()
+                    .query_wasm_smart(
+                        contracts.raffle.clone(),
+                        &QueryMsg::HasEntered {
+                            raffle_id,
+                            address: address.to_string(),
+                        },
+                    )
+                    .unwrap()
+            };
+            assert!(has_entered(&app, "buyer"));
+            assert!(!has_entered(&app, "stranger"));
+        }
+    }
+
+    mod verify_winner {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use raffles::{
+            msg::{ExecuteMsg, QueryMsg},
+            state::RaffleOptionsMsg,
+        };
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, buy_tickets, create_collection, create_raffle, default_raffle_options,
+            draw_raffle, mint_and_approve, setup_raffle_contracts,
+        };
+
+        const CREATOR: &str = "creator";
+        const TICKET_PRICE: u128 = 100;
+
+        #[test]
+        fn drawn_winner_is_verified() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            for buyer in ["alice", "bob", "carol"] {
+                buy_tickets(&mut app, &contracts.raffle, buyer, raffle_id, 2, TICKET_PRICE)
+                    .unwrap();
+            }
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_id, [7u8; 32]);
+
+            let verify = |app: &sg_multi_test::StargazeApp| -> bool {
+                app.wrap()
+                    .query_wasm_smart(
+                        contracts.raffle.clone(),
+                        &QueryMsg::VerifyWinner { raffle_id },
+                    )
+                    .unwrap()
+            };
+            // Nothing to verify before the raffle is claimed
+            assert!(!verify(&app));
+
+            app.execute_contract(
+                Addr::unchecked("anyone"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft { raffle_id },
+                &[],
+            )
+            .unwrap();
+            assert!(verify(&app));
+        }
+    }
+
+    mod raffle_randomness {
+        use cosmwasm_std::{Addr, HexBinary};
+        use cw_multi_test::Executor;
+        use raffles::{
+            msg::{ExecuteMsg, QueryMsg, RaffleRandomnessResponse, RaffleResponse},
+            state::RaffleOptionsMsg,
+        };
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, buy_tickets, create_collection, create_raffle, default_raffle_options,
+            draw_raffle, mint_and_approve, setup_raffle_contracts,
+        };
+
+        const CREATOR: &str = "creator";
+        const TICKET_PRICE: u128 = 100;
+
+        #[test]
+        fn beacon_reproduces_the_draw() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                TICKET_PRICE,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            for buyer in ["alice", "bob", "carol"] {
+                buy_tickets(&mut app, &contracts.raffle, buyer, raffle_id, 2, TICKET_PRICE)
+                    .unwrap();
+            }
+            let query_randomness = |app: &sg_multi_test::StargazeApp| -> RaffleRandomnessResponse {
+                app.wrap()
+                    .query_wasm_smart(
+                        contracts.raffle.clone(),
+                        &QueryMsg::RaffleRandomness { raffle_id },
+                    )
+                    .unwrap()
+            };
+            assert_eq!(
+                query_randomness(&app),
+                RaffleRandomnessResponse {
+                    randomness: None,
+                    winner_ticket_id: None,
+                }
+            );
+
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_id, [7u8; 32]);
+            let response = query_randomness(&app);
+            assert_eq!(response.randomness, Some(HexBinary::from([7u8; 32].as_slice())));
+            let winner_ticket_id = response.winner_ticket_id.unwrap();
+            let ticket_owner: Addr = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::TicketOwnerAt {
+                        raffle_id,
+                        ticket_id: winner_ticket_id,
+                    },
+                )
+                .unwrap();
+
+            app.execute_contract(
+                Addr::unchecked("anyone"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::ClaimNft { raffle_id },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(query_randomness(&app), response);
+            let raffle: RaffleResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::RaffleInfo { raffle_id })
+                .unwrap();
+            assert_eq!(raffle.raffle_info.unwrap().winner, Some(ticket_owner));
+        }
+    }
+
+    mod raffle_infos {
+        use raffles::{
+            msg::{QueryMsg, RaffleResponse},
+            state::RaffleState,
+        };
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, create_raffle, default_raffle_options, mint_and_approve,
+            setup_raffle_contracts,
+        };
+
+        #[test]
+        fn missing_raffles_have_no_info() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let mut raffle_ids = vec![];
+            for _ in 0..2 {
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, "creator", &contracts.raffle);
+                raffle_ids.push(
+                    create_raffle(
+                        &mut app,
+                        &contracts.raffle,
+                        "creator",
+                        vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                        100,
+                        default_raffle_options(),
+                    )
+                    .unwrap(),
+                );
+            }
+
+            let raffles: Vec<RaffleResponse> = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::RaffleInfos {
+                        raffle_ids: vec![raffle_ids[1], 42, raffle_ids[0]],
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                raffles
+                    .iter()
+                    .map(|raffle| raffle.raffle_id)
+                    .collect::<Vec<u64>>(),
+                vec![raffle_ids[1], 42, raffle_ids[0]]
+            );
+            assert_eq!(raffles[0].raffle_state, RaffleState::Started);
+            assert!(raffles[0].raffle_info.is_some());
+            assert!(raffles[1].raffle_info.is_none());
+            assert!(raffles[2].raffle_info.is_some());
+
+            // The number of raffles per query is capped
+            app.wrap()
+                .query_wasm_smart::<Vec<RaffleResponse>>(
+                    contracts.raffle.clone(),
+                    &QueryMsg::RaffleInfos {
+                        raffle_ids: (0..101).collect(),
+                    },
+                )
+                .unwrap_err();
+        }
+    }
+
+    mod price_stats {
+        use cosmwasm_std::{Addr, Uint128};
+        use cw_multi_test::Executor;
+        use raffles::msg::{ExecuteMsg, PriceStatsResponse, QueryMsg};
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, create_raffle, default_raffle_options, mint_and_approve,
+            setup_raffle_contracts,
+        };
+
+        #[test]
+        fn min_max_and_most_common_prices() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let mut raffle_ids = vec![];
+            for (i, price) in [200, 50, 200, 500, 10].into_iter().enumerate() {
+                let creator = format!("creator{i}");
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, &creator, &contracts.raffle);
+                raffle_ids.push(
+                    create_raffle(
+                        &mut app,
+                        &contracts.raffle,
+                        &creator,
+                        vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                        price,
+                        default_raffle_options(),
+                    )
+                    .unwrap(),
+                );
+            }
+            // Cancelled raffles are not taken into account
+            app.execute_contract(
+                Addr::unchecked("creator4"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::CancelRaffle {
+                    raffle_id: raffle_ids[4],
+                },
+                &[],
+            )
+            .unwrap();
+
+            let stats: PriceStatsResponse = app
+                .wrap()
+                .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::PriceStats { denom: None })
+                .unwrap();
+            assert_eq!(
+                stats,
+                PriceStatsResponse {
+                    denom: NATIVE_DENOM.to_string(),
+                    raffles_sampled: 4,
+                    min: Some(Uint128::new(50)),
+                    max: Some(Uint128::new(500)),
+                    most_common: Some(Uint128::new(200)),
+                }
+            );
+
+            // No raffle is priced in another denom
+            let stats: PriceStatsResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::PriceStats {
+                        denom: Some("uatom".to_string()),
+                    },
+                )
+                .unwrap();
+            assert_eq!(stats.raffles_sampled, 0);
+            assert_eq!(stats.min, None);
+        }
+    }
+    mod randomness_status {
+        use cosmwasm_std::Addr;
+        use cw_multi_test::Executor;
+        use raffles::{
+            msg::{ExecuteMsg, QueryMsg, RandomnessStatusResponse},
+            state::RaffleOptionsMsg,
+        };
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, buy_tickets, create_collection, create_raffle, default_raffle_options,
+            mint_and_approve, mint_native, send_nois_randomness, setup_raffle_contracts, NOIS_AMOUNT,
+            OWNER_ADDR,
+        };
+
+        const CREATOR: &str = "creator";
+
+        #[test]
+        fn follows_the_draw() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+            buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, 100).unwrap();
+
+            let status = |app: &StargazeApp| -> RandomnessStatusResponse {
+                app.wrap()
+                    .query_wasm_smart(
+                        contracts.raffle.clone(),
+                        &QueryMsg::RandomnessStatus { raffle_id },
+                    )
+                    .unwrap()
+            };
+            assert_eq!(
+                status(&app),
+                RandomnessStatusResponse {
+                    requested: false,
+                    received: false
+                }
+            );
+
+            advance_time(&mut app, 101);
+            mint_native(&mut app, contracts.raffle.as_str(), NOIS_AMOUNT);
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::UpdateRandomness { raffle_id },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(
+                status(&app),
+                RandomnessStatusResponse {
+                    requested: true,
+                    received: false
+                }
+            );
+
+            send_nois_randomness(&mut app, &contracts, raffle_id, [3u8; 32]).unwrap();
+            assert_eq!(
+                status(&app),
+                RandomnessStatusResponse {
+                    requested: true,
+                    received: true
+                }
+            );
+        }
+    }
+
+    mod sale_time_remaining {
+        use raffles::{msg::QueryMsg, state::RaffleOptionsMsg};
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, create_collection, create_raffle, default_raffle_options,
+            mint_and_approve, setup_raffle_contracts,
+        };
+
+        const CREATOR: &str = "creator";
+
+        #[test]
+        fn counts_down_during_the_sale() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let start = app.block_info().time.plus_seconds(10);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                RaffleOptionsMsg {
+                    raffle_start_timestamp: Some(start),
+                    raffle_duration: Some(100),
+                    ..default_raffle_options()
+                },
+            )
+            .unwrap();
+
+            let remaining = |app: &StargazeApp| -> Option<u64> {
+                app.wrap()
+                    .query_wasm_smart(
+                        contracts.raffle.clone(),
+                        &QueryMsg::SaleTimeRemaining { raffle_id },
+                    )
+                    .unwrap()
+            };
+            assert_eq!(remaining(&app), None);
+
+            advance_time(&mut app, 50);
+            assert_eq!(remaining(&app), Some(60));
+
+            advance_time(&mut app, 60);
+            assert_eq!(remaining(&app), Some(0));
+        }
+    }
+
+    mod raffles_by_collection {
+        use raffles::msg::{AllRafflesResponse, QueryMsg};
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, create_raffle, default_raffle_options, mint_and_approve,
+            setup_raffle_contracts,
+        };
+
+        const CREATOR: &str = "creator";
+
+        #[test]
+        fn only_raffles_of_the_collection_listed() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let (other_minter, other_collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+
+            let mut expected = vec![];
+            for (raffle_minter, raffle_collection) in [
+                (&minter, &collection),
+                (&other_minter, &other_collection),
+                (&minter, &collection),
+            ] {
+                let token_id = mint_and_approve(
+                    &mut app,
+                    raffle_minter,
+                    raffle_collection,
+                    CREATOR,
+                    &contracts.raffle,
+                );
+                let raffle_id = create_raffle(
+                    &mut app,
+                    &contracts.raffle,
+                    CREATOR,
+                    vec![AssetInfo::sg721(raffle_collection.as_str(), &token_id)],
+                    100,
+                    default_raffle_options(),
+                )
+                .unwrap();
+                if *raffle_collection == collection {
+                    expected.push(raffle_id);
+                }
+            }
+            expected.reverse();
+
+            let response: AllRafflesResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::RafflesByCollection {
+                        address: collection.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            let raffle_ids: Vec<u64> = response.raffles.iter().map(|r| r.raffle_id).collect();
+            assert_eq!(raffle_ids, expected);
+        }
+    }
+
+    mod awaiting_claim {
+        use raffles::{
+            msg::{AllRafflesResponse, QueryMsg},
+            state::{RaffleOptionsMsg, RaffleState},
+        };
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, buy_tickets, create_collection, create_raffle, default_raffle_options,
+            draw_raffle, mint_and_approve, setup_raffle_contracts,
+        };
+
+        const CREATOR: &str = "creator";
+
+        #[test]
+        fn only_finished_raffles_listed() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+
+            let mut raffle_ids = vec![];
+            for raffle_duration in [100, 10_000] {
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+                let raffle_id = create_raffle(
+                    &mut app,
+                    &contracts.raffle,
+                    CREATOR,
+                    vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    100,
+                    RaffleOptionsMsg {
+                        raffle_duration: Some(raffle_duration),
+                        ..default_raffle_options()
+                    },
+                )
+                .unwrap();
+                buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 1, 100).unwrap();
+                raffle_ids.push(raffle_id);
+            }
+            advance_time(&mut app, 101);
+            draw_raffle(&mut app, &contracts, raffle_ids[0], [1u8; 32]);
+
+            let response: AllRafflesResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::AwaitingClaim {
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(response.raffles.len(), 1);
+            assert_eq!(response.raffles[0].raffle_id, raffle_ids[0]);
+            assert_eq!(response.raffles[0].raffle_state, RaffleState::Finished);
+        }
+    }
+
+    mod collection_participation {
+        use raffles::msg::QueryMsg;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            buy_tickets, create_collection, create_raffle, default_raffle_options,
+            mint_and_approve, setup_raffle_contracts,
+        };
+
+        const CREATOR: &str = "creator";
+
+        #[test]
+        fn shared_buyers_counted_once() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let (other_minter, other_collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+
+            for (minter, collection, buyers) in [
+                (&minter, &collection, vec!["buyer1", "buyer2"]),
+                (&minter, &collection, vec!["buyer1"]),
+                (&other_minter, &other_collection, vec!["buyer3"]),
+            ] {
+                let token_id =
+                    mint_and_approve(&mut app, minter, collection, CREATOR, &contracts.raffle);
+                let raffle_id = create_raffle(
+                    &mut app,
+                    &contracts.raffle,
+                    CREATOR,
+                    vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    100,
+                    default_raffle_options(),
+                )
+                .unwrap();
+                for buyer in buyers {
+                    buy_tickets(&mut app, &contracts.raffle, buyer, raffle_id, 1, 100).unwrap();
+                }
+            }
+
+            let participation: u32 = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::CollectionParticipation {
+                        address: collection.to_string(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(participation, 2);
+        }
+    }
+
+    mod prize_value {
+        use cosmwasm_std::{coin, Addr};
+        use cw_multi_test::Executor;
+        use raffles::msg::{ExecuteMsg, PrizeValueResponse, QueryMsg};
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::{
+            contract_boxes::contract_price_oracle,
+            helpers::{
+                create_collection, create_raffle, default_raffle_options, mint_and_approve,
+                setup_raffle_contracts, OWNER_ADDR,
+            },
+        };
+
+        const CREATOR: &str = "creator";
+
+        #[test]
+        fn prize_valued_at_collection_floors() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let mut assets = vec![];
+            let mut collections = vec![];
+            for _ in 0..3 {
+                let (minter, collection) =
+                    create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+                assets.push(AssetInfo::sg721(collection.as_str(), &token_id));
+                collections.push(collection);
+            }
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                assets,
+                100,
+                default_raffle_options(),
+            )
+            .unwrap();
+            let query_value = |app: &sg_multi_test::StargazeApp| -> PrizeValueResponse {
+                app.wrap()
+                    .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::PrizeValue { raffle_id })
+                    .unwrap()
+            };
+
+            // Without an oracle, the NFTs have no value
+            assert_eq!(
+                query_value(&app),
+                PrizeValueResponse {
+                    assets: vec![None, None, None],
+                    total: vec![],
+                }
+            );
+
+            // The oracle has no data on the last collection
+            let oracle_id = app.store_code(contract_price_oracle());
+            let oracle = app
+                .instantiate_contract(
+                    oracle_id,
+                    Addr::unchecked(OWNER_ADDR),
+                    &vec![
+                        (collections[0].to_string(), coin(1_000, NATIVE_DENOM)),
+                        (collections[1].to_string(), coin(2_500, NATIVE_DENOM)),
+                    ],
+                    &[],
+                    "price-oracle",
+                    None,
+                )
+                .unwrap();
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::SetPriceOracle {
+                    price_oracle: Some(oracle.to_string()),
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(
+                query_value(&app),
+                PrizeValueResponse {
+                    assets: vec![
+                        Some(coin(1_000, NATIVE_DENOM)),
+                        Some(coin(2_500, NATIVE_DENOM)),
+                        None,
+                    ],
+                    total: vec![coin(3_500, NATIVE_DENOM)],
+                }
+            );
+        }
+    }
+
+    mod raffle_fees {
+        use cosmwasm_std::{Addr, Decimal};
+        use cw_multi_test::Executor;
+        use raffles::msg::{ExecuteMsg, QueryMsg, RaffleFeesResponse};
+        use sg_multi_test::StargazeApp;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, create_raffle, default_raffle_options, mint_and_approve,
+            setup_raffle_contracts_with, FEE_ADDR, OWNER_ADDR,
+        };
+
+        const CREATOR: &str = "creator";
+
+        #[test]
+        fn snapshot_survives_config_change() {
+            let (mut app, contracts) = setup_raffle_contracts_with(|msg| {
+                msg.rand_fee = Some(Decimal::percent(5));
+            });
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, CREATOR, &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                CREATOR,
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                default_raffle_options(),
+            )
+            .unwrap();
+
+            let query_fees = |app: &StargazeApp| -> RaffleFeesResponse {
+                app.wrap()
+                    .query_wasm_smart(
+                        contracts.raffle.clone(),
+                        &QueryMsg::RaffleFees { raffle_id },
+                    )
+                    .unwrap()
+            };
+            let fees = query_fees(&app);
+            assert_eq!(fees.fee_addr, Addr::unchecked(FEE_ADDR));
+            assert_eq!(fees.rand_fee, Decimal::percent(5));
+
+            app.execute_contract(
+                Addr::unchecked(OWNER_ADDR),
+                contracts.raffle.clone(),
+                &ExecuteMsg::UpdateConfig {
+                    name: None,
+                    owner: None,
+                    fee_addr: Some("new_fee_addr".to_string()),
+                    minimum_raffle_duration: None,
+                    minimum_raffle_timeout: None,
+                    creation_fee_denom: None,
+                    creation_fee_amount: None,
+                    raffle_fee: Some(Decimal::percent(20)),
+                    rand_fee: Some(Decimal::percent(10)),
+                    nois_proxy_addr: None,
+                    nois_proxy_denom: None,
+                    nois_proxy_amount: None,
+                    cancel_lockout_seconds: None,
+                    max_nois_proxy_amount: None,
+                    clear_max_nois_proxy_amount: None,
+                },
+                &[],
+            )
+            .unwrap();
+            assert_ne!(fees.raffle_fee, Decimal::percent(20));
+            assert_eq!(query_fees(&app), fees);
+        }
+    }
+
+    mod all_raffles {
+        use cosmwasm_std::{to_json_vec, Addr};
+        use cw_multi_test::Executor;
+        use raffles::{
+            msg::{AllRafflesResponse, ExecuteMsg, QueryFilters, QueryMsg, RaffleResponse},
+            state::{RaffleState, RAFFLE_INFO},
+        };
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            create_collection, create_raffle, default_raffle_options, mint_and_approve,
+            setup_raffle_contracts, write_contract_storage,
+        };
+
+        #[test]
+        fn empty_scan_reports_actual_state() {
+            let (mut app, contracts) = setup_raffle_contracts();
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let token_id =
+                mint_and_approve(&mut app, &minter, &collection, "creator", &contracts.raffle);
+            let raffle_id = create_raffle(
+                &mut app,
+                &contracts.raffle,
+                "creator",
+                vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                100,
+                default_raffle_options(),
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked("creator"),
+                contracts.raffle.clone(),
+                &ExecuteMsg::CancelRaffle { raffle_id },
+                &[],
+            )
+            .unwrap();
+
+            // More cancelled raffles than a single scan goes through
+            let cancelled: RaffleResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::RaffleInfo { raffle_id },
+                )
+                .unwrap();
+            let cancelled = to_json_vec(&cancelled.raffle_info.unwrap()).unwrap();
+            for raffle_id in 1..=101 {
+                write_contract_storage(
+                    &mut app,
+                    &contracts.raffle,
+                    &RAFFLE_INFO.key(raffle_id),
+                    &cancelled,
+                );
+            }
+
+            let response: AllRafflesResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contracts.raffle.clone(),
+                    &QueryMsg::AllRaffles {
+                        start_after: None,
+                        limit: None,
+                        filters: Some(QueryFilters {
+                            states: Some(vec![RaffleState::Started.to_string()]),
+                            owner: None,
+                            ticket_depositor: None,
+                            contains_token: None,
+                        }),
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                response.raffles,
+                vec![RaffleResponse {
+                    raffle_id: 2,
+                    raffle_state: RaffleState::Cancelled,
+                    raffle_info: None,
+                }]
+            );
+        }
+    }
+
+    mod treasury_earned {
+        use cosmwasm_std::{coin, Addr, Coin, Decimal, Uint128};
+        use cw_multi_test::Executor;
+        use raffles::{
+            msg::{ExecuteMsg, QueryMsg},
+            state::RaffleOptionsMsg,
+        };
+        use sg_multi_test::StargazeApp;
+        use sg_std::NATIVE_DENOM;
+        use utils::state::AssetInfo;
+
+        use crate::common_setup::helpers::{
+            advance_time, buy_tickets, create_collection, create_raffle, default_raffle_options,
+            draw_raffle, mint_and_approve, native_balance, setup_raffle_contracts_with, FEE_ADDR,
+        };
+
+        #[test]
+        fn grows_by_treasury_cut_on_claim() {
+            let (mut app, contracts) = setup_raffle_contracts_with(|msg| {
+                msg.raffle_fee = Some(Decimal::percent(10));
+            });
+            let (minter, collection) =
+                create_collection(&mut app, &contracts.factory, contracts.sg721_code_id);
+            let treasury_earned = |app: &StargazeApp| -> Vec<Coin> {
+                app.wrap()
+                    .query_wasm_smart(contracts.raffle.clone(), &QueryMsg::TreasuryEarned {})
+                    .unwrap()
+            };
+            assert_eq!(treasury_earned(&app), vec![]);
+
+            let mut earned = Uint128::zero();
+            for randomness in [[1u8; 32], [2u8; 32]] {
+                let token_id =
+                    mint_and_approve(&mut app, &minter, &collection, "creator", &contracts.raffle);
+                let raffle_id = create_raffle(
+                    &mut app,
+                    &contracts.raffle,
+                    "creator",
+                    vec![AssetInfo::sg721(collection.as_str(), &token_id)],
+                    100,
+                    RaffleOptionsMsg {
+                        raffle_duration: Some(100),
+                        ..default_raffle_options()
+                    },
+                )
+                .unwrap();
+                buy_tickets(&mut app, &contracts.raffle, "buyer", raffle_id, 3, 100).unwrap();
+                advance_time(&mut app, 101);
+                draw_raffle(&mut app, &contracts, raffle_id, randomness);
+
+                let fee_balance = native_balance(&app, FEE_ADDR);
+                app.execute_contract(
+                    Addr::unchecked("anyone"),
+                    contracts.raffle.clone(),
+                    &ExecuteMsg::ClaimNft { raffle_id },
+                    &[],
+                )
+                .unwrap();
+                let fee_amount = native_balance(&app, FEE_ADDR) - fee_balance;
+                assert!(!fee_amount.is_zero());
+                earned += fee_amount;
+                assert_eq!(
+                    treasury_earned(&app),
+                    vec![coin(earned.u128(), NATIVE_DENOM)]
+                );
+            }
+        }
+    }
+}